@@ -0,0 +1,219 @@
+//!
+//! Separable blur passes shared by the blur elements
+//!
+//! The frame is blurred along rows and then along columns. Every pass except the last one writes into an
+//! `Rgba16Float` texture of the context texture pool, the passes ping-pong between two of them, the last pass writes
+//! the output. Shaders have the same bindings:
+//! * `0` - uniform parameters
+//! * `1` - input texture
+//! * `2` - intermediate `rgba16float` storage texture
+//! * `3` - output `rgba8unorm` storage texture
+//!
+
+use deka_gst_wgpu::WgpuContext;
+
+/// Format of the intermediate textures, keeps precision between passes
+const INTERMEDIATE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Direction of a pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlurDirection {
+    Horizontal,
+    Vertical,
+}
+
+/// Entry points of a blur shader
+#[derive(Debug, Clone, Copy)]
+pub struct BlurEntryPoints {
+    /// Blurs rows into an intermediate texture
+    pub horizontal: &'static str,
+    /// Blurs columns into an intermediate texture
+    pub vertical: &'static str,
+    /// Blurs columns into the output texture
+    pub vertical_output: &'static str,
+}
+
+/// Frame to blur
+#[derive(Debug, Clone, Copy)]
+pub struct BlurFrame<'a> {
+    pub params: &'a wgpu::Buffer,
+    pub input: &'a wgpu::TextureView,
+    pub output: &'a wgpu::TextureView,
+    pub size: [u32; 2],
+}
+
+#[derive(Debug)]
+pub struct SeparableBlur {
+    horizontal: wgpu::ComputePipeline,
+    vertical: wgpu::ComputePipeline,
+    vertical_output: wgpu::ComputePipeline,
+    /// Workgroups covering the frame of the size in the direction
+    workgroups: fn(BlurDirection, [u32; 2]) -> (u32, u32),
+}
+
+impl SeparableBlur {
+    pub fn new(
+        ctx: &WgpuContext,
+        shader: fn() -> wgpu::ShaderModuleDescriptor<'static>,
+        entry_points: BlurEntryPoints,
+        workgroups: fn(BlurDirection, [u32; 2]) -> (u32, u32),
+    ) -> Self {
+        let uniform = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let input = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let storage = |binding, format| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+
+        let intermediate = [uniform, input, storage(2, INTERMEDIATE_FORMAT)];
+        let output = [uniform, input, storage(3, wgpu::TextureFormat::Rgba8Unorm)];
+
+        // Shared with other elements of the context, compiled once
+        Self {
+            horizontal: ctx.compute_pipeline(
+                shader(),
+                entry_points.horizontal,
+                Some(&[&intermediate]),
+            ),
+            vertical: ctx.compute_pipeline(shader(), entry_points.vertical, Some(&[&intermediate])),
+            vertical_output: ctx.compute_pipeline(
+                shader(),
+                entry_points.vertical_output,
+                Some(&[&output]),
+            ),
+            workgroups,
+        }
+    }
+
+    /// Creates bind groups of `passes` blurs in each direction, intermediate textures are taken from the pool
+    pub fn prepare(&self, ctx: &WgpuContext, frame: BlurFrame<'_>, passes: u32) -> BlurSteps {
+        let device = ctx.device();
+        let steps_count = 2 * passes.max(1);
+
+        let intermediates = (0..(steps_count - 1).min(2))
+            .map(|_| {
+                ctx.texture_pool().acquire(
+                    device,
+                    wgpu::Extent3d {
+                        width: frame.size[0],
+                        height: frame.size[1],
+                        depth_or_array_layers: 1,
+                    },
+                    INTERMEDIATE_FORMAT,
+                    wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+                )
+            })
+            .collect::<Vec<_>>();
+        let views = intermediates
+            .iter()
+            .map(|x| x.create_view(&Default::default()))
+            .collect::<Vec<_>>();
+
+        let mut steps = Vec::with_capacity(steps_count as usize);
+        let mut input = frame.input;
+        for step in 0..steps_count {
+            // Blurs commute, so all rows are blurred first
+            let direction = if step < passes.max(1) {
+                BlurDirection::Horizontal
+            } else {
+                BlurDirection::Vertical
+            };
+            let last = step + 1 == steps_count;
+
+            let (pipeline, binding, output) = match (direction, last) {
+                (_, true) => (&self.vertical_output, 3, frame.output),
+                (BlurDirection::Horizontal, false) => {
+                    (&self.horizontal, 2, &views[step as usize % 2])
+                }
+                (BlurDirection::Vertical, false) => (&self.vertical, 2, &views[step as usize % 2]),
+            };
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: frame.params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(input),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding,
+                        resource: wgpu::BindingResource::TextureView(output),
+                    },
+                ],
+            });
+
+            steps.push(BlurStep {
+                pipeline: pipeline.clone(),
+                bind_group,
+                workgroups: (self.workgroups)(direction, frame.size),
+            });
+            input = output;
+        }
+
+        BlurSteps {
+            steps,
+            intermediates,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BlurStep {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    workgroups: (u32, u32),
+}
+
+/// Passes of a frame made by [`SeparableBlur::prepare`]
+#[derive(Debug)]
+pub struct BlurSteps {
+    steps: Vec<BlurStep>,
+    intermediates: Vec<wgpu::Texture>,
+}
+
+impl BlurSteps {
+    /// Dispatches the passes in order
+    pub fn record(&self, pass: &mut wgpu::ComputePass<'_>) {
+        for step in &self.steps {
+            pass.set_pipeline(&step.pipeline);
+            pass.set_bind_group(0, &step.bind_group, &[]);
+            pass.dispatch_workgroups(step.workgroups.0, step.workgroups.1, 1);
+        }
+    }
+
+    /// Returns the intermediate textures to the pool, the passes must be recorded already
+    pub fn release(self, ctx: &WgpuContext) {
+        for texture in self.intermediates {
+            ctx.texture_pool().release(texture);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//!
+//! Textures of compute filters taking frames in WGPU buffers
+//!
+//! Shaders read and write textures, so the frame is copied from the input buffer into the input texture and the
+//! output texture, written by the shader, is copied into the output buffer. Frames are packed 4 bytes per pixel.
+//!
+
+use deka_gst_wgpu::{
+    crop::{clear_around_crop, CropRect, FrameLayout},
+    WgpuContext,
+};
+
+#[derive(Debug)]
+pub struct BufferFilterTextures {
+    pub input_texture: wgpu::Texture,
+    pub output_texture: wgpu::Texture,
+    pub input_view: wgpu::TextureView,
+    pub output_view: wgpu::TextureView,
+}
+
+impl BufferFilterTextures {
+    /// Creates `Rgba8Unorm` textures of the input and output frame sizes, the output one is a storage texture which
+    /// can be cleared
+    pub fn new(
+        device: &wgpu::Device,
+        in_info: &gst_video::VideoInfo,
+        out_info: &gst_video::VideoInfo,
+    ) -> Self {
+        let input_texture_descriptor = wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: in_info.width(),
+                height: in_info.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+        let input_texture = device.create_texture(&input_texture_descriptor);
+
+        let output_texture_descriptor = wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: out_info.width(),
+                height: out_info.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        };
+        let output_texture = device.create_texture(&output_texture_descriptor);
+
+        Self {
+            input_view: input_texture.create_view(&Default::default()),
+            output_view: output_texture.create_view(&Default::default()),
+            input_texture,
+            output_texture,
+        }
+    }
+
+    /// Copies the `crop` rectangle of the frame with `layout` in `buffer` into the corner of the input texture
+    ///
+    /// The part of the rectangle which does not fit the texture is left out.
+    pub fn copy_from_buffer(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        layout: &FrameLayout,
+        crop: CropRect,
+    ) {
+        let size = self.input_texture.size();
+        encoder.copy_buffer_to_texture(
+            wgpu::TexelCopyBufferInfo {
+                buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: layout.crop_offset(crop, 4),
+                    bytes_per_row: Some(layout.stride),
+                    rows_per_image: None,
+                },
+            },
+            self.input_texture.as_image_copy(),
+            crop.fit_size(size.width, size.height).extent(),
+        );
+    }
+
+    /// Clears the output texture unless the result of the `crop` rectangle covers it, see [`clear_around_crop`]
+    pub fn clear_output(
+        &self,
+        ctx: &WgpuContext,
+        encoder: &mut wgpu::CommandEncoder,
+        crop: CropRect,
+    ) -> bool {
+        clear_around_crop(ctx, encoder, &self.output_texture, crop)
+    }
+
+    /// Copies the whole output texture into the frame in `buffer`
+    pub fn copy_to_buffer(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        out_info: &gst_video::VideoInfo,
+    ) {
+        encoder.copy_texture_to_buffer(
+            self.output_texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * out_info.width()),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: out_info.width(),
+                height: out_info.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
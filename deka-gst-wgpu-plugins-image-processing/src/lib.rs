@@ -1,9 +1,24 @@
+mod blur;
+mod buffer_textures;
+mod sobel;
+mod wgpu_box_blur;
 mod wgpu_buffer_download;
 mod wgpu_buffer_upload;
+mod wgpu_canny;
+mod wgpu_compositor;
+mod wgpu_convert;
+mod wgpu_convolve;
+mod wgpu_crop;
+mod wgpu_flip;
+mod wgpu_gaussian_blur;
+mod wgpu_profiler_tracer;
+mod wgpu_scale;
+mod wgpu_shader;
 mod wgpu_sobel_buf;
 mod wgpu_sobel_mem;
 mod wgpu_texture_copy;
 mod wgpu_texture_download;
+mod wgpu_texture_meta_upload;
 mod wgpu_texture_upload;
 
 extern crate gstreamer as gst;
@@ -20,6 +35,18 @@ fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
     wgpu_texture_upload::register(plugin)?;
     wgpu_texture_copy::register(plugin)?;
     wgpu_texture_download::register(plugin)?;
+    wgpu_texture_meta_upload::register(plugin)?;
+    wgpu_convert::register(plugin)?;
+    wgpu_scale::register(plugin)?;
+    wgpu_flip::register(plugin)?;
+    wgpu_crop::register(plugin)?;
+    wgpu_compositor::register(plugin)?;
+    wgpu_shader::register(plugin)?;
+    wgpu_convolve::register(plugin)?;
+    wgpu_gaussian_blur::register(plugin)?;
+    wgpu_box_blur::register(plugin)?;
+    wgpu_canny::register(plugin)?;
+    wgpu_profiler_tracer::register(plugin)?;
     Ok(())
 }
 
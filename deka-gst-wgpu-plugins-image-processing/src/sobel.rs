@@ -0,0 +1,509 @@
+//!
+//! Sobel operator shared by the Sobel elements
+//!
+//! The operator computes both gradients with the 3x3 Sobel kernels, the edge pixels of the filtered part are repeated
+//! outside of it. Frames are copied through storage buffers, the input is RGBx or RGBA and the output is one of
+//! [`SobelOutput`]. GStreamer has no float video format, so R32Float gradients are negotiated as 4 bytes per pixel
+//! `RGBA` caps with the [`GST_CAPS_FEATURE_FORMAT_R32FLOAT`] feature, which elements unaware of it do not accept.
+//!
+
+use deka_gst_wgpu::{
+    crop::{CropRect, FrameLayout},
+    texture_memory::GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT,
+    WgpuContext,
+};
+use gst::prelude::*;
+use parking_lot::Mutex;
+
+use crate::glib;
+
+/// Caps with this feature have R32Float frames, their video format is `RGBA` as it has the same size of pixels
+pub const GST_CAPS_FEATURE_FORMAT_R32FLOAT: &str = "format:R32Float";
+
+/// Value the Sobel elements output, values match `MODE_*` constants in `sobel/shader.wgsl`
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstWgpuSobelMode")]
+pub enum SobelMode {
+    #[enum_value(name = "Gradient along the x axis", nick = "horizontal")]
+    Horizontal = 0,
+    #[enum_value(name = "Gradient along the y axis", nick = "vertical")]
+    Vertical = 1,
+    #[default]
+    #[enum_value(name = "Magnitude of the gradient", nick = "magnitude")]
+    Magnitude = 2,
+    #[enum_value(name = "Direction of the gradient", nick = "angle")]
+    Angle = 3,
+}
+
+/// Layout of output frames, values match `OUTPUT_*` constants in `sobel/shader.wgsl`
+///
+/// 8 bit outputs have absolute gradients and the magnitude clamped to 1, angles in `[-pi, pi]` are mapped to
+/// `[0, 1]`. R32Float has signed gradients and angles in radians.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SobelOutput {
+    /// RGBx or RGBA, colors are filtered separately unless `grayscale` is set
+    Rgba = 0,
+    /// Luma gradients
+    Gray8 = 1,
+    /// Luma gradients, 32 bit float
+    R32Float = 2,
+}
+
+impl SobelOutput {
+    /// Output of the fixed caps, `None` for unsupported video formats
+    pub fn from_caps(caps: &gst::CapsRef) -> Option<Self> {
+        let info = gst_video::VideoInfo::from_caps(caps).ok()?;
+        let r32float = caps
+            .features(0)
+            .is_some_and(|x| x.contains(GST_CAPS_FEATURE_FORMAT_R32FLOAT));
+
+        match (info.format(), r32float) {
+            (gst_video::VideoFormat::Rgba, true) => Some(Self::R32Float),
+            (_, true) => None,
+            (gst_video::VideoFormat::Gray8, false) => Some(Self::Gray8),
+            (gst_video::VideoFormat::Rgbx | gst_video::VideoFormat::Rgba, false) => {
+                Some(Self::Rgba)
+            }
+            _ => None,
+        }
+    }
+}
+
+const DEFAULT_GRAYSCALE: bool = false;
+const DEFAULT_THRESHOLD: f64 = 0.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SobelSettings {
+    pub mode: SobelMode,
+    pub grayscale: bool,
+    pub threshold: f64,
+}
+
+impl Default for SobelSettings {
+    fn default() -> Self {
+        Self {
+            mode: SobelMode::default(),
+            grayscale: DEFAULT_GRAYSCALE,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl SobelSettings {
+    /// Properties of the settings, all of them can be changed while playing
+    pub fn param_specs() -> Vec<glib::ParamSpec> {
+        vec![
+            glib::ParamSpecEnum::builder_with_default("mode", SobelMode::default())
+                .nick("Mode")
+                .blurb("Value written for each pixel")
+                .mutable_playing()
+                .build(),
+            glib::ParamSpecBoolean::builder("grayscale")
+                .nick("Grayscale")
+                .blurb("Filter luma instead of each color, GRAY8 and R32Float outputs always use luma")
+                .default_value(DEFAULT_GRAYSCALE)
+                .mutable_playing()
+                .build(),
+            glib::ParamSpecDouble::builder("threshold")
+                .nick("Threshold")
+                .blurb("Pixels with weaker edges are zero, the magnitude of a full black to white edge is 4")
+                .minimum(0.0)
+                .default_value(DEFAULT_THRESHOLD)
+                .mutable_playing()
+                .build(),
+        ]
+    }
+
+    /// Sets the property, returns false if it is not one of [`Self::param_specs`]
+    pub fn set_property(&mut self, value: &glib::Value, pspec: &glib::ParamSpec) -> bool {
+        match pspec.name() {
+            "mode" => self.mode = value.get().expect("type checked upstream"),
+            "grayscale" => self.grayscale = value.get().expect("type checked upstream"),
+            "threshold" => self.threshold = value.get().expect("type checked upstream"),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Value of the property, `None` if it is not one of [`Self::param_specs`]
+    pub fn property(&self, pspec: &glib::ParamSpec) -> Option<glib::Value> {
+        match pspec.name() {
+            "mode" => Some(self.mode.to_value()),
+            "grayscale" => Some(self.grayscale.to_value()),
+            "threshold" => Some(self.threshold.to_value()),
+            _ => None,
+        }
+    }
+}
+
+/// Video formats of input frames
+pub fn sink_formats() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+    [gst_video::VideoFormat::Rgbx, gst_video::VideoFormat::Rgba]
+}
+
+/// Video formats of 8 bit output frames, the R32Float one is made by [`with_src_formats`]
+pub fn src_formats() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+    [
+        gst_video::VideoFormat::Rgbx,
+        gst_video::VideoFormat::Rgba,
+        gst_video::VideoFormat::Gray8,
+    ]
+}
+
+/// Copy of caps with input formats, without the R32Float feature of the output
+pub fn with_sink_formats(caps: &gst::CapsRef) -> gst::Caps {
+    let formats = gst::List::new(sink_formats().into_iter().map(|x| x.to_str()));
+    let mut builder = gst::Caps::builder_full();
+
+    for (s, features) in caps.iter_with_features() {
+        let mut new_s = s.to_owned();
+        new_s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT);
+        new_s.set("format", formats.clone());
+        let mut new_features = features.to_owned();
+        new_features.remove(GST_CAPS_FEATURE_FORMAT_R32FLOAT);
+        builder = builder.structure_with_features(new_s, new_features);
+    }
+
+    builder.build()
+}
+
+/// Copy of caps with output formats, 8 bit ones go first and R32Float is the last
+pub fn with_src_formats(caps: &gst::CapsRef) -> gst::Caps {
+    let formats = gst::List::new(src_formats().into_iter().map(|x| x.to_str()));
+    let mut builder = gst::Caps::builder_full();
+
+    for (s, features) in caps.iter_with_features() {
+        let mut new_s = s.to_owned();
+        new_s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT);
+        new_s.set("format", formats.clone());
+        builder = builder.structure_with_features(new_s, features.to_owned());
+    }
+    for (s, features) in caps.iter_with_features() {
+        let mut new_s = s.to_owned();
+        new_s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT);
+        new_s.set("format", gst_video::VideoFormat::Rgba.to_str());
+        let mut new_features = features.to_owned();
+        new_features.add(GST_CAPS_FEATURE_FORMAT_R32FLOAT);
+        builder = builder.structure_with_features(new_s, new_features);
+    }
+
+    builder.build()
+}
+
+/// Bytes of the parameters, the layout must match `Params` in `sobel/shader.wgsl`
+const PARAMS_SIZE: u64 = 48;
+
+/// Compute pipeline of the operator with the storage buffers for frames of the negotiated size
+#[derive(Debug)]
+pub struct SobelPipeline {
+    pipeline: wgpu::ComputePipeline,
+    /// Parameters of the frame, written by [`Self::write_params`]
+    params: wgpu::Buffer,
+    /// Words last written into `params`, unchanged ones are not written again
+    written: Mutex<Option<[u32; 12]>>,
+    /// Bindings of the parameters and storage buffers, they are the same for every frame
+    bind_group: wgpu::BindGroup,
+    input: wgpu::Buffer,
+    output: wgpu::Buffer,
+    kind: SobelOutput,
+    /// Size of the negotiated input frame
+    in_size: (u32, u32),
+    in_stride: u32,
+    out_stride: u32,
+}
+
+impl SobelPipeline {
+    pub fn new(
+        ctx: &WgpuContext,
+        in_info: &gst_video::VideoInfo,
+        out_info: &gst_video::VideoInfo,
+        kind: SobelOutput,
+    ) -> Self {
+        let device = ctx.device();
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        // Shared with other Sobel elements of the context, compiled once
+        let pipeline = ctx.compute_pipeline(
+            wgpu::include_wgsl!("sobel/shader.wgsl"),
+            "computeSobel",
+            Some(&[&[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, false),
+            ]]),
+        );
+
+        let input = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sobel input"),
+            size: in_info.size() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sobel output"),
+            size: out_info.size() as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sobel params"),
+            size: PARAMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            pipeline,
+            params,
+            written: Mutex::new(None),
+            bind_group,
+            input,
+            output,
+            kind,
+            in_size: (in_info.width(), in_info.height()),
+            in_stride: in_info.stride()[0] as u32 / 4,
+            out_stride: out_info.stride()[0] as u32 / 4,
+        }
+    }
+
+    /// Part of `crop` which fits the input storage buffer, it is copied into the corner of it
+    fn fit(&self, crop: CropRect) -> CropRect {
+        crop.fit_size(self.in_size.0, self.in_size.1)
+    }
+
+    /// Writes parameters of the frame with `crop`, ordered after the commands of previous frames
+    ///
+    /// Call it before recording the frame, see [`WgpuContext::write_buffer`]. A write submits the pending batch, so
+    /// nothing is written while the crop and the settings stay the same.
+    pub fn write_params(
+        &self,
+        ctx: &WgpuContext,
+        crop: CropRect,
+        settings: &SobelSettings,
+    ) -> Result<(), glib::BoolError> {
+        let crop = self.fit(crop);
+        let flags = if settings.grayscale { 1u32 } else { 0 };
+        let words = [
+            crop.width,
+            crop.height,
+            0,
+            0,
+            self.in_stride,
+            self.out_stride,
+            settings.mode as u32,
+            self.kind as u32,
+            flags,
+            (settings.threshold as f32).to_bits(),
+            0,
+            0,
+        ];
+
+        let mut written = self.written.lock();
+        if *written == Some(words) {
+            return Ok(());
+        }
+
+        ctx.write_buffer(
+            &self.params,
+            0,
+            wgpu::BufferSize::new(PARAMS_SIZE).unwrap(),
+            |view| {
+                for (chunk, word) in view.chunks_exact_mut(4).zip(words) {
+                    chunk.copy_from_slice(&word.to_le_bytes());
+                }
+            },
+        )
+        .ok_or_else(|| glib::bool_error!("cannot write sobel parameters"))?;
+
+        *written = Some(words);
+        Ok(())
+    }
+
+    /// Records a copy of the `crop` rectangle of the frame with `layout` in `input` into the input storage buffer
+    pub fn copy_input(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::Buffer,
+        layout: &FrameLayout,
+        crop: CropRect,
+    ) {
+        layout.copy_crop_to_buffer(
+            encoder,
+            input,
+            self.fit(crop),
+            4,
+            &self.input,
+            4 * self.in_stride,
+        );
+    }
+
+    /// Records filtering of the copied `crop` rectangle into the corner of the frame in `output`, the rest of the
+    /// frame is zero
+    ///
+    /// The rectangle must be the one passed to [`Self::copy_input`] and [`Self::write_params`].
+    pub fn record(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: Option<&str>,
+        output: &wgpu::Buffer,
+        crop: CropRect,
+    ) {
+        let crop = self.fit(crop);
+
+        // The output storage buffer is reused, so the part around the rectangle would keep older frames
+        if !crop.covers(self.in_size.0, self.in_size.1) {
+            encoder.clear_buffer(&self.output, 0, None);
+        }
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+
+            let words_x = match self.kind {
+                SobelOutput::Gray8 => crop.width.div_ceil(4),
+                SobelOutput::Rgba | SobelOutput::R32Float => crop.width,
+            };
+            pass.dispatch_workgroups(words_x.div_ceil(8), crop.height.div_ceil(8), 1);
+        }
+
+        encoder.copy_buffer_to_buffer(&self.output, 0, output, 0, self.output.size());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deka_gst_wgpu::PollType;
+    use wgpu::util::DeviceExt;
+
+    use super::*;
+
+    #[test]
+    fn suppressed_angles_are_zero() {
+        gst::init().unwrap();
+        let Ok(ctx) = WgpuContext::new_with_options(
+            &Default::default(),
+            PollType::Manual,
+            Default::default(),
+        ) else {
+            return;
+        };
+        let device = ctx.device();
+        let info = gst_video::VideoInfo::builder(gst_video::VideoFormat::Rgba, 8, 8)
+            .build()
+            .unwrap();
+        let pipeline = SobelPipeline::new(&ctx, &info, &info, SobelOutput::Rgba);
+
+        // A flat frame has no edges, every pixel is under the threshold
+        let input = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &[128u8, 128, 128, 255].repeat(info.size() / 4),
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: info.size() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let settings = SobelSettings {
+            mode: SobelMode::Angle,
+            threshold: 0.5,
+            ..Default::default()
+        };
+        let crop = CropRect::full(info.width(), info.height());
+
+        pipeline.write_params(&ctx, crop, &settings).unwrap();
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pipeline.copy_input(&mut encoder, &input, &FrameLayout::from_info(&info), crop);
+        pipeline.record(&mut encoder, None, &output, crop);
+        ctx.submit([encoder.finish()]);
+
+        output.map_async(wgpu::MapMode::Read, .., |result| result.unwrap());
+        device.poll(wgpu::PollType::wait_indefinitely()).unwrap();
+
+        // The angle of 0 would be mid-gray, suppressed pixels are black
+        let data = output.get_mapped_range(..);
+        for pixel in data.chunks_exact(4) {
+            assert_eq!(pixel, [0, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn r32float_output_needs_its_feature() {
+        gst::init().unwrap();
+        let sink_caps = gst_video::VideoCapsBuilder::new()
+            .format(gst_video::VideoFormat::Rgba)
+            .width(4)
+            .height(4)
+            .build();
+
+        let src_caps = with_src_formats(&sink_caps);
+        let r32float = src_caps
+            .iter_with_features()
+            .filter(|(_, features)| features.contains(GST_CAPS_FEATURE_FORMAT_R32FLOAT))
+            .map(|(s, features)| {
+                gst::Caps::builder_full()
+                    .structure_with_features(s.to_owned(), features.to_owned())
+                    .build()
+            })
+            .next()
+            .unwrap();
+        assert_eq!(
+            SobelOutput::from_caps(&r32float),
+            Some(SobelOutput::R32Float)
+        );
+
+        // Peers unaware of the feature take plain RGBA, which is the 8 bit output
+        let plain = sink_caps.intersect(&src_caps);
+        assert_eq!(SobelOutput::from_caps(&plain), Some(SobelOutput::Rgba));
+        assert!(!r32float.can_intersect(&sink_caps));
+
+        // The input is plain again
+        assert!(with_sink_formats(&r32float).is_subset(&with_sink_formats(&sink_caps)));
+    }
+}
@@ -0,0 +1,162 @@
+// Sobel operator over frames in storage buffers
+//
+// The input is packed RGBA8, one word per pixel. The output is a frame of `output` kind written from its corner, one
+// invocation writes one word of it: one pixel of RGBA8 or R32Float, four pixels of GRAY8.
+
+const MODE_HORIZONTAL: u32 = 0u;
+const MODE_VERTICAL: u32 = 1u;
+const MODE_MAGNITUDE: u32 = 2u;
+const MODE_ANGLE: u32 = 3u;
+
+const OUTPUT_RGBA: u32 = 0u;
+const OUTPUT_GRAY8: u32 = 1u;
+const OUTPUT_R32FLOAT: u32 = 2u;
+
+const FLAG_GRAYSCALE: u32 = 1u;
+
+const PI: f32 = 3.14159265358979;
+
+// BT.709 luma coefficients
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+struct Params {
+    // Size of the filtered part of the input, written to the output corner
+    size: vec2<u32>,
+    // Corner of the filtered part in the input
+    origin: vec2<u32>,
+    // Row strides in words
+    in_stride: u32,
+    out_stride: u32,
+    mode: u32,
+    output: u32,
+    flags: u32,
+    threshold: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var<storage, read> input: array<u32>;
+
+@group(0) @binding(2)
+var<storage, read_write> output: array<u32>;
+
+// Pixel of the filtered part, the edge pixels are repeated outside of it
+fn load(pos: vec2<i32>) -> vec3<f32> {
+    let p = vec2<u32>(clamp(pos, vec2<i32>(0), vec2<i32>(params.size) - 1)) + params.origin;
+    let color = unpack4x8unorm(input[p.y * params.in_stride + p.x]).rgb;
+
+    // Single channel outputs always work on luma
+    if (params.flags & FLAG_GRAYSCALE) != 0u || params.output != OUTPUT_RGBA {
+        return vec3<f32>(dot(LUMA, color));
+    }
+    return color;
+}
+
+struct Gradient {
+    x: vec3<f32>,
+    y: vec3<f32>,
+}
+
+fn gradient(pos: vec2<i32>) -> Gradient {
+    let tl = load(pos + vec2<i32>(-1, -1));
+    let t = load(pos + vec2<i32>(0, -1));
+    let tr = load(pos + vec2<i32>(1, -1));
+    let l = load(pos + vec2<i32>(-1, 0));
+    let r = load(pos + vec2<i32>(1, 0));
+    let bl = load(pos + vec2<i32>(-1, 1));
+    let b = load(pos + vec2<i32>(0, 1));
+    let br = load(pos + vec2<i32>(1, 1));
+
+    return Gradient(
+        (tr + 2.0 * r + br) - (tl + 2.0 * l + bl),
+        (bl + 2.0 * b + br) - (tl + 2.0 * t + tr),
+    );
+}
+
+// Value of the mode, gradients are signed and angles are in radians in (-pi, pi]
+fn raw_value(g: Gradient) -> vec3<f32> {
+    switch params.mode {
+        case MODE_HORIZONTAL: {
+            return g.x;
+        }
+        case MODE_VERTICAL: {
+            return g.y;
+        }
+        case MODE_ANGLE: {
+            return atan2(g.y, g.x);
+        }
+        default: {
+            return sqrt(g.x * g.x + g.y * g.y);
+        }
+    }
+}
+
+// True where the edge strength is under the threshold
+fn suppressed(g: Gradient) -> vec3<bool> {
+    var strength: vec3<f32>;
+    switch params.mode {
+        case MODE_HORIZONTAL: {
+            strength = abs(g.x);
+        }
+        case MODE_VERTICAL: {
+            strength = abs(g.y);
+        }
+        default: {
+            strength = sqrt(g.x * g.x + g.y * g.y);
+        }
+    }
+
+    return strength < vec3<f32>(params.threshold);
+}
+
+// Value of the pixel, zero where the edge strength is under the threshold
+fn value(pos: vec2<i32>) -> vec3<f32> {
+    let g = gradient(pos);
+    return select(raw_value(g), vec3<f32>(0.0), suppressed(g));
+}
+
+// Value stored in 8 bits: absolute gradients and magnitude are clamped, angles are mapped to [0, 1]
+//
+// Suppressed pixels are zero after the mapping, so in angle mode they are not mid-gray like angles of 0.
+fn unorm_value(pos: vec2<i32>) -> vec3<f32> {
+    let g = gradient(pos);
+    let v = raw_value(g);
+
+    var mapped: vec3<f32>;
+    if params.mode == MODE_ANGLE {
+        mapped = (v + PI) / (2.0 * PI);
+    } else {
+        mapped = clamp(abs(v), vec3<f32>(0.0), vec3<f32>(1.0));
+    }
+    return select(mapped, vec3<f32>(0.0), suppressed(g));
+}
+
+@compute @workgroup_size(8, 8)
+fn computeSobel(@builtin(global_invocation_id) id: vec3<u32>) {
+    let pixels_per_word = select(1u, 4u, params.output == OUTPUT_GRAY8);
+    let x0 = id.x * pixels_per_word;
+    if x0 >= params.size.x || id.y >= params.size.y {
+        return;
+    }
+
+    let index = id.y * params.out_stride + id.x;
+    let pos = vec2<i32>(i32(x0), i32(id.y));
+
+    switch params.output {
+        case OUTPUT_GRAY8: {
+            var gray = vec4<f32>(0.0);
+            for (var i = 0u; i < 4u && x0 + i < params.size.x; i++) {
+                gray[i] = unorm_value(pos + vec2<i32>(i32(i), 0)).r;
+            }
+            output[index] = pack4x8unorm(gray);
+        }
+        case OUTPUT_R32FLOAT: {
+            output[index] = bitcast<u32>(value(pos).r);
+        }
+        default: {
+            output[index] = pack4x8unorm(vec4<f32>(unorm_value(pos), 1.0));
+        }
+    }
+}
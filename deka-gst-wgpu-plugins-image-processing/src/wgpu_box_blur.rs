@@ -0,0 +1,23 @@
+mod imp;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Plugin that blurs WGPU textures with a box filter, rows and columns are blurred in separate passes
+    ///  gst-launch-1.0 videotestsrc ! dekawgpubufferupload ! dekawgputextureupload ! dekawgpuboxblur radius=8 passes=3 ! dekawgputexturedownload ! autovideosink
+    ///
+    /// Every pixel becomes the average of `radius` pixels on each side, up to 64. Repeated `passes` approach a
+    /// Gaussian blur. The edge pixels are repeated outside of the frame.
+    pub struct WgpuBoxBlur(ObjectSubclass<imp::WgpuBoxBlur>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpuboxblur",
+        gst::Rank::NONE,
+        WgpuBoxBlur::static_type(),
+    )
+}
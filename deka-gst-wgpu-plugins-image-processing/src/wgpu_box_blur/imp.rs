@@ -0,0 +1,512 @@
+use std::sync::LazyLock;
+
+use deka_gst_wgpu::{
+    batcher::flush_on_eos,
+    caps::{fixate_wgpu_usages, transform::gst_caps_with_texture_usages, WgpuCapsInfo},
+    element::{decide_texture_allocation, filter_caps, ElementContext},
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_memory::{WgpuTextureMemory, WgpuTextureMemoryExt},
+    WgpuContext, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+    },
+    subclass::prelude::*,
+};
+use gst_base::subclass::{prelude::*, BaseTransformMode};
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+use wgpu::util::DeviceExt;
+
+use crate::blur::{BlurDirection, BlurEntryPoints, BlurFrame, SeparableBlur};
+use crate::glib;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpuboxblur",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU box blur"),
+    )
+});
+
+/// Largest radius of the box, matches `MAX_RADIUS` in `shader.wgsl`
+const MAX_RADIUS: u32 = 64;
+const MAX_PASSES: u32 = 4;
+const DEFAULT_RADIUS: u32 = 2;
+const DEFAULT_PASSES: u32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    radius: u32,
+    passes: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            radius: DEFAULT_RADIUS,
+            passes: DEFAULT_PASSES,
+        }
+    }
+}
+
+impl Settings {
+    /// Uniform parameters of the frame, layout matches `Params` in `shader.wgsl`
+    fn params(&self, size: [u32; 2]) -> Vec<u8> {
+        [size[0], size[1], self.radius.min(MAX_RADIUS), 0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct BlurState {
+    blur: SeparableBlur,
+    size: [u32; 2],
+}
+
+#[derive(Debug)]
+pub struct WgpuBoxBlur {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<Settings>,
+    state: Mutex<Option<BlurState>>,
+}
+
+impl WgpuBoxBlur {
+    fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader loads texels of the texture
+        [
+            wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader stores texels into the texture
+        [
+            wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn allowed_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+        [gst_video::VideoFormat::Rgba, gst_video::VideoFormat::Rgbx]
+    }
+
+    /// A workgroup blurs 64 pixels of a row or a column, matches `TILE` in `shader.wgsl`
+    fn workgroups(direction: BlurDirection, size: [u32; 2]) -> (u32, u32) {
+        match direction {
+            BlurDirection::Horizontal => (size[0].div_ceil(64), size[1]),
+            BlurDirection::Vertical => (size[1].div_ceil(64), size[0]),
+        }
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuBoxBlur {
+    const NAME: &'static str = "GstWgpuBoxBlur";
+    type Type = super::WgpuBoxBlur;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuBoxBlur {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![
+                stats_param_spec(),
+                glib::ParamSpecUInt::builder("radius")
+                    .nick("Radius")
+                    .blurb("Pixels averaged on each side")
+                    .maximum(MAX_RADIUS)
+                    .default_value(DEFAULT_RADIUS)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("passes")
+                    .nick("Passes")
+                    .blurb("How many times the box is applied, three are close to a Gaussian blur")
+                    .minimum(1)
+                    .maximum(MAX_PASSES)
+                    .default_value(DEFAULT_PASSES)
+                    .mutable_playing()
+                    .build(),
+            ];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock();
+        match pspec.name() {
+            "radius" => {
+                settings.radius = value.get().expect("type checked upstream");
+            }
+            "passes" => {
+                settings.passes = value.get().expect("type checked upstream");
+            }
+            _ => {
+                if !self.wgpu_context.set_property(value, pspec) {
+                    unimplemented!()
+                }
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            "radius" => self.settings.lock().radius.to_value(),
+            "passes" => self.settings.lock().passes.to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+impl GstObjectImpl for WgpuBoxBlur {}
+impl ElementImpl for WgpuBoxBlur {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU box blur",
+                "Filter/Effect/Video",
+                "Blurs video in WGPU textures with a box filter",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let def_ctx = WgpuContext::default();
+            let limits = def_ctx.limits();
+
+            let base_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(WgpuBoxBlur::allowed_texture_formats_as_gst())
+                .height_range(1..limits.max_texture_dimension_2d as i32)
+                .width_range(1..limits.max_texture_dimension_2d as i32)
+                .build();
+
+            let sink_caps =
+                gst_caps_with_texture_usages(&base_caps, WgpuBoxBlur::sink_allowed_usages);
+            let src_caps =
+                gst_caps_with_texture_usages(&base_caps, WgpuBoxBlur::src_allowed_usages);
+
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &sink_caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+}
+
+impl BaseTransformImpl for WgpuBoxBlur {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    // Frames with the same caps are still blurred
+    const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock() = None;
+        Ok(())
+    }
+
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(event)
+    }
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let other_caps = if direction == gst::PadDirection::Sink {
+            gst_caps_with_texture_usages(caps.clone(), Self::src_allowed_usages)
+        } else {
+            gst_caps_with_texture_usages(caps.clone(), Self::sink_allowed_usages)
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            filter
+        );
+
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
+    }
+
+    fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+        let sink_usages =
+            match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.texture_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+        if !sink_usages.contains(wgpu::TextureUsages::TEXTURE_BINDING) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in input caps cannot be bound",
+                sink_usages
+            ));
+        }
+
+        let src_info = match WgpuCapsInfo::from_caps(outcaps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot read output caps: {}",
+                    err
+                ));
+            }
+        };
+        let src_usages = match src_info.texture_usages() {
+            Ok(usage) => usage,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot get texture usage in output caps: {}",
+                    err
+                ));
+            }
+        };
+        if !src_usages.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in output caps cannot be used as storage",
+                src_usages
+            ));
+        }
+        if src_info
+            .texture_format
+            .is_some_and(|x| x != wgpu::TextureFormat::Rgba8Unorm)
+        {
+            return Err(gst::loggable_error!(
+                CAT,
+                "output texture format({:?}) cannot be used as storage",
+                src_info.texture_format
+            ));
+        }
+
+        self.parent_set_caps(incaps, outcaps)
+    }
+
+    fn transform(
+        &self,
+        inbuf: &gst::Buffer,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        assert!(0 < inbuf.n_memory());
+        assert!(0 < outbuf.n_memory());
+
+        let state_lock = self.state.lock();
+        let Some(state) = &*state_lock else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let inmem = inbuf.peek_memory(0);
+        let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid input memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let outmem = outbuf.peek_memory(0);
+        let Some(outmem) = outmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid output memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        // Texels are blurred as stored, so sRGB textures are viewed as linear
+        let in_view = inmem.create_view_as(inmem.texture().format().remove_srgb_suffix());
+        let out_view = outmem.create_view_as(wgpu::TextureFormat::Rgba8Unorm);
+
+        let obj = self.obj();
+        let ctx = self.wgpu_context.locked();
+        let label = ctx.label(obj.upcast_ref(), "box blur", inbuf.pts());
+
+        // Properties change while playing, a buffer written by the queue would be overwritten before the
+        // batched commands of previous frames run
+        let settings = *self.settings.lock();
+        let params_buffer = ctx
+            .device()
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &settings.params(state.size),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let steps = state.blur.prepare(
+            &ctx,
+            BlurFrame {
+                params: &params_buffer,
+                input: &in_view,
+                output: &out_view,
+                size: state.size,
+            },
+            settings.passes.clamp(1, MAX_PASSES),
+        );
+
+        let mut profiler_lock = self.profiler.lock();
+        let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+        ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+            ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: label.as_deref(),
+                    timestamp_writes: None,
+                });
+                steps.record(&mut pass);
+            });
+        });
+        steps.release(&ctx);
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn unit_size(&self, caps: &gst::Caps) -> Option<usize> {
+        let video_caps = gst_video::VideoInfo::from_caps(&caps).ok()?;
+        Some(video_caps.size())
+    }
+
+    fn decide_allocation(
+        &self,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let Some(ctx) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before start"
+            ));
+        };
+
+        decide_texture_allocation(
+            self.obj().upcast_ref(),
+            &ctx,
+            query,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            Some(wgpu::TextureFormat::Rgba8Unorm),
+        )
+    }
+}
+
+impl VideoFilterImpl for WgpuBoxBlur {
+    fn set_info(
+        &self,
+        _incaps: &gst::Caps,
+        in_info: &gst_video::VideoInfo,
+        _outcaps: &gst::Caps,
+        out_info: &gst_video::VideoInfo,
+    ) -> Result<(), gst::LoggableError> {
+        if (in_info.width(), in_info.height()) != (out_info.width(), out_info.height()) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "output size {}x{} does not match input size {}x{}",
+                out_info.width(),
+                out_info.height(),
+                in_info.width(),
+                in_info.height()
+            ));
+        }
+
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
+
+        let blur = SeparableBlur::new(
+            &wgpu_context,
+            || wgpu::include_wgsl!("shader.wgsl"),
+            BlurEntryPoints {
+                horizontal: "boxHorizontal",
+                vertical: "boxVertical",
+                vertical_output: "boxVerticalOutput",
+            },
+            Self::workgroups,
+        );
+
+        *self.state.lock() = Some(BlurState {
+            blur,
+            size: [in_info.width(), in_info.height()],
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+// Separable box blur, the edge pixels are repeated outside of the frame
+//
+// A workgroup blurs a run of TILE pixels of a row or a column. The run with `radius` pixels around it is loaded into
+// workgroup memory once, so every pixel is read from the texture about once per pass.
+
+const TILE: u32 = 64u;
+const MAX_RADIUS: u32 = 64u;
+
+struct Params {
+    size: vec2<u32>,
+    radius: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var input: texture_2d<f32>;
+
+@group(0) @binding(2)
+var intermediate: texture_storage_2d<rgba16float, write>;
+
+@group(0) @binding(3)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+var<workgroup> run: array<vec4<f32>, 192>; // TILE + 2 * MAX_RADIUS
+
+// Coordinates of `along` pixel of the `line` row or column
+fn coord(along: i32, line: u32, horizontal: bool) -> vec2<i32> {
+    return select(vec2<i32>(i32(line), along), vec2<i32>(along, i32(line)), horizontal);
+}
+
+// Average of the pixel `local` of the run starting at `start`, all invocations of the workgroup must call it
+fn box_average(local: u32, start: u32, line: u32, horizontal: bool) -> vec4<f32> {
+    let radius = min(params.radius, MAX_RADIUS);
+    let line_length = select(params.size.y, params.size.x, horizontal);
+
+    for (var i = local; i < TILE + 2u * radius; i += TILE) {
+        let along = clamp(i32(start + i) - i32(radius), 0, i32(line_length) - 1);
+        run[i] = textureLoad(input, coord(along, line, horizontal), 0);
+    }
+    workgroupBarrier();
+
+    var sum = vec4<f32>(0.0);
+    for (var i = 0u; i <= 2u * radius; i++) {
+        sum += run[local + i];
+    }
+
+    return sum / f32(2u * radius + 1u);
+}
+
+@compute @workgroup_size(64)
+fn boxHorizontal(@builtin(local_invocation_index) local: u32, @builtin(workgroup_id) group: vec3<u32>) {
+    let start = group.x * TILE;
+    let value = box_average(local, start, group.y, true);
+
+    if start + local < params.size.x {
+        textureStore(intermediate, vec2<u32>(start + local, group.y), value);
+    }
+}
+
+@compute @workgroup_size(64)
+fn boxVertical(@builtin(local_invocation_index) local: u32, @builtin(workgroup_id) group: vec3<u32>) {
+    let start = group.x * TILE;
+    let value = box_average(local, start, group.y, false);
+
+    if start + local < params.size.y {
+        textureStore(intermediate, vec2<u32>(group.y, start + local), value);
+    }
+}
+
+@compute @workgroup_size(64)
+fn boxVerticalOutput(@builtin(local_invocation_index) local: u32, @builtin(workgroup_id) group: vec3<u32>) {
+    let start = group.x * TILE;
+    let value = box_average(local, start, group.y, false);
+
+    if start + local < params.size.y {
+        textureStore(output, vec2<u32>(group.y, start + local), clamp(value, vec4<f32>(0.0), vec4<f32>(1.0)));
+    }
+}
@@ -3,18 +3,23 @@ use std::sync::LazyLock;
 use crate::glib;
 
 use deka_gst_wgpu::buffer_memory::{WgpuBufferMemory, GST_CAPS_FIELD_WGPU_BUFFER_USAGE};
+use deka_gst_wgpu::caps::{
+    fixate_wgpu_usages_requiring, transform::gst_caps_with_plain_usages,
+    usage::WgpuBufferUsageFlags, WgpuCapsInfo,
+};
 
 use deka_gst_wgpu::{prelude::*, WgpuBufferMemoryAllocator};
 use glib::object::Cast;
 use glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
-use gst::prelude::ElementExt;
 use gst::subclass::prelude::*;
 use gst_base::subclass::prelude::{BaseTransformImpl, BaseTransformImplExt};
 use gst_base::subclass::BaseTransformMode;
 use gst_video::prelude::*;
 use parking_lot::Mutex;
 
-use deka_gst_wgpu::{WgpuContext, GST_CONTEXT_WGPU_TYPE};
+use deka_gst_wgpu::element::{filter_caps, ElementContext};
+use deka_gst_wgpu::profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY};
+use deka_gst_wgpu::WgpuContextOptions;
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -26,47 +31,27 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
 
 #[derive(Debug)]
 pub struct WgpuBufferDownload {
-    wgpu_context: Mutex<Option<WgpuContext>>,
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
     sink_usages: Mutex<wgpu::BufferUsages>,
 }
 
 impl WgpuBufferDownload {
-    pub fn set_wgpu_context(&self, context: WgpuContext) {
-        let mut lock: parking_lot::lock_api::MutexGuard<
-            '_,
-            parking_lot::RawMutex,
-            Option<WgpuContext>,
-        > = self.wgpu_context.lock();
-
-        if lock.is_some() {
-            return;
-        }
-
-        *lock = Some(context);
-    }
-
-    fn create_own_context(&self) {
-        gst::info!(CAT, imp: self, "creating own wgpu context");
-
-        let obj = self.obj();
-        let element = obj.upcast_ref::<gst::Element>();
-
-        let wgpu_ctx = WgpuContext::default();
-        let ctx = wgpu_ctx.as_gst_context();
-        self.set_context(&ctx);
-
-        let message = gst::message::HaveContext::builder(ctx)
-            .src(&*self.obj())
-            .build();
-        element.post_message(message).unwrap();
+    /// Buffers with these usages can be mapped for read by CPU elements directly, the ones
+    /// with COPY_SRC only are read back through a staging buffer by the memory itself
+    ///
+    /// MAP_WRITE buffers cannot be mapped for read, so they are not here.
+    fn mappable_usages() -> wgpu::BufferUsages {
+        wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_SRC
     }
 
     fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
         [
-            wgpu::BufferUsages::MAP_WRITE,
+            wgpu::BufferUsages::COPY_SRC,
             wgpu::BufferUsages::MAP_READ,
-            wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
             wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            // Storage buffers written by compute elements like dekawgpuconvert
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE,
         ]
     }
 }
@@ -79,13 +64,46 @@ impl ObjectSubclass for WgpuBufferDownload {
 
     fn with_class(_klass: &Self::Class) -> Self {
         Self {
-            wgpu_context: Mutex::new(None),
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
             sink_usages: Mutex::new(wgpu::BufferUsages::empty()),
         }
     }
 }
 
-impl ObjectImpl for WgpuBufferDownload {}
+impl ObjectImpl for WgpuBufferDownload {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![stats_param_spec()];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        if !self.wgpu_context.set_property(value, pspec) {
+            unimplemented!()
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
 impl GstObjectImpl for WgpuBufferDownload {}
 impl ElementImpl for WgpuBufferDownload {
     fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -108,7 +126,7 @@ impl ElementImpl for WgpuBufferDownload {
 
             let sink_caps_builder = WgpuBufferDownload::sink_allowed_usages()
                 .into_iter()
-                .map(|usage| usage.bits())
+                .map(WgpuBufferUsageFlags::from)
                 .fold(gst::Caps::builder_full(), |builder, item| {
                     builder
                         .structure_with_features(
@@ -125,7 +143,7 @@ impl ElementImpl for WgpuBufferDownload {
                         )
                 });
 
-            let sink_caps = sink_caps_builder.build();
+            let sink_caps = gst_caps_with_plain_usages(sink_caps_builder.build());
             let src_caps = gst::Caps::builder_full()
                 .structure(gst::Structure::new_empty("audio/x-raw"))
                 .structure(gst::Structure::new_empty("video/x-raw"))
@@ -152,16 +170,8 @@ impl ElementImpl for WgpuBufferDownload {
     }
 
     fn set_context(&self, context: &gst::Context) {
-        if context.context_type() == GST_CONTEXT_WGPU_TYPE {
-            gst::debug!(CAT, imp: self, "Received wgpu context");
-
-            let Some(wgpu_ctx) = WgpuContext::map_gst_context_to_wgpu(context.clone()) else {
-                gst::error!(CAT, imp: self, "Received invalid wgpu context");
-                return;
-            };
-
-            self.set_wgpu_context(wgpu_ctx);
-        }
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
 
         self.parent_set_context(context);
     }
@@ -173,24 +183,8 @@ impl BaseTransformImpl for WgpuBufferDownload {
     const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
 
     fn start(&self) -> Result<(), gst::ErrorMessage> {
-        let obj = self.obj();
-        let element = obj.upcast_ref::<gst::Element>();
-
-        match WgpuContext::query_context_from_nearby_elements(element) {
-            Ok(true) => {
-                gst::info!(CAT, imp: self, "using shared wgpu context");
-                Ok(())
-            }
-            Ok(false) => {
-                self.create_own_context();
-                Ok(())
-            }
-            Err(err) => {
-                gst::error!(CAT, imp: self, "failed to query wgpu context from nearby elements: {}", err);
-                self.create_own_context();
-                Ok(())
-            }
-        }
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
     }
 
     fn transform_caps(
@@ -218,7 +212,7 @@ impl BaseTransformImpl for WgpuBufferDownload {
             for s in caps.iter() {
                 builder = Self::sink_allowed_usages()
                     .into_iter()
-                    .map(|usage| usage.bits())
+                    .map(WgpuBufferUsageFlags::from)
                     .fold(builder, |builder, item| {
                         let mut new_s = s.to_owned();
                         new_s.set(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, item);
@@ -226,7 +220,7 @@ impl BaseTransformImpl for WgpuBufferDownload {
                     });
             }
 
-            builder.build()
+            gst_caps_with_plain_usages(builder.build())
         };
 
         gst::trace!(
@@ -239,34 +233,40 @@ impl BaseTransformImpl for WgpuBufferDownload {
             filter
         );
 
-        // In the end we need to filter the caps through an optional filter caps to get rid of any
-        // unwanted caps.
-        if let Some(filter) = filter {
-            Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
-        } else {
-            Some(other_caps)
-        }
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        // Frames are read on CPU, the memory which can be mapped for read avoids the staging copy
+        let required = WgpuCapsInfo {
+            buffer_usages: (direction == gst::PadDirection::Src)
+                .then_some(wgpu::BufferUsages::MAP_READ),
+            ..Default::default()
+        };
+        let othercaps = fixate_wgpu_usages_requiring(othercaps, &required);
+        self.parent_fixate_caps(direction, caps, othercaps)
     }
 
     fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
         gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
 
-        let Some(incaps_s) = incaps.structure(0) else {
-            return Err(gst::loggable_error!(CAT, "missing structure in input caps"));
-        };
-
-        let sink_usages_bits: u32 = match incaps_s.get(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
-            Ok(usage) => usage,
-            Err(err) => {
-                return Err(gst::loggable_error!(
-                    CAT,
-                    "cannot get buffer usage in input caps: {}",
-                    err
-                ));
-            }
-        };
-        let sink_usages = wgpu::BufferUsages::from_bits_truncate(sink_usages_bits);
-        if !sink_usages.intersects(wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::MAP_READ) {
+        let sink_usages =
+            match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.buffer_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get buffer usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+        if !sink_usages.intersects(Self::mappable_usages()) {
             return Err(gst::loggable_error!(
                 CAT,
                 "buffer usage({:?} in input caps cannot be mapped",
@@ -295,7 +295,7 @@ impl BaseTransformImpl for WgpuBufferDownload {
 
         let usages = wgpu_mem.buffer().usage();
 
-        if usages.intersects(wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::MAP_WRITE) {
+        if usages.intersects(Self::mappable_usages()) {
             if !old_passthrough {
                 gst::debug!(CAT, imp: self, "buffer({usages:?}) can be mapped as is, passthrough");
                 self.obj().set_passthrough(true);
@@ -349,10 +349,22 @@ impl BaseTransformImpl for WgpuBufferDownload {
         let ctx = self.wgpu_context.lock().clone().unwrap();
         let copy_size = inmem.size().min(outmem.size()) as u64;
 
-        let mut encoder = ctx.device().create_command_encoder(&Default::default());
-        encoder.copy_buffer_to_buffer(inmem.buffer(), 0, outmem.buffer(), 0, copy_size);
+        let label = ctx.label(self.obj().upcast_ref(), "download", inbuf.pts());
+        let mut encoder = ctx
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: label.as_deref(),
+            });
+        {
+            let mut profiler_lock = self.profiler.lock();
+            let profiler =
+                ElementProfiler::get_or_init(&mut profiler_lock, &ctx, self.obj().upcast_ref());
+            ElementProfiler::profile_encoder(profiler, &mut encoder, |encoder| {
+                encoder.copy_buffer_to_buffer(inmem.buffer(), 0, outmem.buffer(), 0, copy_size);
+            });
+        }
 
-        let token = ctx.queue().submit([encoder.finish()]);
+        let token = ctx.submit([encoder.finish()]);
         if let Err(err) = ctx.device().poll(wgpu::PollType::Wait {
             submission_index: Some(token),
             timeout: None,
@@ -377,7 +389,7 @@ impl BaseTransformImpl for WgpuBufferDownload {
         }
 
         // TODO: What if element after us needs specific alignment?
-        if sink_usages.intersects(wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::MAP_WRITE) {
+        if sink_usages.intersects(Self::mappable_usages()) {
             gst::debug!(CAT, imp: self, "buffer({sink_usages:?}) can be mapped as is, passthrough");
             self.obj().set_passthrough(true);
             self.obj().reconfigure_src();
@@ -414,12 +426,14 @@ impl BaseTransformImpl for WgpuBufferDownload {
             return Ok(());
         }
 
-        // Have to create own buffers with COPY_DST and MAP_WRITE
-        let ctx = self.wgpu_context.lock().as_ref().cloned().unwrap();
+        // Have to create own buffers with COPY_DST and MAP_READ
+        let ctx = self.wgpu_context.get().unwrap();
+        let label = ctx.label(self.obj().upcast_ref(), "output buffers", None);
         let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(
             ctx,
             wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        );
+        )
+        .with_label(label);
         let params = gst::AllocationParams::default();
         query.add_allocation_param(Some(&allocator), params);
 
@@ -437,11 +451,8 @@ impl BaseTransformImpl for WgpuBufferDownload {
             return Err(gst::loggable_error!(CAT, "No caps in allocation query"));
         };
 
-        let Some(caps_s) = caps.structure(0) else {
-            return Err(gst::loggable_error!(CAT, "No structure in caps"));
-        };
-
-        let sink_usages_bits: u32 = match caps_s.get(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
+        let sink_usages = match WgpuCapsInfo::from_caps(caps).and_then(|info| info.buffer_usages())
+        {
             Ok(usage) => usage,
             Err(err) => {
                 return Err(gst::loggable_error!(
@@ -451,11 +462,12 @@ impl BaseTransformImpl for WgpuBufferDownload {
                 ));
             }
         };
-        let sink_usages = wgpu::BufferUsages::from_bits_truncate(sink_usages_bits);
 
-        let ctx = self.wgpu_context.lock().as_ref().cloned().unwrap();
+        let ctx = self.wgpu_context.get().unwrap();
 
-        let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, sink_usages);
+        let label = ctx.label(self.obj().upcast_ref(), "input buffers", None);
+        let allocator =
+            WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, sink_usages).with_label(label);
         let params = gst::AllocationParams::default();
         query.add_allocation_param(Some(&allocator), params);
 
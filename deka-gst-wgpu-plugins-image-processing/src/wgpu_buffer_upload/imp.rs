@@ -2,18 +2,24 @@ use std::sync::LazyLock;
 
 use crate::glib;
 
+use deka_gst_wgpu::batcher::flush_on_eos;
 use deka_gst_wgpu::buffer_memory::{WgpuBufferMemory, GST_CAPS_FIELD_WGPU_BUFFER_USAGE};
+use deka_gst_wgpu::caps::{
+    fixate_wgpu_usages, transform::gst_caps_with_plain_usages, usage::WgpuBufferUsageFlags,
+    WgpuCapsInfo,
+};
+use deka_gst_wgpu::crop::{CropRect, FrameLayout};
 use deka_gst_wgpu::{prelude::*, WgpuBufferMemoryAllocator};
 use glib::object::Cast;
 use glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
-use gst::prelude::ElementExt;
 use gst::subclass::prelude::*;
 use gst_base::subclass::prelude::*;
 use gst_base::subclass::BaseTransformMode;
 use gst_video::prelude::*;
 use parking_lot::Mutex;
 
-use deka_gst_wgpu::{WgpuContext, GST_CONTEXT_WGPU_TYPE};
+use deka_gst_wgpu::element::{filter_caps, ElementContext};
+use deka_gst_wgpu::WgpuContextOptions;
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -25,51 +31,120 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
 
 #[derive(Debug)]
 pub struct WgpuBufferUpload {
-    wgpu_context: Mutex<Option<WgpuContext>>,
+    wgpu_context: ElementContext,
     src_usages: Mutex<wgpu::BufferUsages>,
+    /// Video info of the output, `None` for audio
+    src_info: Mutex<Option<gst_video::VideoInfo>>,
+    /// Whether downstream accepts `GstVideoCropMeta`, the crop is applied while copying otherwise
+    downstream_crop_meta: Mutex<bool>,
 }
 
 impl WgpuBufferUpload {
-    pub fn set_wgpu_context(&self, context: WgpuContext) {
-        let mut lock: parking_lot::lock_api::MutexGuard<
-            '_,
-            parking_lot::RawMutex,
-            Option<WgpuContext>,
-        > = self.wgpu_context.lock();
-
-        if lock.is_some() {
-            return;
-        }
-
-        *lock = Some(context);
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
+        // MAP_WRITE buffers cannot be mapped for read by downstream, so only GPU local buffers are output. They
+        // are filled through the queue
+        [
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::STORAGE,
+        ]
     }
 
-    fn create_own_context(&self) {
-        gst::info!(CAT, imp: self, "creating own wgpu context");
+    /// The output buffer must have one of these usages to be filled
+    fn writable_usages() -> wgpu::BufferUsages {
+        wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_DST
+    }
 
-        let obj = self.obj();
-        let element = obj.upcast_ref::<gst::Element>();
+    /// Layout, crop rectangle and output info of `inbuf` if its crop meta cannot go downstream
+    ///
+    /// Only frames with one packed plane are cropped, the meta of other frames is dropped with a warning.
+    fn crop_to_apply(
+        &self,
+        inbuf: &gst::BufferRef,
+    ) -> Option<(FrameLayout, CropRect, gst_video::VideoInfo)> {
+        if *self.downstream_crop_meta.lock() || inbuf.meta::<gst_video::VideoCropMeta>().is_none() {
+            return None;
+        }
 
-        let wgpu_ctx = WgpuContext::default();
-        let ctx = wgpu_ctx.as_gst_context();
-        self.set_context(&ctx);
+        let info = self.src_info.lock().clone()?;
+        let format_info = info.format_info();
+        if info.n_planes() != 1 || format_info.pixel_stride()[0] <= 0 || format_info.is_tiled() {
+            gst::warning!(CAT, imp: self, "cannot crop {:?} frames, the crop meta is dropped", info.format());
+            return None;
+        }
 
-        let message = gst::message::HaveContext::builder(ctx)
-            .src(&*self.obj())
-            .build();
-        element.post_message(message).unwrap();
+        let layout = FrameLayout::from_buffer(inbuf, &info);
+        let crop = layout
+            .crop_rect(inbuf)
+            .fit_size(info.width(), info.height());
+        Some((layout, crop, info))
     }
 
-    /// Locks context
-    fn locked_context(&self) -> parking_lot::MappedMutexGuard<'_, WgpuContext> {
-        parking_lot::MutexGuard::map(self.wgpu_context.lock(), |x| x.as_mut().unwrap())
-    }
+    /// Records a copy of `crop` from `inmem` into `outmem` if both are WGPU buffers which can be copied aligned
+    ///
+    /// Returns false if the copy has to be done on CPU.
+    fn copy_crop_on_gpu(
+        &self,
+        inbuf: &gst::BufferRef,
+        outmem: &WgpuBufferMemory,
+        layout: FrameLayout,
+        crop: CropRect,
+        info: &gst_video::VideoInfo,
+    ) -> bool {
+        let Some(inmem) = inbuf
+            .peek_memory(0)
+            .downcast_memory_ref::<WgpuBufferMemory>()
+        else {
+            return false;
+        };
 
-    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
-        [
-            wgpu::BufferUsages::MAP_WRITE,
-            wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+        let pixel_size = info.format_info().pixel_stride()[0] as u32;
+        let dst_stride = info.stride()[0] as u32;
+        // The frame is at the offset of the memory in the buffer, copies must be aligned
+        let layout = FrameLayout {
+            offset: layout.offset + inmem.offset() as u64,
+            ..layout
+        };
+        let aligned = [
+            layout.crop_offset(crop, pixel_size),
+            layout.stride as u64,
+            dst_stride as u64,
+            crop.width as u64 * pixel_size as u64,
         ]
+        .iter()
+        .all(|x| x % wgpu::COPY_BUFFER_ALIGNMENT == 0);
+
+        if !aligned
+            || outmem.offset() != 0
+            || inmem.context() != outmem.context()
+            || !inmem
+                .buffer()
+                .usage()
+                .contains(wgpu::BufferUsages::COPY_SRC)
+            || !outmem
+                .buffer()
+                .usage()
+                .contains(wgpu::BufferUsages::COPY_DST)
+        {
+            return false;
+        }
+
+        let obj = self.obj();
+        let ctx = outmem.context();
+        ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+            // The rest of the frame keeps no content of older frames
+            encoder.clear_buffer(outmem.buffer(), 0, None);
+            layout.copy_crop_to_buffer(
+                encoder,
+                inmem.buffer(),
+                crop,
+                pixel_size,
+                outmem.buffer(),
+                dst_stride,
+            );
+        });
+        true
     }
 }
 
@@ -81,13 +156,33 @@ impl ObjectSubclass for WgpuBufferUpload {
 
     fn with_class(_klass: &Self::Class) -> Self {
         Self {
-            wgpu_context: Mutex::new(None),
+            wgpu_context: ElementContext::default(),
             src_usages: Mutex::new(wgpu::BufferUsages::empty()),
+            src_info: Mutex::new(None),
+            downstream_crop_meta: Mutex::new(false),
         }
     }
 }
 
-impl ObjectImpl for WgpuBufferUpload {}
+impl ObjectImpl for WgpuBufferUpload {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> =
+            LazyLock::new(WgpuContextOptions::param_specs);
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        if !self.wgpu_context.set_property(value, pspec) {
+            unimplemented!()
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        self.wgpu_context
+            .property(pspec)
+            .unwrap_or_else(|| unimplemented!())
+    }
+}
 impl GstObjectImpl for WgpuBufferUpload {}
 impl ElementImpl for WgpuBufferUpload {
     fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -115,24 +210,24 @@ impl ElementImpl for WgpuBufferUpload {
 
             let src_caps_builder = WgpuBufferUpload::src_allowed_usages()
                 .into_iter()
-                .map(|usage| usage.bits())
-                .fold(gst::Caps::builder_full(), |builder, bits| {
+                .map(WgpuBufferUsageFlags::from)
+                .fold(gst::Caps::builder_full(), |builder, flags| {
                     builder
                         .structure_with_features(
                             gst::Structure::builder("audio/x-raw")
-                                .field(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, bits)
+                                .field(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, flags)
                                 .build(),
                             mem_feature.clone(),
                         )
                         .structure_with_features(
                             gst::Structure::builder("video/x-raw")
-                                .field(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, bits)
+                                .field(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, flags)
                                 .build(),
                             mem_feature.clone(),
                         )
                 });
 
-            let src_caps = src_caps_builder.build();
+            let src_caps = gst_caps_with_plain_usages(src_caps_builder.build());
 
             vec![
                 gst::PadTemplate::new(
@@ -155,16 +250,8 @@ impl ElementImpl for WgpuBufferUpload {
     }
 
     fn set_context(&self, context: &gst::Context) {
-        if context.context_type() == GST_CONTEXT_WGPU_TYPE {
-            gst::debug!(CAT, imp: self, "Received wgpu context");
-
-            let Some(wgpu_ctx) = WgpuContext::map_gst_context_to_wgpu(context.clone()) else {
-                gst::error!(CAT, imp: self, "Received invalid wgpu context");
-                return;
-            };
-
-            self.set_wgpu_context(wgpu_ctx);
-        }
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
 
         self.parent_set_context(context);
     }
@@ -176,24 +263,8 @@ impl BaseTransformImpl for WgpuBufferUpload {
     const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
 
     fn start(&self) -> Result<(), gst::ErrorMessage> {
-        let obj = self.obj();
-        let element = obj.upcast_ref::<gst::Element>();
-
-        match WgpuContext::query_context_from_nearby_elements(element) {
-            Ok(true) => {
-                gst::info!(CAT, imp: self, "using shared wgpu context");
-                Ok(())
-            }
-            Ok(false) => {
-                self.create_own_context();
-                Ok(())
-            }
-            Err(err) => {
-                gst::error!(CAT, imp: self, "failed to query wgpu context from nearby elements: {}", err);
-                self.create_own_context();
-                Ok(())
-            }
-        }
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
     }
 
     fn transform_caps(
@@ -221,7 +292,7 @@ impl BaseTransformImpl for WgpuBufferUpload {
             for s in caps.iter() {
                 builder = Self::src_allowed_usages()
                     .into_iter()
-                    .map(|usage| usage.bits())
+                    .map(WgpuBufferUsageFlags::from)
                     .fold(builder, |builder, item| {
                         let mut new_s = s.to_owned();
                         new_s.set(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, item);
@@ -229,7 +300,7 @@ impl BaseTransformImpl for WgpuBufferUpload {
                     });
             }
 
-            builder.build()
+            gst_caps_with_plain_usages(builder.build())
         };
 
         gst::trace!(
@@ -242,65 +313,76 @@ impl BaseTransformImpl for WgpuBufferUpload {
             filter
         );
 
-        // In the end we need to filter the caps through an optional filter caps to get rid of any
-        // unwanted caps.
-        if let Some(filter) = filter {
-            Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
-        } else {
-            Some(other_caps)
-        }
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
     }
 
     fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
         gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
 
-        let Some(outcaps_s) = outcaps.structure(0) else {
-            return Err(gst::loggable_error!(
-                CAT,
-                "missing structure in output caps"
-            ));
-        };
-
-        let src_usages_bits: u32 = match outcaps_s.get(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
-            Ok(usage) => usage,
-            Err(err) => {
-                return Err(gst::loggable_error!(
-                    CAT,
-                    "cannot get buffer usage in input caps: {}",
-                    err
-                ));
-            }
-        };
-        let src_usages = wgpu::BufferUsages::from_bits_truncate(src_usages_bits);
-        if !src_usages.intersects(wgpu::BufferUsages::MAP_WRITE) {
+        let src_usages =
+            match WgpuCapsInfo::from_caps(outcaps).and_then(|info| info.buffer_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get buffer usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+        if !src_usages.intersects(Self::writable_usages()) {
             return Err(gst::loggable_error!(
                 CAT,
-                "buffer usage({:?} in output caps cannot be mapped for write",
+                "buffer usage({:?} in output caps can be neither mapped for write nor used as copy dst",
                 src_usages
             ));
         }
 
         *self.src_usages.lock() = src_usages;
+        *self.src_info.lock() = gst_video::VideoInfo::from_caps(outcaps).ok();
 
         self.parent_set_caps(incaps, outcaps)
     }
 
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(event)
+    }
+
     fn before_transform(&self, inbuf: &gst::BufferRef) {
         assert!(0 < inbuf.n_memory());
 
         let mem = inbuf.peek_memory(0);
         let old_passthrough = self.obj().is_passthrough();
 
-        let Some(wgpu_mem) = mem.downcast_memory_ref::<WgpuBufferMemory>() else {
+        // Frames with crop meta downstream does not accept are cropped by the copy
+        let crops = inbuf.meta::<gst_video::VideoCropMeta>().is_some()
+            && !*self.downstream_crop_meta.lock();
+
+        let Some(wgpu_mem) = mem
+            .downcast_memory_ref::<WgpuBufferMemory>()
+            .filter(|_| !crops)
+        else {
             if old_passthrough == true {
-                gst::warning!(CAT, imp: self, "the previous element does not use our allocator, have to copy");
+                gst::warning!(CAT, imp: self, "the previous element does not use our allocator or its frame has to be cropped, have to copy");
                 self.obj().set_passthrough(false);
                 self.obj().reconfigure_src();
             }
             return;
         };
 
-        if self.locked_context().as_ptr() != wgpu_mem.context().as_ptr() {
+        if self.wgpu_context.locked().as_ptr() != wgpu_mem.context().as_ptr() {
             // TODO: handle it somehow
             panic!("context not in sync");
         }
@@ -330,7 +412,23 @@ impl BaseTransformImpl for WgpuBufferUpload {
             .downcast_memory::<WgpuBufferMemory>()
             .unwrap();
 
-        outmem.fill_from_gst(inmem).map_err(|e| {
+        let result = match self.crop_to_apply(inbuf) {
+            None => outmem.fill_from_gst(inmem),
+            Some((layout, crop, info)) => {
+                if self.copy_crop_on_gpu(inbuf, &outmem, layout, crop, &info) {
+                    Ok(())
+                } else {
+                    let pixel_size = info.format_info().pixel_stride()[0] as u32;
+                    let dst_stride = info.stride()[0] as u32;
+                    inmem.map_readable().and_then(|src| {
+                        outmem.fill_with(|dst| {
+                            layout.copy_crop_to_slice(&src, crop, pixel_size, dst, dst_stride)
+                        })
+                    })
+                }
+            }
+        };
+        result.map_err(|e| {
             gst::error!(CAT, imp: self, "Error copying memory: {e}");
             gst::FlowError::Error
         })?;
@@ -349,11 +447,15 @@ impl BaseTransformImpl for WgpuBufferUpload {
         query: &mut gst::query::Allocation,
     ) -> Result<(), gst::LoggableError> {
         // if we are here, we need to copy from system memory, creating buffer
-        // with MAP_WRITE required for output buffer
+        // with MAP_WRITE or COPY_DST required for output buffer
         //
 
         gst::info!(CAT, imp: self, "Deciding allocs");
 
+        *self.downstream_crop_meta.lock() = query
+            .find_allocation_meta::<gst_video::VideoCropMeta>()
+            .is_some();
+
         let src_usages = self.src_usages.lock();
         if src_usages.is_empty() {
             return Err(gst::loggable_error!(
@@ -374,9 +476,10 @@ impl BaseTransformImpl for WgpuBufferUpload {
 
             match wgpu_allocator.explicit_usages() {
                 Some(usages) => {
-                    let required = wgpu::BufferUsages::MAP_WRITE;
-                    if !usages.contains(required) {
+                    let required = Self::writable_usages();
+                    if !usages.intersects(required) {
                         gst::trace!(CAT, imp: self, "skipping allocator at {pos}, usages is incorrect {} != {}", required.bits(), usages.bits());
+                        to_remove.push(pos);
                     }
                 }
                 None => {
@@ -397,8 +500,10 @@ impl BaseTransformImpl for WgpuBufferUpload {
             return Ok(());
         }
 
-        let ctx = self.wgpu_context.lock().as_ref().cloned().unwrap();
-        let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, *src_usages);
+        let ctx = self.wgpu_context.get().unwrap();
+        let label = ctx.label(self.obj().upcast_ref(), "output buffers", None);
+        let allocator =
+            WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, *src_usages).with_label(label);
         let params = gst::AllocationParams::default();
         query.add_allocation_param(Some(&allocator), params);
 
@@ -418,12 +523,44 @@ impl BaseTransformImpl for WgpuBufferUpload {
             ));
         }
 
-        let ctx = self.wgpu_context.lock().as_ref().cloned().unwrap();
+        // Upstream writes the frames on CPU, without MAP_WRITE it gets system memory which is uploaded by queue
+        if src_usages.contains(wgpu::BufferUsages::MAP_WRITE) {
+            let ctx = self.wgpu_context.get().unwrap();
 
-        let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, *src_usages);
-        let params = gst::AllocationParams::default();
-        query.add_allocation_param(Some(&allocator), params);
+            let label = ctx.label(self.obj().upcast_ref(), "input buffers", None);
+            let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, *src_usages)
+                .with_label(label);
+            let params = gst::AllocationParams::default();
+            query.add_allocation_param(Some(&allocator), params);
+        } else {
+            gst::debug!(
+                CAT,
+                imp: self,
+                "output usages {:?} cannot be mapped for write, proposing system memory",
+                *src_usages
+            );
+        }
+
+        // Crop meta is forwarded only if downstream accepts it, the frames are cropped while copying otherwise
+        if *self.downstream_crop_meta.lock() {
+            query.add_allocation_meta::<gst_video::VideoCropMeta>(None);
+        }
 
         Ok(())
     }
+
+    fn transform_meta<'a>(
+        &self,
+        outbuf: &mut gst::BufferRef,
+        meta: gst::MetaRef<'a, gst::Meta>,
+        inbuf: &'a gst::BufferRef,
+    ) -> bool {
+        // The memory is copied as is if downstream accepts the meta, the rectangle stays valid then. Otherwise the
+        // copy is cropped already
+        if meta.downcast_ref::<gst_video::VideoCropMeta>().is_some() {
+            return *self.downstream_crop_meta.lock();
+        }
+
+        self.parent_transform_meta(outbuf, meta, inbuf)
+    }
 }
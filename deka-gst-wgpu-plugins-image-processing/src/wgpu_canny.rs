@@ -0,0 +1,26 @@
+mod imp;
+mod params;
+mod pipeline;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Plugin that detects edges with the Canny algorithm
+    ///  gst-launch-1.0 videotestsrc pattern=ball ! dekawgpubufferupload ! dekawgpucanny low-threshold=0.05 high-threshold=0.15 ! dekawgpubufferdownload ! videoconvert ! autovideosink
+    ///
+    /// Luma of RGBx/RGBA frames in WGPU buffers is smoothed with a Gaussian kernel, its Sobel gradient is thinned to
+    /// local maxima and they are traced with two thresholds. The output is a GRAY8 edge map, edges are white. All
+    /// stages are recorded into one command encoder.
+    pub struct WgpuCanny(ObjectSubclass<imp::WgpuCanny>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpucanny",
+        gst::Rank::NONE,
+        WgpuCanny::static_type(),
+    )
+}
@@ -0,0 +1,563 @@
+use std::sync::LazyLock;
+
+use deka_gst_wgpu::{
+    buffer_memory::GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER,
+    caps::{fixate_wgpu_usages, make_wgpu_buffer_usages_for_caps, WgpuCapsInfo},
+    crop::{CropRect, FrameLayout},
+    element::{filter_caps, ElementContext},
+    prelude::WgpuBufferMemoryExt,
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_memory::GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT,
+    WgpuBufferMemory, WgpuBufferMemoryAllocator, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+    },
+    subclass::prelude::*,
+};
+use gst_base::subclass::{prelude::*, BaseTransformMode};
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+
+use super::params::{gaussian_weights, CannyParams, MAX_APERTURE, MAX_SMOOTH_RADIUS};
+use super::pipeline::CannyPipeline;
+use crate::{glib, sobel};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpucanny",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU Canny edge detector"),
+    )
+});
+
+const DEFAULT_LOW_THRESHOLD: f64 = 0.1;
+const DEFAULT_HIGH_THRESHOLD: f64 = 0.2;
+const DEFAULT_APERTURE: u32 = 3;
+const DEFAULT_SIGMA: f64 = 1.4;
+const DEFAULT_HYSTERESIS_PASSES: u32 = 4;
+const MAX_HYSTERESIS_PASSES: u32 = 32;
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    low_threshold: f64,
+    high_threshold: f64,
+    aperture: u32,
+    sigma: f64,
+    hysteresis_passes: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            low_threshold: DEFAULT_LOW_THRESHOLD,
+            high_threshold: DEFAULT_HIGH_THRESHOLD,
+            aperture: DEFAULT_APERTURE,
+            sigma: DEFAULT_SIGMA,
+            hysteresis_passes: DEFAULT_HYSTERESIS_PASSES,
+        }
+    }
+}
+
+impl Settings {
+    fn params(&self, crop: CropRect, state: &CannyState) -> CannyParams {
+        CannyParams {
+            crop,
+            in_stride: state.in_stride,
+            out_stride: state.out_stride,
+            smooth: gaussian_weights(self.sigma),
+            aperture: self.aperture,
+            low_threshold: self.low_threshold as f32,
+            high_threshold: self.high_threshold as f32,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CannyState {
+    pipeline: CannyPipeline,
+    /// Row strides of the frames in words
+    in_stride: u32,
+    out_stride: u32,
+}
+
+#[derive(Debug)]
+pub struct WgpuCanny {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<Settings>,
+    state: Mutex<Option<CannyState>>,
+    usages: Mutex<(wgpu::BufferUsages, wgpu::BufferUsages)>,
+}
+
+impl WgpuCanny {
+    fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
+        [
+            wgpu::BufferUsages::COPY_SRC,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::MAP_WRITE,
+        ]
+    }
+
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
+        [
+            wgpu::BufferUsages::COPY_DST,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        ]
+    }
+
+    /// Copy of caps with the GRAY8 format of edge maps
+    fn with_src_format(caps: &gst::CapsRef) -> gst::Caps {
+        let mut builder = gst::Caps::builder_full();
+
+        for (s, features) in caps.iter_with_features() {
+            let mut new_s = s.to_owned();
+            new_s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT);
+            new_s.set("format", gst_video::VideoFormat::Gray8.to_str());
+            builder = builder.structure_with_features(new_s, features.to_owned());
+        }
+
+        builder.build()
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuCanny {
+    const NAME: &'static str = "GstWgpuCanny";
+    type Type = super::WgpuCanny;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(None),
+            usages: Mutex::new((wgpu::BufferUsages::empty(), wgpu::BufferUsages::empty())),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuCanny {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![
+                stats_param_spec(),
+                glib::ParamSpecDouble::builder("low-threshold")
+                    .nick("Low Threshold")
+                    .blurb("Weaker edges are dropped, the magnitude of a black to white step is 1")
+                    .minimum(0.0)
+                    .default_value(DEFAULT_LOW_THRESHOLD)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecDouble::builder("high-threshold")
+                    .nick("High Threshold")
+                    .blurb("Stronger edges are kept, edges between the thresholds are kept if they touch them")
+                    .minimum(0.0)
+                    .default_value(DEFAULT_HIGH_THRESHOLD)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("aperture")
+                    .nick("Aperture")
+                    .blurb("Size of the Sobel kernels, 3, 5 or 7, even sizes are rounded up")
+                    .minimum(3)
+                    .maximum(MAX_APERTURE)
+                    .default_value(DEFAULT_APERTURE)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecDouble::builder("sigma")
+                    .nick("Sigma")
+                    .blurb("Standard deviation of the Gaussian smoothing in pixels, 0 disables it")
+                    .minimum(0.0)
+                    .maximum(MAX_SMOOTH_RADIUS as f64 / 3.0)
+                    .default_value(DEFAULT_SIGMA)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecUInt::builder("hysteresis-passes")
+                    .nick("Hysteresis Passes")
+                    .blurb("Passes tracing weak edges from strong ones, each one crosses one more 8x8 tile")
+                    .minimum(1)
+                    .maximum(MAX_HYSTERESIS_PASSES)
+                    .default_value(DEFAULT_HYSTERESIS_PASSES)
+                    .mutable_playing()
+                    .build(),
+            ];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock();
+        match pspec.name() {
+            "low-threshold" => {
+                settings.low_threshold = value.get().expect("type checked upstream");
+            }
+            "high-threshold" => {
+                settings.high_threshold = value.get().expect("type checked upstream");
+            }
+            "aperture" => {
+                settings.aperture = value.get().expect("type checked upstream");
+            }
+            "sigma" => {
+                settings.sigma = value.get().expect("type checked upstream");
+            }
+            "hysteresis-passes" => {
+                settings.hysteresis_passes = value.get().expect("type checked upstream");
+            }
+            _ => {
+                if !self.wgpu_context.set_property(value, pspec) {
+                    unimplemented!()
+                }
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            "low-threshold" => self.settings.lock().low_threshold.to_value(),
+            "high-threshold" => self.settings.lock().high_threshold.to_value(),
+            "aperture" => self.settings.lock().aperture.to_value(),
+            "sigma" => self.settings.lock().sigma.to_value(),
+            "hysteresis-passes" => self.settings.lock().hysteresis_passes.to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+impl GstObjectImpl for WgpuCanny {}
+impl ElementImpl for WgpuCanny {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU Canny edge detector",
+                "Filter/Effect/Video",
+                "Detects edges of video in WGPU buffers with the Canny algorithm",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let base_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(sobel::sink_formats())
+                .features([GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER])
+                .build();
+
+            let src_caps = make_wgpu_buffer_usages_for_caps(
+                &WgpuCanny::with_src_format(&base_caps),
+                WgpuCanny::src_allowed_usages,
+            );
+            let sink_caps =
+                make_wgpu_buffer_usages_for_caps(&base_caps, WgpuCanny::sink_allowed_usages);
+
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &sink_caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+}
+
+impl BaseTransformImpl for WgpuCanny {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock() = None;
+        Ok(())
+    }
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let other_caps = if direction == gst::PadDirection::Sink {
+            make_wgpu_buffer_usages_for_caps(&Self::with_src_format(caps), Self::src_allowed_usages)
+        } else {
+            make_wgpu_buffer_usages_for_caps(
+                &sobel::with_sink_formats(caps),
+                Self::sink_allowed_usages,
+            )
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            filter
+        );
+
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
+    }
+
+    fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+        let src_usages =
+            match WgpuCapsInfo::from_caps(outcaps).and_then(|info| info.buffer_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get buffer usage in output caps: {}",
+                        err
+                    ));
+                }
+            };
+
+        let sink_usages =
+            match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.buffer_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get buffer usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+
+        if !sink_usages.contains(wgpu::BufferUsages::COPY_SRC) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "input caps({:?}) cannot be used as copy src",
+                sink_usages
+            ));
+        }
+
+        if !src_usages.contains(wgpu::BufferUsages::COPY_DST) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "output caps({:?}) cannot be used as copy dst",
+                src_usages
+            ));
+        }
+
+        {
+            let mut usages_lock = self.usages.lock();
+            *usages_lock = (sink_usages, src_usages);
+        }
+
+        self.parent_set_caps(incaps, outcaps)
+    }
+
+    fn transform(
+        &self,
+        inbuf: &gst::Buffer,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let Some(mem) = inbuf
+            .peek_memory(0)
+            .downcast_memory_ref::<WgpuBufferMemory>()
+        else {
+            gst::error!(CAT, imp: self, "unsupported memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let mem_usage = mem.buffer().usage();
+        if !mem_usage.contains(wgpu::BufferUsages::COPY_SRC) {
+            gst::error!(CAT, imp: self, "memory({:?}) missing COPY_SRC", mem_usage);
+            return Err(gst::FlowError::NotNegotiated);
+        }
+
+        let obj = self.obj();
+        let self_as_filter = obj.upcast_ref::<gst_video::VideoFilter>();
+        let Some(in_info) = self_as_filter.input_video_info() else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        // Only the crop rectangle is filtered, its edges go to the frame corner and the rest is zero
+        let layout = FrameLayout::from_buffer(inbuf, &in_info);
+        let crop = layout
+            .crop_rect(inbuf)
+            .fit_size(in_info.width(), in_info.height());
+
+        let state_lock = self.state.lock();
+        let Some(state) = &*state_lock else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let Some(wgpu_context) = &*self.wgpu_context.lock() else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let inbuffer = mem.buffer();
+
+        let outmem = match outbuf.peek_memory_mut(0) {
+            Ok(m) => m,
+            Err(err) => {
+                gst::error!(CAT, imp: self, "can't get mutable out memory: {err}");
+                return Err(gst::FlowError::NotNegotiated);
+            }
+        };
+
+        let Some(outmem) = outmem.downcast_memory_mut::<WgpuBufferMemory>() else {
+            gst::error!(CAT, imp: self, "unsupported out memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let outbuffer = outmem.buffer();
+
+        let label = wgpu_context.label(obj.upcast_ref(), "canny", inbuf.pts());
+        let mut encoder =
+            wgpu_context
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: label.as_deref(),
+                });
+
+        let settings = *self.settings.lock();
+        // The crop rectangle is copied into the corner of the input storage buffer
+        let params = settings.params(CropRect::full(crop.width, crop.height), state);
+        if let Err(err) = state.pipeline.write_params(wgpu_context, &params) {
+            gst::error!(CAT, imp: self, "Error writing parameters: {err}");
+            return Err(gst::FlowError::Error);
+        }
+        state
+            .pipeline
+            .copy_input(&mut encoder, inbuffer, &layout, crop);
+
+        let mut profiler_lock = self.profiler.lock();
+        let profiler =
+            ElementProfiler::get_or_init(&mut profiler_lock, wgpu_context, obj.upcast_ref());
+        // The stages are separate passes, so the whole encoder is measured
+        ElementProfiler::profile_encoder(profiler, &mut encoder, |encoder| {
+            state.pipeline.record(
+                encoder,
+                label.as_deref(),
+                outbuffer,
+                &params,
+                settings.hysteresis_passes.clamp(1, MAX_HYSTERESIS_PASSES),
+            );
+        });
+
+        let command_buffer = encoder.finish();
+
+        wgpu_context.submit([command_buffer]);
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn propose_allocation(
+        &self,
+        _decide_query: Option<&gst::query::Allocation>,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let (caps, _needs_pool) = query.get();
+
+        let Some(caps) = caps else {
+            return Err(gst::loggable_error!(CAT, "No caps in allocation query"));
+        };
+
+        let sink_usages = match WgpuCapsInfo::from_caps(caps).and_then(|info| info.buffer_usages())
+        {
+            Ok(usage) => usage,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot get buffer usage in input caps: {}",
+                    err
+                ));
+            }
+        };
+
+        let ctx = self.wgpu_context.get().unwrap();
+
+        let label = ctx.label(self.obj().upcast_ref(), "input buffers", None);
+        let allocator =
+            WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, sink_usages).with_label(label);
+        let params = gst::AllocationParams::default();
+        query.add_allocation_param(Some(&allocator), params);
+
+        // The padding around the crop rectangle is not filtered
+        query.add_allocation_meta::<gst_video::VideoCropMeta>(None);
+
+        Ok(())
+    }
+}
+
+impl VideoFilterImpl for WgpuCanny {
+    fn set_info(
+        &self,
+        _incaps: &gst::Caps,
+        in_info: &gst_video::VideoInfo,
+        _outcaps: &gst::Caps,
+        out_info: &gst_video::VideoInfo,
+    ) -> Result<(), gst::LoggableError> {
+        if out_info.format() != gst_video::VideoFormat::Gray8 {
+            return Err(gst::loggable_error!(
+                CAT,
+                "unsupported output format {:?}",
+                out_info.format()
+            ));
+        }
+
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
+
+        *self.state.lock() = Some(CannyState {
+            pipeline: CannyPipeline::new(&wgpu_context, in_info, out_info),
+            in_stride: in_info.stride()[0] as u32 / 4,
+            out_stride: out_info.stride()[0] as u32 / 4,
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+//!
+//! Uniform parameters of the Canny shader, the layout must match `Params` in `shader.wgsl`
+//!
+
+use deka_gst_wgpu::crop::CropRect;
+
+/// Largest radius of the smoothing kernel, matches the size of `smooth` in `shader.wgsl`
+pub const MAX_SMOOTH_RADIUS: u32 = 15;
+/// Largest Sobel aperture, matches the size of `sobel_smooth` and `sobel_derivative` in `shader.wgsl`
+pub const MAX_APERTURE: u32 = 7;
+
+/// Smoothing and derivative parts of the separable Sobel kernels of sizes 3, 5 and 7
+const SOBEL_KERNELS: [(&[f32], &[f32]); 3] = [
+    (&[1.0, 2.0, 1.0], &[-1.0, 0.0, 1.0]),
+    (&[1.0, 4.0, 6.0, 4.0, 1.0], &[-1.0, -2.0, 0.0, 2.0, 1.0]),
+    (
+        &[1.0, 6.0, 15.0, 20.0, 15.0, 6.0, 1.0],
+        &[-1.0, -4.0, -5.0, 0.0, 5.0, 4.0, 1.0],
+    ),
+];
+
+/// Normalized weights of the Gaussian kernel for offsets `0..=radius`, the zero sigma keeps the frame as is
+pub fn gaussian_weights(sigma: f64) -> Vec<f32> {
+    if sigma <= 0.0 {
+        return vec![1.0];
+    }
+
+    let radius = ((3.0 * sigma).ceil() as u32).min(MAX_SMOOTH_RADIUS);
+    let weights = (0..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<_>>();
+    // Offsets except zero are taken on both sides
+    let sum = weights[0] + 2.0 * weights[1..].iter().sum::<f64>();
+
+    weights.iter().map(|x| (x / sum) as f32).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct CannyParams {
+    /// Filtered part of the input frame
+    pub crop: CropRect,
+    /// Row strides in words
+    pub in_stride: u32,
+    pub out_stride: u32,
+    /// Gaussian weights made by [`gaussian_weights`]
+    pub smooth: Vec<f32>,
+    /// Size of the Sobel kernels, even sizes are rounded up
+    pub aperture: u32,
+    pub low_threshold: f32,
+    pub high_threshold: f32,
+}
+
+impl CannyParams {
+    /// Size of the uniform buffer
+    pub const SIZE: u64 = 176;
+
+    /// Odd aperture in `3..=MAX_APERTURE`
+    fn effective_aperture(&self) -> u32 {
+        (self.aperture | 1).clamp(3, MAX_APERTURE)
+    }
+
+    /// Bytes of the uniform buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let aperture = self.effective_aperture();
+        let (sobel_smooth, sobel_derivative) = SOBEL_KERNELS[(aperture as usize - 3) / 2];
+
+        // A step from black to white has the magnitude 1 whatever the aperture is
+        let scale = 1.0
+            / (sobel_smooth.iter().sum::<f32>()
+                * sobel_derivative.iter().filter(|x| 0.0 < **x).sum::<f32>());
+        // A low threshold above the high one would keep weak edges without strong ones
+        let low_threshold = self.low_threshold.min(self.high_threshold);
+
+        let mut words = vec![
+            self.crop.width,
+            self.crop.height,
+            self.crop.x,
+            self.crop.y,
+            self.in_stride,
+            self.out_stride,
+            self.smooth.len() as u32 - 1,
+            aperture / 2,
+            low_threshold.to_bits(),
+            self.high_threshold.to_bits(),
+            scale.to_bits(),
+            0,
+        ];
+
+        let mut push_vec4s = |values: &[f32], count: usize| {
+            let start = words.len();
+            words.extend(values.iter().map(|x| x.to_bits()));
+            words.resize(start + 4 * count, 0);
+        };
+        push_vec4s(&self.smooth, (MAX_SMOOTH_RADIUS as usize + 1).div_ceil(4));
+        push_vec4s(sobel_smooth, (MAX_APERTURE as usize).div_ceil(4));
+        push_vec4s(sobel_derivative, (MAX_APERTURE as usize).div_ceil(4));
+
+        words.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    /// Workgroups covering the filtered part of the frame
+    pub fn workgroups(&self) -> (u32, u32) {
+        (self.crop.width.div_ceil(8), self.crop.height.div_ceil(8))
+    }
+
+    /// Workgroups covering the output words of the filtered part, a word has four GRAY8 pixels
+    pub fn output_workgroups(&self) -> (u32, u32) {
+        (
+            self.crop.width.div_ceil(4).div_ceil(8),
+            self.crop.height.div_ceil(8),
+        )
+    }
+}
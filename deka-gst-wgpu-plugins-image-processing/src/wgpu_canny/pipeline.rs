@@ -0,0 +1,249 @@
+//!
+//! Compute pipelines of the Canny stages with the storage buffers for frames of the negotiated size
+//!
+
+use deka_gst_wgpu::{
+    crop::{CropRect, FrameLayout},
+    WgpuContext,
+};
+
+use parking_lot::Mutex;
+
+use super::params::CannyParams;
+use crate::glib;
+
+#[derive(Debug)]
+struct CannyStage {
+    name: &'static str,
+    pipeline: wgpu::ComputePipeline,
+}
+
+#[derive(Debug)]
+pub struct CannyPipeline {
+    smooth_horizontal: CannyStage,
+    smooth_vertical: CannyStage,
+    gradient: CannyStage,
+    suppress: CannyStage,
+    hysteresis: CannyStage,
+    write_edges: CannyStage,
+    /// Parameters of the frame, written by [`Self::write_params`]
+    params: wgpu::Buffer,
+    /// Bytes last written into `params`, unchanged ones are not written again
+    written: Mutex<Vec<u8>>,
+    /// Bindings of every stage, they are the same for every frame
+    bind_group: wgpu::BindGroup,
+    input: wgpu::Buffer,
+    output: wgpu::Buffer,
+    /// Row stride of the input storage buffer in bytes
+    in_stride: u32,
+    /// Size of the negotiated frames
+    size: (u32, u32),
+}
+
+impl CannyPipeline {
+    pub fn new(
+        ctx: &WgpuContext,
+        in_info: &gst_video::VideoInfo,
+        out_info: &gst_video::VideoInfo,
+    ) -> Self {
+        let device = ctx.device();
+        let pixels = in_info.width() as u64 * in_info.height() as u64;
+
+        let storage_buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        let input = storage_buffer(
+            "canny input",
+            in_info.size() as u64,
+            wgpu::BufferUsages::COPY_DST,
+        );
+        let blurred_rows = storage_buffer(
+            "canny blurred rows",
+            4 * pixels,
+            wgpu::BufferUsages::empty(),
+        );
+        let blurred = storage_buffer("canny blurred", 4 * pixels, wgpu::BufferUsages::empty());
+        let gradients = storage_buffer("canny gradients", 8 * pixels, wgpu::BufferUsages::empty());
+        let edges = storage_buffer("canny edges", 4 * pixels, wgpu::BufferUsages::empty());
+        let output = storage_buffer(
+            "canny output",
+            out_info.size() as u64,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage(1, true),
+            storage(2, false),
+            storage(3, false),
+            storage(4, false),
+            storage(5, false),
+            storage(6, false),
+        ];
+
+        // Every stage has all of the bindings, so one layout fits all entry points
+        let stage = |name: &'static str, entry_point| {
+            // Shared with other Canny elements of the context, compiled once
+            let pipeline = ctx.compute_pipeline(
+                wgpu::include_wgsl!("shader.wgsl"),
+                entry_point,
+                Some(&[&layout]),
+            );
+
+            CannyStage { name, pipeline }
+        };
+        let smooth_horizontal = stage("smooth rows", "smoothHorizontal");
+
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("canny params"),
+            size: CannyParams::SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Storage buffers follow the parameters in binding order
+        let entries = [
+            &params,
+            &input,
+            &blurred_rows,
+            &blurred,
+            &gradients,
+            &edges,
+            &output,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect::<Vec<_>>();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &smooth_horizontal.pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        Self {
+            smooth_horizontal,
+            smooth_vertical: stage("smooth columns", "smoothVertical"),
+            gradient: stage("gradient", "gradient"),
+            suppress: stage("non-maximum suppression", "suppress"),
+            hysteresis: stage("hysteresis", "hysteresis"),
+            write_edges: stage("write edges", "writeEdges"),
+            params,
+            written: Mutex::new(Vec::new()),
+            bind_group,
+            input,
+            output,
+            in_stride: in_info.stride()[0] as u32,
+            size: (in_info.width(), in_info.height()),
+        }
+    }
+
+    /// Writes parameters of the frame, ordered after the commands of previous frames
+    ///
+    /// Call it before recording the frame, see [`WgpuContext::write_buffer`]. The write is skipped if the parameters
+    /// are the ones of the previous frame, as it submits the pending batch.
+    pub fn write_params(
+        &self,
+        ctx: &WgpuContext,
+        params: &CannyParams,
+    ) -> Result<(), glib::BoolError> {
+        let bytes = params.to_bytes();
+        debug_assert_eq!(bytes.len() as u64, CannyParams::SIZE);
+
+        let mut written = self.written.lock();
+        if *written == bytes {
+            return Ok(());
+        }
+
+        ctx.write_buffer(
+            &self.params,
+            0,
+            wgpu::BufferSize::new(CannyParams::SIZE).unwrap(),
+            |view| view.copy_from_slice(&bytes),
+        )
+        .ok_or_else(|| glib::bool_error!("cannot write canny parameters"))?;
+
+        *written = bytes;
+        Ok(())
+    }
+
+    /// Records a copy of the `crop` rectangle of the frame with `layout` in `input` into the corner of the input
+    /// storage buffer, the rectangle must fit the negotiated frame
+    pub fn copy_input(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::Buffer,
+        layout: &FrameLayout,
+        crop: CropRect,
+    ) {
+        layout.copy_crop_to_buffer(encoder, input, crop, 4, &self.input, self.in_stride);
+    }
+
+    /// Records all stages for the copied frame into `output`, every stage is a compute pass of its own
+    ///
+    /// The output outside of the filtered part is zero. `params` must be the ones passed to [`Self::write_params`].
+    pub fn record(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: Option<&str>,
+        output: &wgpu::Buffer,
+        params: &CannyParams,
+        hysteresis_passes: u32,
+    ) {
+        // The output storage buffer is reused, so the part around the crop would keep older frames
+        if !params.crop.covers(self.size.0, self.size.1) {
+            encoder.clear_buffer(&self.output, 0, None);
+        }
+
+        let workgroups = params.workgroups();
+        let stages = [
+            (&self.smooth_horizontal, workgroups),
+            (&self.smooth_vertical, workgroups),
+            (&self.gradient, workgroups),
+            (&self.suppress, workgroups),
+        ]
+        .into_iter()
+        .chain((0..hysteresis_passes).map(|_| (&self.hysteresis, workgroups)))
+        .chain([(&self.write_edges, params.output_workgroups())]);
+
+        for (stage, (workgroup_x, workgroup_y)) in stages {
+            let pass_label = label.map(|x| format!("{x} {}", stage.name));
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: pass_label.as_deref(),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&stage.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.dispatch_workgroups(workgroup_x, workgroup_y, 1);
+        }
+
+        encoder.copy_buffer_to_buffer(&self.output, 0, output, 0, self.output.size());
+    }
+}
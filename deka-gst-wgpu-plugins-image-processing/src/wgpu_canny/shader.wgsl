@@ -0,0 +1,272 @@
+// Canny edge detector over frames in storage buffers
+//
+// Stages run in order, each one reads what the previous wrote:
+// 1. `smoothHorizontal` and `smoothVertical` blur luma of the input with a Gaussian kernel
+// 2. `gradient` applies the Sobel kernels, keeps the magnitude and the direction rounded to 45 degrees
+// 3. `suppress` keeps maxima along the direction and classifies them with the thresholds
+// 4. `hysteresis` promotes weak edges touching strong ones, it is dispatched several times
+// 5. `writeEdges` writes strong edges into the GRAY8 output
+//
+// Intermediate buffers have one value per pixel of the filtered part, the output is written from its corner. The edge
+// pixels are repeated outside of the filtered part.
+
+const EDGE_NONE: u32 = 0u;
+const EDGE_WEAK: u32 = 1u;
+const EDGE_STRONG: u32 = 2u;
+
+// Iterations of one hysteresis pass, enough to cross a tile along any straight line
+const HYSTERESIS_ITERATIONS: u32 = 16u;
+
+// BT.709 luma coefficients
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+const PI: f32 = 3.14159265358979;
+
+struct Params {
+    // Size of the filtered part of the input, written to the output corner
+    size: vec2<u32>,
+    // Corner of the filtered part in the input
+    origin: vec2<u32>,
+    // Row strides in words
+    in_stride: u32,
+    out_stride: u32,
+    smooth_radius: u32,
+    aperture_radius: u32,
+    low_threshold: f32,
+    high_threshold: f32,
+    // Makes the magnitude of a black to white step 1
+    gradient_scale: f32,
+    _padding: u32,
+    // Gaussian weights of offsets 0..=smooth_radius, uniform arrays have 16 bytes stride so they are packed by four
+    smooth_weights: array<vec4<f32>, 4>,
+    // Separable Sobel kernels of offsets -aperture_radius..=aperture_radius
+    sobel_smooth: array<vec4<f32>, 2>,
+    sobel_derivative: array<vec4<f32>, 2>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var<storage, read> input: array<u32>;
+
+@group(0) @binding(2)
+var<storage, read_write> blurred_rows: array<f32>;
+
+@group(0) @binding(3)
+var<storage, read_write> blurred: array<f32>;
+
+// Magnitude and direction sector, 0 is horizontal, 1 is 45 degrees down, 2 is vertical, 3 is 45 degrees up
+@group(0) @binding(4)
+var<storage, read_write> gradients: array<vec2<f32>>;
+
+// One of EDGE_* for each pixel, hysteresis passes read neighbours of other workgroups while they are written
+@group(0) @binding(5)
+var<storage, read_write> edges: array<atomic<u32>>;
+
+@group(0) @binding(6)
+var<storage, read_write> output: array<u32>;
+
+fn clamp_pos(pos: vec2<i32>) -> vec2<u32> {
+    return vec2<u32>(clamp(pos, vec2<i32>(0), vec2<i32>(params.size) - 1));
+}
+
+fn index(pos: vec2<u32>) -> u32 {
+    return pos.y * params.size.x + pos.x;
+}
+
+fn is_inside(pos: vec2<i32>) -> bool {
+    return all(vec2<i32>(0) <= pos) && all(pos < vec2<i32>(params.size));
+}
+
+fn luma(pos: vec2<i32>) -> f32 {
+    let p = clamp_pos(pos) + params.origin;
+    let color = unpack4x8unorm(input[p.y * params.in_stride + p.x]).rgb;
+    return dot(LUMA, color);
+}
+
+fn smooth_weight(offset: u32) -> f32 {
+    return params.smooth_weights[offset / 4u][offset % 4u];
+}
+
+@compute @workgroup_size(8, 8)
+fn smoothHorizontal(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+
+    let pos = vec2<i32>(id.xy);
+    var sum = smooth_weight(0u) * luma(pos);
+    for (var i = 1; i <= i32(params.smooth_radius); i++) {
+        sum += smooth_weight(u32(i)) * (luma(pos - vec2<i32>(i, 0)) + luma(pos + vec2<i32>(i, 0)));
+    }
+
+    blurred_rows[index(id.xy)] = sum;
+}
+
+@compute @workgroup_size(8, 8)
+fn smoothVertical(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+
+    let pos = vec2<i32>(id.xy);
+    var sum = smooth_weight(0u) * blurred_rows[index(id.xy)];
+    for (var i = 1; i <= i32(params.smooth_radius); i++) {
+        let before = blurred_rows[index(clamp_pos(pos - vec2<i32>(0, i)))];
+        let after = blurred_rows[index(clamp_pos(pos + vec2<i32>(0, i)))];
+        sum += smooth_weight(u32(i)) * (before + after);
+    }
+
+    blurred[index(id.xy)] = sum;
+}
+
+fn sobel_smooth(offset: i32) -> f32 {
+    let i = u32(offset + i32(params.aperture_radius));
+    return params.sobel_smooth[i / 4u][i % 4u];
+}
+
+fn sobel_derivative(offset: i32) -> f32 {
+    let i = u32(offset + i32(params.aperture_radius));
+    return params.sobel_derivative[i / 4u][i % 4u];
+}
+
+@compute @workgroup_size(8, 8)
+fn gradient(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+
+    let pos = vec2<i32>(id.xy);
+    let r = i32(params.aperture_radius);
+    var g = vec2<f32>(0.0);
+    for (var y = -r; y <= r; y++) {
+        for (var x = -r; x <= r; x++) {
+            let value = blurred[index(clamp_pos(pos + vec2<i32>(x, y)))];
+            g += value * vec2<f32>(
+                sobel_derivative(x) * sobel_smooth(y),
+                sobel_smooth(x) * sobel_derivative(y),
+            );
+        }
+    }
+    g *= params.gradient_scale;
+
+    // Opposite directions have the same neighbours, so the angle is folded into [0, pi)
+    var angle = atan2(g.y, g.x);
+    if angle < 0.0 {
+        angle += PI;
+    }
+    let sector = u32(round(angle / (PI / 4.0))) % 4u;
+
+    gradients[index(id.xy)] = vec2<f32>(length(g), f32(sector));
+}
+
+// Magnitude of the neighbour, zero outside of the filtered part
+fn magnitude(pos: vec2<i32>) -> f32 {
+    if !is_inside(pos) {
+        return 0.0;
+    }
+    return gradients[index(vec2<u32>(pos))].x;
+}
+
+@compute @workgroup_size(8, 8)
+fn suppress(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+
+    let pos = vec2<i32>(id.xy);
+    let g = gradients[index(id.xy)];
+    var direction: vec2<i32>;
+    switch u32(g.y) {
+        case 0u: {
+            direction = vec2<i32>(1, 0);
+        }
+        case 1u: {
+            direction = vec2<i32>(1, 1);
+        }
+        case 2u: {
+            direction = vec2<i32>(0, 1);
+        }
+        default: {
+            direction = vec2<i32>(-1, 1);
+        }
+    }
+
+    // Plateaus keep one pixel of the edge, the one before the equal neighbour
+    let is_maximum = magnitude(pos - direction) < g.x && magnitude(pos + direction) <= g.x;
+
+    var edge = EDGE_NONE;
+    if is_maximum && params.high_threshold <= g.x {
+        edge = EDGE_STRONG;
+    } else if is_maximum && params.low_threshold <= g.x {
+        edge = EDGE_WEAK;
+    }
+
+    atomicStore(&edges[index(id.xy)], edge);
+}
+
+// Tile of the workgroup with one pixel around it
+var<workgroup> tile: array<u32, 100>;
+
+fn tile_index(local: vec2<u32>) -> u32 {
+    return local.y * 10u + local.x;
+}
+
+@compute @workgroup_size(8, 8)
+fn hysteresis(
+    @builtin(local_invocation_id) local: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) group: vec3<u32>,
+) {
+    let tile_origin = vec2<i32>(group.xy * 8u) - 1;
+    for (var i = local_index; i < 100u; i += 64u) {
+        let pos = tile_origin + vec2<i32>(i32(i % 10u), i32(i / 10u));
+        var edge = EDGE_NONE;
+        if is_inside(pos) {
+            edge = atomicLoad(&edges[index(vec2<u32>(pos))]);
+        }
+        tile[i] = edge;
+    }
+    workgroupBarrier();
+
+    // Edges only grow, so the strong ones spread through connected weak ones within the tile
+    let center = local.xy + 1u;
+    for (var iteration = 0u; iteration < HYSTERESIS_ITERATIONS; iteration++) {
+        var promote = false;
+        if tile[tile_index(center)] == EDGE_WEAK {
+            for (var y = 0u; y < 3u; y++) {
+                for (var x = 0u; x < 3u; x++) {
+                    promote = promote || tile[tile_index(center + vec2<u32>(x, y) - 1u)] == EDGE_STRONG;
+                }
+            }
+        }
+        workgroupBarrier();
+
+        if promote {
+            tile[tile_index(center)] = EDGE_STRONG;
+        }
+        workgroupBarrier();
+    }
+
+    // Neighbouring tiles pick the promoted edges up in the next pass
+    let pos = vec2<i32>(group.xy * 8u + local.xy);
+    if is_inside(pos) && tile[tile_index(center)] == EDGE_STRONG {
+        atomicStore(&edges[index(vec2<u32>(pos))], EDGE_STRONG);
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn writeEdges(@builtin(global_invocation_id) id: vec3<u32>) {
+    let x0 = id.x * 4u;
+    if x0 >= params.size.x || id.y >= params.size.y {
+        return;
+    }
+
+    var gray = vec4<f32>(0.0);
+    for (var i = 0u; i < 4u && x0 + i < params.size.x; i++) {
+        let edge = atomicLoad(&edges[index(vec2<u32>(x0 + i, id.y))]);
+        gray[i] = select(0.0, 1.0, edge == EDGE_STRONG);
+    }
+    output[id.y * params.out_stride + id.x] = pack4x8unorm(gray);
+}
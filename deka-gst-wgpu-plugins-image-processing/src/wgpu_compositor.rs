@@ -0,0 +1,61 @@
+mod imp;
+mod pad;
+mod params;
+
+use gst::glib;
+use gst::prelude::*;
+
+/// How a pad is blended with the frames below it, values match `compositor`
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstWgpuCompositorOperator")]
+pub enum CompositorOperator {
+    #[enum_value(name = "Source", nick = "source")]
+    Source = 0,
+    #[default]
+    #[enum_value(name = "Over", nick = "over")]
+    Over = 1,
+    #[enum_value(name = "Add", nick = "add")]
+    Add = 2,
+}
+
+/// Color the output is cleared to before the pads are drawn
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstWgpuCompositorBackground")]
+pub enum CompositorBackground {
+    #[default]
+    #[enum_value(name = "Black", nick = "black")]
+    Black = 0,
+    #[enum_value(name = "White", nick = "white")]
+    White = 1,
+    #[enum_value(
+        name = "Transparent Background to enable further compositing",
+        nick = "transparent"
+    )]
+    Transparent = 2,
+}
+
+glib::wrapper! {
+
+    /// Plugin that composites WGPU textures of its request pads, same as `compositor`
+    ///  gst-launch-1.0 dekawgpucompositor name=comp sink_1::xpos=1280 sink_1::ypos=720 sink_1::width=640 sink_1::height=360 ! dekawgputexturedownload ! autovideosink videotestsrc ! video/x-raw,width=1920,height=1080 ! dekawgpubufferupload ! dekawgputextureupload ! comp. videotestsrc pattern=ball ! dekawgpubufferupload ! dekawgputextureupload ! comp.
+    ///
+    /// Pads are drawn by zorder with a render pass into pooled textures, the frames never leave the GPU.
+    /// The output has premultiplied alpha, which only matters with `background=transparent`.
+    pub struct WgpuCompositor(ObjectSubclass<imp::WgpuCompositor>) @extends gst_video::VideoAggregator, gst_base::Aggregator, gst::Element, gst::Object, @implements gst::ChildProxy;
+}
+
+glib::wrapper! {
+    /// Sink pad of [`WgpuCompositor`] with the position, size and blending of its frames
+    pub struct WgpuCompositorPad(ObjectSubclass<pad::WgpuCompositorPad>) @extends gst_video::VideoAggregatorPad, gst_base::AggregatorPad, gst::Pad, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpucompositor",
+        gst::Rank::NONE,
+        WgpuCompositor::static_type(),
+    )
+}
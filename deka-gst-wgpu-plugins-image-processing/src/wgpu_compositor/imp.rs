@@ -0,0 +1,888 @@
+use std::{num::NonZeroU64, sync::LazyLock};
+
+use deka_gst_wgpu::{
+    batcher::flush_on_eos,
+    caps::{fixate_wgpu_usages, transform::gst_caps_with_texture_usages, WgpuCapsInfo},
+    element::{decide_texture_allocation, ElementContext},
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_cache::ResourceOwner,
+    texture_memory::{WgpuTextureMemory, WgpuTextureMemoryExt, WgpuTextureMemoryRef},
+    WgpuContext, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+    },
+    prelude::ElementExt,
+    subclass::prelude::*,
+};
+use gst_base::subclass::prelude::*;
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+use wgpu::util::DeviceExt;
+
+use super::params::{PadParams, PAD_PARAMS_SIZE};
+use super::{CompositorBackground, CompositorOperator};
+use crate::glib;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpucompositor",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU video compositor"),
+    )
+});
+
+/// Size and rate of the output when no pad has negotiated yet, same as `compositor`
+const DEFAULT_WIDTH: i32 = 320;
+const DEFAULT_HEIGHT: i32 = 240;
+const DEFAULT_FPS: i32 = 25;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Settings {
+    background: CompositorBackground,
+}
+
+#[derive(Debug)]
+struct CompositorState {
+    /// Pipelines indexed by [`CompositorOperator`], they differ only by the blend state
+    pipelines: [wgpu::RenderPipeline; 3],
+    input_layout: wgpu::BindGroupLayout,
+    params_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Distance between parameters of pads in the uniform buffer, dynamic offsets must be aligned
+    params_stride: u64,
+    /// Owner of the objects above for the bind group caches of the textures
+    resources: ResourceOwner,
+}
+
+impl CompositorState {
+    fn new(ctx: &WgpuContext) -> Self {
+        let device = ctx.device();
+        // Shared with other compositors of the context, compiled once
+        let module = ctx.shader_module(wgpu::include_wgsl!("shader.wgsl"));
+
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(PAD_PARAMS_SIZE),
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&input_layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+
+        // The shader outputs premultiplied colors
+        let pipeline = |operator: CompositorOperator| {
+            let blend = match operator {
+                CompositorOperator::Source => None,
+                CompositorOperator::Over => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                CompositorOperator::Add => Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                }),
+            };
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: Some("vertexMain"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: Some("fragmentMain"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    // Blending is done in linear light, the target encodes the result to sRGB
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let alignment = ctx.limits().min_uniform_buffer_offset_alignment as u64;
+
+        Self {
+            pipelines: [
+                pipeline(CompositorOperator::Source),
+                pipeline(CompositorOperator::Over),
+                pipeline(CompositorOperator::Add),
+            ],
+            input_layout,
+            params_layout,
+            sampler,
+            params_stride: PAD_PARAMS_SIZE.next_multiple_of(alignment),
+            resources: ResourceOwner::new(),
+        }
+    }
+
+    /// Bind group sampling `inmem`, true if the shader has to decode sRGB as the texture has no sRGB view
+    fn input_bind_group(&self, inmem: &WgpuTextureMemoryRef) -> (wgpu::BindGroup, bool) {
+        // Sampling through the sRGB view decodes the input to linear light, like the output view encodes it
+        let format = inmem.texture().format();
+        let srgb_format = format.add_srgb_suffix();
+        let (in_view, decode_srgb) = if inmem.can_view_as(srgb_format) {
+            (inmem.create_view_as(srgb_format), false)
+        } else {
+            (inmem.default_view(), !format.is_srgb())
+        };
+
+        // Textures of the pools come again and again, their bind groups are cached
+        let bind_group = inmem.bind_group(
+            &self.input_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&in_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            &[&self.resources],
+        );
+
+        (bind_group, decode_srgb)
+    }
+
+    /// Bind group of the parameters of all layers, one after another at [`Self::params_stride`]
+    fn params_bind_group(&self, device: &wgpu::Device, params: &[PadParams]) -> wgpu::BindGroup {
+        // Parameters of all pads go to one buffer of the frame, a buffer written by the queue would
+        // be overwritten before the batched commands of previous frames run
+        let stride = self.params_stride as usize;
+        let mut contents = vec![0; stride * params.len().max(1)];
+        for (pos, pad_params) in params.iter().enumerate() {
+            contents[pos * stride..][..PAD_PARAMS_SIZE as usize]
+                .copy_from_slice(&pad_params.to_bytes());
+        }
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &contents,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &params_buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(PAD_PARAMS_SIZE),
+                }),
+            }],
+        })
+    }
+
+    /// Records drawing of `layers` over the `clear` background into the sRGB view of the output
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: Option<&str>,
+        out_view: &wgpu::TextureView,
+        layers: &[(wgpu::BindGroup, CompositorOperator)],
+        params_bind_group: &wgpu::BindGroup,
+        clear: wgpu::Color,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: out_view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        for (pos, (bind_group, operator)) in layers.iter().enumerate() {
+            let offset = (pos as u64 * self.params_stride) as u32;
+            pass.set_pipeline(&self.pipelines[*operator as usize]);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.set_bind_group(1, params_bind_group, &[offset]);
+            pass.draw(0..4, 0..1);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct WgpuCompositor {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<Settings>,
+    state: Mutex<Option<CompositorState>>,
+}
+
+impl WgpuCompositor {
+    fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader samples the texture
+        [
+            wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The render pass draws into the texture
+        [
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn sink_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+        [
+            gst_video::VideoFormat::Rgba,
+            gst_video::VideoFormat::Rgbx,
+            gst_video::VideoFormat::Bgra,
+            gst_video::VideoFormat::Bgrx,
+        ]
+    }
+
+    fn src_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+        [gst_video::VideoFormat::Rgba, gst_video::VideoFormat::Rgbx]
+    }
+
+    fn sink_pads(&self) -> Vec<super::WgpuCompositorPad> {
+        self.obj()
+            .sink_pads()
+            .into_iter()
+            .filter_map(|pad| pad.downcast::<super::WgpuCompositorPad>().ok())
+            .collect()
+    }
+
+    /// Output size covering every pad and the highest input framerate
+    fn best_size_and_rate(&self) -> (i32, i32, gst::Fraction) {
+        let mut best: Option<(i32, i32, gst::Fraction)> = None;
+
+        for pad in self.sink_pads() {
+            let Some(info) = pad.video_info() else {
+                continue;
+            };
+
+            let settings = pad.imp().settings();
+            let (width, height) = settings.output_size(info.width(), info.height());
+            let right = settings.xpos.saturating_add(width as i32);
+            let bottom = settings.ypos.saturating_add(height as i32);
+            let fps = info.fps();
+
+            best = Some(match best {
+                None => (right, bottom, fps),
+                Some((best_width, best_height, best_fps)) => (
+                    best_width.max(right),
+                    best_height.max(bottom),
+                    if best_fps < fps { fps } else { best_fps },
+                ),
+            });
+        }
+
+        let Some((width, height, fps)) = best else {
+            return (
+                DEFAULT_WIDTH,
+                DEFAULT_HEIGHT,
+                gst::Fraction::new(DEFAULT_FPS, 1),
+            );
+        };
+
+        // Variable framerate inputs give no rate to follow
+        let fps = if fps.numer() == 0 {
+            gst::Fraction::new(DEFAULT_FPS, 1)
+        } else {
+            fps
+        };
+
+        (width.max(1), height.max(1), fps)
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuCompositor {
+    const NAME: &'static str = "GstWgpuCompositor";
+    type Type = super::WgpuCompositor;
+    type ParentType = gst_video::VideoAggregator;
+    type Interfaces = (gst::ChildProxy,);
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuCompositor {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![
+                stats_param_spec(),
+                glib::ParamSpecEnum::builder::<CompositorBackground>("background")
+                    .nick("Background")
+                    .blurb("Background type")
+                    .mutable_playing()
+                    .build(),
+            ];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "background" => {
+                self.settings.lock().background = value.get().expect("type checked upstream")
+            }
+            _ => {
+                if !self.wgpu_context.set_property(value, pspec) {
+                    unimplemented!()
+                }
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            "background" => self.settings.lock().background.to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+
+impl GstObjectImpl for WgpuCompositor {}
+impl ElementImpl for WgpuCompositor {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU video compositor",
+                "Filter/Editor/Video/Compositor",
+                "Composites WGPU textures of multiple inputs",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let def_ctx = WgpuContext::default();
+            let limits = def_ctx.limits();
+
+            let caps_with_formats = |formats| {
+                gst_video::VideoCapsBuilder::new()
+                    .format_list(formats)
+                    .height_range(1..limits.max_texture_dimension_2d as i32)
+                    .width_range(1..limits.max_texture_dimension_2d as i32)
+                    .build()
+            };
+
+            let sink_caps = gst_caps_with_texture_usages(
+                caps_with_formats(WgpuCompositor::sink_texture_formats_as_gst()),
+                WgpuCompositor::sink_allowed_usages,
+            );
+            let src_caps = gst_caps_with_texture_usages(
+                caps_with_formats(WgpuCompositor::src_texture_formats_as_gst()),
+                WgpuCompositor::src_allowed_usages,
+            );
+
+            vec![
+                gst::PadTemplate::with_gtype(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                    gst_base::AggregatorPad::static_type(),
+                )
+                .unwrap(),
+                gst::PadTemplate::with_gtype(
+                    "sink_%u",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Request,
+                    &sink_caps,
+                    super::WgpuCompositorPad::static_type(),
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+
+    fn request_new_pad(
+        &self,
+        templ: &gst::PadTemplate,
+        name: Option<&str>,
+        caps: Option<&gst::Caps>,
+    ) -> Option<gst::Pad> {
+        let pad = self.parent_request_new_pad(templ, name, caps)?;
+        // Pad properties are set as `sink_0::xpos=...` through the child proxy
+        self.obj().child_added(&pad, &pad.name());
+        Some(pad)
+    }
+
+    fn release_pad(&self, pad: &gst::Pad) {
+        self.obj().child_removed(pad, &pad.name());
+        self.parent_release_pad(pad);
+    }
+}
+
+impl ChildProxyImpl for WgpuCompositor {
+    fn child_by_index(&self, index: u32) -> Option<glib::Object> {
+        self.obj()
+            .sink_pads()
+            .into_iter()
+            .nth(index as usize)
+            .map(|pad| pad.upcast())
+    }
+
+    fn child_by_name(&self, name: &str) -> Option<glib::Object> {
+        self.obj()
+            .sink_pads()
+            .into_iter()
+            .find(|pad| pad.name() == name)
+            .map(|pad| pad.upcast())
+    }
+
+    fn children_count(&self) -> u32 {
+        self.obj().num_sink_pads() as u32
+    }
+}
+
+impl AggregatorImpl for WgpuCompositor {
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+
+        self.parent_start()
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock() = None;
+        self.parent_stop()
+    }
+
+    fn sink_event(&self, aggregator_pad: &gst_base::AggregatorPad, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(aggregator_pad, event)
+    }
+
+    fn sink_query(
+        &self,
+        aggregator_pad: &gst_base::AggregatorPad,
+        query: &mut gst::QueryRef,
+    ) -> bool {
+        // Any size and format of the template is drawn, inputs do not have to match each other
+        match query.view_mut() {
+            gst::QueryViewMut::Caps(q) => {
+                let template = aggregator_pad.pad_template_caps();
+                let caps = if let Some(filter) = q.filter() {
+                    filter.intersect_with_mode(&template, gst::CapsIntersectMode::First)
+                } else {
+                    template
+                };
+                q.set_result(Some(&*caps));
+                true
+            }
+            gst::QueryViewMut::AcceptCaps(q) => {
+                let template = aggregator_pad.pad_template_caps();
+                let accepted = q.caps().is_subset(&template);
+                q.set_result(accepted);
+                true
+            }
+            _ => self.parent_sink_query(aggregator_pad, query),
+        }
+    }
+
+    fn fixate_src_caps(&self, caps: gst::Caps) -> gst::Caps {
+        let mut caps = fixate_wgpu_usages(caps).truncate();
+        let (width, height, fps) = self.best_size_and_rate();
+
+        if let Some(s) = caps.make_mut().structure_mut(0) {
+            s.fixate_field_nearest_int("width", width);
+            s.fixate_field_nearest_int("height", height);
+            s.fixate_field_nearest_fraction("framerate", fps);
+        }
+
+        caps.fixate();
+        caps
+    }
+
+    fn negotiated_src_caps(&self, caps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?}", caps);
+
+        let src_usages = match WgpuCapsInfo::from_caps(caps).and_then(|info| info.texture_usages())
+        {
+            Ok(usage) => usage,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot get texture usage in output caps: {}",
+                    err
+                ));
+            }
+        };
+        if !src_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in output caps cannot be used as render attachment",
+                src_usages
+            ));
+        }
+
+        self.parent_negotiated_src_caps(caps)?;
+
+        // Pipelines do not depend on the caps, they are created once
+        if self.state.lock().is_some() {
+            return Ok(());
+        }
+
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
+
+        *self.state.lock() = Some(CompositorState::new(&wgpu_context));
+
+        Ok(())
+    }
+
+    fn decide_allocation(
+        &self,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let Some(ctx) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before start"
+            ));
+        };
+
+        // Either format is rendered through the sRGB view, the negotiated one tells consumers how to sample it
+        decide_texture_allocation(
+            self.obj().upcast_ref(),
+            &ctx,
+            query,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+            Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+        )
+    }
+}
+
+impl VideoAggregatorImpl for WgpuCompositor {
+    fn update_caps(&self, caps: &gst::Caps) -> Result<gst::Caps, gst::LoggableError> {
+        // Inputs of any format are drawn into RGBA textures
+        let template = self.obj().src_pad().pad_template_caps();
+        Ok(caps.intersect_with_mode(&template, gst::CapsIntersectMode::First))
+    }
+
+    fn aggregate_frames(
+        &self,
+        token: &gst_video::subclass::AggregateFramesToken,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        assert!(0 < outbuf.n_memory());
+
+        let obj = self.obj();
+        let Some(out_info) = obj.video_info() else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let state_lock = self.state.lock();
+        let Some(state) = &*state_lock else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let outmem = outbuf.peek_memory(0);
+        let Some(outmem) = outmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid output memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+        let out_view = outmem.create_view_as(wgpu::TextureFormat::Rgba8UnormSrgb);
+
+        // Sink pads are sorted by zorder, so later pads are drawn on top
+        let mut layers = vec![];
+        let mut params = vec![];
+        for pad in self.sink_pads() {
+            let (Some(buffer), Some(in_info)) = (pad.current_buffer(token), pad.video_info())
+            else {
+                continue;
+            };
+
+            let settings = pad.imp().settings();
+            if settings.alpha <= 0.0 && settings.operator != CompositorOperator::Source {
+                continue;
+            }
+
+            let inmem = buffer.peek_memory(0);
+            let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+                gst::error!(CAT, obj: pad, "invalid input memory");
+                return Err(gst::FlowError::NotNegotiated);
+            };
+
+            let (bind_group, decode_srgb) = state.input_bind_group(inmem);
+
+            let (width, height) = settings.output_size(in_info.width(), in_info.height());
+            params.push(PadParams::new(
+                [settings.xpos, settings.ypos],
+                [width, height],
+                [out_info.width(), out_info.height()],
+                settings.alpha,
+                !in_info.has_alpha(),
+                decode_srgb,
+            ));
+            layers.push((bind_group, settings.operator));
+        }
+
+        let ctx = self.wgpu_context.locked();
+        let params_bind_group = state.params_bind_group(ctx.device(), &params);
+
+        let clear = match self.settings.lock().background {
+            CompositorBackground::Black => wgpu::Color::BLACK,
+            CompositorBackground::White => wgpu::Color::WHITE,
+            CompositorBackground::Transparent => wgpu::Color::TRANSPARENT,
+        };
+
+        let label = ctx.label(obj.upcast_ref(), "composite", outbuf.pts());
+        let mut profiler_lock = self.profiler.lock();
+        let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+        ctx.record(obj.upcast_ref(), outbuf.pts(), |encoder| {
+            ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                state.draw(
+                    encoder,
+                    label.as_deref(),
+                    &out_view,
+                    &layers,
+                    &params_bind_group,
+                    clear,
+                )
+            });
+        });
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deka_gst_wgpu::{texture_memory::WgpuTextureMemoryAllocator, PollType};
+    use gst::prelude::*;
+
+    use super::*;
+
+    /// Texture memory of a `size`x`size` frame from own allocator
+    fn texture_memory(
+        ctx: &WgpuContext,
+        size: u32,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+        view_formats: &'static [wgpu::TextureFormat],
+    ) -> WgpuTextureMemory {
+        let descriptor = wgpu::TextureDescriptor {
+            label: None,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            mip_level_count: 1,
+            sample_count: 1,
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            usage,
+            view_formats,
+        };
+
+        WgpuTextureMemoryAllocator::new(ctx.clone(), descriptor)
+            .alloc((size * size * 4) as usize, None)
+            .unwrap()
+            .downcast_memory::<WgpuTextureMemory>()
+            .unwrap()
+    }
+
+    #[test]
+    fn unorm_only_input_is_decoded() {
+        gst::init().unwrap();
+        let Ok(ctx) = WgpuContext::new_with_options(
+            &Default::default(),
+            PollType::Manual,
+            Default::default(),
+        ) else {
+            return;
+        };
+        let device = ctx.device();
+        let state = CompositorState::new(&ctx);
+        const SIZE: u32 = 4;
+
+        // Textures of other allocators have no sRGB view
+        let inmem = texture_memory(
+            &ctx,
+            SIZE,
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            &[],
+        );
+        let pixel = [64, 128, 192, 255];
+        ctx.queue().write_texture(
+            inmem.texture().as_image_copy(),
+            &pixel.repeat((SIZE * SIZE) as usize),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(SIZE * 4),
+                rows_per_image: None,
+            },
+            inmem.texture().size(),
+        );
+        let outmem = texture_memory(
+            &ctx,
+            SIZE,
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            &[wgpu::TextureFormat::Rgba8UnormSrgb],
+        );
+
+        let (bind_group, decode_srgb) = state.input_bind_group(&inmem);
+        assert!(decode_srgb);
+        let params = [PadParams::new(
+            [0, 0],
+            [SIZE, SIZE],
+            [SIZE, SIZE],
+            1.0,
+            false,
+            decode_srgb,
+        )];
+        let params_bind_group = state.params_bind_group(device, &params);
+
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (wgpu::COPY_BYTES_PER_ROW_ALIGNMENT * SIZE) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        state.draw(
+            &mut encoder,
+            None,
+            &outmem.create_view_as(wgpu::TextureFormat::Rgba8UnormSrgb),
+            &[(bind_group, CompositorOperator::Over)],
+            &params_bind_group,
+            wgpu::Color::BLACK,
+        );
+        encoder.copy_texture_to_buffer(
+            outmem.texture().as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            outmem.texture().size(),
+        );
+        ctx.queue().submit([encoder.finish()]);
+
+        readback.map_async(wgpu::MapMode::Read, .., |result| result.unwrap());
+        device.poll(wgpu::PollType::wait_indefinitely()).unwrap();
+
+        // The input is decoded once and encoded once, so the values come back as they were
+        let data = readback.get_mapped_range(..);
+        for row in data.chunks_exact(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize) {
+            for (value, expected) in row[..(SIZE * 4) as usize].iter().zip(pixel.iter().cycle()) {
+                assert!(value.abs_diff(*expected) <= 1, "{value} != {expected}");
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::LazyLock;
+
+use gst::{
+    glib::subclass::{object::ObjectImpl, types::ObjectSubclass},
+    subclass::prelude::*,
+};
+use gst_base::subclass::prelude::*;
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+
+use super::CompositorOperator;
+use crate::glib;
+
+const DEFAULT_ALPHA: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PadSettings {
+    pub xpos: i32,
+    pub ypos: i32,
+    /// Width of the frame in the output, the input width if not positive
+    pub width: i32,
+    /// Height of the frame in the output, the input height if not positive
+    pub height: i32,
+    pub alpha: f64,
+    pub operator: CompositorOperator,
+}
+
+impl Default for PadSettings {
+    fn default() -> Self {
+        Self {
+            xpos: 0,
+            ypos: 0,
+            width: 0,
+            height: 0,
+            alpha: DEFAULT_ALPHA,
+            operator: CompositorOperator::default(),
+        }
+    }
+}
+
+impl PadSettings {
+    /// Size of the frame in the output for the input with `in_width`x`in_height`
+    pub fn output_size(&self, in_width: u32, in_height: u32) -> (u32, u32) {
+        let width = if 0 < self.width {
+            self.width as u32
+        } else {
+            in_width
+        };
+        let height = if 0 < self.height {
+            self.height as u32
+        } else {
+            in_height
+        };
+
+        (width, height)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WgpuCompositorPad {
+    settings: Mutex<PadSettings>,
+}
+
+impl WgpuCompositorPad {
+    pub fn settings(&self) -> PadSettings {
+        *self.settings.lock()
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuCompositorPad {
+    const NAME: &'static str = "GstWgpuCompositorPad";
+    type Type = super::WgpuCompositorPad;
+    type ParentType = gst_video::VideoAggregatorPad;
+
+    fn class_init(klass: &mut Self::Class) {
+        // Textures cannot be mapped, the compositor binds the current buffers itself. Without these
+        // functions the aggregator does not map the frames, same as glvideomixer does.
+        unsafe {
+            let klass = &mut *(klass as *mut Self::Class
+                as *mut gst_video::ffi::GstVideoAggregatorPadClass);
+            klass.prepare_frame = None;
+            klass.clean_frame = None;
+        }
+    }
+}
+
+impl ObjectImpl for WgpuCompositorPad {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecInt::builder("xpos")
+                    .nick("X Position")
+                    .blurb("X position of the picture")
+                    .mutable_playing()
+                    .controllable()
+                    .build(),
+                glib::ParamSpecInt::builder("ypos")
+                    .nick("Y Position")
+                    .blurb("Y position of the picture")
+                    .mutable_playing()
+                    .controllable()
+                    .build(),
+                glib::ParamSpecInt::builder("width")
+                    .nick("Width")
+                    .blurb("Width of the picture, the input width if not positive")
+                    .mutable_playing()
+                    .controllable()
+                    .build(),
+                glib::ParamSpecInt::builder("height")
+                    .nick("Height")
+                    .blurb("Height of the picture, the input height if not positive")
+                    .mutable_playing()
+                    .controllable()
+                    .build(),
+                glib::ParamSpecDouble::builder("alpha")
+                    .nick("Alpha")
+                    .blurb("Alpha of the picture")
+                    .minimum(0.0)
+                    .maximum(1.0)
+                    .default_value(DEFAULT_ALPHA)
+                    .mutable_playing()
+                    .controllable()
+                    .build(),
+                glib::ParamSpecEnum::builder::<CompositorOperator>("operator")
+                    .nick("Operator")
+                    .blurb("Blending operator to use for blending this pad over the previous ones")
+                    .mutable_playing()
+                    .build(),
+            ]
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock();
+        match pspec.name() {
+            "xpos" => settings.xpos = value.get().expect("type checked upstream"),
+            "ypos" => settings.ypos = value.get().expect("type checked upstream"),
+            "width" => settings.width = value.get().expect("type checked upstream"),
+            "height" => settings.height = value.get().expect("type checked upstream"),
+            "alpha" => settings.alpha = value.get().expect("type checked upstream"),
+            "operator" => settings.operator = value.get().expect("type checked upstream"),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let settings = self.settings.lock();
+        match pspec.name() {
+            "xpos" => settings.xpos.to_value(),
+            "ypos" => settings.ypos.to_value(),
+            "width" => settings.width.to_value(),
+            "height" => settings.height.to_value(),
+            "alpha" => settings.alpha.to_value(),
+            "operator" => settings.operator.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl GstObjectImpl for WgpuCompositorPad {}
+impl PadImpl for WgpuCompositorPad {}
+impl AggregatorPadImpl for WgpuCompositorPad {}
+impl VideoAggregatorPadImpl for WgpuCompositorPad {}
@@ -0,0 +1,67 @@
+//!
+//! Uniform parameters of the compositing shader, the layout must match `Params` in `shader.wgsl`
+//!
+
+/// Bytes of [`PadParams`] in the uniform buffer
+pub const PAD_PARAMS_SIZE: u64 = 32;
+
+/// Where and how one pad is drawn into the output
+#[derive(Debug, Clone, Copy)]
+pub struct PadParams {
+    /// Left, top, right and bottom edges in normalized device coordinates
+    pub rect: [f32; 4],
+    pub alpha: f32,
+    /// The input has no alpha channel, like RGBx
+    pub opaque: bool,
+    /// The input is sampled without an sRGB view, so the shader decodes it to linear light
+    pub decode_srgb: bool,
+}
+
+impl PadParams {
+    /// Parameters of the frame with `size` at `origin` of the output with `out_size`
+    pub fn new(
+        origin: [i32; 2],
+        size: [u32; 2],
+        out_size: [u32; 2],
+        alpha: f64,
+        opaque: bool,
+        decode_srgb: bool,
+    ) -> Self {
+        let x = |pos: f64| (pos / out_size[0] as f64 * 2.0 - 1.0) as f32;
+        // Device coordinates go up, frame lines go down
+        let y = |pos: f64| (1.0 - pos / out_size[1] as f64 * 2.0) as f32;
+        let (left, top) = (origin[0] as f64, origin[1] as f64);
+
+        Self {
+            rect: [
+                x(left),
+                y(top),
+                x(left + size[0] as f64),
+                y(top + size[1] as f64),
+            ],
+            alpha: alpha as f32,
+            opaque,
+            decode_srgb,
+        }
+    }
+
+    /// Bytes of the uniform buffer
+    pub fn to_bytes(&self) -> [u8; PAD_PARAMS_SIZE as usize] {
+        let words = [
+            self.rect[0].to_bits(),
+            self.rect[1].to_bits(),
+            self.rect[2].to_bits(),
+            self.rect[3].to_bits(),
+            self.alpha.to_bits(),
+            self.opaque as u32,
+            self.decode_srgb as u32,
+            0,
+        ];
+
+        let mut out = [0; PAD_PARAMS_SIZE as usize];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+}
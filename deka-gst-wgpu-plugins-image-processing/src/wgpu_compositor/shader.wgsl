@@ -0,0 +1,52 @@
+// Draws one input frame as a rectangle of the output, the blend state of the pipeline applies the operator
+
+struct Params {
+    // Left, top, right and bottom edges in normalized device coordinates
+    rect: vec4<f32>,
+    alpha: f32,
+    // Non-zero if the input has no alpha channel
+    opaque: u32,
+    // Non-zero if the input is sampled without an sRGB view
+    decode_srgb: u32,
+    _padding: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+@group(1) @binding(0) var<uniform> params: Params;
+
+@vertex
+fn vertexMain(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Corners of the triangle strip: top-left, top-right, bottom-left, bottom-right
+    let uv = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let position = mix(params.rect.xy, params.rect.zw, uv);
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn srgbToLinear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fragmentMain(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureSample(input, input_sampler, in.uv);
+    // Blending is done in linear light like the sRGB views give it
+    if params.decode_srgb != 0u {
+        color = vec4<f32>(srgbToLinear(color.rgb), color.a);
+    }
+    let alpha = select(color.a, 1.0, params.opaque != 0u) * params.alpha;
+
+    // Premultiplied, so every operator is a plain blend state
+    return vec4<f32>(color.rgb * alpha, alpha);
+}
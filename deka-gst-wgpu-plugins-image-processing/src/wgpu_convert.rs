@@ -0,0 +1,25 @@
+mod imp;
+mod params;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Plugin that converts video between YUV and RGB formats on GPU
+    ///  gst-launch-1.0 videotestsrc ! video/x-raw,format=NV12 ! dekawgpubufferupload ! dekawgpuconvert ! video/x-raw(memory:WgpuTexture),format=RGBA ! dekawgputexturedownload ! autovideosink
+    ///
+    /// YUV frames are kept in WGPU buffers with the same layout as in system memory, RGB frames are either in buffers
+    /// or in textures. Colorimetry matrix, range and chroma siting are taken from caps, transfer function and primaries
+    /// are not converted.
+    pub struct WgpuConvert(ObjectSubclass<imp::WgpuConvert>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpuconvert",
+        gst::Rank::NONE,
+        WgpuConvert::static_type(),
+    )
+}
@@ -0,0 +1,817 @@
+use std::sync::LazyLock;
+
+use deka_gst_wgpu::{
+    batcher::flush_on_eos,
+    buffer_memory::GST_CAPS_FIELD_WGPU_BUFFER_USAGE,
+    caps::{
+        fixate_wgpu_usages,
+        transform::{gst_caps_with_buffer_usages, gst_caps_with_texture_usages},
+        WgpuCapsInfo,
+    },
+    element::{decide_texture_allocation, filter_caps, set_pooled_allocation, ElementContext},
+    prelude::*,
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_cache::ResourceOwner,
+    texture_memory::{
+        WgpuTextureMemory, WgpuTextureMemoryExt, GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT,
+        GST_CAPS_FIELD_WGPU_TEXTURE_USAGE,
+    },
+    WgpuBufferMemory, WgpuBufferMemoryAllocator, WgpuContext, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+    },
+    subclass::prelude::*,
+};
+use gst_base::subclass::{prelude::*, BaseTransformMode};
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+use wgpu::util::DeviceExt;
+
+use super::params::ConvertParams;
+use crate::glib;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpuconvert",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU video format converter"),
+    )
+});
+
+/// Formats of frames in WGPU buffers, both input and output
+const BUFFER_FORMATS: &[gst_video::VideoFormat] = &[
+    gst_video::VideoFormat::Nv12,
+    gst_video::VideoFormat::I420,
+    gst_video::VideoFormat::Yuy2,
+    gst_video::VideoFormat::P01010le,
+    gst_video::VideoFormat::Rgba,
+    gst_video::VideoFormat::Rgbx,
+    gst_video::VideoFormat::Bgra,
+    gst_video::VideoFormat::Bgrx,
+    gst_video::VideoFormat::Gray8,
+];
+
+/// Formats of input textures, any of them can be sampled
+const SINK_TEXTURE_FORMATS: &[gst_video::VideoFormat] = &[
+    gst_video::VideoFormat::Rgba,
+    gst_video::VideoFormat::Rgbx,
+    gst_video::VideoFormat::Bgra,
+    gst_video::VideoFormat::Bgrx,
+    gst_video::VideoFormat::Gray8,
+];
+
+/// Formats of output textures, the shader writes `rgba8unorm` storage texture
+const SRC_TEXTURE_FORMATS: &[gst_video::VideoFormat] =
+    &[gst_video::VideoFormat::Rgba, gst_video::VideoFormat::Rgbx];
+
+/// Fields which are changed by the conversion
+const CONVERTED_FIELDS: &[&str] = &["format", "colorimetry", "chroma-site"];
+
+#[derive(Debug)]
+struct ConvertState {
+    params: ConvertParams,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    /// Bound instead of the input buffer when the input is a texture
+    dummy_in_buffer: wgpu::Buffer,
+    /// Bound instead of the output buffer when the output is a texture
+    dummy_out_buffer: wgpu::Buffer,
+    /// Bound instead of the input texture when the input is a buffer
+    dummy_in_view: wgpu::TextureView,
+    /// Bound instead of the output texture when the output is a buffer
+    dummy_out_view: wgpu::TextureView,
+    /// Owner of the objects above for the bind group caches of the textures
+    resources: ResourceOwner,
+}
+
+#[derive(Debug)]
+pub struct WgpuConvert {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    state: Mutex<Option<ConvertState>>,
+
+    sink_info: Mutex<WgpuCapsInfo>,
+    src_info: Mutex<WgpuCapsInfo>,
+}
+
+impl WgpuConvert {
+    fn sink_buffer_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
+        // The shader reads the frame as storage buffer
+        [
+            wgpu::BufferUsages::STORAGE,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        ]
+    }
+
+    fn sink_texture_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader loads texels of the texture
+        [
+            wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn src_buffer_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
+        // The shader writes the frame as storage buffer
+        [
+            wgpu::BufferUsages::STORAGE,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        ]
+    }
+
+    fn src_texture_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader stores texels into the texture
+        [
+            wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    /// Copy of caps with any of `formats`, the fields changed by conversion are removed
+    fn with_formats(caps: &gst::CapsRef, formats: &[gst_video::VideoFormat]) -> gst::Caps {
+        let formats = gst::List::new(formats.iter().map(|x| x.to_str().as_str()));
+        let mut builder = gst::Caps::builder_full();
+
+        for s in caps.iter() {
+            let mut new_s = s.to_owned();
+            for field in CONVERTED_FIELDS {
+                new_s.remove_field(*field);
+            }
+            new_s.set("format", formats.clone());
+            builder = builder.structure(new_s);
+        }
+
+        builder.build()
+    }
+
+    /// Caps of input for any of `caps` size and framerate, buffers go first
+    fn sink_caps(caps: &gst::CapsRef) -> gst::Caps {
+        let mut out = gst_caps_with_buffer_usages(
+            Self::with_formats(caps, BUFFER_FORMATS),
+            Self::sink_buffer_usages,
+        );
+        out.merge(gst_caps_with_texture_usages(
+            Self::with_formats(caps, SINK_TEXTURE_FORMATS),
+            Self::sink_texture_usages,
+        ));
+        out
+    }
+
+    /// Caps of output for any of `caps` size and framerate, buffers go first
+    fn src_caps(caps: &gst::CapsRef) -> gst::Caps {
+        let mut out = gst_caps_with_buffer_usages(
+            Self::with_formats(caps, BUFFER_FORMATS),
+            Self::src_buffer_usages,
+        );
+        out.merge(gst_caps_with_texture_usages(
+            Self::with_formats(caps, SRC_TEXTURE_FORMATS),
+            Self::src_texture_usages,
+        ));
+        out
+    }
+
+    /// Copy of caps without WGPU usages and texture format, the memory features are kept
+    fn without_wgpu_fields(caps: &gst::CapsRef) -> gst::Caps {
+        let mut builder = gst::Caps::builder_full();
+
+        for (s, features) in caps.iter_with_features() {
+            let mut new_s = s.to_owned();
+            new_s.remove_field(GST_CAPS_FIELD_WGPU_BUFFER_USAGE);
+            new_s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE);
+            new_s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT);
+            builder = builder.structure_with_features(new_s, features.to_owned());
+        }
+
+        builder.build()
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuConvert {
+    const NAME: &'static str = "GstWgpuConvert";
+    type Type = super::WgpuConvert;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            state: Mutex::new(None),
+            sink_info: Mutex::new(WgpuCapsInfo::default()),
+            src_info: Mutex::new(WgpuCapsInfo::default()),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuConvert {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![stats_param_spec()];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        if !self.wgpu_context.set_property(value, pspec) {
+            unimplemented!()
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+impl GstObjectImpl for WgpuConvert {}
+impl ElementImpl for WgpuConvert {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU video converter",
+                "Filter/Converter/Video",
+                "Converts video between YUV and RGB formats on GPU",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let def_ctx = WgpuContext::default();
+            let limits = def_ctx.limits();
+
+            let base_caps = gst_video::VideoCapsBuilder::new()
+                .height_range(1..limits.max_texture_dimension_2d as i32)
+                .width_range(1..limits.max_texture_dimension_2d as i32)
+                .build();
+
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &WgpuConvert::src_caps(&base_caps),
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &WgpuConvert::sink_caps(&base_caps),
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+}
+
+impl BaseTransformImpl for WgpuConvert {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    const PASSTHROUGH_ON_SAME_CAPS: bool = true;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock() = None;
+        Ok(())
+    }
+
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(event)
+    }
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let other_caps = if direction == gst::PadDirection::Sink {
+            Self::src_caps(caps)
+        } else {
+            Self::sink_caps(caps)
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            filter
+        );
+
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        // Keep format, colorimetry and memory of the other side if the peer accepts them, so nothing is converted
+        let same = othercaps.intersect_with_mode(
+            &Self::without_wgpu_fields(caps),
+            gst::CapsIntersectMode::First,
+        );
+        let othercaps = if same.is_empty() { othercaps } else { same };
+
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
+    }
+
+    fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+        let sink_info = match WgpuCapsInfo::from_caps(incaps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(CAT, "cannot read input caps: {}", err));
+            }
+        };
+
+        let src_info = match WgpuCapsInfo::from_caps(outcaps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot read output caps: {}",
+                    err
+                ));
+            }
+        };
+
+        match (sink_info.buffer_usages, sink_info.texture_usages) {
+            (Some(usages), _) if !usages.contains(wgpu::BufferUsages::STORAGE) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "input buffer usage({:?}) cannot be used as storage",
+                    usages
+                ));
+            }
+            (None, Some(usages)) if !usages.contains(wgpu::TextureUsages::TEXTURE_BINDING) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "input texture usage({:?}) cannot be bound",
+                    usages
+                ));
+            }
+            (None, None) => {
+                return Err(gst::loggable_error!(CAT, "input caps have no usage"));
+            }
+            _ => {}
+        }
+
+        match (src_info.buffer_usages, src_info.texture_usages) {
+            (Some(usages), _) if !usages.contains(wgpu::BufferUsages::STORAGE) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "output buffer usage({:?}) cannot be used as storage",
+                    usages
+                ));
+            }
+            (None, Some(usages)) if !usages.contains(wgpu::TextureUsages::STORAGE_BINDING) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "output texture usage({:?}) cannot be used as storage",
+                    usages
+                ));
+            }
+            (None, Some(_))
+                if src_info
+                    .texture_format
+                    .is_some_and(|x| x != wgpu::TextureFormat::Rgba8Unorm) =>
+            {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "output texture format({:?}) cannot be used as storage",
+                    src_info.texture_format
+                ));
+            }
+            (None, None) => {
+                return Err(gst::loggable_error!(CAT, "output caps have no usage"));
+            }
+            _ => {}
+        }
+
+        *self.sink_info.lock() = sink_info;
+        *self.src_info.lock() = src_info;
+
+        self.parent_set_caps(incaps, outcaps)
+    }
+
+    fn transform(
+        &self,
+        inbuf: &gst::Buffer,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        assert!(0 < inbuf.n_memory());
+        assert!(0 < outbuf.n_memory());
+
+        let state_lock = self.state.lock();
+        let Some(state) = &*state_lock else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let inmem = inbuf.peek_memory(0);
+        let outmem = outbuf.peek_memory(0);
+
+        let in_texture = inmem.downcast_memory_ref::<WgpuTextureMemory>();
+        let in_buffer = match (state.params.in_texture, in_texture) {
+            (true, Some(_)) => &state.dummy_in_buffer,
+            (false, _) => match inmem.downcast_memory_ref::<WgpuBufferMemory>() {
+                Some(mem) => mem.buffer(),
+                None => {
+                    gst::error!(CAT, imp: self, "invalid input memory, expected buffer");
+                    return Err(gst::FlowError::NotNegotiated);
+                }
+            },
+            (true, None) => {
+                gst::error!(CAT, imp: self, "invalid input memory, expected texture");
+                return Err(gst::FlowError::NotNegotiated);
+            }
+        };
+        // Loaded texels must be the stored values, so sRGB textures are viewed as linear
+        let in_view = match in_texture {
+            Some(mem) => mem.create_view_as(mem.texture().format().remove_srgb_suffix()),
+            None => state.dummy_in_view.clone(),
+        };
+
+        let out_texture = outmem.downcast_memory_ref::<WgpuTextureMemory>();
+        let out_buffer = match (state.params.out_texture, out_texture) {
+            (true, Some(_)) => &state.dummy_out_buffer,
+            (false, _) => match outmem.downcast_memory_ref::<WgpuBufferMemory>() {
+                Some(mem) => mem.buffer(),
+                None => {
+                    gst::error!(CAT, imp: self, "invalid output memory, expected buffer");
+                    return Err(gst::FlowError::NotNegotiated);
+                }
+            },
+            (true, None) => {
+                gst::error!(CAT, imp: self, "invalid output memory, expected texture");
+                return Err(gst::FlowError::NotNegotiated);
+            }
+        };
+        let out_view = match out_texture {
+            Some(mem) => mem.create_view_as(wgpu::TextureFormat::Rgba8Unorm),
+            None => state.dummy_out_view.clone(),
+        };
+
+        let entries = [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: state.params_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: in_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&in_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: out_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&out_view),
+            },
+        ];
+
+        let ctx = self.wgpu_context.locked();
+
+        // Textures of the pools come again and again, their bind groups are cached. Buffer memories have no owner
+        // to drop the cached bind group with, so it is cached only when both sides are textures.
+        let bind_group = match (out_texture, in_texture) {
+            (Some(out_mem), Some(in_mem)) => out_mem.bind_group(
+                &state.bind_group_layout,
+                &entries,
+                &[&state.resources, in_mem.owner()],
+            ),
+            _ => ctx.device().create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &state.bind_group_layout,
+                entries: &entries,
+            }),
+        };
+
+        let obj = self.obj();
+        let label = ctx.label(obj.upcast_ref(), "convert", inbuf.pts());
+        let (workgroup_x, workgroup_y) = state.params.workgroups();
+
+        let mut profiler_lock = self.profiler.lock();
+        let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+        ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+            ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: label.as_deref(),
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(&state.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(workgroup_x, workgroup_y, 1);
+            });
+        });
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn unit_size(&self, caps: &gst::Caps) -> Option<usize> {
+        let video_caps = gst_video::VideoInfo::from_caps(&caps).ok()?;
+        Some(video_caps.size())
+    }
+
+    fn propose_allocation(
+        &self,
+        decide_query: Option<&gst::query::Allocation>,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        // Buffers are passed as is in passthrough
+        if decide_query.is_none() {
+            return self.parent_propose_allocation(decide_query, query);
+        }
+
+        let sink_usages = self.sink_info.lock().buffer_usages;
+        let Some(sink_usages) = sink_usages else {
+            // Textures are allocated by upstream
+            return Ok(());
+        };
+
+        let ctx = self.wgpu_context.get().unwrap();
+
+        let label = ctx.label(self.obj().upcast_ref(), "input buffers", None);
+        let allocator =
+            WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, sink_usages).with_label(label);
+        let params = gst::AllocationParams::default();
+        query.add_allocation_param(Some(&allocator), params);
+
+        Ok(())
+    }
+
+    fn decide_allocation(
+        &self,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let src_info = *self.src_info.lock();
+        if src_info.buffer_usages.is_none() && src_info.texture_usages.is_none() {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before negotiation"
+            ));
+        }
+
+        let Some(ctx) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before start"
+            ));
+        };
+
+        let Some(usages) = src_info.buffer_usages else {
+            return decide_texture_allocation(
+                self.obj().upcast_ref(),
+                &ctx,
+                query,
+                wgpu::TextureUsages::STORAGE_BINDING,
+                Some(wgpu::TextureFormat::Rgba8Unorm),
+            );
+        };
+
+        let (caps, _needs_pool) = query.get();
+        let Some(caps) = caps.map(|x| x.to_owned()) else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called wo caps"
+            ));
+        };
+
+        let info = match gst_video::VideoInfo::from_caps(&caps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(CAT, "invalid output caps: {}", err));
+            }
+        };
+
+        let suitable = query
+            .allocation_params()
+            .iter()
+            .find_map(|(allocator, params)| {
+                allocator
+                    .and_downcast_ref::<WgpuBufferMemoryAllocator>()
+                    .filter(|x| {
+                        x.explicit_usages()
+                            .is_some_and(|x| x.contains(wgpu::BufferUsages::STORAGE))
+                    })
+                    .map(|x| (x.clone(), params.clone()))
+            });
+
+        let (allocator, params) = match suitable {
+            Some(found) => {
+                gst::trace!(CAT, imp: self, "using allocator of downstream: {:?}", found.0);
+                found
+            }
+            None => {
+                gst::debug!(CAT, imp: self, "have to use own allocator");
+
+                let label = ctx.label(self.obj().upcast_ref(), "output buffers", None);
+                let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, usages)
+                    .with_label(label);
+                let params = gst::AllocationParams::new(gst::MemoryFlags::empty(), 0, 0, 0);
+                (allocator, params)
+            }
+        };
+
+        set_pooled_allocation(query, &caps, info.size(), &allocator, params)
+    }
+}
+
+impl VideoFilterImpl for WgpuConvert {
+    fn set_info(
+        &self,
+        _incaps: &gst::Caps,
+        in_info: &gst_video::VideoInfo,
+        _outcaps: &gst::Caps,
+        out_info: &gst_video::VideoInfo,
+    ) -> Result<(), gst::LoggableError> {
+        let in_texture = self.sink_info.lock().buffer_usages.is_none();
+        let out_texture = self.src_info.lock().buffer_usages.is_none();
+
+        let Some(params) = ConvertParams::new(in_info, out_info, in_texture, out_texture) else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "unsupported conversion {:?} -> {:?}",
+                in_info.format(),
+                out_info.format()
+            ));
+        };
+
+        // The shader accesses planes by 32-bit words
+        let aligned = |offsets: &[u32; 4], strides: &[u32; 4]| {
+            offsets.iter().chain(strides).all(|x| x % 4 == 0)
+        };
+        if (!in_texture && !aligned(&params.in_offsets, &params.in_strides))
+            || (!out_texture && !aligned(&params.out_offsets, &params.out_strides))
+        {
+            return Err(gst::loggable_error!(
+                CAT,
+                "planes of the frame are not aligned to 4 bytes"
+            ));
+        }
+
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
+        let device = wgpu_context.device();
+
+        let storage_buffer = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        // Shared with other converters of the context, compiled once
+        let pipeline = wgpu_context.compute_pipeline(
+            wgpu::include_wgsl!("shader.wgsl"),
+            "convert",
+            Some(&[&[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_buffer(1, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage_buffer(3, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ]]),
+        );
+        let bind_group_layout = pipeline.get_bind_group_layout(0);
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &params.to_bytes(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let dummy_buffer = || {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: 4,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+
+        let dummy_view = |usage| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: None,
+                    size: wgpu::Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    usage,
+                    view_formats: &[],
+                })
+                .create_view(&Default::default())
+        };
+
+        gst::debug!(CAT, imp: self, "converting with {:?}", params);
+
+        *self.state.lock() = Some(ConvertState {
+            params,
+            pipeline,
+            bind_group_layout,
+            params_buffer,
+            dummy_in_buffer: dummy_buffer(),
+            dummy_out_buffer: dummy_buffer(),
+            dummy_in_view: dummy_view(wgpu::TextureUsages::TEXTURE_BINDING),
+            dummy_out_view: dummy_view(wgpu::TextureUsages::STORAGE_BINDING),
+            resources: ResourceOwner::new(),
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+//!
+//! Uniform parameters of the conversion shader, the layout must match `Params` in `shader.wgsl`
+//!
+
+/// Formats of the video frame known to the shader, values match `FORMAT_*` constants in `shader.wgsl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ShaderFormat {
+    Rgba = 0,
+    Rgbx = 1,
+    Bgra = 2,
+    Bgrx = 3,
+    Gray8 = 4,
+    Nv12 = 5,
+    I420 = 6,
+    Yuy2 = 7,
+    P010 = 8,
+}
+
+impl ShaderFormat {
+    pub fn from_video_format(format: gst_video::VideoFormat) -> Option<Self> {
+        match format {
+            gst_video::VideoFormat::Rgba => Some(Self::Rgba),
+            gst_video::VideoFormat::Rgbx => Some(Self::Rgbx),
+            gst_video::VideoFormat::Bgra => Some(Self::Bgra),
+            gst_video::VideoFormat::Bgrx => Some(Self::Bgrx),
+            gst_video::VideoFormat::Gray8 => Some(Self::Gray8),
+            gst_video::VideoFormat::Nv12 => Some(Self::Nv12),
+            gst_video::VideoFormat::I420 => Some(Self::I420),
+            gst_video::VideoFormat::Yuy2 => Some(Self::Yuy2),
+            gst_video::VideoFormat::P01010le => Some(Self::P010),
+            _ => None,
+        }
+    }
+
+    /// True for formats which store luma and chroma and need the colorimetry matrix
+    pub fn is_yuv(&self) -> bool {
+        matches!(self, Self::Nv12 | Self::I420 | Self::Yuy2 | Self::P010)
+    }
+}
+
+/// Kr and Kb of the matrix, BT.601 is used when the matrix is unknown or RGB
+fn kr_kb(matrix: gst_video::VideoColorMatrix) -> (f64, f64) {
+    match matrix {
+        gst_video::VideoColorMatrix::Bt709 => (0.2126, 0.0722),
+        gst_video::VideoColorMatrix::Bt2020 => (0.2627, 0.0593),
+        gst_video::VideoColorMatrix::Smpte240m => (0.212, 0.087),
+        gst_video::VideoColorMatrix::Fcc => (0.30, 0.11),
+        _ => (0.299, 0.114),
+    }
+}
+
+/// Normalized offsets and scales of luma and chroma as `(y_off, y_scale, c_off, c_scale)`
+fn range_coefficients(range: gst_video::VideoColorRange, depth: u32) -> (f64, f64, f64, f64) {
+    let max = ((1u32 << depth) - 1) as f64;
+    let scale = (1u32 << (depth - 8)) as f64;
+    let c_off = 128.0 * scale / max;
+
+    match range {
+        gst_video::VideoColorRange::Range16_235 => (
+            16.0 * scale / max,
+            219.0 * scale / max,
+            c_off,
+            224.0 * scale / max,
+        ),
+        _ => (0.0, 1.0, c_off, 1.0),
+    }
+}
+
+/// Matrices between non-linear RGB and normalized YUV of the video info, rows are applied to `(x, y, z, 1)`
+///
+/// Returns `(rgb_to_yuv, yuv_to_rgb)`.
+fn yuv_matrices(info: &gst_video::VideoInfo) -> ([[f32; 4]; 3], [[f32; 4]; 3]) {
+    let colorimetry = info.colorimetry();
+    let (kr, kb) = kr_kb(colorimetry.matrix());
+    let kg = 1.0 - kr - kb;
+    let depth = info.format_info().depth()[0];
+    let (y_off, y_scale, c_off, c_scale) = range_coefficients(colorimetry.range(), depth);
+
+    let pb = 2.0 * (1.0 - kb);
+    let pr = 2.0 * (1.0 - kr);
+    let rgb_to_yuv = [
+        [y_scale * kr, y_scale * kg, y_scale * kb, y_off],
+        [
+            -c_scale * kr / pb,
+            -c_scale * kg / pb,
+            c_scale * (1.0 - kb) / pb,
+            c_off,
+        ],
+        [
+            c_scale * (1.0 - kr) / pr,
+            -c_scale * kg / pr,
+            -c_scale * kb / pr,
+            c_off,
+        ],
+    ];
+
+    let a = 1.0 / y_scale;
+    let b = 1.0 / c_scale;
+    let g_pb = pb * kb / kg;
+    let g_pr = pr * kr / kg;
+    let yuv_to_rgb = [
+        [a, 0.0, pr * b, -a * y_off - pr * b * c_off],
+        [
+            a,
+            -g_pb * b,
+            -g_pr * b,
+            -a * y_off + (g_pb + g_pr) * b * c_off,
+        ],
+        [a, pb * b, 0.0, -a * y_off - pb * b * c_off],
+    ];
+
+    (
+        rgb_to_yuv.map(|row| row.map(|x| x as f32)),
+        yuv_to_rgb.map(|row| row.map(|x| x as f32)),
+    )
+}
+
+/// Chroma siting as `1` for horizontally and `2` for vertically cosited chroma
+fn chroma_site_bits(info: &gst_video::VideoInfo) -> u32 {
+    let site = info.chroma_site();
+    let mut bits = 0;
+    if site.contains(gst_video::VideoChromaSite::H_COSITED) {
+        bits |= 1;
+    }
+    if site.contains(gst_video::VideoChromaSite::V_COSITED) {
+        bits |= 2;
+    }
+    bits
+}
+
+/// Byte offsets and strides of up to 3 planes
+fn planes(info: &gst_video::VideoInfo) -> ([u32; 4], [u32; 4]) {
+    let mut offsets = [0; 4];
+    let mut strides = [0; 4];
+    for (plane, (offset, stride)) in info.offset().iter().zip(info.stride()).enumerate().take(4) {
+        offsets[plane] = *offset as u32;
+        strides[plane] = *stride as u32;
+    }
+    (offsets, strides)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConvertParams {
+    pub in_format: ShaderFormat,
+    pub out_format: ShaderFormat,
+    pub in_chroma_site: u32,
+    pub out_chroma_site: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The input is a sampled texture instead of buffer
+    pub in_texture: bool,
+    /// The output is a storage texture instead of buffer
+    pub out_texture: bool,
+    pub in_offsets: [u32; 4],
+    pub in_strides: [u32; 4],
+    pub out_offsets: [u32; 4],
+    pub out_strides: [u32; 4],
+    pub in_matrix: [[f32; 4]; 3],
+    pub out_matrix: [[f32; 4]; 3],
+}
+
+impl ConvertParams {
+    /// Parameters to convert frames of `in_info` to `out_info`, `None` if a format is not supported
+    pub fn new(
+        in_info: &gst_video::VideoInfo,
+        out_info: &gst_video::VideoInfo,
+        in_texture: bool,
+        out_texture: bool,
+    ) -> Option<Self> {
+        let in_format = ShaderFormat::from_video_format(in_info.format())?;
+        let out_format = ShaderFormat::from_video_format(out_info.format())?;
+        let (in_offsets, in_strides) = planes(in_info);
+        let (out_offsets, out_strides) = planes(out_info);
+        let (_, in_matrix) = yuv_matrices(in_info);
+        let (out_matrix, _) = yuv_matrices(out_info);
+
+        Some(Self {
+            in_format,
+            out_format,
+            in_chroma_site: chroma_site_bits(in_info),
+            out_chroma_site: chroma_site_bits(out_info),
+            width: in_info.width(),
+            height: in_info.height(),
+            in_texture,
+            out_texture,
+            in_offsets,
+            in_strides,
+            out_offsets,
+            out_strides,
+            in_matrix,
+            out_matrix,
+        })
+    }
+
+    /// Number of workgroups to dispatch, every invocation converts a block of 8x2 pixels
+    pub fn workgroups(&self) -> (u32, u32) {
+        (
+            self.width.div_ceil(8).div_ceil(8),
+            self.height.div_ceil(2).div_ceil(8),
+        )
+    }
+
+    /// Bytes of the uniform buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut words: Vec<u32> = vec![
+            self.in_format as u32,
+            self.out_format as u32,
+            self.in_chroma_site,
+            self.out_chroma_site,
+            self.width,
+            self.height,
+            self.in_texture as u32,
+            self.out_texture as u32,
+        ];
+        words.extend(self.in_offsets);
+        words.extend(self.in_strides);
+        words.extend(self.out_offsets);
+        words.extend(self.out_strides);
+        words.extend(self.in_matrix.iter().flatten().map(|x| x.to_bits()));
+        words.extend(self.out_matrix.iter().flatten().map(|x| x.to_bits()));
+
+        words.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+}
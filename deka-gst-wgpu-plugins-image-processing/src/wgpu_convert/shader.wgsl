@@ -0,0 +1,398 @@
+// Converts video frames between RGB and YUV formats
+//
+// Every invocation converts a block of 8x2 pixels, so it owns whole 32-bit words of every plane in the output buffer
+// as long as strides and plane offsets are multiples of 4 as GStreamer aligns them by default.
+
+const FORMAT_RGBA: u32 = 0u;
+const FORMAT_RGBX: u32 = 1u;
+const FORMAT_BGRA: u32 = 2u;
+const FORMAT_BGRX: u32 = 3u;
+const FORMAT_GRAY8: u32 = 4u;
+const FORMAT_NV12: u32 = 5u;
+const FORMAT_I420: u32 = 6u;
+const FORMAT_YUY2: u32 = 7u;
+const FORMAT_P010: u32 = 8u;
+
+const CHROMA_H_COSITED: u32 = 1u;
+const CHROMA_V_COSITED: u32 = 2u;
+
+const BLOCK_WIDTH: u32 = 8u;
+const BLOCK_HEIGHT: u32 = 2u;
+
+struct Params {
+    in_format: u32,
+    out_format: u32,
+    in_chroma_site: u32,
+    out_chroma_site: u32,
+    size: vec2<u32>,
+    in_texture: u32,
+    out_texture: u32,
+    in_offsets: vec4<u32>,
+    in_strides: vec4<u32>,
+    out_offsets: vec4<u32>,
+    out_strides: vec4<u32>,
+    // Rows applied to (y, u, v, 1)
+    in_matrix: array<vec4<f32>, 3>,
+    // Rows applied to (r, g, b, 1)
+    out_matrix: array<vec4<f32>, 3>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var<storage, read> in_buf: array<u32>;
+
+@group(0) @binding(2)
+var in_tex: texture_2d<f32>;
+
+@group(0) @binding(3)
+var<storage, read_write> out_buf: array<u32>;
+
+@group(0) @binding(4)
+var out_tex: texture_storage_2d<rgba8unorm, write>;
+
+// Pixels of the block in RGBA, row by row
+var<private> block: array<vec4<f32>, 16>;
+
+fn load_u8(offset: u32) -> f32 {
+    let word = in_buf[offset / 4u];
+    return f32((word >> ((offset % 4u) * 8u)) & 0xffu) / 255.0;
+}
+
+// 10 bits stored in the most significant bits of 16 bits little endian sample
+fn load_u10(offset: u32) -> f32 {
+    let word = in_buf[offset / 4u];
+    return f32(((word >> ((offset % 4u) * 8u)) & 0xffffu) >> 6u) / 1023.0;
+}
+
+fn load_luma(x: u32, y: u32) -> f32 {
+    let row = params.in_offsets.x + y * params.in_strides.x;
+    switch params.in_format {
+        case FORMAT_YUY2: {
+            return load_u8(row + x * 2u);
+        }
+        case FORMAT_P010: {
+            return load_u10(row + x * 2u);
+        }
+        default: {
+            return load_u8(row + x);
+        }
+    }
+}
+
+fn chroma_size() -> vec2<u32> {
+    let width = (params.size.x + 1u) / 2u;
+    if params.in_format == FORMAT_YUY2 {
+        return vec2<u32>(width, params.size.y);
+    }
+    return vec2<u32>(width, (params.size.y + 1u) / 2u);
+}
+
+// Chroma sample at position of the chroma plane
+fn load_chroma_sample(x: u32, y: u32) -> vec2<f32> {
+    switch params.in_format {
+        case FORMAT_NV12: {
+            let offset = params.in_offsets.y + y * params.in_strides.y + x * 2u;
+            return vec2<f32>(load_u8(offset), load_u8(offset + 1u));
+        }
+        case FORMAT_I420: {
+            let u = load_u8(params.in_offsets.y + y * params.in_strides.y + x);
+            let v = load_u8(params.in_offsets.z + y * params.in_strides.z + x);
+            return vec2<f32>(u, v);
+        }
+        case FORMAT_YUY2: {
+            let offset = params.in_offsets.x + y * params.in_strides.x + x * 4u;
+            return vec2<f32>(load_u8(offset + 1u), load_u8(offset + 3u));
+        }
+        case FORMAT_P010: {
+            let offset = params.in_offsets.y + y * params.in_strides.y + x * 4u;
+            return vec2<f32>(load_u10(offset), load_u10(offset + 2u));
+        }
+        default: {
+            return vec2<f32>(0.5, 0.5);
+        }
+    }
+}
+
+// Position of the pixel on the chroma plane depending on siting of the chroma
+fn chroma_position(pos: u32, cosited: bool) -> f32 {
+    if cosited {
+        return f32(pos) * 0.5;
+    }
+    return f32(pos) * 0.5 - 0.25;
+}
+
+// Chroma of the pixel, bilinearly interpolated between the chroma samples
+fn load_chroma(x: u32, y: u32) -> vec2<f32> {
+    let size = vec2<i32>(chroma_size());
+    let px = chroma_position(x, (params.in_chroma_site & CHROMA_H_COSITED) != 0u);
+    var py = f32(y);
+    if params.in_format != FORMAT_YUY2 {
+        py = chroma_position(y, (params.in_chroma_site & CHROMA_V_COSITED) != 0u);
+    }
+
+    let x0 = i32(floor(px));
+    let y0 = i32(floor(py));
+    let t = vec2<f32>(px - f32(x0), py - f32(y0));
+
+    let xs = vec2<u32>(vec2<i32>(clamp(x0, 0, size.x - 1), clamp(x0 + 1, 0, size.x - 1)));
+    let ys = vec2<u32>(vec2<i32>(clamp(y0, 0, size.y - 1), clamp(y0 + 1, 0, size.y - 1)));
+
+    let top = mix(load_chroma_sample(xs.x, ys.x), load_chroma_sample(xs.y, ys.x), t.x);
+    let bottom = mix(load_chroma_sample(xs.x, ys.y), load_chroma_sample(xs.y, ys.y), t.x);
+    return mix(top, bottom, t.y);
+}
+
+fn yuv_to_rgb(yuv: vec3<f32>) -> vec3<f32> {
+    let v = vec4<f32>(yuv, 1.0);
+    let rgb = vec3<f32>(
+        dot(params.in_matrix[0], v),
+        dot(params.in_matrix[1], v),
+        dot(params.in_matrix[2], v)
+    );
+    return clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn rgb_to_yuv(rgb: vec3<f32>) -> vec3<f32> {
+    let v = vec4<f32>(rgb, 1.0);
+    let yuv = vec3<f32>(
+        dot(params.out_matrix[0], v),
+        dot(params.out_matrix[1], v),
+        dot(params.out_matrix[2], v)
+    );
+    return clamp(yuv, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn is_opaque(format: u32) -> bool {
+    return format != FORMAT_RGBA && format != FORMAT_BGRA;
+}
+
+fn load_pixel(x: u32, y: u32) -> vec4<f32> {
+    if params.in_texture != 0u {
+        let color = textureLoad(in_tex, vec2<u32>(x, y), 0);
+        if params.in_format == FORMAT_GRAY8 {
+            return vec4<f32>(color.rrr, 1.0);
+        }
+        if is_opaque(params.in_format) {
+            return vec4<f32>(color.rgb, 1.0);
+        }
+        return color;
+    }
+
+    switch params.in_format {
+        case FORMAT_RGBA, FORMAT_RGBX: {
+            let color = unpack4x8unorm(in_buf[(params.in_offsets.x + y * params.in_strides.x + x * 4u) / 4u]);
+            if params.in_format == FORMAT_RGBX {
+                return vec4<f32>(color.rgb, 1.0);
+            }
+            return color;
+        }
+        case FORMAT_BGRA, FORMAT_BGRX: {
+            let color = unpack4x8unorm(in_buf[(params.in_offsets.x + y * params.in_strides.x + x * 4u) / 4u]).zyxw;
+            if params.in_format == FORMAT_BGRX {
+                return vec4<f32>(color.rgb, 1.0);
+            }
+            return color;
+        }
+        case FORMAT_GRAY8: {
+            let gray = load_u8(params.in_offsets.x + y * params.in_strides.x + x);
+            return vec4<f32>(gray, gray, gray, 1.0);
+        }
+        default: {
+            let yuv = vec3<f32>(load_luma(x, y), load_chroma(x, y));
+            return vec4<f32>(yuv_to_rgb(yuv), 1.0);
+        }
+    }
+}
+
+fn pixel(row: u32, column: u32) -> vec4<f32> {
+    return block[row * BLOCK_WIDTH + column];
+}
+
+fn luma(row: u32, column: u32) -> f32 {
+    return rgb_to_yuv(pixel(row, column).rgb).x;
+}
+
+// Chroma of pixels `2 * pair` and `2 * pair + 1` of the row, for horizontally subsampled formats
+fn chroma_422(row: u32, pair: u32) -> vec2<f32> {
+    var rgb = pixel(row, 2u * pair).rgb;
+    if (params.out_chroma_site & CHROMA_H_COSITED) == 0u {
+        rgb = (rgb + pixel(row, 2u * pair + 1u).rgb) * 0.5;
+    }
+    return rgb_to_yuv(rgb).yz;
+}
+
+// Chroma of 2x2 pixels starting at `2 * pair` column, for formats subsampled in both directions
+fn chroma_420(pair: u32) -> vec2<f32> {
+    var top = pixel(0u, 2u * pair).rgb;
+    var bottom = pixel(1u, 2u * pair).rgb;
+    if (params.out_chroma_site & CHROMA_H_COSITED) == 0u {
+        top = (top + pixel(0u, 2u * pair + 1u).rgb) * 0.5;
+        bottom = (bottom + pixel(1u, 2u * pair + 1u).rgb) * 0.5;
+    }
+
+    var rgb = top;
+    if (params.out_chroma_site & CHROMA_V_COSITED) == 0u {
+        rgb = (top + bottom) * 0.5;
+    }
+    return rgb_to_yuv(rgb).yz;
+}
+
+fn u10(value: f32) -> u32 {
+    return u32(round(clamp(value, 0.0, 1.0) * 1023.0)) << 6u;
+}
+
+// Writes 8-bit luma of the row, 4 pixels per word
+fn store_luma_u8(x0: u32, y: u32, row: u32) {
+    let base = params.out_offsets.x + y * params.out_strides.x + x0;
+    for (var word = 0u; word < 2u; word++) {
+        let x = 4u * word;
+        if x0 + x >= params.size.x {
+            break;
+        }
+        let value = vec4<f32>(luma(row, x), luma(row, x + 1u), luma(row, x + 2u), luma(row, x + 3u));
+        out_buf[base / 4u + word] = pack4x8unorm(value);
+    }
+}
+
+// Writes 10-bit luma of the row, 2 pixels per word
+fn store_luma_u10(x0: u32, y: u32, row: u32) {
+    let base = params.out_offsets.x + y * params.out_strides.x + x0 * 2u;
+    for (var word = 0u; word < 4u; word++) {
+        let x = 2u * word;
+        if x0 + x >= params.size.x {
+            break;
+        }
+        out_buf[base / 4u + word] = u10(luma(row, x)) | (u10(luma(row, x + 1u)) << 16u);
+    }
+}
+
+fn store_rgb(x0: u32, y: u32, row: u32) {
+    let base = params.out_offsets.x + y * params.out_strides.x + x0 * 4u;
+    for (var x = 0u; x < BLOCK_WIDTH; x++) {
+        if x0 + x >= params.size.x {
+            break;
+        }
+
+        var color = pixel(row, x);
+        if params.out_format == FORMAT_BGRA || params.out_format == FORMAT_BGRX {
+            color = color.zyxw;
+        }
+        if is_opaque(params.out_format) {
+            color.w = 1.0;
+        }
+        out_buf[base / 4u + x] = pack4x8unorm(color);
+    }
+}
+
+fn store_yuy2(x0: u32, y: u32, row: u32) {
+    let base = params.out_offsets.x + y * params.out_strides.x + x0 * 2u;
+    for (var pair = 0u; pair < 4u; pair++) {
+        if x0 + 2u * pair >= params.size.x {
+            break;
+        }
+        let uv = chroma_422(row, pair);
+        let value = vec4<f32>(luma(row, 2u * pair), uv.x, luma(row, 2u * pair + 1u), uv.y);
+        out_buf[base / 4u + pair] = pack4x8unorm(value);
+    }
+}
+
+fn store_chroma_420(x0: u32, y0: u32) {
+    let cx0 = x0 / 2u;
+    let cy = y0 / 2u;
+    let chroma_width = (params.size.x + 1u) / 2u;
+
+    switch params.out_format {
+        case FORMAT_NV12: {
+            let base = params.out_offsets.y + cy * params.out_strides.y + cx0 * 2u;
+            for (var word = 0u; word < 2u; word++) {
+                if cx0 + 2u * word >= chroma_width {
+                    break;
+                }
+                let first = chroma_420(2u * word);
+                let second = chroma_420(2u * word + 1u);
+                out_buf[base / 4u + word] = pack4x8unorm(vec4<f32>(first, second));
+            }
+        }
+        case FORMAT_I420: {
+            let u = vec4<f32>(chroma_420(0u).x, chroma_420(1u).x, chroma_420(2u).x, chroma_420(3u).x);
+            let v = vec4<f32>(chroma_420(0u).y, chroma_420(1u).y, chroma_420(2u).y, chroma_420(3u).y);
+            out_buf[(params.out_offsets.y + cy * params.out_strides.y + cx0) / 4u] = pack4x8unorm(u);
+            out_buf[(params.out_offsets.z + cy * params.out_strides.z + cx0) / 4u] = pack4x8unorm(v);
+        }
+        case FORMAT_P010: {
+            let base = params.out_offsets.y + cy * params.out_strides.y + cx0 * 4u;
+            for (var pair = 0u; pair < 4u; pair++) {
+                if cx0 + pair >= chroma_width {
+                    break;
+                }
+                let uv = chroma_420(pair);
+                out_buf[base / 4u + pair] = u10(uv.x) | (u10(uv.y) << 16u);
+            }
+        }
+        default: {}
+    }
+}
+
+fn store_block(x0: u32, y0: u32) {
+    for (var row = 0u; row < BLOCK_HEIGHT; row++) {
+        let y = y0 + row;
+        if y >= params.size.y {
+            break;
+        }
+
+        if params.out_texture != 0u {
+            for (var x = 0u; x < BLOCK_WIDTH; x++) {
+                if x0 + x >= params.size.x {
+                    break;
+                }
+                var color = pixel(row, x);
+                if is_opaque(params.out_format) {
+                    color.w = 1.0;
+                }
+                textureStore(out_tex, vec2<u32>(x0 + x, y), color);
+            }
+            continue;
+        }
+
+        switch params.out_format {
+            case FORMAT_RGBA, FORMAT_RGBX, FORMAT_BGRA, FORMAT_BGRX: {
+                store_rgb(x0, y, row);
+            }
+            case FORMAT_GRAY8, FORMAT_NV12, FORMAT_I420: {
+                store_luma_u8(x0, y, row);
+            }
+            case FORMAT_P010: {
+                store_luma_u10(x0, y, row);
+            }
+            case FORMAT_YUY2: {
+                store_yuy2(x0, y, row);
+            }
+            default: {}
+        }
+    }
+
+    if params.out_texture == 0u {
+        store_chroma_420(x0, y0);
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn convert(@builtin(global_invocation_id) id: vec3<u32>) {
+    let x0 = id.x * BLOCK_WIDTH;
+    let y0 = id.y * BLOCK_HEIGHT;
+    if x0 >= params.size.x || y0 >= params.size.y {
+        return;
+    }
+
+    for (var row = 0u; row < BLOCK_HEIGHT; row++) {
+        for (var x = 0u; x < BLOCK_WIDTH; x++) {
+            let px = min(x0 + x, params.size.x - 1u);
+            let py = min(y0 + row, params.size.y - 1u);
+            block[row * BLOCK_WIDTH + x] = load_pixel(px, py);
+        }
+    }
+
+    store_block(x0, y0);
+}
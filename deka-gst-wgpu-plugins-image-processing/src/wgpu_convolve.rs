@@ -0,0 +1,52 @@
+mod imp;
+mod params;
+
+use gst::glib;
+use gst::prelude::*;
+
+/// How pixels outside the frame are read, values match `BORDER_*` constants in `shader.wgsl`
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstWgpuConvolveBorder")]
+pub enum ConvolveBorder {
+    #[default]
+    #[enum_value(name = "Repeat the edge pixels", nick = "clamp")]
+    Clamp = 0,
+    #[enum_value(name = "Mirror the frame at the edges", nick = "mirror")]
+    Mirror = 1,
+    #[enum_value(name = "Take pixels of the opposite edge", nick = "wrap")]
+    Wrap = 2,
+    #[enum_value(name = "Use border-value", nick = "constant")]
+    Constant = 3,
+}
+
+/// What the kernel is applied to
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstWgpuConvolveChannels")]
+pub enum ConvolveChannels {
+    #[default]
+    #[enum_value(name = "Each color channel", nick = "rgb")]
+    Rgb = 0,
+    #[enum_value(name = "Luma only, chroma is kept", nick = "luma")]
+    Luma = 1,
+}
+
+glib::wrapper! {
+
+    /// Plugin that convolves video with an NxN kernel, up to 11x11
+    ///  gst-launch-1.0 videotestsrc ! dekawgpubufferupload ! dekawgputextureupload ! dekawgpuconvolve kernel="<1.0,2.0,1.0,0.0,0.0,0.0,-1.0,-2.0,-1.0>" absolute=true ! dekawgputexturedownload ! autovideosink
+    ///
+    /// Frames come either in WGPU textures or in WGPU buffers, the output is in the same memory. The result is the
+    /// weighted sum divided by `divisor` plus `bias`, alpha is kept.
+    pub struct WgpuConvolve(ObjectSubclass<imp::WgpuConvolve>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpuconvolve",
+        gst::Rank::NONE,
+        WgpuConvolve::static_type(),
+    )
+}
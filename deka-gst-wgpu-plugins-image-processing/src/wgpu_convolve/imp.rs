@@ -0,0 +1,952 @@
+use std::sync::LazyLock;
+
+use deka_gst_wgpu::{
+    batcher::flush_on_eos,
+    buffer_memory::GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER,
+    caps::{
+        fixate_wgpu_usages,
+        transform::{gst_caps_with_buffer_usages, gst_caps_with_texture_usages},
+        WgpuCapsInfo,
+    },
+    crop::FrameLayout,
+    element::{decide_texture_allocation, filter_caps, ElementContext},
+    prelude::WgpuBufferMemoryExt,
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_memory::{
+        WgpuTextureMemory, WgpuTextureMemoryExt, GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE,
+    },
+    WgpuBufferMemory, WgpuBufferMemoryAllocator, WgpuContext, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+    },
+    subclass::prelude::*,
+};
+use gst_base::subclass::{prelude::*, BaseTransformMode};
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+use wgpu::util::DeviceExt;
+
+use super::params::{kernel_size, ConvolveParams, MAX_KERNEL_SIZE};
+use super::{ConvolveBorder, ConvolveChannels};
+use crate::{buffer_textures::BufferFilterTextures, glib};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpuconvolve",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU convolution filter"),
+    )
+});
+
+const DEFAULT_DIVISOR: f64 = 0.0;
+const DEFAULT_BIAS: f64 = 0.0;
+const DEFAULT_BORDER_VALUE: f64 = 0.0;
+const DEFAULT_ABSOLUTE: bool = false;
+
+#[derive(Debug, Clone)]
+struct Settings {
+    /// Weights row by row, a square of `kernel_size`
+    kernel: Vec<f32>,
+    kernel_size: u32,
+    divisor: f64,
+    bias: f64,
+    border: ConvolveBorder,
+    border_value: f64,
+    channels: ConvolveChannels,
+    absolute: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        // Identity
+        Self {
+            kernel: vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+            kernel_size: 3,
+            divisor: DEFAULT_DIVISOR,
+            bias: DEFAULT_BIAS,
+            border: ConvolveBorder::default(),
+            border_value: DEFAULT_BORDER_VALUE,
+            channels: ConvolveChannels::default(),
+            absolute: DEFAULT_ABSOLUTE,
+        }
+    }
+}
+
+impl Settings {
+    fn params(&self, size: [u32; 2]) -> ConvolveParams {
+        ConvolveParams {
+            size,
+            kernel_size: self.kernel_size,
+            border: self.border,
+            divisor: ConvolveParams::effective_divisor(self.divisor, &self.kernel),
+            bias: self.bias as f32,
+            border_value: self.border_value as f32,
+            channels: self.channels,
+            absolute: self.absolute,
+            kernel: self.kernel.clone(),
+        }
+    }
+}
+
+/// Memory of the negotiated frames
+#[derive(Debug, Clone, Copy)]
+enum ConvolveMemory {
+    /// Frames in WGPU textures, with the usages of the output textures
+    Texture(wgpu::TextureUsages),
+    /// Frames in WGPU buffers
+    Buffer,
+}
+
+#[derive(Debug)]
+struct ConvolveState {
+    pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    /// Textures the frames in buffers are copied through, `None` for frames in textures
+    textures: Option<BufferFilterTextures>,
+}
+
+#[derive(Debug)]
+pub struct WgpuConvolve {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<Settings>,
+    state: Mutex<Option<ConvolveState>>,
+
+    memory: Mutex<Option<ConvolveMemory>>,
+}
+
+impl WgpuConvolve {
+    fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader loads texels of the texture
+        [
+            wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader stores texels into the texture
+        [
+            wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn sink_allowed_buffer_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
+        [
+            wgpu::BufferUsages::COPY_SRC,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::MAP_WRITE,
+        ]
+    }
+
+    fn src_allowed_buffer_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
+        [
+            wgpu::BufferUsages::COPY_DST,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        ]
+    }
+
+    fn allowed_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+        [gst_video::VideoFormat::Rgba, gst_video::VideoFormat::Rgbx]
+    }
+
+    /// Caps with frames in textures and in buffers, usages of each memory are made by the factories
+    fn caps_with_usages<C, FT, IT, FB, IB>(
+        caps: C,
+        texture_usages: FT,
+        buffer_usages: FB,
+    ) -> gst::Caps
+    where
+        C: AsRef<gst::CapsRef>,
+        FT: Fn() -> IT,
+        IT: IntoIterator<Item = wgpu::TextureUsages>,
+        FB: Fn() -> IB,
+        IB: IntoIterator<Item = wgpu::BufferUsages>,
+    {
+        let mut out = gst::Caps::new_empty();
+
+        // Each memory keeps its own, the element does not move frames between textures and buffers
+        for (s, features) in caps.as_ref().iter_with_features() {
+            let single = gst::Caps::builder_full()
+                .structure_with_features(s.to_owned(), features.to_owned())
+                .build();
+
+            let other = if features.contains(GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER) {
+                gst_caps_with_buffer_usages(&single, &buffer_usages)
+            } else {
+                gst_caps_with_texture_usages(&single, &texture_usages)
+            };
+            out.merge(other);
+        }
+
+        out
+    }
+
+    /// Reads the kernel from an array of doubles, it must be a square of up to [`MAX_KERNEL_SIZE`] weights
+    fn parse_kernel(value: &glib::Value) -> Result<(Vec<f32>, u32), String> {
+        let array = value
+            .get::<gst::Array>()
+            .map_err(|err| format!("not an array: {err}"))?;
+
+        let kernel = array
+            .iter()
+            .map(|x| {
+                x.get::<f64>()
+                    .map(|x| x as f32)
+                    .map_err(|err| format!("invalid weight {x:?}: {err}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let Some(size) = kernel_size(kernel.len()) else {
+            return Err(format!(
+                "{} weights do not make a square kernel up to {MAX_KERNEL_SIZE}x{MAX_KERNEL_SIZE}",
+                kernel.len()
+            ));
+        };
+
+        Ok((kernel, size))
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuConvolve {
+    const NAME: &'static str = "GstWgpuConvolve";
+    type Type = super::WgpuConvolve;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(None),
+            memory: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuConvolve {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![
+                stats_param_spec(),
+                gst::ParamSpecArray::builder("kernel")
+                    .nick("Kernel")
+                    .blurb("Weights of the square kernel row by row, from 1x1 up to 11x11")
+                    .element_spec(
+                        &glib::ParamSpecDouble::builder("weight")
+                            .nick("Weight")
+                            .blurb("Weight of one pixel")
+                            .build(),
+                    )
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecDouble::builder("divisor")
+                    .nick("Divisor")
+                    .blurb("Divisor of the weighted sum, 0 for the sum of weights or 1 if it is 0")
+                    .default_value(DEFAULT_DIVISOR)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecDouble::builder("bias")
+                    .nick("Bias")
+                    .blurb("Added to the divided sum, in the 0..1 range of the channels")
+                    .default_value(DEFAULT_BIAS)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("border", ConvolveBorder::default())
+                    .nick("Border")
+                    .blurb("How pixels outside the frame are read")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecDouble::builder("border-value")
+                    .nick("Border Value")
+                    .blurb("Value of the channels outside the frame for the constant border")
+                    .minimum(0.0)
+                    .maximum(1.0)
+                    .default_value(DEFAULT_BORDER_VALUE)
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecEnum::builder_with_default("channels", ConvolveChannels::default())
+                    .nick("Channels")
+                    .blurb("Convolve each color channel or only luma")
+                    .mutable_playing()
+                    .build(),
+                glib::ParamSpecBoolean::builder("absolute")
+                    .nick("Absolute")
+                    .blurb("Take the absolute value of the divided sum before the bias is added")
+                    .default_value(DEFAULT_ABSOLUTE)
+                    .mutable_playing()
+                    .build(),
+            ];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock();
+        match pspec.name() {
+            "kernel" => match Self::parse_kernel(value) {
+                Ok((kernel, size)) => {
+                    settings.kernel = kernel;
+                    settings.kernel_size = size;
+                }
+                Err(err) => {
+                    // The previous kernel is kept, frames are still filtered
+                    gst::error!(CAT, imp: self, "invalid kernel: {}", err);
+                }
+            },
+            "divisor" => {
+                settings.divisor = value.get().expect("type checked upstream");
+            }
+            "bias" => {
+                settings.bias = value.get().expect("type checked upstream");
+            }
+            "border" => {
+                settings.border = value.get().expect("type checked upstream");
+            }
+            "border-value" => {
+                settings.border_value = value.get().expect("type checked upstream");
+            }
+            "channels" => {
+                settings.channels = value.get().expect("type checked upstream");
+            }
+            "absolute" => {
+                settings.absolute = value.get().expect("type checked upstream");
+            }
+            _ => {
+                if !self.wgpu_context.set_property(value, pspec) {
+                    unimplemented!()
+                }
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            "kernel" => {
+                gst::Array::new(self.settings.lock().kernel.iter().map(|x| *x as f64)).to_value()
+            }
+            "divisor" => self.settings.lock().divisor.to_value(),
+            "bias" => self.settings.lock().bias.to_value(),
+            "border" => self.settings.lock().border.to_value(),
+            "border-value" => self.settings.lock().border_value.to_value(),
+            "channels" => self.settings.lock().channels.to_value(),
+            "absolute" => self.settings.lock().absolute.to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+impl GstObjectImpl for WgpuConvolve {}
+impl ElementImpl for WgpuConvolve {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU convolution filter",
+                "Filter/Effect/Video",
+                "Convolves video in WGPU textures or buffers with a configurable kernel",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let def_ctx = WgpuContext::default();
+            let limits = def_ctx.limits();
+
+            let texture_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(WgpuConvolve::allowed_texture_formats_as_gst())
+                .height_range(1..limits.max_texture_dimension_2d as i32)
+                .width_range(1..limits.max_texture_dimension_2d as i32)
+                .build();
+            let buffer_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(WgpuConvolve::allowed_texture_formats_as_gst())
+                .height_range(1..limits.max_texture_dimension_2d as i32)
+                .width_range(1..limits.max_texture_dimension_2d as i32)
+                .features([GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER])
+                .build();
+
+            let mut sink_caps =
+                gst_caps_with_texture_usages(&texture_caps, WgpuConvolve::sink_allowed_usages);
+            sink_caps.merge(gst_caps_with_buffer_usages(
+                &buffer_caps,
+                WgpuConvolve::sink_allowed_buffer_usages,
+            ));
+            let mut src_caps =
+                gst_caps_with_texture_usages(&texture_caps, WgpuConvolve::src_allowed_usages);
+            src_caps.merge(gst_caps_with_buffer_usages(
+                &buffer_caps,
+                WgpuConvolve::src_allowed_buffer_usages,
+            ));
+
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &sink_caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+}
+
+impl BaseTransformImpl for WgpuConvolve {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock() = None;
+        Ok(())
+    }
+
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(event)
+    }
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let other_caps = if direction == gst::PadDirection::Sink {
+            Self::caps_with_usages(
+                caps,
+                Self::src_allowed_usages,
+                Self::src_allowed_buffer_usages,
+            )
+        } else {
+            Self::caps_with_usages(
+                caps,
+                Self::sink_allowed_usages,
+                Self::sink_allowed_buffer_usages,
+            )
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            filter
+        );
+
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
+    }
+
+    fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+        let in_info = match WgpuCapsInfo::from_caps(incaps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(CAT, "cannot read input caps: {}", err));
+            }
+        };
+        let out_info = match WgpuCapsInfo::from_caps(outcaps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot read output caps: {}",
+                    err
+                ));
+            }
+        };
+
+        let is_buffer = |caps: &gst::Caps| {
+            caps.features(0)
+                .is_some_and(|f| f.contains(GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER))
+        };
+        let is_texture = |caps: &gst::Caps| {
+            caps.features(0)
+                .is_some_and(|f| f.contains(GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE))
+        };
+
+        let memory = if is_buffer(incaps) && is_buffer(outcaps) {
+            let sink_usages = match in_info.buffer_usages() {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get buffer usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+            if !sink_usages.contains(wgpu::BufferUsages::COPY_SRC) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "input caps({:?}) cannot be used as copy src",
+                    sink_usages
+                ));
+            }
+
+            let src_usages = match out_info.buffer_usages() {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get buffer usage in output caps: {}",
+                        err
+                    ));
+                }
+            };
+            if !src_usages.contains(wgpu::BufferUsages::COPY_DST) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "output caps({:?}) cannot be used as copy dst",
+                    src_usages
+                ));
+            }
+
+            ConvolveMemory::Buffer
+        } else if is_texture(incaps) && is_texture(outcaps) {
+            let sink_usages = match in_info.texture_usages() {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+            if !sink_usages.contains(wgpu::TextureUsages::TEXTURE_BINDING) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "texture usage({:?}) in input caps cannot be bound",
+                    sink_usages
+                ));
+            }
+
+            let src_usages = match out_info.texture_usages() {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in output caps: {}",
+                        err
+                    ));
+                }
+            };
+            if !src_usages.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "texture usage({:?}) in output caps cannot be used as storage",
+                    src_usages
+                ));
+            }
+            if out_info
+                .texture_format
+                .is_some_and(|x| x != wgpu::TextureFormat::Rgba8Unorm)
+            {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "output texture format({:?}) cannot be used as storage",
+                    out_info.texture_format
+                ));
+            }
+
+            ConvolveMemory::Texture(src_usages)
+        } else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "input and output frames must be both in WGPU textures or both in WGPU buffers"
+            ));
+        };
+
+        *self.memory.lock() = Some(memory);
+
+        self.parent_set_caps(incaps, outcaps)
+    }
+
+    fn transform(
+        &self,
+        inbuf: &gst::Buffer,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        assert!(0 < inbuf.n_memory());
+        assert!(0 < outbuf.n_memory());
+
+        let state_lock = self.state.lock();
+        let Some(state) = &*state_lock else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let obj = self.obj();
+        let ctx = self.wgpu_context.locked();
+        let device = ctx.device();
+        let label = ctx.label(obj.upcast_ref(), "convolve", inbuf.pts());
+
+        let Some(textures) = &state.textures else {
+            let inmem = inbuf.peek_memory(0);
+            let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+                gst::error!(CAT, imp: self, "invalid input memory");
+                return Err(gst::FlowError::NotNegotiated);
+            };
+
+            let outmem = outbuf.peek_memory(0);
+            let Some(outmem) = outmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+                gst::error!(CAT, imp: self, "invalid output memory");
+                return Err(gst::FlowError::NotNegotiated);
+            };
+
+            // Weights apply to the stored values, so sRGB textures are viewed as linear
+            let in_view = inmem.create_view_as(inmem.texture().format().remove_srgb_suffix());
+            let out_view = outmem.create_view_as(wgpu::TextureFormat::Rgba8Unorm);
+            let size = [outmem.texture().width(), outmem.texture().height()];
+
+            let params = self.settings.lock().params(size);
+            let (workgroup_x, workgroup_y) = params.workgroups();
+            // Properties change while playing, a buffer written by the queue would be overwritten before the
+            // batched commands of previous frames run
+            let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: &params.to_bytes(),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &state.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&in_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&out_view),
+                    },
+                ],
+            });
+
+            let mut profiler_lock = self.profiler.lock();
+            let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+            ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+                ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                        label: label.as_deref(),
+                        timestamp_writes: None,
+                    });
+
+                    pass.set_pipeline(&state.pipeline);
+                    pass.set_bind_group(0, &bind_group, &[]);
+                    pass.dispatch_workgroups(workgroup_x, workgroup_y, 1);
+                });
+            });
+
+            return Ok(gst::FlowSuccess::Ok);
+        };
+
+        let Some(inmem) = inbuf
+            .peek_memory(0)
+            .downcast_memory_ref::<WgpuBufferMemory>()
+        else {
+            gst::error!(CAT, imp: self, "unsupported memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let self_as_filter = obj.upcast_ref::<gst_video::VideoFilter>();
+        let Some(in_info) = self_as_filter.input_video_info() else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+        let Some(out_info) = self_as_filter.output_video_info() else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        // Only the crop rectangle is filtered, its edges are the borders and its result goes to the cleared frame corner
+        let layout = FrameLayout::from_buffer(inbuf, &in_info);
+        let crop = layout
+            .crop_rect(inbuf)
+            .fit_size(in_info.width(), in_info.height());
+
+        let outmem = match outbuf.peek_memory_mut(0) {
+            Ok(m) => m,
+            Err(err) => {
+                gst::error!(CAT, imp: self, "can't get mutable out memory: {err}");
+                return Err(gst::FlowError::NotNegotiated);
+            }
+        };
+        let Some(outmem) = outmem.downcast_memory_mut::<WgpuBufferMemory>() else {
+            gst::error!(CAT, imp: self, "unsupported out memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let params = self.settings.lock().params([crop.width, crop.height]);
+        let (workgroup_x, workgroup_y) = params.workgroups();
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &params.to_bytes(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &state.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&textures.input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&textures.output_view),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: label.as_deref(),
+        });
+
+        let mut profiler_lock = self.profiler.lock();
+        let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+        let cleared = ElementProfiler::profile_encoder(profiler, &mut encoder, |encoder| {
+            textures.copy_from_buffer(encoder, inmem.buffer(), &layout, crop);
+            let cleared = textures.clear_output(&ctx, encoder, crop);
+
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: label.as_deref(),
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(&state.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(workgroup_x, workgroup_y, 1);
+            }
+
+            textures.copy_to_buffer(encoder, outmem.buffer(), &out_info);
+            cleared
+        });
+        if !cleared {
+            gst::warning!(CAT, imp: self, "cannot clear the output around {:?}", crop);
+        }
+
+        // The output buffer is mapped right after, the copies cannot wait for the batch
+        ctx.submit([encoder.finish()]);
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn unit_size(&self, caps: &gst::Caps) -> Option<usize> {
+        let video_caps = gst_video::VideoInfo::from_caps(&caps).ok()?;
+        Some(video_caps.size())
+    }
+
+    fn propose_allocation(
+        &self,
+        decide_query: Option<&gst::query::Allocation>,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let (caps, _needs_pool) = query.get();
+        let Some(caps) = caps else {
+            return Err(gst::loggable_error!(CAT, "No caps in allocation query"));
+        };
+
+        let is_buffer = caps
+            .features(0)
+            .is_some_and(|f| f.contains(GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER));
+        if !is_buffer {
+            return self.parent_propose_allocation(decide_query, query);
+        }
+
+        let sink_usages = match WgpuCapsInfo::from_caps(caps).and_then(|info| info.buffer_usages())
+        {
+            Ok(usage) => usage,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot get buffer usage in input caps: {}",
+                    err
+                ));
+            }
+        };
+
+        let ctx = self.wgpu_context.get().unwrap();
+
+        let label = ctx.label(self.obj().upcast_ref(), "input buffers", None);
+        let allocator =
+            WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, sink_usages).with_label(label);
+        let params = gst::AllocationParams::default();
+        query.add_allocation_param(Some(&allocator), params);
+
+        // The padding around the crop rectangle is not filtered
+        query.add_allocation_meta::<gst_video::VideoCropMeta>(None);
+
+        Ok(())
+    }
+
+    fn decide_allocation(
+        &self,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        match *self.memory.lock() {
+            Some(ConvolveMemory::Texture(_)) => {}
+            Some(ConvolveMemory::Buffer) => return self.parent_decide_allocation(query),
+            None => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "decide_allocation called before negotiation"
+                ));
+            }
+        }
+
+        let Some(ctx) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before start"
+            ));
+        };
+
+        decide_texture_allocation(
+            self.obj().upcast_ref(),
+            &ctx,
+            query,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            Some(wgpu::TextureFormat::Rgba8Unorm),
+        )
+    }
+}
+
+impl VideoFilterImpl for WgpuConvolve {
+    fn set_info(
+        &self,
+        _incaps: &gst::Caps,
+        in_info: &gst_video::VideoInfo,
+        _outcaps: &gst::Caps,
+        out_info: &gst_video::VideoInfo,
+    ) -> Result<(), gst::LoggableError> {
+        let Some(memory) = *self.memory.lock() else {
+            return Err(gst::loggable_error!(CAT, "set_info called before set_caps"));
+        };
+
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
+        let device = wgpu_context.device();
+
+        // Shared with other convolutions of the context, compiled once
+        let pipeline = wgpu_context.compute_pipeline(
+            wgpu::include_wgsl!("shader.wgsl"),
+            "convolve",
+            Some(&[&[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ]]),
+        );
+
+        let textures = match memory {
+            ConvolveMemory::Texture(_) => None,
+            ConvolveMemory::Buffer => Some(BufferFilterTextures::new(device, in_info, out_info)),
+        };
+
+        gst::debug!(CAT, imp: self, "convolving frames in {:?}", memory);
+
+        *self.state.lock() = Some(ConvolveState {
+            layout: pipeline.get_bind_group_layout(0),
+            pipeline,
+            textures,
+        });
+
+        Ok(())
+    }
+}
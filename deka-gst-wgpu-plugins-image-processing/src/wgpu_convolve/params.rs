@@ -0,0 +1,78 @@
+//!
+//! Uniform parameters of the convolution shader, the layout must match `Params` in `shader.wgsl`
+//!
+
+use super::{ConvolveBorder, ConvolveChannels};
+
+/// Largest kernel is `MAX_KERNEL_SIZE`x`MAX_KERNEL_SIZE`
+pub const MAX_KERNEL_SIZE: u32 = 11;
+/// Weights in the uniform array, which is packed into `vec4`s
+const KERNEL_WORDS: usize = (MAX_KERNEL_SIZE * MAX_KERNEL_SIZE).div_ceil(4) as usize * 4;
+
+const FLAG_LUMA: u32 = 1;
+const FLAG_ABSOLUTE: u32 = 2;
+
+/// Side of the square kernel with `len` weights, `None` if it is not a square up to [`MAX_KERNEL_SIZE`]
+pub fn kernel_size(len: usize) -> Option<u32> {
+    (1..=MAX_KERNEL_SIZE).find(|size| (size * size) as usize == len)
+}
+
+#[derive(Debug, Clone)]
+pub struct ConvolveParams {
+    /// Size of the filtered part of the frame
+    pub size: [u32; 2],
+    pub kernel_size: u32,
+    pub border: ConvolveBorder,
+    /// Not zero, the sum of the weights if it was not set
+    pub divisor: f32,
+    pub bias: f32,
+    pub border_value: f32,
+    pub channels: ConvolveChannels,
+    pub absolute: bool,
+    /// Weights row by row
+    pub kernel: Vec<f32>,
+}
+
+impl ConvolveParams {
+    /// Divisor of the weighted sum, `divisor` if it is set, otherwise the sum of weights or 1 for zero sum kernels
+    pub fn effective_divisor(divisor: f64, kernel: &[f32]) -> f32 {
+        if divisor != 0.0 {
+            return divisor as f32;
+        }
+
+        let sum = kernel.iter().sum::<f32>();
+        if sum == 0.0 {
+            1.0
+        } else {
+            sum
+        }
+    }
+
+    /// Bytes of the uniform buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let flags = match self.channels {
+            ConvolveChannels::Rgb => 0,
+            ConvolveChannels::Luma => FLAG_LUMA,
+        } | if self.absolute { FLAG_ABSOLUTE } else { 0 };
+
+        let mut words = vec![
+            self.size[0],
+            self.size[1],
+            self.kernel_size,
+            self.border as u32,
+            self.divisor.to_bits(),
+            self.bias.to_bits(),
+            self.border_value.to_bits(),
+            flags,
+        ];
+        words.extend(self.kernel.iter().map(|x| x.to_bits()));
+        words.resize(8 + KERNEL_WORDS, 0);
+
+        words.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    /// Workgroups covering the filtered part of the frame
+    pub fn workgroups(&self) -> (u32, u32) {
+        (self.size[0].div_ceil(8), self.size[1].div_ceil(8))
+    }
+}
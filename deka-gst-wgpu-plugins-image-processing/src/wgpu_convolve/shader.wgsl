@@ -0,0 +1,127 @@
+// Convolves the frame with an NxN kernel anchored at its center, N is up to 11
+
+const BORDER_CLAMP: u32 = 0u;
+const BORDER_MIRROR: u32 = 1u;
+const BORDER_WRAP: u32 = 2u;
+const BORDER_CONSTANT: u32 = 3u;
+
+const FLAG_LUMA: u32 = 1u;
+const FLAG_ABSOLUTE: u32 = 2u;
+
+// BT.709 luma coefficients
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+struct Params {
+    // Size of the filtered part of the frame, starting at the corner
+    size: vec2<u32>,
+    kernel_size: u32,
+    border: u32,
+    divisor: f32,
+    bias: f32,
+    border_value: f32,
+    flags: u32,
+    // Weights row by row, uniform arrays have 16 bytes stride so they are packed by four
+    kernel: array<vec4<f32>, 31>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var input: texture_2d<f32>;
+
+@group(0) @binding(2)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+fn weight(index: u32) -> f32 {
+    return params.kernel[index / 4u][index % 4u];
+}
+
+// Coordinate inside [0, size) the border mode reads instead of `x`, -1 for the constant
+fn border_coord(x: i32, size: i32) -> i32 {
+    if x >= 0 && x < size {
+        return x;
+    }
+
+    switch params.border {
+        case BORDER_MIRROR: {
+            // The edge pixel is not repeated: -1 reads 1
+            let period = 2 * (size - 1);
+            if period == 0 {
+                return 0;
+            }
+            var m = x % period;
+            if m < 0 {
+                m += period;
+            }
+            return select(m, period - m, m >= size);
+        }
+        case BORDER_WRAP: {
+            var m = x % size;
+            if m < 0 {
+                m += size;
+            }
+            return m;
+        }
+        case BORDER_CONSTANT: {
+            return -1;
+        }
+        default: {
+            return clamp(x, 0, size - 1);
+        }
+    }
+}
+
+fn load(pos: vec2<i32>) -> vec3<f32> {
+    let size = vec2<i32>(params.size);
+    let x = border_coord(pos.x, size.x);
+    let y = border_coord(pos.y, size.y);
+    if x < 0 || y < 0 {
+        return vec3<f32>(params.border_value);
+    }
+
+    return textureLoad(input, vec2<i32>(x, y), 0).rgb;
+}
+
+@compute @workgroup_size(8, 8)
+fn convolve(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+
+    let n = i32(params.kernel_size);
+    let anchor = n / 2;
+    let pos = vec2<i32>(id.xy);
+
+    var sum = vec3<f32>(0.0);
+    for (var ky = 0; ky < n; ky++) {
+        for (var kx = 0; kx < n; kx++) {
+            let w = weight(u32(ky * n + kx));
+            if w == 0.0 {
+                continue;
+            }
+            sum += w * load(pos + vec2<i32>(kx - anchor, ky - anchor));
+        }
+    }
+
+    let center = textureLoad(input, id.xy, 0);
+    let absolute = (params.flags & FLAG_ABSOLUTE) != 0u;
+
+    var color: vec3<f32>;
+    if (params.flags & FLAG_LUMA) != 0u {
+        // Luma is linear in RGB, so the convolved luma is the luma of the convolved color
+        var luma = dot(LUMA, sum) / params.divisor;
+        if absolute {
+            luma = abs(luma);
+        }
+        color = center.rgb + (luma + params.bias - dot(LUMA, center.rgb));
+    } else {
+        var value = sum / params.divisor;
+        if absolute {
+            value = abs(value);
+        }
+        color = value + params.bias;
+    }
+
+    textureStore(output, id.xy, vec4<f32>(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), center.a));
+}
@@ -0,0 +1,23 @@
+mod imp;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Plugin that crops WGPU textures, same as `videocrop`
+    ///  gst-launch-1.0 videotestsrc ! dekawgpubufferupload ! dekawgputextureupload ! dekawgpucrop left=100 top=50 ! dekawgputexturedownload ! autovideosink
+    ///
+    /// The sub-rectangle is copied with `copy_texture_to_texture`. Crop meta of the input is applied first, so the
+    /// properties are relative to the visible part of the frame.
+    pub struct WgpuCrop(ObjectSubclass<imp::WgpuCrop>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpucrop",
+        gst::Rank::NONE,
+        WgpuCrop::static_type(),
+    )
+}
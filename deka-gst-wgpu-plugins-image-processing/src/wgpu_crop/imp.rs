@@ -0,0 +1,499 @@
+use std::sync::LazyLock;
+
+use deka_gst_wgpu::{
+    batcher::flush_on_eos,
+    caps::{fixate_wgpu_usages, transform::gst_caps_with_texture_usages, WgpuCapsInfo},
+    crop::{buffer_crop_rect, clear_around_crop, CropRect},
+    element::{decide_texture_allocation, filter_caps, ElementContext},
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_memory::{WgpuTextureMemory, WgpuTextureMemoryExt},
+    WgpuContext, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+    },
+    subclass::prelude::*,
+};
+use gst_base::subclass::{prelude::*, BaseTransformMode};
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+
+use crate::glib;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpucrop",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU video crop"),
+    )
+});
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Settings {
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+}
+
+impl Settings {
+    fn horizontal(&self) -> i32 {
+        self.left.saturating_add(self.right).min(i32::MAX as u32) as i32
+    }
+
+    fn vertical(&self) -> i32 {
+        self.top.saturating_add(self.bottom).min(i32::MAX as u32) as i32
+    }
+}
+
+#[derive(Debug)]
+pub struct WgpuCrop {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<Settings>,
+}
+
+impl WgpuCrop {
+    fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // We need to be able to copy from the texture
+        [
+            wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // We want to copy into the texture
+        [
+            wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn allowed_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+        [
+            gst_video::VideoFormat::Rgba,
+            gst_video::VideoFormat::Rgbx,
+            gst_video::VideoFormat::Bgra,
+            gst_video::VideoFormat::Bgrx,
+            gst_video::VideoFormat::Gray8,
+        ]
+    }
+
+    /// Size field changed by `delta`, `None` if nothing positive is left
+    fn adjust_size(value: &glib::SendValue, delta: i32) -> Option<glib::SendValue> {
+        if let Ok(size) = value.get::<i32>() {
+            let size = size + delta;
+            return (0 < size).then(|| size.to_send_value());
+        }
+
+        if let Ok(range) = value.get::<gst::IntRange<i32>>() {
+            let max = range.max().saturating_add(delta);
+            if max < 1 {
+                return None;
+            }
+            let min = range.min().saturating_add(delta).max(1);
+            if min == max {
+                return Some(min.to_send_value());
+            }
+            return Some(gst::IntRange::new(min, max).to_send_value());
+        }
+
+        None
+    }
+
+    /// Copy of caps with width and height changed by `horizontal` and `vertical`
+    fn with_size_changed(caps: &gst::CapsRef, horizontal: i32, vertical: i32) -> gst::Caps {
+        let mut builder = gst::Caps::builder_full();
+
+        for s in caps.iter() {
+            let mut new_s = s.to_owned();
+
+            let width = s
+                .value("width")
+                .ok()
+                .and_then(|x| Self::adjust_size(x, horizontal));
+            let height = s
+                .value("height")
+                .ok()
+                .and_then(|x| Self::adjust_size(x, vertical));
+            let (Some(width), Some(height)) = (width, height) else {
+                continue;
+            };
+
+            new_s.set_value("width", width);
+            new_s.set_value("height", height);
+            builder = builder.structure(new_s);
+        }
+
+        builder.build()
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuCrop {
+    const NAME: &'static str = "GstWgpuCrop";
+    type Type = super::WgpuCrop;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuCrop {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let side = |name: &'static str, nick: &'static str, blurb: &'static str| {
+                glib::ParamSpecUInt::builder(name)
+                    .nick(nick)
+                    .blurb(blurb)
+                    .maximum(i32::MAX as u32)
+                    .mutable_playing()
+                    .build()
+            };
+
+            let mut properties = vec![
+                stats_param_spec(),
+                side("left", "Left", "Pixels to crop at left"),
+                side("right", "Right", "Pixels to crop at right"),
+                side("top", "Top", "Pixels to crop at top"),
+                side("bottom", "Bottom", "Pixels to crop at bottom"),
+            ];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        {
+            let mut settings = self.settings.lock();
+            let value = value.get().expect("type checked upstream");
+            match pspec.name() {
+                "left" => settings.left = value,
+                "right" => settings.right = value,
+                "top" => settings.top = value,
+                "bottom" => settings.bottom = value,
+                _ => {
+                    if !self.wgpu_context.set_property(value, pspec) {
+                        unimplemented!()
+                    }
+                }
+            }
+        }
+
+        // Output size depends on the crop
+        self.obj().reconfigure_src();
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            "left" => self.settings.lock().left.to_value(),
+            "right" => self.settings.lock().right.to_value(),
+            "top" => self.settings.lock().top.to_value(),
+            "bottom" => self.settings.lock().bottom.to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+impl GstObjectImpl for WgpuCrop {}
+impl ElementImpl for WgpuCrop {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU video crop",
+                "Filter/Effect/Video",
+                "Crops video in WGPU textures",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let def_ctx = WgpuContext::default();
+            let limits = def_ctx.limits();
+
+            let base_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(WgpuCrop::allowed_texture_formats_as_gst())
+                .height_range(1..limits.max_texture_dimension_2d as i32)
+                .width_range(1..limits.max_texture_dimension_2d as i32)
+                .build();
+
+            let sink_caps = gst_caps_with_texture_usages(&base_caps, WgpuCrop::sink_allowed_usages);
+            let src_caps = gst_caps_with_texture_usages(&base_caps, WgpuCrop::src_allowed_usages);
+
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &sink_caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+}
+
+impl BaseTransformImpl for WgpuCrop {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    // Nothing is cropped if the size stays the same
+    const PASSTHROUGH_ON_SAME_CAPS: bool = true;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
+
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(event)
+    }
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let settings = *self.settings.lock();
+
+        let other_caps = if direction == gst::PadDirection::Sink {
+            let cropped =
+                Self::with_size_changed(caps, -settings.horizontal(), -settings.vertical());
+            gst_caps_with_texture_usages(cropped, Self::src_allowed_usages)
+        } else {
+            let uncropped =
+                Self::with_size_changed(caps, settings.horizontal(), settings.vertical());
+            gst_caps_with_texture_usages(uncropped, Self::sink_allowed_usages)
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            filter
+        );
+
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
+    }
+
+    fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+        let sink_usages =
+            match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.texture_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+        if !sink_usages.contains(wgpu::TextureUsages::COPY_SRC) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in input caps cannot be used as copy source",
+                sink_usages
+            ));
+        }
+
+        let src_usages =
+            match WgpuCapsInfo::from_caps(outcaps).and_then(|info| info.texture_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in output caps: {}",
+                        err
+                    ));
+                }
+            };
+        if !src_usages.contains(wgpu::TextureUsages::COPY_DST) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in output caps cannot be used as copy destination",
+                src_usages
+            ));
+        }
+
+        self.parent_set_caps(incaps, outcaps)
+    }
+
+    fn transform(
+        &self,
+        inbuf: &gst::Buffer,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        assert!(0 < inbuf.n_memory());
+        assert!(0 < outbuf.n_memory());
+
+        let inmem = inbuf.peek_memory(0);
+        let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid input memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let outmem = outbuf.peek_memory(0);
+        let Some(outmem) = outmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid output memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let obj = self.obj();
+
+        // The properties crop the visible part of the frame
+        let settings = *self.settings.lock();
+        let src = inmem.texture();
+        let visible = buffer_crop_rect(inbuf, src.width(), src.height());
+        let dst = outmem.texture();
+        let origin = wgpu::Origin3d {
+            x: visible.x + settings.left,
+            y: visible.y + settings.top,
+            z: 0,
+        };
+        let size = wgpu::Extent3d {
+            width: dst.width().min(visible.width.saturating_sub(settings.left)),
+            height: dst
+                .height()
+                .min(visible.height.saturating_sub(settings.top)),
+            depth_or_array_layers: 1,
+        };
+        let empty = size.width == 0 || size.height == 0;
+        if empty {
+            gst::warning!(CAT, imp: self, "nothing is left after crop of {:?}", visible);
+        }
+
+        let ctx = self.wgpu_context.locked();
+        let mut profiler_lock = self.profiler.lock();
+        let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+        let cleared = ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+            ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                // The output keeps nothing of older frames around the copied part
+                let copied = CropRect::full(size.width, size.height);
+                let cleared = clear_around_crop(&ctx, encoder, dst, copied);
+                if empty {
+                    return cleared;
+                }
+
+                encoder.copy_texture_to_texture(
+                    wgpu::TexelCopyTextureInfo {
+                        texture: src,
+                        aspect: wgpu::TextureAspect::All,
+                        mip_level: 0,
+                        origin,
+                    },
+                    wgpu::TexelCopyTextureInfo {
+                        texture: dst,
+                        aspect: wgpu::TextureAspect::All,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                    },
+                    size,
+                );
+                cleared
+            })
+        });
+        if !cleared {
+            gst::warning!(CAT, imp: self, "cannot clear the texture around the crop");
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn unit_size(&self, caps: &gst::Caps) -> Option<usize> {
+        let video_caps = gst_video::VideoInfo::from_caps(&caps).ok()?;
+        Some(video_caps.size())
+    }
+
+    fn propose_allocation(
+        &self,
+        decide_query: Option<&gst::query::Allocation>,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        self.parent_propose_allocation(decide_query, query)?;
+
+        // Crop meta is applied by the copy, in passthrough it would reach the peer unapplied
+        if !self.obj().is_passthrough() {
+            query.add_allocation_meta::<gst_video::VideoCropMeta>(None);
+        }
+
+        Ok(())
+    }
+
+    fn decide_allocation(
+        &self,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let Some(ctx) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before start"
+            ));
+        };
+
+        decide_texture_allocation(
+            self.obj().upcast_ref(),
+            &ctx,
+            query,
+            wgpu::TextureUsages::COPY_DST,
+            None,
+        )
+    }
+}
+
+impl VideoFilterImpl for WgpuCrop {}
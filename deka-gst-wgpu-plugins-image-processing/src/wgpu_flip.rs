@@ -0,0 +1,23 @@
+mod imp;
+mod video_direction;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Plugin that rotates, mirrors and transposes WGPU textures, same as `videoflip`
+    ///  gst-launch-1.0 videotestsrc ! dekawgpubufferupload ! dekawgputextureupload ! dekawgpuflip video-direction=90r ! dekawgputexturedownload ! autovideosink
+    ///
+    /// Implements `GstVideoDirection`, with `video-direction=auto` the method follows `image-orientation` tags.
+    pub struct WgpuFlip(ObjectSubclass<imp::WgpuFlip>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object, @implements video_direction::VideoDirection;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpuflip",
+        gst::Rank::NONE,
+        WgpuFlip::static_type(),
+    )
+}
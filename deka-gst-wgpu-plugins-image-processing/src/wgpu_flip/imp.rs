@@ -0,0 +1,688 @@
+use std::sync::LazyLock;
+
+use deka_gst_wgpu::{
+    batcher::flush_on_eos,
+    caps::{fixate_wgpu_usages, transform::gst_caps_with_texture_usages, WgpuCapsInfo},
+    element::{decide_texture_allocation, filter_caps, ElementContext},
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_cache::ResourceOwner,
+    texture_memory::{WgpuTextureMemory, WgpuTextureMemoryExt},
+    WgpuContext, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+        translate::IntoGlib,
+    },
+    subclass::prelude::*,
+};
+use gst_base::subclass::{prelude::*, BaseTransformMode};
+use gst_video::{prelude::*, subclass::prelude::*, VideoOrientationMethod};
+use parking_lot::Mutex;
+use wgpu::util::DeviceExt;
+
+use super::video_direction::VideoDirection;
+use crate::glib;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpuflip",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU video flipper"),
+    )
+});
+
+/// Methods the shader implements, in order of `GstVideoOrientationMethod` values
+const SHADER_METHODS: [VideoOrientationMethod; 8] = [
+    VideoOrientationMethod::Identity,
+    VideoOrientationMethod::_90r,
+    VideoOrientationMethod::_180,
+    VideoOrientationMethod::_90l,
+    VideoOrientationMethod::Horiz,
+    VideoOrientationMethod::Vert,
+    VideoOrientationMethod::UlLr,
+    VideoOrientationMethod::UrLl,
+];
+
+/// Whether `method` exchanges width and height of the frame
+fn swaps_dimensions(method: VideoOrientationMethod) -> bool {
+    matches!(
+        method,
+        VideoOrientationMethod::_90r
+            | VideoOrientationMethod::_90l
+            | VideoOrientationMethod::UlLr
+            | VideoOrientationMethod::UrLl
+    )
+}
+
+/// Method which undoes `image-orientation` tag value, as `videoflip` maps them
+fn method_from_tag(orientation: &str) -> Option<VideoOrientationMethod> {
+    Some(match orientation {
+        "rotate-0" => VideoOrientationMethod::Identity,
+        "rotate-90" => VideoOrientationMethod::_90r,
+        "rotate-180" => VideoOrientationMethod::_180,
+        "rotate-270" => VideoOrientationMethod::_90l,
+        "flip-rotate-0" => VideoOrientationMethod::Horiz,
+        "flip-rotate-90" => VideoOrientationMethod::UlLr,
+        "flip-rotate-180" => VideoOrientationMethod::Vert,
+        "flip-rotate-270" => VideoOrientationMethod::UrLl,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    direction: VideoOrientationMethod,
+    /// Method from the last `image-orientation` tag, used with `auto` direction
+    tag_method: VideoOrientationMethod,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            direction: VideoOrientationMethod::Identity,
+            tag_method: VideoOrientationMethod::Identity,
+        }
+    }
+}
+
+impl Settings {
+    /// Method applied to the frames
+    fn active_method(&self) -> VideoOrientationMethod {
+        match self.direction {
+            VideoOrientationMethod::Auto => self.tag_method,
+            x if SHADER_METHODS.contains(&x) => x,
+            _ => VideoOrientationMethod::Identity,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FlipState {
+    /// Method the caps were negotiated for
+    method: VideoOrientationMethod,
+    same_caps: bool,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Uniform buffers for every method of [`SHADER_METHODS`]
+    ///
+    /// Commands are batched, so the method cannot be rewritten in one buffer while earlier frames are not submitted.
+    params_buffers: Vec<wgpu::Buffer>,
+    /// Owner of the objects above for the bind group caches of the textures
+    resources: ResourceOwner,
+}
+
+#[derive(Debug)]
+pub struct WgpuFlip {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<Settings>,
+    state: Mutex<Option<FlipState>>,
+}
+
+impl WgpuFlip {
+    fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader loads texels of the texture
+        [
+            wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader stores texels into the texture
+        [
+            wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn allowed_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+        [gst_video::VideoFormat::Rgba, gst_video::VideoFormat::Rgbx]
+    }
+
+    /// Copy of caps with width and height exchanged, pixel aspect ratio is inverted too
+    fn with_swapped_size(caps: &gst::CapsRef) -> gst::Caps {
+        let mut builder = gst::Caps::builder_full();
+
+        for s in caps.iter() {
+            let mut new_s = s.to_owned();
+            if let (Ok(width), Ok(height)) = (s.value("width"), s.value("height")) {
+                new_s.set_value("width", height.clone());
+                new_s.set_value("height", width.clone());
+            }
+            if let Ok(par) = s.get::<gst::Fraction>("pixel-aspect-ratio") {
+                new_s.set(
+                    "pixel-aspect-ratio",
+                    gst::Fraction::new(par.denom(), par.numer()),
+                );
+            }
+            builder = builder.structure(new_s);
+        }
+
+        builder.build()
+    }
+
+    /// Applies the changed method: toggles passthrough and renegotiates if the size may change
+    fn method_changed(&self) {
+        let method = self.settings.lock().active_method();
+        let same_caps = self.state.lock().as_ref().is_some_and(|x| x.same_caps);
+        gst::debug!(CAT, imp: self, "active method is {:?}", method);
+
+        let obj = self.obj();
+        obj.set_passthrough(method == VideoOrientationMethod::Identity && same_caps);
+        obj.reconfigure_src();
+    }
+
+    /// Remembers `image-orientation` of tag event, returns event to forward
+    ///
+    /// With `auto` direction the frames are turned upright, so the tag is removed from the forwarded event.
+    fn handle_tags(&self, event: gst::Event) -> gst::Event {
+        let gst::EventView::Tag(tag_event) = event.view() else {
+            return event;
+        };
+
+        let tags = tag_event.tag();
+        let Some(orientation) = tags.get::<gst::tags::ImageOrientation>() else {
+            return event;
+        };
+        let Some(method) = method_from_tag(orientation.get()) else {
+            gst::warning!(CAT, imp: self, "unsupported image orientation {}", orientation.get());
+            return event;
+        };
+
+        let auto = {
+            let mut settings = self.settings.lock();
+            settings.tag_method = method;
+            settings.direction == VideoOrientationMethod::Auto
+        };
+        if !auto {
+            return event;
+        }
+
+        gst::info!(CAT, imp: self, "image orientation {} changes method to {:?}", orientation.get(), method);
+        self.method_changed();
+
+        let mut tags = tags.to_owned();
+        tags.make_mut().remove::<gst::tags::ImageOrientation>();
+        gst::event::Tag::builder(tags)
+            .seqnum(event.seqnum())
+            .build()
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuFlip {
+    const NAME: &'static str = "GstWgpuFlip";
+    type Type = super::WgpuFlip;
+    type ParentType = gst_video::VideoFilter;
+    type Interfaces = (VideoDirection,);
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuFlip {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![
+                stats_param_spec(),
+                glib::ParamSpecOverride::for_interface::<VideoDirection>("video-direction"),
+            ];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "video-direction" => {
+                self.settings.lock().direction = value.get().expect("type checked upstream");
+                self.method_changed();
+            }
+            _ => {
+                if !self.wgpu_context.set_property(value, pspec) {
+                    unimplemented!()
+                }
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            "video-direction" => self.settings.lock().direction.to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+impl GstObjectImpl for WgpuFlip {}
+impl ElementImpl for WgpuFlip {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU video flipper",
+                "Filter/Effect/Video",
+                "Rotates, mirrors and transposes video in WGPU textures",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let def_ctx = WgpuContext::default();
+            let limits = def_ctx.limits();
+
+            let base_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(WgpuFlip::allowed_texture_formats_as_gst())
+                .height_range(1..limits.max_texture_dimension_2d as i32)
+                .width_range(1..limits.max_texture_dimension_2d as i32)
+                .build();
+
+            let sink_caps = gst_caps_with_texture_usages(&base_caps, WgpuFlip::sink_allowed_usages);
+            let src_caps = gst_caps_with_texture_usages(&base_caps, WgpuFlip::src_allowed_usages);
+
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &sink_caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+}
+
+impl BaseTransformImpl for WgpuFlip {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    // Frames with the same caps are still flipped, passthrough is enabled for identity only
+    const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock() = None;
+        self.settings.lock().tag_method = VideoOrientationMethod::Identity;
+        Ok(())
+    }
+
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        let event = self.handle_tags(event);
+        self.parent_sink_event(event)
+    }
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        // Rotation by 90 degrees is its own inverse in terms of size, so both directions swap the same way
+        let method = self.settings.lock().active_method();
+        let sized = if swaps_dimensions(method) {
+            Self::with_swapped_size(caps)
+        } else {
+            caps.clone()
+        };
+
+        let other_caps = if direction == gst::PadDirection::Sink {
+            gst_caps_with_texture_usages(sized, Self::src_allowed_usages)
+        } else {
+            gst_caps_with_texture_usages(sized, Self::sink_allowed_usages)
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?} for {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            method,
+            filter
+        );
+
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
+    }
+
+    fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+        let sink_usages =
+            match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.texture_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+        if !sink_usages.contains(wgpu::TextureUsages::TEXTURE_BINDING) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in input caps cannot be bound",
+                sink_usages
+            ));
+        }
+
+        let src_info = match WgpuCapsInfo::from_caps(outcaps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot read output caps: {}",
+                    err
+                ));
+            }
+        };
+        let src_usages = match src_info.texture_usages() {
+            Ok(usage) => usage,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot get texture usage in output caps: {}",
+                    err
+                ));
+            }
+        };
+        if !src_usages.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in output caps cannot be used as storage",
+                src_usages
+            ));
+        }
+        if src_info
+            .texture_format
+            .is_some_and(|x| x != wgpu::TextureFormat::Rgba8Unorm)
+        {
+            return Err(gst::loggable_error!(
+                CAT,
+                "output texture format({:?}) cannot be used as storage",
+                src_info.texture_format
+            ));
+        }
+
+        self.parent_set_caps(incaps, outcaps)
+    }
+
+    fn transform(
+        &self,
+        inbuf: &gst::Buffer,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        assert!(0 < inbuf.n_memory());
+        assert!(0 < outbuf.n_memory());
+
+        let state_lock = self.state.lock();
+        let Some(state) = &*state_lock else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        // Until the renegotiation after a method change, only methods keeping the negotiated size can be applied
+        let method = self.settings.lock().active_method();
+        let method = if swaps_dimensions(method) == swaps_dimensions(state.method) {
+            method
+        } else {
+            state.method
+        };
+        let params_buffer = &state.params_buffers[method.into_glib() as usize];
+
+        let inmem = inbuf.peek_memory(0);
+        let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid input memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let outmem = outbuf.peek_memory(0);
+        let Some(outmem) = outmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid output memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        // Texels are copied as stored, so sRGB textures are viewed as linear
+        let in_view = inmem.create_view_as(inmem.texture().format().remove_srgb_suffix());
+        let out_view = outmem.create_view_as(wgpu::TextureFormat::Rgba8Unorm);
+
+        // Textures of the pools come again and again, their bind groups are cached
+        let bind_group = outmem.bind_group(
+            &state.bind_group_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&in_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&out_view),
+                },
+            ],
+            &[&state.resources, inmem.owner()],
+        );
+
+        let obj = self.obj();
+        let ctx = self.wgpu_context.locked();
+        let label = ctx.label(obj.upcast_ref(), "flip", inbuf.pts());
+        let out_texture = outmem.texture();
+        let workgroup_x = out_texture.width().div_ceil(8);
+        let workgroup_y = out_texture.height().div_ceil(8);
+
+        let mut profiler_lock = self.profiler.lock();
+        let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+        ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+            ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: label.as_deref(),
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(&state.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(workgroup_x, workgroup_y, 1);
+            });
+        });
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn unit_size(&self, caps: &gst::Caps) -> Option<usize> {
+        let video_caps = gst_video::VideoInfo::from_caps(&caps).ok()?;
+        Some(video_caps.size())
+    }
+
+    fn decide_allocation(
+        &self,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let Some(ctx) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before start"
+            ));
+        };
+
+        decide_texture_allocation(
+            self.obj().upcast_ref(),
+            &ctx,
+            query,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            Some(wgpu::TextureFormat::Rgba8Unorm),
+        )
+    }
+}
+
+impl VideoFilterImpl for WgpuFlip {
+    fn set_info(
+        &self,
+        incaps: &gst::Caps,
+        in_info: &gst_video::VideoInfo,
+        outcaps: &gst::Caps,
+        out_info: &gst_video::VideoInfo,
+    ) -> Result<(), gst::LoggableError> {
+        let method = self.settings.lock().active_method();
+        let (in_width, in_height) = if swaps_dimensions(method) {
+            (in_info.height(), in_info.width())
+        } else {
+            (in_info.width(), in_info.height())
+        };
+        if (in_width, in_height) != (out_info.width(), out_info.height()) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "output size {}x{} does not match {:?} of {}x{}",
+                out_info.width(),
+                out_info.height(),
+                method,
+                in_info.width(),
+                in_info.height()
+            ));
+        }
+
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
+        let device = wgpu_context.device();
+
+        // Shared with other flippers of the context, compiled once
+        let pipeline = wgpu_context.compute_pipeline(
+            wgpu::include_wgsl!("shader.wgsl"),
+            "flip",
+            Some(&[&[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ]]),
+        );
+
+        // Layout matches `Params` in `shader.wgsl`
+        let params_buffers = SHADER_METHODS
+            .iter()
+            .map(|method| {
+                let words = [
+                    in_info.width(),
+                    in_info.height(),
+                    method.into_glib() as u32,
+                    0,
+                ];
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: &words
+                        .iter()
+                        .flat_map(|x| x.to_le_bytes())
+                        .collect::<Vec<_>>(),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                })
+            })
+            .collect();
+
+        let same_caps = incaps == outcaps;
+        self.obj()
+            .set_passthrough(method == VideoOrientationMethod::Identity && same_caps);
+
+        gst::debug!(CAT, imp: self, "flipping with {:?}", method);
+
+        *self.state.lock() = Some(FlipState {
+            method,
+            same_caps,
+            bind_group_layout: pipeline.get_bind_group_layout(0),
+            pipeline,
+            params_buffers,
+            resources: ResourceOwner::new(),
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+// Rotates, mirrors or transposes the frame, every invocation writes one output texel
+//
+// Method values match `GstVideoOrientationMethod`.
+
+const METHOD_IDENTITY: u32 = 0u;
+const METHOD_90R: u32 = 1u;
+const METHOD_180: u32 = 2u;
+const METHOD_90L: u32 = 3u;
+const METHOD_HORIZ: u32 = 4u;
+const METHOD_VERT: u32 = 5u;
+const METHOD_UL_LR: u32 = 6u;
+const METHOD_UR_LL: u32 = 7u;
+
+struct Params {
+    in_size: vec2<u32>,
+    method: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var input: texture_2d<f32>;
+
+@group(0) @binding(2)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+// Position of the input texel shown at output position `pos`
+fn source_position(pos: vec2<u32>) -> vec2<u32> {
+    let last = params.in_size - vec2<u32>(1u);
+
+    switch params.method {
+        case METHOD_90R: {
+            return vec2<u32>(pos.y, last.y - pos.x);
+        }
+        case METHOD_180: {
+            return last - pos;
+        }
+        case METHOD_90L: {
+            return vec2<u32>(last.x - pos.y, pos.x);
+        }
+        case METHOD_HORIZ: {
+            return vec2<u32>(last.x - pos.x, pos.y);
+        }
+        case METHOD_VERT: {
+            return vec2<u32>(pos.x, last.y - pos.y);
+        }
+        case METHOD_UL_LR: {
+            return pos.yx;
+        }
+        case METHOD_UR_LL: {
+            return last.xy - pos.yx;
+        }
+        default: {
+            return pos;
+        }
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn flip(@builtin(global_invocation_id) id: vec3<u32>) {
+    let out_size = textureDimensions(output);
+    if id.x >= out_size.x || id.y >= out_size.y {
+        return;
+    }
+
+    textureStore(output, id.xy, textureLoad(input, source_position(id.xy), 0));
+}
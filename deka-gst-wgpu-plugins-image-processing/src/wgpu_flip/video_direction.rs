@@ -0,0 +1,19 @@
+//!
+//! Bindings of `GstVideoDirection` interface, which has no vfuncs, only the `video-direction` property
+//!
+
+use gst::glib;
+use gst_video::ffi;
+
+glib::wrapper! {
+    pub struct VideoDirection(Interface<ffi::GstVideoDirection, ffi::GstVideoDirectionInterface>);
+
+    match fn {
+        type_ => || ffi::gst_video_direction_get_type(),
+    }
+}
+
+unsafe impl<T: glib::subclass::types::ObjectSubclass> glib::subclass::types::IsImplementable<T>
+    for VideoDirection
+{
+}
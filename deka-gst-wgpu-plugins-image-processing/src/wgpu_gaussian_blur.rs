@@ -0,0 +1,23 @@
+mod imp;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Plugin that blurs WGPU textures with a Gaussian kernel, rows and columns are blurred in separate passes
+    ///  gst-launch-1.0 videotestsrc ! dekawgpubufferupload ! dekawgputextureupload ! dekawgpugaussianblur sigma=4.0 ! dekawgputexturedownload ! autovideosink
+    ///
+    /// The kernel covers `radius` pixels on each side, by default three `sigma`, up to 64. The edge pixels are
+    /// repeated outside of the frame.
+    pub struct WgpuGaussianBlur(ObjectSubclass<imp::WgpuGaussianBlur>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpugaussianblur",
+        gst::Rank::NONE,
+        WgpuGaussianBlur::static_type(),
+    )
+}
@@ -0,0 +1,66 @@
+// Separable Gaussian blur, the edge pixels are repeated outside of the frame
+
+struct Params {
+    size: vec2<u32>,
+    radius: u32,
+    _padding: u32,
+    // Normalized weights of offsets 0..=radius, uniform arrays have 16 bytes stride so they are packed by four
+    weights: array<vec4<f32>, 17>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var input: texture_2d<f32>;
+
+@group(0) @binding(2)
+var intermediate: texture_storage_2d<rgba16float, write>;
+
+@group(0) @binding(3)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+fn weight(offset: u32) -> f32 {
+    return params.weights[offset / 4u][offset % 4u];
+}
+
+fn blur(pos: vec2<i32>, step: vec2<i32>) -> vec4<f32> {
+    let last = vec2<i32>(params.size) - 1;
+
+    var sum = weight(0u) * textureLoad(input, pos, 0);
+    for (var i = 1; i <= i32(params.radius); i++) {
+        let before = textureLoad(input, clamp(pos - i * step, vec2<i32>(0), last), 0);
+        let after = textureLoad(input, clamp(pos + i * step, vec2<i32>(0), last), 0);
+        sum += weight(u32(i)) * (before + after);
+    }
+
+    return sum;
+}
+
+@compute @workgroup_size(8, 8)
+fn blurHorizontal(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+
+    textureStore(intermediate, id.xy, blur(vec2<i32>(id.xy), vec2<i32>(1, 0)));
+}
+
+@compute @workgroup_size(8, 8)
+fn blurVertical(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+
+    textureStore(intermediate, id.xy, blur(vec2<i32>(id.xy), vec2<i32>(0, 1)));
+}
+
+@compute @workgroup_size(8, 8)
+fn blurVerticalOutput(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= params.size) {
+        return;
+    }
+
+    let color = blur(vec2<i32>(id.xy), vec2<i32>(0, 1));
+    textureStore(output, id.xy, clamp(color, vec4<f32>(0.0), vec4<f32>(1.0)));
+}
@@ -0,0 +1,105 @@
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Tracer which enables GPU time profiling of WebGPU elements and logs the results
+    ///  GST_TRACERS=wgpuprofiler GST_DEBUG=wgpuprofiler:5 gst-launch-1.0 videotestsrc ! dekawgpubufferupload ! dekawgpusobelbuf ! dekawgpubufferdownload ! autovideosink
+    ///
+    /// Every frame is logged at DEBUG level, the summary per element is logged at INFO level when the tracer is destroyed.
+    pub struct WgpuProfilerTracer(ObjectSubclass<imp::WgpuProfilerTracer>) @extends gst::Tracer, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Tracer::register(
+        Some(plugin),
+        "wgpuprofiler",
+        WgpuProfilerTracer::static_type(),
+    )
+}
+
+mod imp {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, LazyLock};
+
+    use crate::glib;
+
+    use deka_gst_wgpu::profiler::{
+        add_profile_listener, remove_profile_listener, set_profiling_enabled, GpuStats,
+        ProfileListenerId,
+    };
+    use glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
+    use gst::prelude::GstObjectExt;
+    use gst::subclass::prelude::*;
+    use parking_lot::Mutex;
+
+    static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+        gst::DebugCategory::new(
+            "wgpuprofiler",
+            gst::DebugColorFlags::empty(),
+            Some("Deka's WebGPU GPU time profiler tracer"),
+        )
+    });
+
+    #[derive(Debug, Default)]
+    pub struct WgpuProfilerTracer {
+        listener: Mutex<Option<ProfileListenerId>>,
+        /// The latest statistics by element name
+        stats: Arc<Mutex<BTreeMap<String, GpuStats>>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for WgpuProfilerTracer {
+        const NAME: &'static str = "GstWgpuProfilerTracer";
+        type Type = super::WgpuProfilerTracer;
+        type ParentType = gst::Tracer;
+    }
+
+    impl ObjectImpl for WgpuProfilerTracer {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            let stats = Arc::clone(&self.stats);
+            let listener = add_profile_listener(move |element, sample, element_stats| {
+                gst::debug!(
+                    CAT,
+                    obj: element,
+                    "gpu-time={:?} queue-latency={:?} frames={}",
+                    sample.gpu_time,
+                    sample.queue_latency,
+                    element_stats.frames
+                );
+
+                stats
+                    .lock()
+                    .insert(element.name().to_string(), *element_stats);
+            });
+
+            *self.listener.lock() = Some(listener);
+            set_profiling_enabled(true);
+        }
+
+        fn dispose(&self) {
+            if let Some(listener) = self.listener.lock().take() {
+                remove_profile_listener(listener);
+            }
+            set_profiling_enabled(false);
+
+            for (name, stats) in self.stats.lock().iter() {
+                gst::info!(
+                    CAT,
+                    imp: self,
+                    "{}: frames={} gpu-time-avg={:?} gpu-time-max={:?} queue-latency-avg={:?}",
+                    name,
+                    stats.frames,
+                    stats.gpu_time_avg(),
+                    stats.gpu_time_max,
+                    stats.queue_latency_avg()
+                );
+            }
+        }
+    }
+
+    impl GstObjectImpl for WgpuProfilerTracer {}
+    impl TracerImpl for WgpuProfilerTracer {}
+}
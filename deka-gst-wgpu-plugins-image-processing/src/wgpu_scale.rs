@@ -0,0 +1,40 @@
+mod imp;
+mod params;
+
+use gst::glib;
+use gst::prelude::*;
+
+/// Filter used to resample the frame, values match `METHOD_*` constants in `shader.wgsl`
+#[derive(Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "GstWgpuScaleMethod")]
+pub enum ScaleMethod {
+    #[enum_value(name = "Nearest Neighbour", nick = "nearest-neighbour")]
+    Nearest = 0,
+    #[default]
+    #[enum_value(name = "Bilinear", nick = "bilinear")]
+    Bilinear = 1,
+    #[enum_value(name = "Bicubic (Catmull-Rom)", nick = "bicubic")]
+    Bicubic = 2,
+    #[enum_value(name = "Lanczos (3 lobes)", nick = "lanczos")]
+    Lanczos = 3,
+}
+
+glib::wrapper! {
+
+    /// Plugin that resizes WGPU textures
+    ///  gst-launch-1.0 videotestsrc ! video/x-raw,width=3840,height=2160 ! dekawgpubufferupload ! dekawgputextureupload ! dekawgpuscale method=lanczos ! video/x-raw(memory:WgpuTexture),width=640,height=480 ! dekawgputexturedownload ! autovideosink
+    ///
+    /// The filter is widened when downscaling, so every source pixel contributes to the output. Display aspect
+    /// ratio is kept with black borders unless `add-borders` is disabled.
+    pub struct WgpuScale(ObjectSubclass<imp::WgpuScale>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpuscale",
+        gst::Rank::NONE,
+        WgpuScale::static_type(),
+    )
+}
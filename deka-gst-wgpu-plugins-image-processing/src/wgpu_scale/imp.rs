@@ -0,0 +1,673 @@
+use std::sync::LazyLock;
+
+use deka_gst_wgpu::{
+    batcher::flush_on_eos,
+    caps::{fixate_wgpu_usages, transform::gst_caps_with_texture_usages, WgpuCapsInfo},
+    element::{decide_texture_allocation, filter_caps, ElementContext},
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_cache::ResourceOwner,
+    texture_memory::{WgpuTextureMemory, WgpuTextureMemoryExt},
+    WgpuContext, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+    },
+    subclass::prelude::*,
+};
+use gst_base::subclass::{prelude::*, BaseTransformMode};
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+use wgpu::util::DeviceExt;
+
+use super::params::ScaleParams;
+use super::ScaleMethod;
+use crate::glib;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpuscale",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU video scaler"),
+    )
+});
+
+const DEFAULT_ADD_BORDERS: bool = true;
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    method: ScaleMethod,
+    add_borders: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            method: ScaleMethod::default(),
+            add_borders: DEFAULT_ADD_BORDERS,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ScaleState {
+    params: ScaleParams,
+    horizontal: wgpu::ComputePipeline,
+    horizontal_layout: wgpu::BindGroupLayout,
+    vertical: wgpu::ComputePipeline,
+    vertical_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    /// Frame scaled horizontally, written by the first pass and read by the second
+    intermediate_view: wgpu::TextureView,
+    /// Owner of the objects above for the bind group caches of the textures
+    resources: ResourceOwner,
+}
+
+#[derive(Debug)]
+pub struct WgpuScale {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<Settings>,
+    state: Mutex<Option<ScaleState>>,
+}
+
+impl WgpuScale {
+    fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader loads texels of the texture
+        [
+            wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader stores texels into the texture
+        [
+            wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn allowed_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+        [gst_video::VideoFormat::Rgba, gst_video::VideoFormat::Rgbx]
+    }
+
+    /// Copy of caps with any size up to `max_size` and any pixel aspect ratio
+    fn with_any_size(caps: &gst::CapsRef, max_size: u32) -> gst::Caps {
+        let mut builder = gst::Caps::builder_full();
+
+        for s in caps.iter() {
+            let mut new_s = s.to_owned();
+            new_s.set("width", gst::IntRange::new(1, max_size as i32));
+            new_s.set("height", gst::IntRange::new(1, max_size as i32));
+            if new_s.has_field("pixel-aspect-ratio") {
+                new_s.set(
+                    "pixel-aspect-ratio",
+                    gst::FractionRange::new(
+                        gst::Fraction::new(1, i32::MAX),
+                        gst::Fraction::new(i32::MAX, 1),
+                    ),
+                );
+            }
+            builder = builder.structure(new_s);
+        }
+
+        builder.build()
+    }
+
+    /// Fixates size of `othercaps` so the display aspect ratio of fixed `caps` is kept, as `videoscale` does
+    ///
+    /// If both width and height are fixed already, the frame is fitted with borders.
+    fn fixate_size(caps: &gst::CapsRef, mut othercaps: gst::Caps) -> gst::Caps {
+        let Some(from) = caps.structure(0) else {
+            return othercaps;
+        };
+        let (Ok(from_width), Ok(from_height)) =
+            (from.get::<i32>("width"), from.get::<i32>("height"))
+        else {
+            return othercaps;
+        };
+        let from_par = from
+            .get::<gst::Fraction>("pixel-aspect-ratio")
+            .unwrap_or(gst::Fraction::new(1, 1));
+        let dar = from_width as f64 * from_par.numer() as f64
+            / (from_height as f64 * from_par.denom() as f64);
+
+        let othercaps_mut = othercaps.make_mut();
+        let Some(s) = othercaps_mut.structure_mut(0) else {
+            return othercaps;
+        };
+
+        if s.has_field("pixel-aspect-ratio") {
+            s.fixate_field_nearest_fraction("pixel-aspect-ratio", from_par);
+        }
+        let to_par = s
+            .get::<gst::Fraction>("pixel-aspect-ratio")
+            .unwrap_or(gst::Fraction::new(1, 1));
+        let to_par = to_par.numer() as f64 / to_par.denom() as f64;
+
+        match (s.get::<i32>("width").ok(), s.get::<i32>("height").ok()) {
+            (Some(_), Some(_)) => {}
+            (Some(width), None) => {
+                let height = (width as f64 * to_par / dar).round() as i32;
+                s.fixate_field_nearest_int("height", height);
+            }
+            (None, Some(height)) => {
+                let width = (height as f64 * dar / to_par).round() as i32;
+                s.fixate_field_nearest_int("width", width);
+            }
+            (None, None) => {
+                // Keep the height if possible, so only the pixel aspect ratio is compensated
+                s.fixate_field_nearest_int("height", from_height);
+                let height = s.get::<i32>("height").unwrap_or(from_height);
+                let width = (height as f64 * dar / to_par).round() as i32;
+                s.fixate_field_nearest_int("width", width);
+            }
+        }
+
+        othercaps
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuScale {
+    const NAME: &'static str = "GstWgpuScale";
+    type Type = super::WgpuScale;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(None),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuScale {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![
+                stats_param_spec(),
+                glib::ParamSpecEnum::builder_with_default("method", ScaleMethod::default())
+                    .nick("Method")
+                    .blurb("Filter used to resample the frame")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoolean::builder("add-borders")
+                    .nick("Add Borders")
+                    .blurb("Add black borders if necessary to keep the display aspect ratio")
+                    .default_value(DEFAULT_ADD_BORDERS)
+                    .mutable_ready()
+                    .build(),
+            ];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock();
+        match pspec.name() {
+            "method" => {
+                settings.method = value.get().expect("type checked upstream");
+            }
+            "add-borders" => {
+                settings.add_borders = value.get().expect("type checked upstream");
+            }
+            _ => {
+                if !self.wgpu_context.set_property(value, pspec) {
+                    unimplemented!()
+                }
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            "method" => self.settings.lock().method.to_value(),
+            "add-borders" => self.settings.lock().add_borders.to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+impl GstObjectImpl for WgpuScale {}
+impl ElementImpl for WgpuScale {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU video scaler",
+                "Filter/Converter/Video/Scaler",
+                "Resizes video in WGPU textures",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let def_ctx = WgpuContext::default();
+            let limits = def_ctx.limits();
+
+            let base_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(WgpuScale::allowed_texture_formats_as_gst())
+                .height_range(1..limits.max_texture_dimension_2d as i32)
+                .width_range(1..limits.max_texture_dimension_2d as i32)
+                .build();
+
+            let sink_caps =
+                gst_caps_with_texture_usages(&base_caps, WgpuScale::sink_allowed_usages);
+            let src_caps = gst_caps_with_texture_usages(&base_caps, WgpuScale::src_allowed_usages);
+
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &sink_caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+}
+
+impl BaseTransformImpl for WgpuScale {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    const PASSTHROUGH_ON_SAME_CAPS: bool = true;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock() = None;
+        Ok(())
+    }
+
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(event)
+    }
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let max_size = self
+            .wgpu_context
+            .lock()
+            .as_ref()
+            .map(|ctx| ctx.limits().max_texture_dimension_2d)
+            .unwrap_or(wgpu::Limits::default().max_texture_dimension_2d);
+        let any_size = Self::with_any_size(caps, max_size);
+
+        let other_caps = if direction == gst::PadDirection::Sink {
+            gst_caps_with_texture_usages(any_size, Self::src_allowed_usages)
+        } else {
+            gst_caps_with_texture_usages(any_size, Self::sink_allowed_usages)
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            filter
+        );
+
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        let othercaps = Self::fixate_size(caps, othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
+    }
+
+    fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+        let sink_usages =
+            match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.texture_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+        if !sink_usages.contains(wgpu::TextureUsages::TEXTURE_BINDING) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in input caps cannot be bound",
+                sink_usages
+            ));
+        }
+
+        let src_info = match WgpuCapsInfo::from_caps(outcaps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot read output caps: {}",
+                    err
+                ));
+            }
+        };
+        let src_usages = match src_info.texture_usages() {
+            Ok(usage) => usage,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot get texture usage in output caps: {}",
+                    err
+                ));
+            }
+        };
+        if !src_usages.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in output caps cannot be used as storage",
+                src_usages
+            ));
+        }
+        if src_info
+            .texture_format
+            .is_some_and(|x| x != wgpu::TextureFormat::Rgba8Unorm)
+        {
+            return Err(gst::loggable_error!(
+                CAT,
+                "output texture format({:?}) cannot be used as storage",
+                src_info.texture_format
+            ));
+        }
+
+        self.parent_set_caps(incaps, outcaps)
+    }
+
+    fn transform(
+        &self,
+        inbuf: &gst::Buffer,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        assert!(0 < inbuf.n_memory());
+        assert!(0 < outbuf.n_memory());
+
+        let state_lock = self.state.lock();
+        let Some(state) = &*state_lock else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let inmem = inbuf.peek_memory(0);
+        let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid input memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let outmem = outbuf.peek_memory(0);
+        let Some(outmem) = outmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid output memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        // sRGB frames are decoded on sampling, so they are filtered in linear light
+        let in_format = if state.params.encode_srgb {
+            inmem.texture().format().add_srgb_suffix()
+        } else {
+            inmem.texture().format().remove_srgb_suffix()
+        };
+        if !inmem.can_view_as(in_format) {
+            gst::error!(CAT, imp: self, "input texture cannot be viewed as {:?}", in_format);
+            return Err(gst::FlowError::NotNegotiated);
+        }
+        let in_view = inmem.create_view_as(in_format);
+        let out_view = outmem.create_view_as(wgpu::TextureFormat::Rgba8Unorm);
+
+        // Textures of the pools come again and again, their bind groups are cached
+        let horizontal_bind_group = inmem.bind_group(
+            &state.horizontal_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: state.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&in_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&state.intermediate_view),
+                },
+            ],
+            &[&state.resources],
+        );
+        let vertical_bind_group = outmem.bind_group(
+            &state.vertical_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: state.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&state.intermediate_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&out_view),
+                },
+            ],
+            &[&state.resources],
+        );
+
+        let obj = self.obj();
+        let ctx = self.wgpu_context.locked();
+        let label = ctx.label(obj.upcast_ref(), "scale", inbuf.pts());
+        let (horizontal_x, horizontal_y) = state.params.horizontal_workgroups();
+        let (vertical_x, vertical_y) = state.params.vertical_workgroups();
+
+        let mut profiler_lock = self.profiler.lock();
+        let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+        ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+            ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: label.as_deref(),
+                    timestamp_writes: None,
+                });
+
+                pass.set_pipeline(&state.horizontal);
+                pass.set_bind_group(0, &horizontal_bind_group, &[]);
+                pass.dispatch_workgroups(horizontal_x, horizontal_y, 1);
+
+                pass.set_pipeline(&state.vertical);
+                pass.set_bind_group(0, &vertical_bind_group, &[]);
+                pass.dispatch_workgroups(vertical_x, vertical_y, 1);
+            });
+        });
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn unit_size(&self, caps: &gst::Caps) -> Option<usize> {
+        let video_caps = gst_video::VideoInfo::from_caps(&caps).ok()?;
+        Some(video_caps.size())
+    }
+
+    fn decide_allocation(
+        &self,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let Some(ctx) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before start"
+            ));
+        };
+
+        decide_texture_allocation(
+            self.obj().upcast_ref(),
+            &ctx,
+            query,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            Some(wgpu::TextureFormat::Rgba8Unorm),
+        )
+    }
+}
+
+impl VideoFilterImpl for WgpuScale {
+    fn set_info(
+        &self,
+        incaps: &gst::Caps,
+        in_info: &gst_video::VideoInfo,
+        _outcaps: &gst::Caps,
+        out_info: &gst_video::VideoInfo,
+    ) -> Result<(), gst::LoggableError> {
+        let settings = *self.settings.lock();
+        let srgb_input = WgpuCapsInfo::from_caps(incaps)
+            .ok()
+            .and_then(|info| info.texture_format)
+            .is_some_and(|x| x.is_srgb());
+        let params = ScaleParams::new(
+            in_info,
+            out_info,
+            settings.method,
+            settings.add_borders,
+            srgb_input,
+        );
+
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
+        let device = wgpu_context.device();
+
+        let uniform = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let input = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let storage = |binding, format| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+
+        // Shared with other scalers of the context, compiled once
+        let horizontal = wgpu_context.compute_pipeline(
+            wgpu::include_wgsl!("shader.wgsl"),
+            "scaleHorizontal",
+            Some(&[&[uniform, input, storage(2, wgpu::TextureFormat::Rgba16Float)]]),
+        );
+        let vertical = wgpu_context.compute_pipeline(
+            wgpu::include_wgsl!("shader.wgsl"),
+            "scaleVertical",
+            Some(&[&[uniform, input, storage(3, wgpu::TextureFormat::Rgba8Unorm)]]),
+        );
+
+        let intermediate = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: params.intermediate_size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &params.to_bytes(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        gst::debug!(CAT, imp: self, "scaling with {:?}", params);
+
+        *self.state.lock() = Some(ScaleState {
+            params,
+            horizontal_layout: horizontal.get_bind_group_layout(0),
+            horizontal,
+            vertical_layout: vertical.get_bind_group_layout(0),
+            vertical,
+            params_buffer,
+            intermediate_view: intermediate.create_view(&Default::default()),
+            resources: ResourceOwner::new(),
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//!
+//! Uniform parameters of the scaling shader, the layout must match `Params` in `shader.wgsl`
+//!
+
+use super::ScaleMethod;
+
+/// Pixel aspect ratio of the video info as a number
+fn par(info: &gst_video::VideoInfo) -> f64 {
+    let par = info.par();
+    par.numer() as f64 / par.denom() as f64
+}
+
+/// Rectangle of the output where the frame is drawn as `(origin, size)`
+///
+/// With borders the frame keeps its display aspect ratio and is centered, otherwise it is stretched over the output.
+fn destination_rect(
+    in_info: &gst_video::VideoInfo,
+    out_info: &gst_video::VideoInfo,
+    add_borders: bool,
+) -> ([u32; 2], [u32; 2]) {
+    let (out_width, out_height) = (out_info.width(), out_info.height());
+    if !add_borders {
+        return ([0, 0], [out_width, out_height]);
+    }
+
+    let dar = in_info.width() as f64 * par(in_info) / in_info.height() as f64;
+    let out_par = par(out_info);
+
+    let mut width = out_width;
+    let mut height = (out_width as f64 * out_par / dar).round() as u32;
+    if out_height < height {
+        height = out_height;
+        width = (out_height as f64 * dar / out_par).round() as u32;
+    }
+
+    let width = width.clamp(1, out_width);
+    let height = height.clamp(1, out_height);
+
+    (
+        [(out_width - width) / 2, (out_height - height) / 2],
+        [width, height],
+    )
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScaleParams {
+    pub in_size: [u32; 2],
+    pub out_size: [u32; 2],
+    pub rect_origin: [u32; 2],
+    pub rect_size: [u32; 2],
+    pub method: ScaleMethod,
+    /// The input is sampled as linear light, so the result is encoded back to sRGB before it is stored
+    pub encode_srgb: bool,
+}
+
+impl ScaleParams {
+    pub fn new(
+        in_info: &gst_video::VideoInfo,
+        out_info: &gst_video::VideoInfo,
+        method: ScaleMethod,
+        add_borders: bool,
+        encode_srgb: bool,
+    ) -> Self {
+        let (rect_origin, rect_size) = destination_rect(in_info, out_info, add_borders);
+
+        Self {
+            in_size: [in_info.width(), in_info.height()],
+            out_size: [out_info.width(), out_info.height()],
+            rect_origin,
+            rect_size,
+            method,
+            encode_srgb,
+        }
+    }
+
+    /// Size of the texture with the frame scaled horizontally
+    pub fn intermediate_size(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.out_size[0],
+            height: self.in_size[1],
+            depth_or_array_layers: 1,
+        }
+    }
+
+    /// Workgroups of the horizontal pass, which fills the intermediate texture
+    pub fn horizontal_workgroups(&self) -> (u32, u32) {
+        (self.out_size[0].div_ceil(8), self.in_size[1].div_ceil(8))
+    }
+
+    /// Workgroups of the vertical pass, which fills the output
+    pub fn vertical_workgroups(&self) -> (u32, u32) {
+        (self.out_size[0].div_ceil(8), self.out_size[1].div_ceil(8))
+    }
+
+    /// Bytes of the uniform buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let words = [
+            self.in_size[0],
+            self.in_size[1],
+            self.out_size[0],
+            self.out_size[1],
+            self.rect_origin[0],
+            self.rect_origin[1],
+            self.rect_size[0],
+            self.rect_size[1],
+            self.method as u32,
+            self.encode_srgb as u32,
+        ];
+
+        words.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+}
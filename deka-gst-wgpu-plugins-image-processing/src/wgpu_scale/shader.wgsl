@@ -0,0 +1,172 @@
+// Resizes the frame in two separable passes: horizontal into the intermediate texture, then vertical into the output
+//
+// When downscaling the filter is stretched by the scale factor, so it averages all source pixels under the output one.
+// sRGB inputs are sampled through an sRGB view, so they are filtered in linear light and encoded back on store.
+
+const METHOD_NEAREST: u32 = 0u;
+const METHOD_BILINEAR: u32 = 1u;
+const METHOD_BICUBIC: u32 = 2u;
+const METHOD_LANCZOS: u32 = 3u;
+
+// Bounds the cost of strong downscaling, the filter is truncated beyond it
+const MAX_TAPS: f32 = 64.0;
+const PI: f32 = 3.14159265358979;
+
+struct Params {
+    in_size: vec2<u32>,
+    out_size: vec2<u32>,
+    // Rectangle of the output with the frame, the rest is the border
+    rect_origin: vec2<u32>,
+    rect_size: vec2<u32>,
+    method: u32,
+    encode_srgb: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var input: texture_2d<f32>;
+
+@group(0) @binding(2)
+var intermediate: texture_storage_2d<rgba16float, write>;
+
+@group(0) @binding(3)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+fn kernel_support() -> f32 {
+    switch params.method {
+        case METHOD_BILINEAR: {
+            return 1.0;
+        }
+        case METHOD_BICUBIC: {
+            return 2.0;
+        }
+        case METHOD_LANCZOS: {
+            return 3.0;
+        }
+        default: {
+            return 0.5;
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if abs(x) < 1e-5 {
+        return 1.0;
+    }
+    return sin(PI * x) / (PI * x);
+}
+
+fn kernel(x: f32) -> f32 {
+    let t = abs(x);
+    switch params.method {
+        case METHOD_BICUBIC: {
+            // Catmull-Rom, a = -0.5
+            if t < 1.0 {
+                return (1.5 * t - 2.5) * t * t + 1.0;
+            }
+            if t < 2.0 {
+                return ((-0.5 * t + 2.5) * t - 4.0) * t + 2.0;
+            }
+            return 0.0;
+        }
+        case METHOD_LANCZOS: {
+            if t < 3.0 {
+                return sinc(t) * sinc(t / 3.0);
+            }
+            return 0.0;
+        }
+        default: {
+            return max(1.0 - t, 0.0);
+        }
+    }
+}
+
+fn srgb_encode(color: vec4<f32>) -> vec4<f32> {
+    let rgb = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = rgb * 12.92;
+    let high = 1.055 * pow(rgb, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return vec4<f32>(select(high, low, rgb <= vec3<f32>(0.0031308)), color.a);
+}
+
+// Filters the input along `axis` for output position `pos` of the destination rectangle `[origin, origin + len)`
+//
+// `base` is the texel position on the other axis.
+fn resample(base: vec2<i32>, axis: vec2<i32>, pos: u32, origin: u32, len: u32, in_len: u32) -> vec4<f32> {
+    let ratio = f32(in_len) / f32(len);
+    let center = (f32(pos - origin) + 0.5) * ratio - 0.5;
+    let last = i32(in_len) - 1;
+
+    let nearest = textureLoad(input, base + axis * clamp(i32(floor(center + 0.5)), 0, last), 0);
+    if params.method == METHOD_NEAREST {
+        return nearest;
+    }
+
+    let scale = max(ratio, 1.0);
+    let support = min(kernel_support() * scale, MAX_TAPS * 0.5);
+    let first = i32(ceil(center - support));
+    let end = i32(floor(center + support));
+
+    var sum = vec4<f32>(0.0);
+    var weights = 0.0;
+    for (var i = first; i <= end; i++) {
+        let weight = kernel((f32(i) - center) / scale);
+        sum += weight * textureLoad(input, base + axis * clamp(i, 0, last), 0);
+        weights += weight;
+    }
+
+    if abs(weights) < 1e-5 {
+        return nearest;
+    }
+    return sum / weights;
+}
+
+@compute @workgroup_size(8, 8)
+fn scaleHorizontal(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.out_size.x || id.y >= params.in_size.y {
+        return;
+    }
+
+    // Columns of the border are not read by the vertical pass
+    if id.x < params.rect_origin.x || params.rect_origin.x + params.rect_size.x <= id.x {
+        return;
+    }
+
+    let color = resample(
+        vec2<i32>(0, i32(id.y)),
+        vec2<i32>(1, 0),
+        id.x,
+        params.rect_origin.x,
+        params.rect_size.x,
+        params.in_size.x
+    );
+    textureStore(intermediate, id.xy, color);
+}
+
+@compute @workgroup_size(8, 8)
+fn scaleVertical(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.out_size.x || id.y >= params.out_size.y {
+        return;
+    }
+
+    let inside = (params.rect_origin <= id.xy) & (id.xy < params.rect_origin + params.rect_size);
+    if !all(inside) {
+        textureStore(output, id.xy, vec4<f32>(0.0, 0.0, 0.0, 1.0));
+        return;
+    }
+
+    let color = resample(
+        vec2<i32>(i32(id.x), 0),
+        vec2<i32>(0, 1),
+        id.y,
+        params.rect_origin.y,
+        params.rect_size.y,
+        params.in_size.y
+    );
+    var clamped = clamp(color, vec4<f32>(0.0), vec4<f32>(1.0));
+    if params.encode_srgb != 0u {
+        clamped = srgb_encode(clamped);
+    }
+    textureStore(output, id.xy, clamped);
+}
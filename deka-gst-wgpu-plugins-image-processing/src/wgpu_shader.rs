@@ -0,0 +1,34 @@
+mod imp;
+mod uniforms;
+
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Plugin that runs a user WGSL compute shader over WGPU textures
+    ///  gst-launch-1.0 videotestsrc ! dekawgpubufferupload ! dekawgputextureupload ! dekawgpushader location=invert.wgsl uniforms="u,strength=0.5" ! dekawgputexturedownload ! autovideosink
+    ///
+    /// The shader is taken from `shader` or read from `location`, the entry point is `entry-point`. It is dispatched in
+    /// 8x8 workgroups over the frame, so it must be declared with `@workgroup_size(8, 8)` and skip invocations outside
+    /// the resolution. Bindings of group 0:
+    /// - `@binding(0) var input: texture_2d<f32>` is the input frame, read by `textureLoad`
+    /// - `@binding(1) var output: texture_storage_2d<rgba8unorm, write>` has the same size as the input
+    /// - `@binding(2) var<uniform> frame: Frame` with `resolution: vec2<u32>`, `time: f32` timestamp in seconds and
+    ///   `index: u32` of the frame
+    /// - `@binding(3) var<uniform> uniforms: Uniforms` with fields of the `uniforms` structure in order, each of them is
+    ///   an `i32`, `u32`, `f32` (for floats and doubles) or `u32` (for booleans) member
+    ///
+    /// Compile errors are posted as element errors with line numbers. Without a shader the frames are copied, see
+    /// `default.wgsl` for a template.
+    pub struct WgpuShader(ObjectSubclass<imp::WgpuShader>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgpushader",
+        gst::Rank::NONE,
+        WgpuShader::static_type(),
+    )
+}
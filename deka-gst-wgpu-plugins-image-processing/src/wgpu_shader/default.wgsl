@@ -0,0 +1,36 @@
+// Used when neither `shader` nor `location` is set, copies the input and shows the bindings of user shaders
+
+struct Frame {
+    // Size of the input and the output
+    resolution: vec2<u32>,
+    // Timestamp of the frame in seconds
+    time: f32,
+    // Frames processed since the element started
+    index: u32,
+}
+
+// Fields of the `uniforms` structure in order, each is a 4 bytes scalar
+struct Uniforms {
+    _unused: u32,
+}
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+@group(0) @binding(2)
+var<uniform> frame: Frame;
+
+@group(0) @binding(3)
+var<uniform> uniforms: Uniforms;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= frame.resolution)) {
+        return;
+    }
+
+    textureStore(output, id.xy, textureLoad(input, id.xy, 0));
+}
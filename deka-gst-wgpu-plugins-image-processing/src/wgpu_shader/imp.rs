@@ -0,0 +1,692 @@
+use std::{num::NonZeroU64, sync::LazyLock};
+
+use deka_gst_wgpu::{
+    batcher::flush_on_eos,
+    caps::{fixate_wgpu_usages, transform::gst_caps_with_texture_usages, WgpuCapsInfo},
+    element::{decide_texture_allocation, filter_caps, ElementContext},
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    texture_memory::{WgpuTextureMemory, WgpuTextureMemoryExt},
+    WgpuContext, WgpuContextOptions,
+};
+use gst::{
+    glib::{
+        object::Cast,
+        subclass::{object::ObjectImpl, types::ObjectSubclass},
+    },
+    subclass::prelude::*,
+};
+use gst_base::subclass::{prelude::*, BaseTransformMode};
+use gst_video::{prelude::*, subclass::prelude::*};
+use parking_lot::Mutex;
+use wgpu::util::DeviceExt;
+
+use super::uniforms::{pack_user_uniforms, FrameUniforms, FRAME_UNIFORMS_SIZE};
+use crate::glib;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "dekawgpushader",
+        gst::DebugColorFlags::empty(),
+        Some("Deka's WebGPU user shader filter"),
+    )
+});
+
+const DEFAULT_ENTRY_POINT: &str = "main";
+/// Size of workgroups the shader must declare
+const WORKGROUP_SIZE: u32 = 8;
+
+#[derive(Debug)]
+struct Settings {
+    shader: Option<String>,
+    location: Option<String>,
+    entry_point: String,
+    uniforms: gst::Structure,
+    /// `uniforms` packed for the uniform buffer
+    uniform_bytes: Vec<u8>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let uniforms = gst::Structure::new_empty("uniforms");
+        let uniform_bytes = pack_user_uniforms(&uniforms).unwrap();
+
+        Self {
+            shader: None,
+            location: None,
+            entry_point: DEFAULT_ENTRY_POINT.to_owned(),
+            uniforms,
+            uniform_bytes,
+        }
+    }
+}
+
+impl Settings {
+    /// WGSL source of the shader, the default one copies frames
+    fn source(&self) -> Result<String, String> {
+        if let Some(shader) = &self.shader {
+            return Ok(shader.clone());
+        }
+
+        if let Some(location) = &self.location {
+            return std::fs::read_to_string(location)
+                .map_err(|err| format!("cannot read {}: {}", location, err));
+        }
+
+        Ok(include_str!("default.wgsl").to_owned())
+    }
+}
+
+#[derive(Debug)]
+struct ShaderState {
+    pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    /// Offset of the user uniforms in the uniform buffer, after the frame uniforms
+    uniforms_offset: u64,
+}
+
+#[derive(Debug)]
+pub struct WgpuShader {
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<Settings>,
+    state: Mutex<Option<ShaderState>>,
+    frame_index: Mutex<u32>,
+}
+
+impl WgpuShader {
+    fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader loads texels of the texture
+        [
+            wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+        // The shader stores texels into the texture
+        [
+            wgpu::TextureUsages::STORAGE_BINDING,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        ]
+    }
+
+    fn allowed_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+        [gst_video::VideoFormat::Rgba, gst_video::VideoFormat::Rgbx]
+    }
+
+    /// Compiles the user shader, errors of the source are listed with their lines
+    ///
+    /// User shaders are not put into the shader cache of the context, they are edited and recompiled often
+    /// and invalid ones must not be kept.
+    fn compile(ctx: &WgpuContext, settings: &Settings) -> Result<ShaderState, String> {
+        let source = settings.source()?;
+        let device = ctx.device();
+
+        // Validation errors are captured, otherwise they reach the uncaptured error handler
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: settings.location.as_deref(),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let info = pollster::block_on(module.get_compilation_info());
+        let module_error = pollster::block_on(device.pop_error_scope());
+
+        let messages = info
+            .messages
+            .iter()
+            .filter(|message| matches!(message.message_type, wgpu::CompilationMessageType::Error))
+            .map(|message| match &message.location {
+                Some(location) => format!(
+                    "line {}:{}: {}",
+                    location.line_number, location.line_position, message.message
+                ),
+                None => message.message.clone(),
+            })
+            .collect::<Vec<_>>();
+        if !messages.is_empty() {
+            return Err(messages.join("\n"));
+        }
+        if let Some(err) = module_error {
+            return Err(err.to_string());
+        }
+
+        let uniform = |binding, min_binding_size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size,
+            },
+            count: None,
+        };
+
+        // Mismatches between the shader and the documented layout are found here
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                uniform(2, NonZeroU64::new(FRAME_UNIFORMS_SIZE)),
+                uniform(3, None),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(&settings.entry_point),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: Some(&settings.entry_point),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            return Err(err.to_string());
+        }
+
+        let alignment = ctx.limits().min_uniform_buffer_offset_alignment as u64;
+
+        Ok(ShaderState {
+            pipeline,
+            layout,
+            uniforms_offset: FRAME_UNIFORMS_SIZE.next_multiple_of(alignment),
+        })
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for WgpuShader {
+    const NAME: &'static str = "GstWgpuShader";
+    type Type = super::WgpuShader;
+    type ParentType = gst_video::VideoFilter;
+
+    fn with_class(_klass: &Self::Class) -> Self {
+        Self {
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(Settings::default()),
+            state: Mutex::new(None),
+            frame_index: Mutex::new(0),
+        }
+    }
+}
+
+impl ObjectImpl for WgpuShader {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![
+                stats_param_spec(),
+                glib::ParamSpecString::builder("shader")
+                    .nick("Shader")
+                    .blurb("WGSL source of the compute shader, used instead of location")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("location")
+                    .nick("Location")
+                    .blurb("Path of the WGSL compute shader, read when the caps are negotiated")
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecString::builder("entry-point")
+                    .nick("Entry Point")
+                    .blurb("Compute entry point of the shader")
+                    .default_value(Some(DEFAULT_ENTRY_POINT))
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecBoxed::builder::<gst::Structure>("uniforms")
+                    .nick("Uniforms")
+                    .blurb("Values of the Uniforms struct of the shader, one scalar per field")
+                    .mutable_playing()
+                    .build(),
+            ];
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut settings = self.settings.lock();
+        match pspec.name() {
+            "shader" => {
+                settings.shader = value.get().expect("type checked upstream");
+            }
+            "location" => {
+                settings.location = value.get().expect("type checked upstream");
+            }
+            "entry-point" => {
+                settings.entry_point = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| DEFAULT_ENTRY_POINT.to_owned());
+            }
+            "uniforms" => {
+                let uniforms = value
+                    .get::<Option<gst::Structure>>()
+                    .expect("type checked upstream")
+                    .unwrap_or_else(|| gst::Structure::new_empty("uniforms"));
+
+                // Invalid uniforms keep the previous values, the shader still gets a buffer it expects
+                match pack_user_uniforms(&uniforms) {
+                    Ok(bytes) => {
+                        settings.uniforms = uniforms;
+                        settings.uniform_bytes = bytes;
+                    }
+                    Err(err) => {
+                        gst::error!(CAT, imp: self, "invalid uniforms {}: {}", uniforms, err);
+                    }
+                }
+            }
+            _ => {
+                if !self.wgpu_context.set_property(value, pspec) {
+                    unimplemented!()
+                }
+            }
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            "shader" => self.settings.lock().shader.to_value(),
+            "location" => self.settings.lock().location.to_value(),
+            "entry-point" => self.settings.lock().entry_point.to_value(),
+            "uniforms" => self.settings.lock().uniforms.to_value(),
+            _ => self
+                .wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
+impl GstObjectImpl for WgpuShader {}
+impl ElementImpl for WgpuShader {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "Deka's WebGPU user shader filter",
+                "Filter/Effect/Video",
+                "Runs a user WGSL compute shader over WGPU textures",
+                "Deka <speedcrash100@ya.ru>",
+            )
+        });
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let def_ctx = WgpuContext::default();
+            let limits = def_ctx.limits();
+
+            let base_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(WgpuShader::allowed_texture_formats_as_gst())
+                .height_range(1..limits.max_texture_dimension_2d as i32)
+                .width_range(1..limits.max_texture_dimension_2d as i32)
+                .build();
+
+            let sink_caps =
+                gst_caps_with_texture_usages(&base_caps, WgpuShader::sink_allowed_usages);
+            let src_caps = gst_caps_with_texture_usages(&base_caps, WgpuShader::src_allowed_usages);
+
+            vec![
+                gst::PadTemplate::new(
+                    "src",
+                    gst::PadDirection::Src,
+                    gst::PadPresence::Always,
+                    &src_caps,
+                )
+                .unwrap(),
+                gst::PadTemplate::new(
+                    "sink",
+                    gst::PadDirection::Sink,
+                    gst::PadPresence::Always,
+                    &sink_caps,
+                )
+                .unwrap(),
+            ]
+        });
+        PAD_TEMPLATES.as_ref()
+    }
+
+    fn set_context(&self, context: &gst::Context) {
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
+
+        self.parent_set_context(context);
+    }
+}
+
+impl BaseTransformImpl for WgpuShader {
+    const MODE: BaseTransformMode = BaseTransformMode::NeverInPlace;
+    // The shader runs even if the caps are the same
+    const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn start(&self) -> Result<(), gst::ErrorMessage> {
+        *self.frame_index.lock() = 0;
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock() = None;
+        Ok(())
+    }
+
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(event)
+    }
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let other_caps = if direction == gst::PadDirection::Sink {
+            gst_caps_with_texture_usages(caps, Self::src_allowed_usages)
+        } else {
+            gst_caps_with_texture_usages(caps, Self::sink_allowed_usages)
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            filter
+        );
+
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
+    }
+
+    fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
+        gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+        let sink_usages =
+            match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.texture_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
+        if !sink_usages.contains(wgpu::TextureUsages::TEXTURE_BINDING) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in input caps cannot be bound",
+                sink_usages
+            ));
+        }
+
+        let src_info = match WgpuCapsInfo::from_caps(outcaps) {
+            Ok(info) => info,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot read output caps: {}",
+                    err
+                ));
+            }
+        };
+        let src_usages = match src_info.texture_usages() {
+            Ok(usage) => usage,
+            Err(err) => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "cannot get texture usage in output caps: {}",
+                    err
+                ));
+            }
+        };
+        if !src_usages.contains(wgpu::TextureUsages::STORAGE_BINDING) {
+            return Err(gst::loggable_error!(
+                CAT,
+                "texture usage({:?}) in output caps cannot be used as storage",
+                src_usages
+            ));
+        }
+        if src_info
+            .texture_format
+            .is_some_and(|x| x != wgpu::TextureFormat::Rgba8Unorm)
+        {
+            return Err(gst::loggable_error!(
+                CAT,
+                "output texture format({:?}) cannot be used as storage",
+                src_info.texture_format
+            ));
+        }
+
+        self.parent_set_caps(incaps, outcaps)
+    }
+
+    fn transform(
+        &self,
+        inbuf: &gst::Buffer,
+        outbuf: &mut gst::BufferRef,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        assert!(0 < inbuf.n_memory());
+        assert!(0 < outbuf.n_memory());
+
+        let state_lock = self.state.lock();
+        let Some(state) = &*state_lock else {
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let inmem = inbuf.peek_memory(0);
+        let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid input memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        let outmem = outbuf.peek_memory(0);
+        let Some(outmem) = outmem.downcast_memory_ref::<WgpuTextureMemory>() else {
+            gst::error!(CAT, imp: self, "invalid output memory");
+            return Err(gst::FlowError::NotNegotiated);
+        };
+
+        // Shaders get the stored values, so sRGB textures are viewed as linear
+        let in_view = inmem.create_view_as(inmem.texture().format().remove_srgb_suffix());
+        let out_view = outmem.create_view_as(wgpu::TextureFormat::Rgba8Unorm);
+        let (width, height) = (outmem.texture().width(), outmem.texture().height());
+
+        let index = {
+            let mut frame_index = self.frame_index.lock();
+            let index = *frame_index;
+            *frame_index = frame_index.wrapping_add(1);
+            index
+        };
+        let frame = FrameUniforms {
+            resolution: [width, height],
+            time: inbuf
+                .pts()
+                .map(|pts| pts.nseconds() as f64 / gst::ClockTime::SECOND.nseconds() as f64)
+                .unwrap_or_default() as f32,
+            index,
+        };
+
+        // Uniforms change every frame, a buffer written by the queue would be overwritten before the
+        // batched commands of previous frames run
+        let uniform_bytes = self.settings.lock().uniform_bytes.clone();
+        let offset = state.uniforms_offset as usize;
+        let mut contents = vec![0; offset + uniform_bytes.len()];
+        contents[..FRAME_UNIFORMS_SIZE as usize].copy_from_slice(&frame.to_bytes());
+        contents[offset..].copy_from_slice(&uniform_bytes);
+
+        let obj = self.obj();
+        let ctx = self.wgpu_context.locked();
+        let device = ctx.device();
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &contents,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &state.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&in_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&out_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &uniform_buffer,
+                        offset: 0,
+                        size: NonZeroU64::new(FRAME_UNIFORMS_SIZE),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &uniform_buffer,
+                        offset: state.uniforms_offset,
+                        size: NonZeroU64::new(uniform_bytes.len() as u64),
+                    }),
+                },
+            ],
+        });
+
+        let label = ctx.label(obj.upcast_ref(), "shader", inbuf.pts());
+        let mut profiler_lock = self.profiler.lock();
+        let profiler = ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+        ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+            ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: label.as_deref(),
+                    timestamp_writes: None,
+                });
+
+                pass.set_pipeline(&state.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(
+                    width.div_ceil(WORKGROUP_SIZE),
+                    height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+            });
+        });
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+
+    fn unit_size(&self, caps: &gst::Caps) -> Option<usize> {
+        let video_caps = gst_video::VideoInfo::from_caps(&caps).ok()?;
+        Some(video_caps.size())
+    }
+
+    fn decide_allocation(
+        &self,
+        query: &mut gst::query::Allocation,
+    ) -> Result<(), gst::LoggableError> {
+        let Some(ctx) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "decide_allocation called before start"
+            ));
+        };
+
+        decide_texture_allocation(
+            self.obj().upcast_ref(),
+            &ctx,
+            query,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            Some(wgpu::TextureFormat::Rgba8Unorm),
+        )
+    }
+}
+
+impl VideoFilterImpl for WgpuShader {
+    fn set_info(
+        &self,
+        _incaps: &gst::Caps,
+        _in_info: &gst_video::VideoInfo,
+        _outcaps: &gst::Caps,
+        _out_info: &gst_video::VideoInfo,
+    ) -> Result<(), gst::LoggableError> {
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
+
+        let state = match Self::compile(&wgpu_context, &self.settings.lock()) {
+            Ok(state) => state,
+            Err(err) => {
+                gst::element_imp_error!(
+                    self,
+                    gst::LibraryError::Failed,
+                    ("Failed to compile the shader"),
+                    ["{}", err]
+                );
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "failed to compile the shader: {}",
+                    err
+                ));
+            }
+        };
+
+        *self.state.lock() = Some(state);
+
+        Ok(())
+    }
+}
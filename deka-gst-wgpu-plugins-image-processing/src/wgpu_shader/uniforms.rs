@@ -0,0 +1,67 @@
+//!
+//! Uniforms of user shaders, the layouts are documented on [`super::WgpuShader`]
+//!
+
+/// Bytes of the built-in `Frame` uniform
+pub const FRAME_UNIFORMS_SIZE: u64 = 16;
+
+/// Built-in uniforms, which change every frame
+#[derive(Debug, Clone, Copy)]
+pub struct FrameUniforms {
+    pub resolution: [u32; 2],
+    /// Timestamp of the frame in seconds
+    pub time: f32,
+    /// Frames processed since the element started
+    pub index: u32,
+}
+
+impl FrameUniforms {
+    /// Bytes of the uniform buffer
+    pub fn to_bytes(&self) -> [u8; FRAME_UNIFORMS_SIZE as usize] {
+        let words = [
+            self.resolution[0],
+            self.resolution[1],
+            self.time.to_bits(),
+            self.index,
+        ];
+
+        let mut out = [0; FRAME_UNIFORMS_SIZE as usize];
+        for (chunk, word) in out.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+}
+
+/// Packs fields of `uniforms` as 4 bytes scalars in the field order, padded to 16 bytes
+///
+/// Integers become `i32` or `u32`, floating point numbers `f32` and booleans `u32`. The shader declares a struct with
+/// the same members, so they need no alignment. Uniform buffers cannot be empty, so no fields give 16 zero bytes.
+pub fn pack_user_uniforms(uniforms: &gst::StructureRef) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(uniforms.n_fields() as usize * 4);
+
+    for (name, value) in uniforms.iter() {
+        let word = if let Ok(x) = value.get::<i32>() {
+            x.to_le_bytes()
+        } else if let Ok(x) = value.get::<u32>() {
+            x.to_le_bytes()
+        } else if let Ok(x) = value.get::<f32>() {
+            x.to_le_bytes()
+        } else if let Ok(x) = value.get::<f64>() {
+            (x as f32).to_le_bytes()
+        } else if let Ok(x) = value.get::<bool>() {
+            (x as u32).to_le_bytes()
+        } else {
+            return Err(format!(
+                "uniform {} has unsupported type {}",
+                name,
+                value.type_()
+            ));
+        };
+
+        out.extend_from_slice(&word);
+    }
+
+    out.resize(out.len().next_multiple_of(16).max(16), 0);
+    Ok(out)
+}
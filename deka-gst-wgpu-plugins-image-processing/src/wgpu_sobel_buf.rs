@@ -5,7 +5,9 @@ use gst::prelude::*;
 
 glib::wrapper! {
 
-    /// Plugin that apply Sobel kernel to image
+    /// Plugin that apply Sobel operator to image
+    ///
+    /// Writes either gradient, their magnitude or direction as RGBx/RGBA, GRAY8 or R32Float frames, see [`crate::sobel`]
     pub struct WgpuSobelBuf(ObjectSubclass<imp::WgpuSobelBuf>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
 }
 
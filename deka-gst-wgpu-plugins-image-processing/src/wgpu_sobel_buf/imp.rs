@@ -1,23 +1,29 @@
 use std::sync::LazyLock;
 
 use deka_gst_wgpu::{
-    buffer_memory::GST_CAPS_FIELD_WGPU_BUFFER_USAGE, caps::make_wgpu_buffer_usages_for_caps,
-    prelude::WgpuBufferMemoryExt, WgpuBufferMemory, WgpuBufferMemoryAllocator, WgpuContext,
-    GST_CONTEXT_WGPU_TYPE,
+    batcher::flush_on_eos,
+    caps::{fixate_wgpu_usages, make_wgpu_buffer_usages_for_caps, WgpuCapsInfo},
+    crop::FrameLayout,
+    element::{filter_caps, ElementContext},
+    prelude::WgpuBufferMemoryExt,
+    profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY},
+    WgpuBufferMemory, WgpuBufferMemoryAllocator, WgpuContextOptions,
 };
 use gst::{
     glib::{
         object::Cast,
         subclass::{object::ObjectImpl, types::ObjectSubclass},
     },
-    prelude::ElementExt,
     subclass::prelude::*,
 };
 use gst_base::subclass::{prelude::*, BaseTransformMode};
 use gst_video::{prelude::*, subclass::prelude::*};
 use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
 
-use crate::glib;
+use crate::{
+    glib,
+    sobel::{self, SobelOutput, SobelPipeline, SobelSettings},
+};
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -27,52 +33,16 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     )
 });
 
-#[derive(Debug)]
-struct WebGPUState {
-    input_texture: wgpu::Texture,
-    output_texture: wgpu::Texture,
-    bind_group: wgpu::BindGroup,
-    pipeline: wgpu::ComputePipeline,
-}
-
 #[derive(Debug)]
 pub struct WgpuSobelBuf {
-    wgpu_context: Mutex<Option<WgpuContext>>,
-    pipeline: Mutex<Option<WebGPUState>>,
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<SobelSettings>,
+    pipeline: Mutex<Option<SobelPipeline>>,
     usages: Mutex<(wgpu::BufferUsages, wgpu::BufferUsages)>,
 }
 
 impl WgpuSobelBuf {
-    pub fn set_wgpu_context(&self, context: WgpuContext) {
-        let mut lock: parking_lot::lock_api::MutexGuard<
-            '_,
-            parking_lot::RawMutex,
-            Option<WgpuContext>,
-        > = self.wgpu_context.lock();
-
-        if lock.is_some() {
-            return;
-        }
-
-        *lock = Some(context);
-    }
-
-    fn create_own_context(&self) {
-        gst::info!(CAT, imp: self, "creating own wgpu context");
-
-        let obj = self.obj();
-        let element = obj.upcast_ref::<gst::Element>();
-
-        let wgpu_ctx = WgpuContext::default();
-        let ctx = wgpu_ctx.as_gst_context();
-        self.set_context(&ctx);
-
-        let message = gst::message::HaveContext::builder(ctx)
-            .src(&*self.obj())
-            .build();
-        element.post_message(message).unwrap();
-    }
-
     fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
         [
             wgpu::BufferUsages::COPY_SRC,
@@ -105,14 +75,53 @@ impl ObjectSubclass for WgpuSobelBuf {
 
     fn with_class(_klass: &Self::Class) -> Self {
         Self {
-            wgpu_context: Mutex::new(None),
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(SobelSettings::default()),
             pipeline: Mutex::new(None),
             usages: Mutex::new((wgpu::BufferUsages::empty(), wgpu::BufferUsages::empty())),
         }
     }
 }
 
-impl ObjectImpl for WgpuSobelBuf {}
+impl ObjectImpl for WgpuSobelBuf {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![stats_param_spec()];
+            properties.extend(SobelSettings::param_specs());
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        if !self.settings.lock().set_property(value, pspec)
+            && !self.wgpu_context.set_property(value, pspec)
+        {
+            unimplemented!()
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            _ => self
+                .settings
+                .lock()
+                .property(pspec)
+                .or_else(|| self.wgpu_context.property(pspec))
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
 impl GstObjectImpl for WgpuSobelBuf {}
 impl ElementImpl for WgpuSobelBuf {
     fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -130,12 +139,14 @@ impl ElementImpl for WgpuSobelBuf {
     fn pad_templates() -> &'static [gst::PadTemplate] {
         static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
             let base_caps = gst_video::VideoCapsBuilder::new()
-                .format(gst_video::VideoFormat::Rgbx)
+                .format_list(sobel::sink_formats())
                 .features([deka_gst_wgpu::buffer_memory::GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER])
                 .build();
 
-            let src_caps =
-                make_wgpu_buffer_usages_for_caps(&base_caps, WgpuSobelBuf::src_allowed_usages);
+            let src_caps = make_wgpu_buffer_usages_for_caps(
+                &sobel::with_src_formats(&base_caps),
+                WgpuSobelBuf::src_allowed_usages,
+            );
             let sink_caps =
                 make_wgpu_buffer_usages_for_caps(&base_caps, WgpuSobelBuf::sink_allowed_usages);
 
@@ -160,16 +171,8 @@ impl ElementImpl for WgpuSobelBuf {
     }
 
     fn set_context(&self, context: &gst::Context) {
-        if context.context_type() == GST_CONTEXT_WGPU_TYPE {
-            gst::debug!(CAT, imp: self, "Received wgpu context");
-
-            let Some(wgpu_ctx) = WgpuContext::map_gst_context_to_wgpu(context.clone()) else {
-                gst::error!(CAT, imp: self, "Received invalid wgpu context");
-                return;
-            };
-
-            self.set_wgpu_context(wgpu_ctx);
-        }
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
 
         self.parent_set_context(context);
     }
@@ -181,24 +184,14 @@ impl BaseTransformImpl for WgpuSobelBuf {
     const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
 
     fn start(&self) -> Result<(), gst::ErrorMessage> {
-        let obj = self.obj();
-        let element = obj.upcast_ref::<gst::Element>();
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
 
-        match WgpuContext::query_context_from_nearby_elements(element) {
-            Ok(true) => {
-                gst::info!(CAT, imp: self, "using shared wgpu context");
-                Ok(())
-            }
-            Ok(false) => {
-                self.create_own_context();
-                Ok(())
-            }
-            Err(err) => {
-                gst::error!(CAT, imp: self, "failed to query wgpu context from nearby elements: {}", err);
-                self.create_own_context();
-                Ok(())
-            }
-        }
+    fn sink_event(&self, event: gst::Event) -> bool {
+        flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+        self.parent_sink_event(event)
     }
 
     fn transform_caps(
@@ -208,9 +201,15 @@ impl BaseTransformImpl for WgpuSobelBuf {
         filter: Option<&gst::Caps>,
     ) -> Option<gst::Caps> {
         let other_caps = if direction == gst::PadDirection::Sink {
-            make_wgpu_buffer_usages_for_caps(caps, Self::src_allowed_usages)
+            make_wgpu_buffer_usages_for_caps(
+                &sobel::with_src_formats(caps),
+                Self::src_allowed_usages,
+            )
         } else {
-            make_wgpu_buffer_usages_for_caps(caps, Self::sink_allowed_usages)
+            make_wgpu_buffer_usages_for_caps(
+                &sobel::with_sink_formats(caps),
+                Self::sink_allowed_usages,
+            )
         };
 
         gst::trace!(
@@ -223,51 +222,45 @@ impl BaseTransformImpl for WgpuSobelBuf {
             filter
         );
 
-        // In the end we need to filter the caps through an optional filter caps to get rid of any
-        // unwanted caps.
-        if let Some(filter) = filter {
-            Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
-        } else {
-            Some(other_caps)
-        }
+        Some(filter_caps(other_caps, filter))
+    }
+
+    fn fixate_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        othercaps: gst::Caps,
+    ) -> gst::Caps {
+        let othercaps = fixate_wgpu_usages(othercaps);
+        self.parent_fixate_caps(direction, caps, othercaps)
     }
 
     fn set_caps(&self, incaps: &gst::Caps, outcaps: &gst::Caps) -> Result<(), gst::LoggableError> {
         gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
 
-        let Some(outcaps_s) = outcaps.structure(0) else {
-            return Err(gst::loggable_error!(
-                CAT,
-                "missing structure in output caps"
-            ));
-        };
-
-        let src_usages_bits: u32 = match outcaps_s.get(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
-            Ok(usage) => usage,
-            Err(err) => {
-                return Err(gst::loggable_error!(
-                    CAT,
-                    "cannot get buffer usage in input caps: {}",
-                    err
-                ));
-            }
-        };
-        let src_usages = wgpu::BufferUsages::from_bits_truncate(src_usages_bits);
+        let src_usages =
+            match WgpuCapsInfo::from_caps(outcaps).and_then(|info| info.buffer_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get buffer usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
 
-        let Some(incaps_s) = incaps.structure(0) else {
-            return Err(gst::loggable_error!(CAT, "missing structure in input caps"));
-        };
-        let sink_usages_bits: u32 = match incaps_s.get(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
-            Ok(usage) => usage,
-            Err(err) => {
-                return Err(gst::loggable_error!(
-                    CAT,
-                    "cannot get buffer usage in input caps: {}",
-                    err
-                ));
-            }
-        };
-        let sink_usages = wgpu::BufferUsages::from_bits_truncate(sink_usages_bits);
+        let sink_usages =
+            match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.buffer_usages()) {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get buffer usage in input caps: {}",
+                        err
+                    ));
+                }
+            };
 
         if !sink_usages.contains(wgpu::BufferUsages::COPY_SRC) {
             return Err(gst::loggable_error!(
@@ -318,15 +311,15 @@ impl BaseTransformImpl for WgpuSobelBuf {
             return Err(gst::FlowError::NotNegotiated);
         };
 
-        let Some(out_info) = self_as_filter.output_video_info() else {
-            return Err(gst::FlowError::NotNegotiated);
-        };
+        // Only the crop rectangle is filtered, its result goes to the frame corner and the rest is zero
+        let layout = FrameLayout::from_buffer(inbuf, &in_info);
+        let crop = layout.crop_rect(inbuf);
 
         let Some(pipeline) = &*self.pipeline.lock() else {
             return Err(gst::FlowError::NotNegotiated);
         };
 
-        let Some(wgpu_context) = &*self.wgpu_context.lock() else {
+        let Some(wgpu_context) = self.wgpu_context.get() else {
             return Err(gst::FlowError::NotNegotiated);
         };
 
@@ -347,59 +340,23 @@ impl BaseTransformImpl for WgpuSobelBuf {
 
         let outbuffer = outmem.buffer();
 
-        let mut encoder = wgpu_context
-            .device()
-            .create_command_encoder(&Default::default());
-
-        encoder.copy_buffer_to_texture(
-            wgpu::TexelCopyBufferInfoBase {
-                buffer: &inbuffer,
-                layout: wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * in_info.width()),
-                    rows_per_image: None,
-                },
-            },
-            pipeline.input_texture.as_image_copy(),
-            wgpu::Extent3d {
-                width: in_info.width(),
-                height: in_info.height(),
-                depth_or_array_layers: 1,
-            },
-        );
-
-        {
-            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                ..Default::default()
-            });
-            pass.set_pipeline(&pipeline.pipeline);
-            pass.set_bind_group(0, &pipeline.bind_group, &[]);
+        let label = wgpu_context.label(obj.upcast_ref(), "sobel", inbuf.pts());
 
-            let workgroup_x = in_info.width().div_ceil(8);
-            let workgroup_y = in_info.height().div_ceil(8);
-            pass.dispatch_workgroups(workgroup_x, workgroup_y, 1);
+        let settings = *self.settings.lock();
+        if let Err(err) = pipeline.write_params(&wgpu_context, crop, &settings) {
+            gst::error!(CAT, imp: self, "Error writing parameters: {err}");
+            return Err(gst::FlowError::Error);
         }
 
-        encoder.copy_texture_to_buffer(
-            pipeline.output_texture.as_image_copy(),
-            wgpu::TexelCopyBufferInfo {
-                buffer: outbuffer,
-                layout: wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * out_info.width()),
-                    rows_per_image: None,
-                },
-            },
-            wgpu::Extent3d {
-                width: out_info.width(),
-                height: out_info.height(),
-                depth_or_array_layers: 1,
-            },
-        );
-
-        let command_buffer = encoder.finish();
-
-        wgpu_context.queue().submit([command_buffer]);
+        let mut profiler_lock = self.profiler.lock();
+        let profiler =
+            ElementProfiler::get_or_init(&mut profiler_lock, &wgpu_context, obj.upcast_ref());
+        wgpu_context.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+            pipeline.copy_input(encoder, inbuffer, &layout, crop);
+            ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                pipeline.record(encoder, label.as_deref(), outbuffer, crop);
+            });
+        });
 
         Ok(gst::FlowSuccess::Ok)
     }
@@ -415,11 +372,8 @@ impl BaseTransformImpl for WgpuSobelBuf {
             return Err(gst::loggable_error!(CAT, "No caps in allocation query"));
         };
 
-        let Some(caps_s) = caps.structure(0) else {
-            return Err(gst::loggable_error!(CAT, "No structure in caps"));
-        };
-
-        let sink_usages_bits: u32 = match caps_s.get(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
+        let sink_usages = match WgpuCapsInfo::from_caps(caps).and_then(|info| info.buffer_usages())
+        {
             Ok(usage) => usage,
             Err(err) => {
                 return Err(gst::loggable_error!(
@@ -429,14 +383,18 @@ impl BaseTransformImpl for WgpuSobelBuf {
                 ));
             }
         };
-        let sink_usages = wgpu::BufferUsages::from_bits_truncate(sink_usages_bits);
 
-        let ctx = self.wgpu_context.lock().as_ref().cloned().unwrap();
+        let ctx = self.wgpu_context.get().unwrap();
 
-        let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, sink_usages);
+        let label = ctx.label(self.obj().upcast_ref(), "input buffers", None);
+        let allocator =
+            WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, sink_usages).with_label(label);
         let params = gst::AllocationParams::default();
         query.add_allocation_param(Some(&allocator), params);
 
+        // The padding around the crop rectangle is not filtered
+        query.add_allocation_meta::<gst_video::VideoCropMeta>(None);
+
         Ok(())
     }
 }
@@ -446,123 +404,26 @@ impl VideoFilterImpl for WgpuSobelBuf {
         &self,
         _incaps: &gst::Caps,
         in_info: &gst_video::VideoInfo,
-        _outcaps: &gst::Caps,
+        outcaps: &gst::Caps,
         out_info: &gst_video::VideoInfo,
     ) -> Result<(), gst::LoggableError> {
-        let Some(wgpu_context) = &*self.wgpu_context.lock() else {
-            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
-        };
-        let device = wgpu_context.device();
-
-        let input_texture_descriptor = wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: in_info.width(),
-                height: in_info.height(),
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        };
-        let input_texture = device.create_texture(&input_texture_descriptor);
-
-        let output_texture_descriptor = wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: out_info.width(),
-                height: out_info.height(),
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::STORAGE_BINDING,
-            view_formats: &[],
+        let Some(output) = SobelOutput::from_caps(outcaps) else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "unsupported output caps {}",
+                outcaps
+            ));
         };
-        let output_texture = device.create_texture(&output_texture_descriptor);
-
-        let module = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::WriteOnly,
-                        format: wgpu::TextureFormat::Rgba8Unorm,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-            ],
-        });
-
-        let input_texture_view = input_texture.create_view(&wgpu::TextureViewDescriptor {
-            ..Default::default()
-        });
-
-        let output_texture_view = output_texture.create_view(&wgpu::TextureViewDescriptor {
-            ..Default::default()
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&input_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&output_texture_view),
-                },
-            ],
-        });
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
+        // A copy, so the context is not locked while the pipeline is, see `ElementContext::get`
+        let Some(wgpu_context) = self.wgpu_context.get() else {
+            return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
+        };
 
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("sobel compute"),
-            layout: Some(&pipeline_layout),
-            module: &module,
-            entry_point: Some("computeSobel"),
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-            cache: None,
-        });
+        gst::debug!(CAT, imp: self, "writing {:?} frames", output);
 
-        {
-            let mut pipeline = self.pipeline.lock();
-            *pipeline = Some(WebGPUState {
-                input_texture,
-                output_texture,
-                bind_group,
-                pipeline: compute_pipeline,
-            })
-        }
+        let pipeline = SobelPipeline::new(&wgpu_context, in_info, out_info, output);
+        *self.pipeline.lock() = Some(pipeline);
 
         Ok(())
     }
@@ -5,11 +5,13 @@ use gst::prelude::*;
 
 glib::wrapper! {
 
-    /// Plugin that apply Sobel kernel to image
+    /// Plugin that apply Sobel operator to image
+    ///
+    /// Writes either gradient, their magnitude or direction as RGBx/RGBA, GRAY8 or R32Float frames, see [`crate::sobel`]
     ///
     /// # Sample pipeline
     /// ```bash
-    /// gst-launch-1.0 filesrc location=video.mkv ! decodebin ! videoconvert ! queue ! dekawgpusobelmem ! videoconvert ! autovideosink
+    /// gst-launch-1.0 filesrc location=video.mkv ! decodebin ! videoconvert ! queue ! dekawgpusobelmem mode=magnitude grayscale=true ! videoconvert ! autovideosink
     /// ```
     pub struct WgpuSobelMem(ObjectSubclass<imp::WgpuSobelMem>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
 }
@@ -2,12 +2,13 @@ use std::sync::LazyLock;
 use std::time::Duration;
 
 use crate::glib;
+use crate::sobel::{self, SobelOutput, SobelPipeline, SobelSettings};
 
 use deka_gst_wgpu::buffer_memory::WgpuBufferMemory;
+use deka_gst_wgpu::crop::{CropRect, FrameLayout};
 use deka_gst_wgpu::{prelude::*, WgpuBufferMemoryAllocator};
 use glib::object::Cast;
 use glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
-use gst::prelude::ElementExt;
 use gst::subclass::prelude::*;
 use gst_base::subclass::prelude::{BaseTransformImpl, BaseTransformImplExt};
 use gst_base::subclass::BaseTransformMode;
@@ -15,7 +16,9 @@ use gst_video::prelude::*;
 use gst_video::subclass::prelude::*;
 use parking_lot::Mutex;
 
-use deka_gst_wgpu::{WgpuContext, GST_CONTEXT_WGPU_TYPE};
+use deka_gst_wgpu::element::ElementContext;
+use deka_gst_wgpu::profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY};
+use deka_gst_wgpu::WgpuContextOptions;
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
     gst::DebugCategory::new(
@@ -28,127 +31,65 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
 #[derive(Debug)]
 struct WebGPUState {
     input_buffer: wgpu::Buffer,
-    input_texture: wgpu::Texture,
-    output_texture: wgpu::Texture,
     output_buffer: wgpu::Buffer,
-    bind_group: wgpu::BindGroup,
-    pipeline: wgpu::ComputePipeline,
+    sobel: SobelPipeline,
 }
 
 #[derive(Debug)]
 pub struct WgpuSobelMem {
-    wgpu_context: Mutex<Option<WgpuContext>>,
+    wgpu_context: ElementContext,
+    profiler: Mutex<Option<ElementProfiler>>,
+    settings: Mutex<SobelSettings>,
     pipeline: Mutex<Option<WebGPUState>>,
 }
 
 impl WgpuSobelMem {
-    pub fn set_wgpu_context(&self, context: WgpuContext) {
-        let mut lock: parking_lot::lock_api::MutexGuard<
-            '_,
-            parking_lot::RawMutex,
-            Option<WgpuContext>,
-        > = self.wgpu_context.lock();
-
-        if lock.is_some() {
-            return;
-        }
-
-        *lock = Some(context);
-    }
-
-    fn create_own_context(&self) {
-        gst::info!(CAT, imp: self, "creating own wgpu context");
-
-        let obj = self.obj();
-        let element = obj.upcast_ref::<gst::Element>();
-
-        let wgpu_ctx = WgpuContext::default();
-        let ctx = wgpu_ctx.as_gst_context();
-        self.set_context(&ctx);
-
-        let message = gst::message::HaveContext::builder(ctx)
-            .src(&*self.obj())
-            .build();
-        element.post_message(message).unwrap();
-    }
-
     fn transform_with_gpu(
         &self,
         inbuffer: &wgpu::Buffer,
+        layout: &FrameLayout,
         outframe: &mut gst_video::VideoFrameRef<&mut gst::BufferRef>,
+        crop: CropRect,
         map_input: bool,
     ) -> Result<gst::FlowSuccess, gst::FlowError> {
         let Some(pipeline) = &*self.pipeline.lock() else {
             return Err(gst::FlowError::NotNegotiated);
         };
 
-        let Some(wgpu_context) = &*self.wgpu_context.lock() else {
+        let Some(wgpu_context) = &self.wgpu_context.get() else {
             return Err(gst::FlowError::NotNegotiated);
         };
 
         let obj = self.obj();
-        let self_as_filter = obj.upcast_ref::<gst_video::VideoFilter>();
-        let Some(in_info) = self_as_filter.input_video_info() else {
-            return Err(gst::FlowError::NotNegotiated);
-        };
-
-        let Some(out_info) = self_as_filter.output_video_info() else {
-            return Err(gst::FlowError::NotNegotiated);
-        };
-
-        let mut encoder = wgpu_context
-            .device()
-            .create_command_encoder(&Default::default());
-
-        encoder.copy_buffer_to_texture(
-            wgpu::TexelCopyBufferInfoBase {
-                buffer: &inbuffer,
-                layout: wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * in_info.width()),
-                    rows_per_image: None,
-                },
-            },
-            pipeline.input_texture.as_image_copy(),
-            wgpu::Extent3d {
-                width: in_info.width(),
-                height: in_info.height(),
-                depth_or_array_layers: 1,
-            },
-        );
-
-        {
-            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                ..Default::default()
-            });
-            pass.set_pipeline(&pipeline.pipeline);
-            pass.set_bind_group(0, &pipeline.bind_group, &[]);
-
-            let workgroup_x = in_info.width().div_ceil(8);
-            let workgroup_y = in_info.height().div_ceil(8);
-            pass.dispatch_workgroups(workgroup_x, workgroup_y, 1);
+        let label = wgpu_context.label(obj.upcast_ref(), "sobel", outframe.buffer().pts());
+        let mut encoder =
+            wgpu_context
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: label.as_deref(),
+                });
+
+        let settings = *self.settings.lock();
+        if let Err(err) = pipeline.sobel.write_params(wgpu_context, crop, &settings) {
+            gst::error!(CAT, imp: self, "Error writing parameters: {err}");
+            return Err(gst::FlowError::Error);
         }
-
-        encoder.copy_texture_to_buffer(
-            pipeline.output_texture.as_image_copy(),
-            wgpu::TexelCopyBufferInfoBase {
-                buffer: &pipeline.output_buffer,
-                layout: wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * out_info.width()),
-                    rows_per_image: None,
-                },
-            },
-            wgpu::Extent3d {
-                width: out_info.width(),
-                height: out_info.height(),
-                depth_or_array_layers: 1,
-            },
-        );
+        pipeline
+            .sobel
+            .copy_input(&mut encoder, inbuffer, layout, crop);
+
+        let mut profiler_lock = self.profiler.lock();
+        let profiler =
+            ElementProfiler::get_or_init(&mut profiler_lock, wgpu_context, obj.upcast_ref());
+        ElementProfiler::profile_encoder(profiler, &mut encoder, |encoder| {
+            pipeline
+                .sobel
+                .record(encoder, label.as_deref(), &pipeline.output_buffer, crop);
+        });
 
         let command_buffer = encoder.finish();
 
-        let index = wgpu_context.queue().submit([command_buffer]);
+        let index = wgpu_context.submit([command_buffer]);
 
         let output_slice = pipeline.output_buffer.slice(..);
         output_slice.map_async(wgpu::MapMode::Read, |_| {}); // We depend on poll, so we don't need an callback
@@ -187,13 +128,52 @@ impl ObjectSubclass for WgpuSobelMem {
 
     fn with_class(_klass: &Self::Class) -> Self {
         Self {
-            wgpu_context: Mutex::new(None),
+            wgpu_context: ElementContext::default(),
+            profiler: Mutex::new(None),
+            settings: Mutex::new(SobelSettings::default()),
             pipeline: Mutex::new(None),
         }
     }
 }
 
-impl ObjectImpl for WgpuSobelMem {}
+impl ObjectImpl for WgpuSobelMem {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            let mut properties = vec![stats_param_spec()];
+            properties.extend(SobelSettings::param_specs());
+            properties.extend(WgpuContextOptions::param_specs());
+            properties
+        });
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        if !self.settings.lock().set_property(value, pspec)
+            && !self.wgpu_context.set_property(value, pspec)
+        {
+            unimplemented!()
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            GST_WGPU_STATS_PROPERTY => self
+                .profiler
+                .lock()
+                .as_ref()
+                .map(|x| x.stats())
+                .unwrap_or_default()
+                .to_structure()
+                .to_value(),
+            _ => self
+                .settings
+                .lock()
+                .property(pspec)
+                .or_else(|| self.wgpu_context.property(pspec))
+                .unwrap_or_else(|| unimplemented!()),
+        }
+    }
+}
 impl GstObjectImpl for WgpuSobelMem {}
 impl ElementImpl for WgpuSobelMem {
     fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -210,22 +190,23 @@ impl ElementImpl for WgpuSobelMem {
 
     fn pad_templates() -> &'static [gst::PadTemplate] {
         static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
-            let caps = gst_video::VideoCapsBuilder::new()
-                .format(gst_video::VideoFormat::Rgbx)
+            let sink_caps = gst_video::VideoCapsBuilder::new()
+                .format_list(sobel::sink_formats())
                 .build();
+            let src_caps = sobel::with_src_formats(&sink_caps);
             vec![
                 gst::PadTemplate::new(
                     "src",
                     gst::PadDirection::Src,
                     gst::PadPresence::Always,
-                    &caps,
+                    &src_caps,
                 )
                 .unwrap(),
                 gst::PadTemplate::new(
                     "sink",
                     gst::PadDirection::Sink,
                     gst::PadPresence::Always,
-                    &caps,
+                    &sink_caps,
                 )
                 .unwrap(),
             ]
@@ -234,16 +215,8 @@ impl ElementImpl for WgpuSobelMem {
     }
 
     fn set_context(&self, context: &gst::Context) {
-        if context.context_type() == GST_CONTEXT_WGPU_TYPE {
-            gst::debug!(CAT, imp: self, "Received wgpu context");
-
-            let Some(wgpu_ctx) = WgpuContext::map_gst_context_to_wgpu(context.clone()) else {
-                gst::error!(CAT, imp: self, "Received invalid wgpu context");
-                return;
-            };
-
-            self.set_wgpu_context(wgpu_ctx);
-        }
+        self.wgpu_context
+            .set_gst_context(self.obj().upcast_ref(), context);
 
         self.parent_set_context(context);
     }
@@ -255,23 +228,36 @@ impl BaseTransformImpl for WgpuSobelMem {
     const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
 
     fn start(&self) -> Result<(), gst::ErrorMessage> {
-        let obj = self.obj();
-        let element = obj.upcast_ref::<gst::Element>();
+        self.wgpu_context.find_or_create(self.obj().upcast_ref());
+        Ok(())
+    }
 
-        match WgpuContext::query_context_from_nearby_elements(element) {
-            Ok(true) => {
-                gst::info!(CAT, imp: self, "using shared wgpu context");
-                Ok(())
-            }
-            Ok(false) => {
-                self.create_own_context();
-                Ok(())
-            }
-            Err(err) => {
-                gst::error!(CAT, imp: self, "failed to query wgpu context from nearby elements: {}", err);
-                self.create_own_context();
-                Ok(())
-            }
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let other_caps = if direction == gst::PadDirection::Sink {
+            sobel::with_src_formats(caps)
+        } else {
+            sobel::with_sink_formats(caps)
+        };
+
+        gst::trace!(
+            CAT,
+            imp: self,
+            "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+            caps,
+            other_caps,
+            direction,
+            filter
+        );
+
+        if let Some(filter) = filter {
+            Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
+        } else {
+            Some(other_caps)
         }
     }
 
@@ -291,12 +277,17 @@ impl BaseTransformImpl for WgpuSobelMem {
         };
 
         if let Some(gpu_mem) = mem {
+            let Some(out_info) = self_as_filter.output_video_info() else {
+                return Err(gst::FlowError::NotNegotiated);
+            };
             let Ok(mut outframe) =
-                gst_video::VideoFrameRef::from_buffer_ref_writable(outbuf, &in_info)
+                gst_video::VideoFrameRef::from_buffer_ref_writable(outbuf, &out_info)
             else {
                 return Err(gst::FlowError::NotNegotiated);
             };
-            self.transform_with_gpu(gpu_mem.buffer(), &mut outframe, false)
+            let layout = FrameLayout::from_buffer(inbuf, &in_info);
+            let crop = layout.crop_rect(inbuf);
+            self.transform_with_gpu(gpu_mem.buffer(), &layout, &mut outframe, crop, false)
         } else {
             // Fallback to copy
             gst::warning!(CAT, imp: self, "using ineffective copy");
@@ -309,12 +300,16 @@ impl BaseTransformImpl for WgpuSobelMem {
         _decide_query: Option<&gst::query::Allocation>,
         query: &mut gst::query::Allocation,
     ) -> Result<(), gst::LoggableError> {
-        let allocator =
-            WgpuBufferMemoryAllocator::new(self.wgpu_context.lock().as_ref().cloned().unwrap());
+        let ctx = self.wgpu_context.get().unwrap();
+        let label = ctx.label(self.obj().upcast_ref(), "input buffers", None);
+        let allocator = WgpuBufferMemoryAllocator::new(ctx).with_label(label);
         // Default params for MAP_WRITE buffers
         let params = gst::AllocationParams::default();
         query.add_allocation_param(Some(&allocator), params);
 
+        // The padding around the crop rectangle is not filtered
+        query.add_allocation_meta::<gst_video::VideoCropMeta>(None);
+
         Ok(())
     }
 }
@@ -324,138 +319,47 @@ impl VideoFilterImpl for WgpuSobelMem {
         &self,
         _incaps: &gst::Caps,
         in_info: &gst_video::VideoInfo,
-        _outcaps: &gst::Caps,
+        outcaps: &gst::Caps,
         out_info: &gst_video::VideoInfo,
     ) -> Result<(), gst::LoggableError> {
-        let Some(wgpu_context) = &*self.wgpu_context.lock() else {
+        let Some(output) = SobelOutput::from_caps(outcaps) else {
+            return Err(gst::loggable_error!(
+                CAT,
+                "unsupported output caps {}",
+                outcaps
+            ));
+        };
+
+        // Not held while the pipeline is locked, `transform_with_gpu` locks the pipeline first
+        let Some(wgpu_context) = &self.wgpu_context.get() else {
             return Err(gst::loggable_error!(CAT, "Could not find a WGPU context"));
         };
 
         let device = wgpu_context.device();
 
-        let channels = 4; // RGBx
-        let in_frame_size = in_info.width() as u64 * in_info.height() as u64 * channels;
-        let out_frame_size = out_info.width() as u64 * out_info.height() as u64 * channels;
-
         // This buffer will be used to copy the input frame into.
         let input_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("input frame buffer"),
             mapped_at_creation: true,
-            size: in_frame_size,
+            size: in_info.size() as u64,
             usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
         });
 
         let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("output frame buffer"),
             mapped_at_creation: false,
-            size: out_frame_size,
+            size: out_info.size() as u64,
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
         });
 
-        let texture_descriptor = wgpu::TextureDescriptor {
-            label: Some("input texture"),
-            size: wgpu::Extent3d {
-                width: in_info.width(),
-                height: in_info.height(),
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        };
-
-        let input_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("input texture"),
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-            ..texture_descriptor
-        });
-
-        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("output texture"),
-            usage: wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::STORAGE_BINDING,
-            ..texture_descriptor
-        });
-
-        let module = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::WriteOnly,
-                        format: wgpu::TextureFormat::Rgba8Unorm,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-            ],
-        });
-
-        let input_texture_view = input_texture.create_view(&wgpu::TextureViewDescriptor {
-            ..Default::default()
-        });
-
-        let output_texture_view = output_texture.create_view(&wgpu::TextureViewDescriptor {
-            ..Default::default()
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&input_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&output_texture_view),
-                },
-            ],
-        });
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
-
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("sobel compute"),
-            layout: Some(&pipeline_layout),
-            module: &module,
-            entry_point: Some("computeSobel"),
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-            cache: None,
-        });
+        gst::debug!(CAT, imp: self, "writing {:?} frames", output);
 
         {
             let mut pipeline = self.pipeline.lock();
             *pipeline = Some(WebGPUState {
                 input_buffer,
-                input_texture,
-                output_texture,
                 output_buffer,
-                bind_group,
-                pipeline: compute_pipeline,
+                sobel: SobelPipeline::new(wgpu_context, in_info, out_info, output),
             })
         }
 
@@ -474,14 +378,36 @@ impl VideoFilterImpl for WgpuSobelMem {
             pipeline.input_buffer.clone()
         };
 
+        // The frame may be padded, only the crop rectangle is packed into the corner of the input buffer
+        let info = inframe.info();
+        let crop = FrameLayout::from_buffer(inframe.buffer(), info)
+            .crop_rect(inframe.buffer())
+            .fit_size(info.width(), info.height());
+        let packed = FrameLayout::from_info(info);
+
         let input_slice = input_buffer.slice(..);
         {
             let mut input_mapped = input_slice.get_mapped_range_mut();
-            input_mapped.copy_from_slice(inframe.plane_data(0).unwrap());
+            let data = inframe.plane_data(0).unwrap();
+            let src_stride = inframe.plane_stride()[0] as usize;
+            let dst_stride = packed.stride as usize;
+            let row_size = 4 * crop.width as usize;
+
+            for row in 0..crop.height as usize {
+                let src = (crop.y as usize + row) * src_stride + 4 * crop.x as usize;
+                let dst = row * dst_stride;
+                input_mapped[dst..dst + row_size].copy_from_slice(&data[src..src + row_size]);
+            }
         }
 
         input_buffer.unmap();
 
-        self.transform_with_gpu(&input_buffer, outframe, true)
+        self.transform_with_gpu(
+            &input_buffer,
+            &packed,
+            outframe,
+            CropRect::full(crop.width, crop.height),
+            true,
+        )
     }
 }
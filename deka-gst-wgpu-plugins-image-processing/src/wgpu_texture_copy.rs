@@ -24,13 +24,14 @@ mod imp {
 
     use deka_gst_wgpu::buffer_memory::GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER;
 
+    use deka_gst_wgpu::caps::{fixate_wgpu_usages, WgpuCapsInfo};
+    use deka_gst_wgpu::crop::{buffer_crop_rect, clear_around_crop};
     use deka_gst_wgpu::texture_memory::{
-        WgpuTextureMemory, WgpuTextureMemoryAllocator, WgpuTextureMemoryExt,
-        GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE, GST_CAPS_FIELD_WGPU_TEXTURE_USAGE,
+        WgpuTextureMemory, WgpuTextureMemoryExt, GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE,
     };
+    use deka_gst_wgpu::texture_meta::buffer_texture;
     use glib::object::Cast;
     use glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
-    use gst::prelude::ElementExt;
     use gst::subclass::prelude::*;
     use gst_base::subclass::prelude::*;
     use gst_base::subclass::BaseTransformMode;
@@ -38,7 +39,10 @@ mod imp {
     use gst_video::subclass::prelude::VideoFilterImpl;
     use parking_lot::Mutex;
 
-    use deka_gst_wgpu::{WgpuContext, GST_CONTEXT_WGPU_TYPE};
+    use deka_gst_wgpu::element::{decide_texture_allocation, filter_caps, ElementContext};
+    use deka_gst_wgpu::profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY};
+    use deka_gst_wgpu::WgpuContextOptions;
+    use deka_gst_wgpu::{batcher::flush_on_eos, WgpuContext};
 
     static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
         gst::DebugCategory::new(
@@ -50,48 +54,13 @@ mod imp {
 
     #[derive(Debug)]
     pub struct WgpuTextureCopy {
-        wgpu_context: Mutex<Option<WgpuContext>>,
+        wgpu_context: ElementContext,
+        profiler: Mutex<Option<ElementProfiler>>,
 
         sink_usages: Mutex<wgpu::TextureUsages>,
-        src_usages: Mutex<wgpu::TextureUsages>,
     }
 
     impl WgpuTextureCopy {
-        pub fn set_wgpu_context(&self, context: WgpuContext) {
-            let mut lock: parking_lot::lock_api::MutexGuard<
-                '_,
-                parking_lot::RawMutex,
-                Option<WgpuContext>,
-            > = self.wgpu_context.lock();
-
-            if lock.is_some() {
-                return;
-            }
-
-            *lock = Some(context);
-        }
-
-        fn create_own_context(&self) {
-            gst::info!(CAT, imp: self, "creating own wgpu context");
-
-            let obj = self.obj();
-            let element = obj.upcast_ref::<gst::Element>();
-
-            let wgpu_ctx = WgpuContext::default();
-            let ctx = wgpu_ctx.as_gst_context();
-            self.set_context(&ctx);
-
-            let message = gst::message::HaveContext::builder(ctx)
-                .src(&*self.obj())
-                .build();
-            element.post_message(message).unwrap();
-        }
-
-        /// Locks context
-        fn locked_context(&self) -> parking_lot::MappedMutexGuard<'_, WgpuContext> {
-            parking_lot::MutexGuard::map(self.wgpu_context.lock(), |x| x.as_mut().unwrap())
-        }
-
         fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
             // We need to be able to copy from buffer
             [
@@ -121,14 +90,46 @@ mod imp {
 
         fn with_class(_klass: &Self::Class) -> Self {
             Self {
-                wgpu_context: Mutex::new(None),
-                src_usages: Mutex::new(wgpu::TextureUsages::empty()),
+                wgpu_context: ElementContext::default(),
+                profiler: Mutex::new(None),
                 sink_usages: Mutex::new(wgpu::TextureUsages::empty()),
             }
         }
     }
 
-    impl ObjectImpl for WgpuTextureCopy {}
+    impl ObjectImpl for WgpuTextureCopy {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+                let mut properties = vec![stats_param_spec()];
+                properties.extend(WgpuContextOptions::param_specs());
+                properties
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            if !self.wgpu_context.set_property(value, pspec) {
+                unimplemented!()
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            match pspec.name() {
+                GST_WGPU_STATS_PROPERTY => self
+                    .profiler
+                    .lock()
+                    .as_ref()
+                    .map(|x| x.stats())
+                    .unwrap_or_default()
+                    .to_structure()
+                    .to_value(),
+                _ => self
+                    .wgpu_context
+                    .property(pspec)
+                    .unwrap_or_else(|| unimplemented!()),
+            }
+        }
+    }
     impl GstObjectImpl for WgpuTextureCopy {}
     impl ElementImpl for WgpuTextureCopy {
         fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -166,10 +167,11 @@ mod imp {
                     .features([GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE])
                     .build();
 
-                let sink_caps = deka_gst_wgpu::caps::transform::gst_caps_with_texture_usages(
-                    base_sink_caps,
-                    WgpuTextureCopy::sink_allowed_usages,
-                );
+                let sink_caps =
+                    deka_gst_wgpu::caps::transform::gst_caps_with_texture_or_meta_usages(
+                        base_sink_caps,
+                        WgpuTextureCopy::sink_allowed_usages,
+                    );
 
                 let src_caps = deka_gst_wgpu::caps::transform::gst_caps_with_texture_usages(
                     base_src_caps,
@@ -197,16 +199,8 @@ mod imp {
         }
 
         fn set_context(&self, context: &gst::Context) {
-            if context.context_type() == GST_CONTEXT_WGPU_TYPE {
-                gst::debug!(CAT, imp: self, "Received wgpu context");
-
-                let Some(wgpu_ctx) = WgpuContext::map_gst_context_to_wgpu(context.clone()) else {
-                    gst::error!(CAT, imp: self, "Received invalid wgpu context");
-                    return;
-                };
-
-                self.set_wgpu_context(wgpu_ctx);
-            }
+            self.wgpu_context
+                .set_gst_context(self.obj().upcast_ref(), context);
 
             self.parent_set_context(context);
         }
@@ -218,24 +212,14 @@ mod imp {
         const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
 
         fn start(&self) -> Result<(), gst::ErrorMessage> {
-            let obj = self.obj();
-            let element = obj.upcast_ref::<gst::Element>();
+            self.wgpu_context.find_or_create(self.obj().upcast_ref());
+            Ok(())
+        }
 
-            match WgpuContext::query_context_from_nearby_elements(element) {
-                Ok(true) => {
-                    gst::info!(CAT, imp: self, "using shared wgpu context");
-                    Ok(())
-                }
-                Ok(false) => {
-                    self.create_own_context();
-                    Ok(())
-                }
-                Err(err) => {
-                    gst::error!(CAT, imp: self, "failed to query wgpu context from nearby elements: {}", err);
-                    self.create_own_context();
-                    Ok(())
-                }
-            }
+        fn sink_event(&self, event: gst::Event) -> bool {
+            flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+            self.parent_sink_event(event)
         }
 
         fn transform_caps(
@@ -245,7 +229,7 @@ mod imp {
             filter: Option<&gst::Caps>,
         ) -> Option<gst::Caps> {
             let other_caps = if direction == gst::PadDirection::Src {
-                deka_gst_wgpu::caps::transform::gst_caps_with_texture_usages(
+                deka_gst_wgpu::caps::transform::gst_caps_with_texture_or_meta_usages(
                     caps,
                     Self::sink_allowed_usages,
                 )
@@ -266,13 +250,17 @@ mod imp {
                 filter
             );
 
-            // In the end we need to filter the caps through an optional filter caps to get rid of any
-            // unwanted caps.
-            if let Some(filter) = filter {
-                Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
-            } else {
-                Some(other_caps)
-            }
+            Some(filter_caps(other_caps, filter))
+        }
+
+        fn fixate_caps(
+            &self,
+            direction: gst::PadDirection,
+            caps: &gst::Caps,
+            othercaps: gst::Caps,
+        ) -> gst::Caps {
+            let othercaps = fixate_wgpu_usages(othercaps);
+            self.parent_fixate_caps(direction, caps, othercaps)
         }
 
         fn set_caps(
@@ -283,24 +271,17 @@ mod imp {
             gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
 
             {
-                let Some(outcaps_s) = outcaps.structure(0) else {
-                    return Err(gst::loggable_error!(
-                        CAT,
-                        "missing structure in output caps"
-                    ));
-                };
-
-                let src_usages_bits: u32 = match outcaps_s.get(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE) {
-                    Ok(usage) => usage,
-                    Err(err) => {
-                        return Err(gst::loggable_error!(
-                            CAT,
-                            "cannot get texture usage in output caps: {}",
-                            err
-                        ));
-                    }
-                };
-                let src_usages = wgpu::TextureUsages::from_bits_truncate(src_usages_bits);
+                let src_usages =
+                    match WgpuCapsInfo::from_caps(outcaps).and_then(|info| info.texture_usages()) {
+                        Ok(usage) => usage,
+                        Err(err) => {
+                            return Err(gst::loggable_error!(
+                                CAT,
+                                "cannot get texture usage in output caps: {}",
+                                err
+                            ));
+                        }
+                    };
                 if !src_usages.intersects(wgpu::TextureUsages::COPY_DST) {
                     return Err(gst::loggable_error!(
                         CAT,
@@ -308,26 +289,20 @@ mod imp {
                         src_usages
                     ));
                 }
-
-                *self.src_usages.lock() = src_usages;
             }
 
             {
-                let Some(incaps_s) = incaps.structure(0) else {
-                    return Err(gst::loggable_error!(CAT, "missing structure in input caps"));
-                };
-
-                let sink_usages_bits: u32 = match incaps_s.get(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE) {
-                    Ok(usage) => usage,
-                    Err(err) => {
-                        return Err(gst::loggable_error!(
-                            CAT,
-                            "cannot get texture usage in input caps: {}",
-                            err
-                        ));
-                    }
-                };
-                let sink_usages = wgpu::TextureUsages::from_bits_truncate(sink_usages_bits);
+                let sink_usages =
+                    match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.texture_usages()) {
+                        Ok(usage) => usage,
+                        Err(err) => {
+                            return Err(gst::loggable_error!(
+                                CAT,
+                                "cannot get texture usage in input caps: {}",
+                                err
+                            ));
+                        }
+                    };
                 if !sink_usages.intersects(wgpu::TextureUsages::COPY_SRC) {
                     return Err(gst::loggable_error!(
                         CAT,
@@ -351,9 +326,9 @@ mod imp {
             assert!(0 < outbuf.n_memory());
             // If we are here, we are going to copy to output memory
 
-            let inmem = inbuf.peek_memory(0);
-            let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
-                gst::error!(CAT, imp: self, "invalid input memory");
+            // The texture is either the memory or attached as meta
+            let Some(in_texture) = buffer_texture(inbuf) else {
+                gst::error!(CAT, imp: self, "input buffer has no texture");
                 return Err(gst::FlowError::NotNegotiated);
             };
 
@@ -364,134 +339,82 @@ mod imp {
             };
 
             let obj = self.obj();
-            let self_as_filter = obj.upcast_ref::<gst_video::VideoFilter>();
-            let Some(in_info) = self_as_filter.input_video_info() else {
-                return Err(gst::FlowError::NotNegotiated);
-            };
+
+            // Padding around the crop rectangle is left out, the visible part goes to the texture corner and the rest is
+            // cleared
+            let crop = buffer_crop_rect(inbuf, in_texture.width(), in_texture.height());
 
             {
-                let src = inmem.texture();
+                let src = &in_texture;
                 let dst = outmem.texture();
-                let ctx = self.locked_context();
-                let mut encoder = ctx.device().create_command_encoder(&Default::default());
-                encoder.copy_texture_to_texture(
-                    wgpu::TexelCopyTextureInfo {
-                        texture: src,
-                        aspect: wgpu::TextureAspect::All,
-                        mip_level: 0,
-                        origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                    },
-                    wgpu::TexelCopyTextureInfo {
-                        texture: dst,
-                        aspect: wgpu::TextureAspect::All,
-                        mip_level: 0,
-                        origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                    },
-                    wgpu::Extent3d {
-                        width: in_info.width(),
-                        height: in_info.height(),
-                        depth_or_array_layers: 1,
-                    },
-                );
-
-                ctx.queue().submit([encoder.finish()]);
+                let ctx = self.wgpu_context.locked();
+                let mut profiler_lock = self.profiler.lock();
+                let profiler =
+                    ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+                let cleared = ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+                    ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                        let cleared = clear_around_crop(&ctx, encoder, dst, crop);
+                        encoder.copy_texture_to_texture(
+                            wgpu::TexelCopyTextureInfo {
+                                texture: src,
+                                aspect: wgpu::TextureAspect::All,
+                                mip_level: 0,
+                                origin: crop.origin(),
+                            },
+                            wgpu::TexelCopyTextureInfo {
+                                texture: dst,
+                                aspect: wgpu::TextureAspect::All,
+                                mip_level: 0,
+                                origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                            },
+                            wgpu::Extent3d {
+                                width: crop.width.min(dst.width()),
+                                height: crop.height.min(dst.height()),
+                                depth_or_array_layers: 1,
+                            },
+                        );
+                        cleared
+                    })
+                });
+                if !cleared {
+                    gst::warning!(CAT, imp: self, "cannot clear the texture around {:?}", crop);
+                }
             }
 
             Ok(gst::FlowSuccess::Ok)
         }
 
-        fn decide_allocation(
+        fn propose_allocation(
             &self,
+            decide_query: Option<&gst::query::Allocation>,
             query: &mut gst::query::Allocation,
         ) -> Result<(), gst::LoggableError> {
-            let src_usages = self.src_usages.lock();
-            if src_usages.is_empty() {
-                return Err(gst::loggable_error!(
-                    CAT,
-                    "decide_allocation called before negotiation"
-                ));
-            }
-
-            let mut to_remove = vec![];
-
-            for (pos, (allocator, _params)) in query.allocation_params().iter().enumerate() {
-                let Some(wgpu_allocator) =
-                    allocator.and_downcast_ref::<WgpuTextureMemoryAllocator>()
-                else {
-                    gst::trace!(CAT, imp: self, "skipping allocator at {pos}, not an WGPU texture");
-                    to_remove.push(pos);
-                    continue;
-                };
-
-                let usages = wgpu_allocator.descriptor().usage;
-                let required = wgpu::TextureUsages::COPY_DST;
-                if !usages.contains(required) {
-                    gst::trace!(CAT, imp: self, "skipping allocator at {pos}, usages is incorrect {} != {}", required.bits(), usages.bits());
-                    to_remove.push(pos);
-                }
-            }
+            self.parent_propose_allocation(decide_query, query)?;
 
-            for pos in to_remove.iter().rev() {
-                query.remove_nth_allocation_param(*pos as u32);
-            }
-
-            if 0 < query.allocation_params().len() {
-                return Ok(());
-            }
+            // Only the crop rectangle is copied, so upstream may keep the padding
+            query.add_allocation_meta::<gst_video::VideoCropMeta>(None);
 
-            let (caps, _needs_pool) = query.get();
+            Ok(())
+        }
 
-            let Some(caps) = caps else {
+        fn decide_allocation(
+            &self,
+            query: &mut gst::query::Allocation,
+        ) -> Result<(), gst::LoggableError> {
+            let Some(ctx) = self.wgpu_context.get() else {
                 return Err(gst::loggable_error!(
                     CAT,
-                    "decide_allocation called wo caps"
+                    "decide_allocation called before start"
                 ));
             };
 
-            let Some(s) = caps.structure(0) else {
-                return Err(gst::loggable_error!(CAT, "caps structure missing"));
-            };
-
-            let width: i32 = match s.get("width") {
-                Ok(v) => v,
-                Err(err) => {
-                    return Err(gst::loggable_error!(CAT, "can't find width: {}", err));
-                }
-            };
-
-            let height: i32 = match s.get("height") {
-                Ok(v) => v,
-                Err(err) => {
-                    return Err(gst::loggable_error!(CAT, "can't find width: {}", err));
-                }
-            };
-
-            let desciptor = wgpu::TextureDescriptor {
-                label: None,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8Unorm, // FIXME< Should be gotten from caps
-                mip_level_count: 1,
-                sample_count: 1,
-                size: wgpu::Extent3d {
-                    width: width as u32,
-                    height: height as u32,
-                    depth_or_array_layers: 1,
-                },
-                usage: *src_usages,
-                view_formats: &[],
-            };
-
-            let ctx = self.wgpu_context.lock().as_ref().cloned().unwrap();
-            let allocator = WgpuTextureMemoryAllocator::new(ctx, desciptor);
-            let params = gst::AllocationParams::new(gst::MemoryFlags::NOT_MAPPABLE, 0, 0, 0);
-            query.add_allocation_param(Some(&allocator), params);
-
-            // No pool support at the moment
-            while !query.allocation_pools().is_empty() {
-                query.remove_nth_allocation_pool(0);
-            }
-
-            Ok(())
+            decide_texture_allocation(
+                self.obj().upcast_ref(),
+                &ctx,
+                query,
+                wgpu::TextureUsages::COPY_DST,
+                None,
+            )
         }
     }
 
@@ -21,17 +21,15 @@ mod imp {
 
     use crate::glib;
 
-    use deka_gst_wgpu::buffer_memory::{
-        WgpuBufferMemory, GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER, GST_CAPS_FIELD_WGPU_BUFFER_USAGE,
-    };
+    use deka_gst_wgpu::buffer_memory::{WgpuBufferMemory, GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER};
+    use deka_gst_wgpu::caps::{fixate_wgpu_usages, WgpuCapsInfo};
     use deka_gst_wgpu::texture_memory::{
-        WgpuTextureMemory, WgpuTextureMemoryAllocator, WgpuTextureMemoryExt,
-        GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE, GST_CAPS_FIELD_WGPU_TEXTURE_USAGE,
+        WgpuTextureMemoryAllocator, GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE,
     };
+    use deka_gst_wgpu::texture_meta::buffer_texture;
     use deka_gst_wgpu::{prelude::*, WgpuBufferMemoryAllocator};
     use glib::object::Cast;
     use glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
-    use gst::prelude::ElementExt;
     use gst::subclass::prelude::*;
     use gst_base::subclass::prelude::*;
     use gst_base::subclass::BaseTransformMode;
@@ -39,7 +37,9 @@ mod imp {
     use gst_video::subclass::prelude::VideoFilterImpl;
     use parking_lot::Mutex;
 
-    use deka_gst_wgpu::{WgpuContext, GST_CONTEXT_WGPU_TYPE};
+    use deka_gst_wgpu::element::{filter_caps, ElementContext};
+    use deka_gst_wgpu::profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY};
+    use deka_gst_wgpu::{WgpuContext, WgpuContextOptions};
     use wgpu::TexelCopyBufferLayout;
 
     static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
@@ -52,48 +52,14 @@ mod imp {
 
     #[derive(Debug)]
     pub struct WgpuTextureUpload {
-        wgpu_context: Mutex<Option<WgpuContext>>,
+        wgpu_context: ElementContext,
+        profiler: Mutex<Option<ElementProfiler>>,
 
         sink_usages: Mutex<wgpu::TextureUsages>,
         src_usages: Mutex<wgpu::BufferUsages>,
     }
 
     impl WgpuTextureUpload {
-        pub fn set_wgpu_context(&self, context: WgpuContext) {
-            let mut lock: parking_lot::lock_api::MutexGuard<
-                '_,
-                parking_lot::RawMutex,
-                Option<WgpuContext>,
-            > = self.wgpu_context.lock();
-
-            if lock.is_some() {
-                return;
-            }
-
-            *lock = Some(context);
-        }
-
-        fn create_own_context(&self) {
-            gst::info!(CAT, imp: self, "creating own wgpu context");
-
-            let obj = self.obj();
-            let element = obj.upcast_ref::<gst::Element>();
-
-            let wgpu_ctx = WgpuContext::default();
-            let ctx = wgpu_ctx.as_gst_context();
-            self.set_context(&ctx);
-
-            let message = gst::message::HaveContext::builder(ctx)
-                .src(&*self.obj())
-                .build();
-            element.post_message(message).unwrap();
-        }
-
-        /// Locks context
-        fn locked_context(&self) -> parking_lot::MappedMutexGuard<'_, WgpuContext> {
-            parking_lot::MutexGuard::map(self.wgpu_context.lock(), |x| x.as_mut().unwrap())
-        }
-
         fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
             // We need to be able to copy from buffer
             [
@@ -129,14 +95,47 @@ mod imp {
 
         fn with_class(_klass: &Self::Class) -> Self {
             Self {
-                wgpu_context: Mutex::new(None),
+                wgpu_context: ElementContext::default(),
+                profiler: Mutex::new(None),
                 src_usages: Mutex::new(wgpu::BufferUsages::empty()),
                 sink_usages: Mutex::new(wgpu::TextureUsages::empty()),
             }
         }
     }
 
-    impl ObjectImpl for WgpuTextureUpload {}
+    impl ObjectImpl for WgpuTextureUpload {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+                let mut properties = vec![stats_param_spec()];
+                properties.extend(WgpuContextOptions::param_specs());
+                properties
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            if !self.wgpu_context.set_property(value, pspec) {
+                unimplemented!()
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            match pspec.name() {
+                GST_WGPU_STATS_PROPERTY => self
+                    .profiler
+                    .lock()
+                    .as_ref()
+                    .map(|x| x.stats())
+                    .unwrap_or_default()
+                    .to_structure()
+                    .to_value(),
+                _ => self
+                    .wgpu_context
+                    .property(pspec)
+                    .unwrap_or_else(|| unimplemented!()),
+            }
+        }
+    }
     impl GstObjectImpl for WgpuTextureUpload {}
     impl ElementImpl for WgpuTextureUpload {
         fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -174,10 +173,11 @@ mod imp {
                     .features([GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE])
                     .build();
 
-                let sink_caps = deka_gst_wgpu::caps::transform::gst_caps_with_texture_usages(
-                    base_sink_caps,
-                    WgpuTextureUpload::sink_allowed_usages,
-                );
+                let sink_caps =
+                    deka_gst_wgpu::caps::transform::gst_caps_with_texture_or_meta_usages(
+                        base_sink_caps,
+                        WgpuTextureUpload::sink_allowed_usages,
+                    );
 
                 let src_caps = deka_gst_wgpu::caps::transform::gst_caps_with_buffer_usages(
                     base_src_caps,
@@ -205,16 +205,8 @@ mod imp {
         }
 
         fn set_context(&self, context: &gst::Context) {
-            if context.context_type() == GST_CONTEXT_WGPU_TYPE {
-                gst::debug!(CAT, imp: self, "Received wgpu context");
-
-                let Some(wgpu_ctx) = WgpuContext::map_gst_context_to_wgpu(context.clone()) else {
-                    gst::error!(CAT, imp: self, "Received invalid wgpu context");
-                    return;
-                };
-
-                self.set_wgpu_context(wgpu_ctx);
-            }
+            self.wgpu_context
+                .set_gst_context(self.obj().upcast_ref(), context);
 
             self.parent_set_context(context);
         }
@@ -226,24 +218,8 @@ mod imp {
         const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
 
         fn start(&self) -> Result<(), gst::ErrorMessage> {
-            let obj = self.obj();
-            let element = obj.upcast_ref::<gst::Element>();
-
-            match WgpuContext::query_context_from_nearby_elements(element) {
-                Ok(true) => {
-                    gst::info!(CAT, imp: self, "using shared wgpu context");
-                    Ok(())
-                }
-                Ok(false) => {
-                    self.create_own_context();
-                    Ok(())
-                }
-                Err(err) => {
-                    gst::error!(CAT, imp: self, "failed to query wgpu context from nearby elements: {}", err);
-                    self.create_own_context();
-                    Ok(())
-                }
-            }
+            self.wgpu_context.find_or_create(self.obj().upcast_ref());
+            Ok(())
         }
 
         fn transform_caps(
@@ -253,7 +229,7 @@ mod imp {
             filter: Option<&gst::Caps>,
         ) -> Option<gst::Caps> {
             let other_caps = if direction == gst::PadDirection::Src {
-                deka_gst_wgpu::caps::transform::gst_caps_with_texture_usages(
+                deka_gst_wgpu::caps::transform::gst_caps_with_texture_or_meta_usages(
                     caps,
                     Self::sink_allowed_usages,
                 )
@@ -274,13 +250,17 @@ mod imp {
                 filter
             );
 
-            // In the end we need to filter the caps through an optional filter caps to get rid of any
-            // unwanted caps.
-            if let Some(filter) = filter {
-                Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
-            } else {
-                Some(other_caps)
-            }
+            Some(filter_caps(other_caps, filter))
+        }
+
+        fn fixate_caps(
+            &self,
+            direction: gst::PadDirection,
+            caps: &gst::Caps,
+            othercaps: gst::Caps,
+        ) -> gst::Caps {
+            let othercaps = fixate_wgpu_usages(othercaps);
+            self.parent_fixate_caps(direction, caps, othercaps)
         }
 
         fn set_caps(
@@ -291,24 +271,17 @@ mod imp {
             gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
 
             {
-                let Some(outcaps_s) = outcaps.structure(0) else {
-                    return Err(gst::loggable_error!(
-                        CAT,
-                        "missing structure in output caps"
-                    ));
-                };
-
-                let src_usages_bits: u32 = match outcaps_s.get(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
-                    Ok(usage) => usage,
-                    Err(err) => {
-                        return Err(gst::loggable_error!(
-                            CAT,
-                            "cannot get buffer usage in output caps: {}",
-                            err
-                        ));
-                    }
-                };
-                let src_usages = wgpu::BufferUsages::from_bits_truncate(src_usages_bits);
+                let src_usages =
+                    match WgpuCapsInfo::from_caps(outcaps).and_then(|info| info.buffer_usages()) {
+                        Ok(usage) => usage,
+                        Err(err) => {
+                            return Err(gst::loggable_error!(
+                                CAT,
+                                "cannot get buffer usage in output caps: {}",
+                                err
+                            ));
+                        }
+                    };
                 if !src_usages.intersects(wgpu::BufferUsages::COPY_DST) {
                     return Err(gst::loggable_error!(
                         CAT,
@@ -321,21 +294,17 @@ mod imp {
             }
 
             {
-                let Some(incaps_s) = incaps.structure(0) else {
-                    return Err(gst::loggable_error!(CAT, "missing structure in input caps"));
-                };
-
-                let sink_usages_bits: u32 = match incaps_s.get(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE) {
-                    Ok(usage) => usage,
-                    Err(err) => {
-                        return Err(gst::loggable_error!(
-                            CAT,
-                            "cannot get texture usage in input caps: {}",
-                            err
-                        ));
-                    }
-                };
-                let sink_usages = wgpu::TextureUsages::from_bits_truncate(sink_usages_bits);
+                let sink_usages =
+                    match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.texture_usages()) {
+                        Ok(usage) => usage,
+                        Err(err) => {
+                            return Err(gst::loggable_error!(
+                                CAT,
+                                "cannot get texture usage in input caps: {}",
+                                err
+                            ));
+                        }
+                    };
                 if !sink_usages.intersects(wgpu::TextureUsages::COPY_SRC) {
                     return Err(gst::loggable_error!(
                         CAT,
@@ -359,9 +328,9 @@ mod imp {
             assert!(0 < outbuf.n_memory());
             // If we are here, we are going to copy to output memory
 
-            let inmem = inbuf.peek_memory(0);
-            let Some(inmem) = inmem.downcast_memory_ref::<WgpuTextureMemory>() else {
-                gst::error!(CAT, imp: self, "invalid input memory");
+            // The texture is either the memory or attached as meta
+            let Some(in_texture) = buffer_texture(inbuf) else {
+                gst::error!(CAT, imp: self, "input buffer has no texture");
                 return Err(gst::FlowError::NotNegotiated);
             };
 
@@ -378,34 +347,44 @@ mod imp {
             };
 
             {
-                let src = inmem.texture();
+                let src = &in_texture;
                 let dst = outmem.buffer();
-                let ctx = self.locked_context();
-                let mut encoder = ctx.device().create_command_encoder(&Default::default());
-
-                encoder.copy_texture_to_buffer(
-                    wgpu::TexelCopyTextureInfo {
-                        texture: src,
-                        aspect: wgpu::TextureAspect::All,
-                        mip_level: 0,
-                        origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                    },
-                    wgpu::TexelCopyBufferInfo {
-                        buffer: dst,
-                        layout: TexelCopyBufferLayout {
-                            offset: 0,
-                            bytes_per_row: Some(4 * in_info.width()),
-                            rows_per_image: None,
+                let ctx = self.wgpu_context.locked();
+                let label = ctx.label(obj.upcast_ref(), "download", inbuf.pts());
+                let mut encoder =
+                    ctx.device()
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: label.as_deref(),
+                        });
+
+                let mut profiler_lock = self.profiler.lock();
+                let profiler =
+                    ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+                ElementProfiler::profile_encoder(profiler, &mut encoder, |encoder| {
+                    encoder.copy_texture_to_buffer(
+                        wgpu::TexelCopyTextureInfo {
+                            texture: src,
+                            aspect: wgpu::TextureAspect::All,
+                            mip_level: 0,
+                            origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
                         },
-                    },
-                    wgpu::Extent3d {
-                        width: in_info.width(),
-                        height: in_info.height(),
-                        depth_or_array_layers: 1,
-                    },
-                );
+                        wgpu::TexelCopyBufferInfo {
+                            buffer: dst,
+                            layout: TexelCopyBufferLayout {
+                                offset: 0,
+                                bytes_per_row: Some(4 * in_info.width()),
+                                rows_per_image: None,
+                            },
+                        },
+                        wgpu::Extent3d {
+                            width: in_info.width(),
+                            height: in_info.height(),
+                            depth_or_array_layers: 1,
+                        },
+                    );
+                });
 
-                ctx.queue().submit([encoder.finish()]);
+                ctx.submit([encoder.finish()]);
             }
 
             Ok(gst::FlowSuccess::Ok)
@@ -462,8 +441,10 @@ mod imp {
 
             gst::warning!(CAT, imp: self, "have to use own allocator");
 
-            let ctx = self.wgpu_context.lock().as_ref().cloned().unwrap();
-            let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, *src_usages);
+            let ctx = self.wgpu_context.get().unwrap();
+            let label = ctx.label(self.obj().upcast_ref(), "output buffers", None);
+            let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(ctx, *src_usages)
+                .with_label(label);
             let params = gst::AllocationParams::new(gst::MemoryFlags::empty(), 0, 0, 0);
             query.add_allocation_param(Some(&allocator), params);
 
@@ -0,0 +1,391 @@
+use gst::glib;
+use gst::prelude::*;
+
+glib::wrapper! {
+
+    /// Plugin that keeps the system memory buffer and attaches its GPU copy as WgpuTextureMeta
+    pub struct WgpuTextureMetaUpload(ObjectSubclass<imp::WgpuTextureMetaUpload>) @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "dekawgputexturemetaupload",
+        gst::Rank::NONE,
+        WgpuTextureMetaUpload::static_type(),
+    )
+}
+
+mod imp {
+    use std::sync::LazyLock;
+
+    use crate::glib;
+
+    use deka_gst_wgpu::caps::{fixate_wgpu_usages, format::srgb_view_formats, WgpuCapsInfo};
+    use deka_gst_wgpu::crop::FrameLayout;
+    use deka_gst_wgpu::texture_memory::{
+        WgpuTextureMemory, WgpuTextureMemoryAllocator, WgpuTextureMemoryExt,
+    };
+    use deka_gst_wgpu::texture_meta::WgpuTextureMeta;
+    use glib::object::Cast;
+    use glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
+    use gst::subclass::prelude::*;
+    use gst_base::subclass::prelude::*;
+    use gst_base::subclass::BaseTransformMode;
+    use gst_video::prelude::*;
+    use gst_video::subclass::prelude::VideoFilterImpl;
+    use parking_lot::Mutex;
+
+    use deka_gst_wgpu::element::{filter_caps, ElementContext};
+    use deka_gst_wgpu::{WgpuContext, WgpuContextOptions};
+
+    static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+        gst::DebugCategory::new(
+            "dekawgputexturemetaupload",
+            gst::DebugColorFlags::empty(),
+            Some("Deka's WebGPU upload to texture meta plugin"),
+        )
+    });
+
+    #[derive(Debug)]
+    pub struct WgpuTextureMetaUpload {
+        wgpu_context: ElementContext,
+
+        /// Textures for the negotiated output caps, they come back when downstream drops the meta
+        texture_pool: Mutex<Option<gst::BufferPool>>,
+    }
+
+    impl WgpuTextureMetaUpload {
+        fn src_allowed_usages() -> impl IntoIterator<Item = wgpu::TextureUsages> {
+            // We write into the texture through the queue
+            [
+                wgpu::TextureUsages::COPY_DST,
+                wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+                wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+                wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::STORAGE_BINDING,
+                wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+            ]
+        }
+
+        /// Formats with a texture format in the caps, see [`deka_gst_wgpu::caps::format`]
+        fn allowed_texture_formats_as_gst() -> impl IntoIterator<Item = gst_video::VideoFormat> {
+            [
+                gst_video::VideoFormat::Rgba,
+                gst_video::VideoFormat::Rgbx,
+                gst_video::VideoFormat::Bgra,
+                gst_video::VideoFormat::Bgrx,
+                gst_video::VideoFormat::Gray8,
+            ]
+        }
+
+        fn replace_texture_pool(&self, pool: Option<gst::BufferPool>) {
+            let old = std::mem::replace(&mut *self.texture_pool.lock(), pool);
+            if let Some(old) = old {
+                // Textures still held by buffers are dropped when the buffers go
+                let _ = old.set_active(false);
+            }
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for WgpuTextureMetaUpload {
+        const NAME: &'static str = "GstWgpuTextureMetaUpload";
+        type Type = super::WgpuTextureMetaUpload;
+        type ParentType = gst_video::VideoFilter;
+
+        fn with_class(_klass: &Self::Class) -> Self {
+            Self {
+                wgpu_context: ElementContext::default(),
+                texture_pool: Mutex::new(None),
+            }
+        }
+    }
+
+    impl ObjectImpl for WgpuTextureMetaUpload {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> =
+                LazyLock::new(WgpuContextOptions::param_specs);
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            if !self.wgpu_context.set_property(value, pspec) {
+                unimplemented!()
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            self.wgpu_context
+                .property(pspec)
+                .unwrap_or_else(|| unimplemented!())
+        }
+    }
+    impl GstObjectImpl for WgpuTextureMetaUpload {}
+    impl ElementImpl for WgpuTextureMetaUpload {
+        fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+            static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> =
+                LazyLock::new(|| {
+                    gst::subclass::ElementMetadata::new(
+                        "Deka's WebGPU Texture Meta Upload plugin",
+                        "Filter/Effect/Video",
+                        "Attaches GPU copy of system memory buffer as texture meta",
+                        "Deka <speedcrash100@ya.ru>",
+                    )
+                });
+            Some(&*ELEMENT_METADATA)
+        }
+
+        fn pad_templates() -> &'static [gst::PadTemplate] {
+            static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+                let def_ctx = WgpuContext::default();
+                let limits = def_ctx.limits();
+
+                let sink_caps = gst_video::VideoCapsBuilder::new()
+                    .format_list(WgpuTextureMetaUpload::allowed_texture_formats_as_gst())
+                    .height_range(1..limits.max_texture_dimension_2d as i32)
+                    .width_range(1..limits.max_texture_dimension_2d as i32)
+                    .build();
+
+                let src_caps = deka_gst_wgpu::caps::transform::gst_caps_with_texture_meta_usages(
+                    &sink_caps,
+                    WgpuTextureMetaUpload::src_allowed_usages,
+                );
+
+                vec![
+                    gst::PadTemplate::new(
+                        "sink",
+                        gst::PadDirection::Sink,
+                        gst::PadPresence::Always,
+                        &sink_caps,
+                    )
+                    .unwrap(),
+                    gst::PadTemplate::new(
+                        "src",
+                        gst::PadDirection::Src,
+                        gst::PadPresence::Always,
+                        &src_caps,
+                    )
+                    .unwrap(),
+                ]
+            });
+            PAD_TEMPLATES.as_ref()
+        }
+
+        fn set_context(&self, context: &gst::Context) {
+            self.wgpu_context
+                .set_gst_context(self.obj().upcast_ref(), context);
+
+            self.parent_set_context(context);
+        }
+    }
+
+    impl BaseTransformImpl for WgpuTextureMetaUpload {
+        // The input buffer goes further, only the meta is added
+        const MODE: BaseTransformMode = BaseTransformMode::AlwaysInPlace;
+        const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+        const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+        fn start(&self) -> Result<(), gst::ErrorMessage> {
+            self.wgpu_context.find_or_create(self.obj().upcast_ref());
+            Ok(())
+        }
+
+        fn stop(&self) -> Result<(), gst::ErrorMessage> {
+            self.replace_texture_pool(None);
+            Ok(())
+        }
+
+        fn transform_caps(
+            &self,
+            direction: gst::PadDirection,
+            caps: &gst::Caps,
+            filter: Option<&gst::Caps>,
+        ) -> Option<gst::Caps> {
+            let other_caps = if direction == gst::PadDirection::Src {
+                deka_gst_wgpu::caps::transform::gst_caps_without_texture(caps)
+            } else {
+                deka_gst_wgpu::caps::transform::gst_caps_with_texture_meta_usages(
+                    caps,
+                    Self::src_allowed_usages,
+                )
+            };
+
+            gst::trace!(
+                CAT,
+                imp: self,
+                "Transformed caps from {} to {} in direction {:?}; filter: {:?}",
+                caps,
+                other_caps,
+                direction,
+                filter
+            );
+
+            Some(filter_caps(other_caps, filter))
+        }
+
+        fn fixate_caps(
+            &self,
+            direction: gst::PadDirection,
+            caps: &gst::Caps,
+            othercaps: gst::Caps,
+        ) -> gst::Caps {
+            let othercaps = fixate_wgpu_usages(othercaps);
+            self.parent_fixate_caps(direction, caps, othercaps)
+        }
+
+        fn set_caps(
+            &self,
+            incaps: &gst::Caps,
+            outcaps: &gst::Caps,
+        ) -> Result<(), gst::LoggableError> {
+            gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
+
+            let src_info = match WgpuCapsInfo::from_caps(outcaps) {
+                Ok(info) => info,
+                Err(err) => {
+                    return Err(gst::loggable_error!(CAT, "invalid output caps: {}", err));
+                }
+            };
+
+            let src_usages = match src_info.texture_usages() {
+                Ok(usage) => usage,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture usage in output caps: {}",
+                        err
+                    ));
+                }
+            };
+            if !src_usages.contains(wgpu::TextureUsages::COPY_DST) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "texture usage({:?}) in output caps cannot be used as copy destination",
+                    src_usages
+                ));
+            }
+
+            let src_format = match src_info.texture_format() {
+                Ok(format) => format,
+                Err(err) => {
+                    return Err(gst::loggable_error!(
+                        CAT,
+                        "cannot get texture format in output caps: {}",
+                        err
+                    ));
+                }
+            };
+
+            let info = match gst_video::VideoInfo::from_caps(outcaps) {
+                Ok(info) => info,
+                Err(err) => {
+                    return Err(gst::loggable_error!(CAT, "invalid output caps: {}", err));
+                }
+            };
+
+            let descriptor = wgpu::TextureDescriptor {
+                label: None,
+                dimension: wgpu::TextureDimension::D2,
+                format: src_format,
+                mip_level_count: 1,
+                sample_count: 1,
+                size: wgpu::Extent3d {
+                    width: info.width(),
+                    height: info.height(),
+                    depth_or_array_layers: 1,
+                },
+                usage: src_usages,
+                view_formats: srgb_view_formats(src_format),
+            };
+
+            let ctx = self.wgpu_context.locked().clone();
+            let label = ctx.label(self.obj().upcast_ref(), "textures", None);
+            let allocator = WgpuTextureMemoryAllocator::new(ctx, descriptor).with_label(label);
+            let params = gst::AllocationParams::new(gst::MemoryFlags::NOT_MAPPABLE, 0, 0, 0);
+
+            let pool = gst::BufferPool::new();
+            let mut config = pool.config();
+            config.set_params(Some(outcaps), info.size() as u32, 0, 0);
+            config.set_allocator(Some(&allocator), Some(&params));
+            if let Err(err) = pool.set_config(config) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "failed to configure texture pool: {}",
+                    err
+                ));
+            }
+            if let Err(err) = pool.set_active(true) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "failed to activate texture pool: {}",
+                    err
+                ));
+            }
+            self.replace_texture_pool(Some(pool));
+
+            self.parent_set_caps(incaps, outcaps)
+        }
+
+        fn transform_ip(
+            &self,
+            buf: &mut gst::BufferRef,
+        ) -> Result<gst::FlowSuccess, gst::FlowError> {
+            let obj = self.obj();
+            let self_as_filter = obj.upcast_ref::<gst_video::VideoFilter>();
+            let Some(in_info) = self_as_filter.input_video_info() else {
+                return Err(gst::FlowError::NotNegotiated);
+            };
+
+            let ctx = self.wgpu_context.locked().clone();
+            let Some(pool) = self.texture_pool.lock().clone() else {
+                return Err(gst::FlowError::NotNegotiated);
+            };
+            let texture_buffer = pool.acquire_buffer(None)?;
+            let Some(texture) = texture_buffer
+                .peek_memory(0)
+                .downcast_memory_ref::<WgpuTextureMemory>()
+                .map(|mem| mem.texture().clone())
+            else {
+                gst::error!(CAT, imp: self, "texture pool gave buffer without texture");
+                return Err(gst::FlowError::Error);
+            };
+
+            let layout = FrameLayout::from_buffer(buf, &in_info);
+
+            {
+                // Mapping for read only keeps the memory shared with other branches
+                let Ok(map) = buf.map_readable() else {
+                    gst::error!(CAT, imp: self, "failed to map input buffer");
+                    return Err(gst::FlowError::Error);
+                };
+
+                // Commands of earlier frames may still read the pooled texture, the write goes after them
+                ctx.write_texture(
+                    wgpu::TexelCopyTextureInfo {
+                        texture: &texture,
+                        aspect: wgpu::TextureAspect::All,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                    },
+                    &map,
+                    wgpu::TexelCopyBufferLayout {
+                        offset: layout.offset,
+                        bytes_per_row: Some(layout.stride),
+                        rows_per_image: None,
+                    },
+                    texture.size(),
+                );
+            }
+
+            if WgpuTextureMeta::add_from_buffer(buf, ctx, texture_buffer).is_none() {
+                return Err(gst::FlowError::Error);
+            }
+
+            Ok(gst::FlowSuccess::Ok)
+        }
+    }
+
+    impl VideoFilterImpl for WgpuTextureMetaUpload {}
+}
@@ -21,17 +21,15 @@ mod imp {
 
     use crate::glib;
 
-    use deka_gst_wgpu::buffer_memory::{
-        WgpuBufferMemory, GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER, GST_CAPS_FIELD_WGPU_BUFFER_USAGE,
-    };
+    use deka_gst_wgpu::buffer_memory::{WgpuBufferMemory, GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER};
+    use deka_gst_wgpu::caps::{fixate_wgpu_usages, WgpuCapsInfo};
+    use deka_gst_wgpu::crop::{clear_around_crop, FrameLayout};
     use deka_gst_wgpu::prelude::*;
     use deka_gst_wgpu::texture_memory::{
-        WgpuTextureMemory, WgpuTextureMemoryAllocator, WgpuTextureMemoryExt,
-        GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE, GST_CAPS_FIELD_WGPU_TEXTURE_USAGE,
+        WgpuTextureMemory, WgpuTextureMemoryExt, GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE,
     };
     use glib::object::Cast;
     use glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
-    use gst::prelude::ElementExt;
     use gst::subclass::prelude::*;
     use gst_base::subclass::prelude::*;
     use gst_base::subclass::BaseTransformMode;
@@ -39,7 +37,10 @@ mod imp {
     use gst_video::subclass::prelude::VideoFilterImpl;
     use parking_lot::Mutex;
 
-    use deka_gst_wgpu::{WgpuContext, GST_CONTEXT_WGPU_TYPE};
+    use deka_gst_wgpu::element::{decide_texture_allocation, filter_caps, ElementContext};
+    use deka_gst_wgpu::profiler::{stats_param_spec, ElementProfiler, GST_WGPU_STATS_PROPERTY};
+    use deka_gst_wgpu::WgpuContextOptions;
+    use deka_gst_wgpu::{batcher::flush_on_eos, WgpuContext};
     use wgpu::TexelCopyBufferLayout;
 
     static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
@@ -52,48 +53,13 @@ mod imp {
 
     #[derive(Debug)]
     pub struct WgpuTextureUpload {
-        wgpu_context: Mutex<Option<WgpuContext>>,
+        wgpu_context: ElementContext,
+        profiler: Mutex<Option<ElementProfiler>>,
 
         sink_usages: Mutex<wgpu::BufferUsages>,
-        src_usages: Mutex<wgpu::TextureUsages>,
     }
 
     impl WgpuTextureUpload {
-        pub fn set_wgpu_context(&self, context: WgpuContext) {
-            let mut lock: parking_lot::lock_api::MutexGuard<
-                '_,
-                parking_lot::RawMutex,
-                Option<WgpuContext>,
-            > = self.wgpu_context.lock();
-
-            if lock.is_some() {
-                return;
-            }
-
-            *lock = Some(context);
-        }
-
-        fn create_own_context(&self) {
-            gst::info!(CAT, imp: self, "creating own wgpu context");
-
-            let obj = self.obj();
-            let element = obj.upcast_ref::<gst::Element>();
-
-            let wgpu_ctx = WgpuContext::default();
-            let ctx = wgpu_ctx.as_gst_context();
-            self.set_context(&ctx);
-
-            let message = gst::message::HaveContext::builder(ctx)
-                .src(&*self.obj())
-                .build();
-            element.post_message(message).unwrap();
-        }
-
-        /// Locks context
-        fn locked_context(&self) -> parking_lot::MappedMutexGuard<'_, WgpuContext> {
-            parking_lot::MutexGuard::map(self.wgpu_context.lock(), |x| x.as_mut().unwrap())
-        }
-
         fn sink_allowed_usages() -> impl IntoIterator<Item = wgpu::BufferUsages> {
             // We need to be able to copy from buffer
             [
@@ -128,14 +94,46 @@ mod imp {
 
         fn with_class(_klass: &Self::Class) -> Self {
             Self {
-                wgpu_context: Mutex::new(None),
-                src_usages: Mutex::new(wgpu::TextureUsages::empty()),
+                wgpu_context: ElementContext::default(),
+                profiler: Mutex::new(None),
                 sink_usages: Mutex::new(wgpu::BufferUsages::empty()),
             }
         }
     }
 
-    impl ObjectImpl for WgpuTextureUpload {}
+    impl ObjectImpl for WgpuTextureUpload {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+                let mut properties = vec![stats_param_spec()];
+                properties.extend(WgpuContextOptions::param_specs());
+                properties
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+            if !self.wgpu_context.set_property(value, pspec) {
+                unimplemented!()
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            match pspec.name() {
+                GST_WGPU_STATS_PROPERTY => self
+                    .profiler
+                    .lock()
+                    .as_ref()
+                    .map(|x| x.stats())
+                    .unwrap_or_default()
+                    .to_structure()
+                    .to_value(),
+                _ => self
+                    .wgpu_context
+                    .property(pspec)
+                    .unwrap_or_else(|| unimplemented!()),
+            }
+        }
+    }
     impl GstObjectImpl for WgpuTextureUpload {}
     impl ElementImpl for WgpuTextureUpload {
         fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
@@ -204,16 +202,8 @@ mod imp {
         }
 
         fn set_context(&self, context: &gst::Context) {
-            if context.context_type() == GST_CONTEXT_WGPU_TYPE {
-                gst::debug!(CAT, imp: self, "Received wgpu context");
-
-                let Some(wgpu_ctx) = WgpuContext::map_gst_context_to_wgpu(context.clone()) else {
-                    gst::error!(CAT, imp: self, "Received invalid wgpu context");
-                    return;
-                };
-
-                self.set_wgpu_context(wgpu_ctx);
-            }
+            self.wgpu_context
+                .set_gst_context(self.obj().upcast_ref(), context);
 
             self.parent_set_context(context);
         }
@@ -225,24 +215,14 @@ mod imp {
         const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
 
         fn start(&self) -> Result<(), gst::ErrorMessage> {
-            let obj = self.obj();
-            let element = obj.upcast_ref::<gst::Element>();
+            self.wgpu_context.find_or_create(self.obj().upcast_ref());
+            Ok(())
+        }
 
-            match WgpuContext::query_context_from_nearby_elements(element) {
-                Ok(true) => {
-                    gst::info!(CAT, imp: self, "using shared wgpu context");
-                    Ok(())
-                }
-                Ok(false) => {
-                    self.create_own_context();
-                    Ok(())
-                }
-                Err(err) => {
-                    gst::error!(CAT, imp: self, "failed to query wgpu context from nearby elements: {}", err);
-                    self.create_own_context();
-                    Ok(())
-                }
-            }
+        fn sink_event(&self, event: gst::Event) -> bool {
+            flush_on_eos(self.wgpu_context.lock().as_ref(), &event);
+
+            self.parent_sink_event(event)
         }
 
         fn transform_caps(
@@ -273,13 +253,17 @@ mod imp {
                 filter
             );
 
-            // In the end we need to filter the caps through an optional filter caps to get rid of any
-            // unwanted caps.
-            if let Some(filter) = filter {
-                Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
-            } else {
-                Some(other_caps)
-            }
+            Some(filter_caps(other_caps, filter))
+        }
+
+        fn fixate_caps(
+            &self,
+            direction: gst::PadDirection,
+            caps: &gst::Caps,
+            othercaps: gst::Caps,
+        ) -> gst::Caps {
+            let othercaps = fixate_wgpu_usages(othercaps);
+            self.parent_fixate_caps(direction, caps, othercaps)
         }
 
         fn set_caps(
@@ -290,24 +274,17 @@ mod imp {
             gst::info!(CAT, imp: self, "negotiated caps {:?} -> {:?}", incaps, outcaps);
 
             {
-                let Some(outcaps_s) = outcaps.structure(0) else {
-                    return Err(gst::loggable_error!(
-                        CAT,
-                        "missing structure in output caps"
-                    ));
-                };
-
-                let src_usages_bits: u32 = match outcaps_s.get(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE) {
-                    Ok(usage) => usage,
-                    Err(err) => {
-                        return Err(gst::loggable_error!(
-                            CAT,
-                            "cannot get texture usage in output caps: {}",
-                            err
-                        ));
-                    }
-                };
-                let src_usages = wgpu::TextureUsages::from_bits_truncate(src_usages_bits);
+                let src_usages =
+                    match WgpuCapsInfo::from_caps(outcaps).and_then(|info| info.texture_usages()) {
+                        Ok(usage) => usage,
+                        Err(err) => {
+                            return Err(gst::loggable_error!(
+                                CAT,
+                                "cannot get texture usage in output caps: {}",
+                                err
+                            ));
+                        }
+                    };
                 if !src_usages.intersects(wgpu::TextureUsages::COPY_DST) {
                     return Err(gst::loggable_error!(
                         CAT,
@@ -315,26 +292,20 @@ mod imp {
                         src_usages
                     ));
                 }
-
-                *self.src_usages.lock() = src_usages;
             }
 
             {
-                let Some(incaps_s) = incaps.structure(0) else {
-                    return Err(gst::loggable_error!(CAT, "missing structure in input caps"));
-                };
-
-                let sink_usages_bits: u32 = match incaps_s.get(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
-                    Ok(usage) => usage,
-                    Err(err) => {
-                        return Err(gst::loggable_error!(
-                            CAT,
-                            "cannot get buffer usage in input caps: {}",
-                            err
-                        ));
-                    }
-                };
-                let sink_usages = wgpu::BufferUsages::from_bits_truncate(sink_usages_bits);
+                let sink_usages =
+                    match WgpuCapsInfo::from_caps(incaps).and_then(|info| info.buffer_usages()) {
+                        Ok(usage) => usage,
+                        Err(err) => {
+                            return Err(gst::loggable_error!(
+                                CAT,
+                                "cannot get buffer usage in input caps: {}",
+                                err
+                            ));
+                        }
+                    };
                 if !sink_usages.intersects(wgpu::BufferUsages::COPY_SRC) {
                     return Err(gst::loggable_error!(
                         CAT,
@@ -376,39 +347,68 @@ mod imp {
                 return Err(gst::FlowError::NotNegotiated);
             };
 
+            // Padding around the crop rectangle is left out, the visible part goes to the texture corner and the rest is
+            // cleared
+            let layout = FrameLayout::from_buffer(inbuf, &in_info);
+            let crop = layout.crop_rect(inbuf);
+            let texture = outmem.texture();
+            let copy_width = crop.width.min(texture.width());
+            let copy_height = crop.height.min(texture.height());
+
             {
                 let buffer = inmem.buffer();
-                let texture = outmem.texture();
-                let ctx = self.locked_context();
-                let mut encoder = ctx.device().create_command_encoder(&Default::default());
-                encoder.copy_buffer_to_texture(
-                    wgpu::TexelCopyBufferInfo {
-                        buffer,
-                        layout: TexelCopyBufferLayout {
-                            offset: 0,
-                            bytes_per_row: Some(4 * in_info.width()),
-                            rows_per_image: None,
-                        },
-                    },
-                    wgpu::TexelCopyTextureInfo {
-                        texture,
-                        aspect: wgpu::TextureAspect::All,
-                        mip_level: 0,
-                        origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
-                    },
-                    wgpu::Extent3d {
-                        width: in_info.width(),
-                        height: in_info.height(),
-                        depth_or_array_layers: 1,
-                    },
-                );
-
-                ctx.queue().submit([encoder.finish()]);
+                let ctx = self.wgpu_context.locked();
+                let mut profiler_lock = self.profiler.lock();
+                let profiler =
+                    ElementProfiler::get_or_init(&mut profiler_lock, &ctx, obj.upcast_ref());
+                let cleared = ctx.record(obj.upcast_ref(), inbuf.pts(), |encoder| {
+                    ElementProfiler::profile_encoder(profiler, encoder, |encoder| {
+                        let cleared = clear_around_crop(&ctx, encoder, texture, crop);
+                        encoder.copy_buffer_to_texture(
+                            wgpu::TexelCopyBufferInfo {
+                                buffer,
+                                layout: TexelCopyBufferLayout {
+                                    offset: layout.crop_offset(crop, 4),
+                                    bytes_per_row: Some(layout.stride),
+                                    rows_per_image: None,
+                                },
+                            },
+                            wgpu::TexelCopyTextureInfo {
+                                texture,
+                                aspect: wgpu::TextureAspect::All,
+                                mip_level: 0,
+                                origin: wgpu::Origin3d { x: 0, y: 0, z: 0 },
+                            },
+                            wgpu::Extent3d {
+                                width: copy_width,
+                                height: copy_height,
+                                depth_or_array_layers: 1,
+                            },
+                        );
+                        cleared
+                    })
+                });
+                if !cleared {
+                    gst::warning!(CAT, imp: self, "cannot clear the texture around {:?}", crop);
+                }
             }
 
             Ok(gst::FlowSuccess::Ok)
         }
 
+        fn propose_allocation(
+            &self,
+            decide_query: Option<&gst::query::Allocation>,
+            query: &mut gst::query::Allocation,
+        ) -> Result<(), gst::LoggableError> {
+            self.parent_propose_allocation(decide_query, query)?;
+
+            // Only the crop rectangle is uploaded, so decoders may keep the padding
+            query.add_allocation_meta::<gst_video::VideoCropMeta>(None);
+
+            Ok(())
+        }
+
         fn unit_size(&self, caps: &gst::Caps) -> Option<usize> {
             let video_caps = gst_video::VideoInfo::from_caps(&caps).ok()?;
             Some(video_caps.size())
@@ -418,97 +418,20 @@ mod imp {
             &self,
             query: &mut gst::query::Allocation,
         ) -> Result<(), gst::LoggableError> {
-            let src_usages = self.src_usages.lock();
-            if src_usages.is_empty() {
+            let Some(ctx) = self.wgpu_context.get() else {
                 return Err(gst::loggable_error!(
                     CAT,
-                    "decide_allocation called before negotiation"
+                    "decide_allocation called before start"
                 ));
-            }
-
-            let mut to_remove = vec![];
-
-            for (pos, (allocator, _params)) in query.allocation_params().iter().enumerate() {
-                let Some(wgpu_allocator) =
-                    allocator.and_downcast_ref::<WgpuTextureMemoryAllocator>()
-                else {
-                    gst::trace!(CAT, imp: self, "skipping allocator at {pos}, not an WGPU texture");
-                    to_remove.push(pos);
-                    continue;
-                };
-
-                let usages = wgpu_allocator.descriptor().usage;
-                let required = wgpu::TextureUsages::COPY_DST;
-                if !usages.contains(required) {
-                    gst::trace!(CAT, imp: self, "skipping allocator at {pos}, usages is incorrect {} != {}", required.bits(), usages.bits());
-                    to_remove.push(pos);
-                }
-            }
-
-            for pos in to_remove.iter().rev() {
-                query.remove_nth_allocation_param(*pos as u32);
-            }
-
-            if 0 < query.allocation_params().len() {
-                gst::trace!(CAT, imp: self, "got allocators: {:?}", query.allocation_params());
-                return Ok(());
-            }
-
-            gst::warning!(CAT, imp: self, "have to use own allocator");
-
-            let (caps, _needs_pool) = query.get();
-
-            let Some(caps) = caps else {
-                return Err(gst::loggable_error!(
-                    CAT,
-                    "decide_allocation called wo caps"
-                ));
-            };
-
-            let Some(s) = caps.structure(0) else {
-                return Err(gst::loggable_error!(CAT, "caps structure missing"));
-            };
-
-            let width: i32 = match s.get("width") {
-                Ok(v) => v,
-                Err(err) => {
-                    return Err(gst::loggable_error!(CAT, "can't find width: {}", err));
-                }
             };
 
-            let height: i32 = match s.get("height") {
-                Ok(v) => v,
-                Err(err) => {
-                    return Err(gst::loggable_error!(CAT, "can't find width: {}", err));
-                }
-            };
-
-            let desciptor = wgpu::TextureDescriptor {
-                label: None,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                mip_level_count: 1,
-                sample_count: 1,
-                size: wgpu::Extent3d {
-                    width: width as u32,
-                    height: height as u32,
-                    depth_or_array_layers: 1,
-                },
-                usage: *src_usages,
-                view_formats: &[],
-            };
-
-            let ctx = self.wgpu_context.lock().as_ref().cloned().unwrap();
-            let allocator = WgpuTextureMemoryAllocator::new(ctx, desciptor);
-            let params = gst::AllocationParams::new(gst::MemoryFlags::NOT_MAPPABLE, 0, 0, 0);
-            query.add_allocation_param(Some(&allocator), params);
-
-            // No pool support at the moment
-            while !query.allocation_pools().is_empty() {
-                query.remove_nth_allocation_pool(0);
-            }
-
-            Ok(())
+            decide_texture_allocation(
+                self.obj().upcast_ref(),
+                &ctx,
+                query,
+                wgpu::TextureUsages::COPY_DST,
+                None,
+            )
         }
     }
 
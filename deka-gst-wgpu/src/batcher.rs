@@ -0,0 +1,226 @@
+//!
+//! Merges commands of chained elements into one submission per frame
+//!
+//! Elements record their commands with [`crate::WgpuContext::record`] into the shared encoder. The batch is submitted
+//! when:
+//! * any submission is made with [`crate::WgpuContext::submit`], for example by download which waits for the result
+//! * an element records the second time, so one batch holds at most one frame of every element
+//! * an element records from the other thread than the batch was started in (e.g. after `queue`)
+//! * [`crate::WgpuContext::flush`] is called, for example at EOS by [`flush_on_eos`]
+//!
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::ThreadId;
+
+use gst::prelude::*;
+use parking_lot::Mutex;
+
+use crate::{poller::DevicePoller, WgpuContext};
+
+#[derive(Debug, Default)]
+struct BatchState {
+    encoder: Option<wgpu::CommandEncoder>,
+    /// Thread the batch was started in
+    thread: Option<ThreadId>,
+    /// Addresses of the elements which recorded into the batch, used only for comparison
+    owners: Vec<usize>,
+}
+
+impl BatchState {
+    fn take(&mut self) -> Option<wgpu::CommandBuffer> {
+        self.thread = None;
+        self.owners.clear();
+        self.encoder.take().map(|encoder| encoder.finish())
+    }
+}
+
+/// Shared frame-scoped command encoder of the context, disabled by default
+#[derive(Debug, Default)]
+pub struct CommandBatcher {
+    enabled: AtomicBool,
+    state: Mutex<BatchState>,
+}
+
+impl CommandBatcher {
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// Enables or disables batching, the pending batch is submitted on disable
+    pub fn set_enabled(&self, poller: &DevicePoller, queue: &wgpu::Queue, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Release);
+        if !enabled {
+            self.flush(poller, queue);
+        }
+    }
+
+    /// Records commands of `owner` into the batch or submits them at once if batching is disabled
+    ///
+    /// `f` is called under the batch lock, it must not submit.
+    pub fn record<R>(
+        &self,
+        device: &wgpu::Device,
+        poller: &DevicePoller,
+        queue: &wgpu::Queue,
+        owner: &gst::Element,
+        f: impl FnOnce(&mut wgpu::CommandEncoder) -> R,
+    ) -> R {
+        if !self.is_enabled() {
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let out = f(&mut encoder);
+            poller.submit(queue, [encoder.finish()]);
+            return out;
+        }
+
+        let owner = owner.as_ptr() as usize;
+        let thread = std::thread::current().id();
+
+        let mut state = self.state.lock();
+        let new_frame = state.owners.contains(&owner);
+        let other_thread = state.thread.is_some_and(|x| x != thread);
+        if new_frame || other_thread {
+            if let Some(command_buffer) = state.take() {
+                poller.submit(queue, [command_buffer]);
+            }
+        }
+
+        state.thread = Some(thread);
+        state.owners.push(owner);
+        let encoder = state
+            .encoder
+            .get_or_insert_with(|| device.create_command_encoder(&Default::default()));
+
+        f(encoder)
+    }
+
+    /// Submits the pending batch followed by `command_buffers`
+    pub fn submit<I: IntoIterator<Item = wgpu::CommandBuffer>>(
+        &self,
+        poller: &DevicePoller,
+        queue: &wgpu::Queue,
+        command_buffers: I,
+    ) -> wgpu::SubmissionIndex {
+        // Hold the lock while submitting, so nothing is recorded in between
+        let mut state = self.state.lock();
+        let pending = state.take();
+        poller.submit(queue, pending.into_iter().chain(command_buffers))
+    }
+
+    /// Submits the pending batch, returns `None` if there was nothing to submit
+    pub fn flush(
+        &self,
+        poller: &DevicePoller,
+        queue: &wgpu::Queue,
+    ) -> Option<wgpu::SubmissionIndex> {
+        let mut state = self.state.lock();
+        let pending = state.take()?;
+        Some(poller.submit(queue, [pending]))
+    }
+
+    /// Writes `size` bytes at `offset` of `buffer` through the queue after the commands of the pending batch
+    ///
+    /// Queue writes are executed before the next submission, so the batch is submitted first and nothing is recorded
+    /// until the write is scheduled. `f` fills the written bytes. Returns `None` if the queue cannot make the write.
+    pub fn write_buffer<R>(
+        &self,
+        poller: &DevicePoller,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        size: wgpu::BufferSize,
+        f: impl FnOnce(&mut [u8]) -> R,
+    ) -> Option<R> {
+        let mut state = self.state.lock();
+        if let Some(pending) = state.take() {
+            poller.submit(queue, [pending]);
+        }
+
+        let mut view = queue.write_buffer_with(buffer, offset, size)?;
+        let out = f(&mut view);
+        drop(view);
+
+        // Schedules the write
+        poller.submit(queue, []);
+        Some(out)
+    }
+
+    /// Writes `data` into `texture` through the queue after the commands of the pending batch, like
+    /// [`Self::write_buffer`]
+    pub fn write_texture(
+        &self,
+        poller: &DevicePoller,
+        queue: &wgpu::Queue,
+        texture: wgpu::TexelCopyTextureInfo<'_>,
+        data: &[u8],
+        layout: wgpu::TexelCopyBufferLayout,
+        size: wgpu::Extent3d,
+    ) {
+        let mut state = self.state.lock();
+        if let Some(pending) = state.take() {
+            poller.submit(queue, [pending]);
+        }
+
+        queue.write_texture(texture, data, layout, size);
+
+        // Schedules the write
+        poller.submit(queue, []);
+    }
+}
+
+/// Submits the pending batch of `context` at EOS, call it from `sink_event` of elements which record
+///
+/// Nothing is recorded after EOS, so the last frame would wait in the batch until the next submission otherwise.
+pub fn flush_on_eos(context: Option<&WgpuContext>, event: &gst::EventRef) {
+    if let (gst::EventView::Eos(_), Some(context)) = (event.view(), context) {
+        context.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gst::prelude::*;
+
+    use crate::{WgpuContext, WgpuContextOptions};
+
+    #[test]
+    fn chain_makes_one_submission_per_frame() {
+        gst::init().unwrap();
+        let options = WgpuContextOptions {
+            command_batching: true,
+            ..Default::default()
+        };
+        let Some(ctx) = WgpuContext::for_tests(options) else {
+            return;
+        };
+        assert!(ctx.command_batching());
+
+        let buffer = ctx.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let upload = gst::Bin::with_name("upload");
+        let filter = gst::Bin::with_name("filter");
+        let frame = |ctx: &WgpuContext| {
+            for element in [&upload, &filter] {
+                ctx.record(element.upcast_ref(), None, |encoder| {
+                    encoder.clear_buffer(&buffer, 0, None)
+                });
+            }
+        };
+
+        let start = ctx.poller().submissions();
+        frame(&ctx);
+        assert_eq!(ctx.poller().submissions(), start);
+
+        // The upload records the next frame, so the batch of the first one is submitted
+        frame(&ctx);
+        assert_eq!(ctx.poller().submissions(), start + 1);
+
+        assert!(ctx.flush().is_some());
+        assert_eq!(ctx.poller().submissions(), start + 2);
+        assert!(ctx.flush().is_none());
+    }
+}
@@ -2,7 +2,11 @@
 //! The GstMemory subclass for WgpuBuffers
 //!
 
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
 use std::sync::LazyLock;
+use std::task::{Context, Poll};
 
 use glib::translate::IntoGlibPtr;
 use glib::translate::{from_glib, from_glib_full};
@@ -20,8 +24,8 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
 
 /// Caps with this feature implies that the buffer is a WGPU buffer.
 pub const GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER: &str = "memory:WgpuBuffer";
-/// The field in structure to determinate buffer usage, this is bitmask, the element should allocated output buffers which will
-/// contains all of required buffer usages
+/// The field in structure to determinate buffer usage, this is [`crate::caps::usage::WgpuBufferUsageFlags`], the element should
+/// allocated output buffers which will contains all of required buffer usages
 pub const GST_CAPS_FIELD_WGPU_BUFFER_USAGE: &str = "buffer-usage";
 
 pub trait WgpuBufferMemoryExt {
@@ -58,14 +62,236 @@ impl WgpuBufferMemoryExt for WgpuBufferMemory {
     }
 }
 
+impl WgpuBufferMemoryRef {
+    /// Maps the memory for read without blocking
+    ///
+    /// The future resolves when the buffer is ready to be read. In [`crate::PollType::Manual`] mode
+    /// the device must be polled by the user, otherwise the context poll thread drives the map.
+    pub fn map_read_async(&self) -> WgpuBufferMapReadFuture<'_> {
+        WgpuBufferMapReadFuture {
+            memory: self,
+            progress: imp::AsyncMap::default(),
+        }
+    }
+
+    /// Maps the memory for write without blocking, see [`Self::map_read_async`]
+    pub fn map_write_async(&mut self) -> WgpuBufferMapWriteFuture<'_> {
+        WgpuBufferMapWriteFuture {
+            memory: Some(self),
+            progress: imp::AsyncMap::default(),
+        }
+    }
+
+    /// Pointer to the first byte of the memory window from the base pointer returned by the map
+    fn window_pointer(&self, base: glib::ffi::gpointer) -> *mut u8 {
+        (base as *mut u8).wrapping_add(self.offset())
+    }
+}
+
+/// Future returned by [`WgpuBufferMemoryRef::map_read_async`]
+pub struct WgpuBufferMapReadFuture<'a> {
+    memory: &'a WgpuBufferMemoryRef,
+    progress: imp::AsyncMap,
+}
+
+impl<'a> Future for WgpuBufferMapReadFuture<'a> {
+    type Output = Result<WgpuBufferMapReadGuard<'a>, glib::BoolError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let memory = this.memory;
+
+        memory
+            .0
+            .poll_map_async(&mut this.progress, wgpu::MapMode::Read, cx)
+            .map_ok(|base| WgpuBufferMapReadGuard {
+                memory,
+                data: memory.window_pointer(base),
+            })
+    }
+}
+
+impl Drop for WgpuBufferMapReadFuture<'_> {
+    fn drop(&mut self) {
+        self.memory.0.cancel_map_async(&mut self.progress);
+    }
+}
+
+/// Future returned by [`WgpuBufferMemoryRef::map_write_async`]
+pub struct WgpuBufferMapWriteFuture<'a> {
+    /// Taken by the guard when the map is ready
+    memory: Option<&'a mut WgpuBufferMemoryRef>,
+    progress: imp::AsyncMap,
+}
+
+impl<'a> Future for WgpuBufferMapWriteFuture<'a> {
+    type Output = Result<WgpuBufferMapWriteGuard<'a>, glib::BoolError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let Some(memory) = this.memory.as_deref() else {
+            return Poll::Ready(Err(glib::bool_error!("async map polled after completion")));
+        };
+
+        let base = match memory
+            .0
+            .poll_map_async(&mut this.progress, wgpu::MapMode::Write, cx)
+        {
+            Poll::Ready(Ok(base)) => base,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let data = memory.window_pointer(base);
+        let memory = this.memory.take().unwrap();
+
+        Poll::Ready(Ok(WgpuBufferMapWriteGuard { memory, data }))
+    }
+}
+
+impl Drop for WgpuBufferMapWriteFuture<'_> {
+    fn drop(&mut self) {
+        if let Some(memory) = self.memory.as_deref() {
+            memory.0.cancel_map_async(&mut self.progress);
+        }
+    }
+}
+
+/// Read access to the mapped memory, the memory is unmapped on drop
+pub struct WgpuBufferMapReadGuard<'a> {
+    memory: &'a WgpuBufferMemoryRef,
+    data: *mut u8,
+}
+
+impl Deref for WgpuBufferMapReadGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the window of the memory is mapped while the guard is alive
+        unsafe { std::slice::from_raw_parts(self.data, self.memory.size()) }
+    }
+}
+
+impl Drop for WgpuBufferMapReadGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { self.memory.0.unmap() };
+    }
+}
+
+/// Write access to the mapped memory, the memory is unmapped on drop
+pub struct WgpuBufferMapWriteGuard<'a> {
+    memory: &'a mut WgpuBufferMemoryRef,
+    data: *mut u8,
+}
+
+impl Deref for WgpuBufferMapWriteGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the window of the memory is mapped while the guard is alive
+        unsafe { std::slice::from_raw_parts(self.data, self.memory.size()) }
+    }
+}
+
+impl DerefMut for WgpuBufferMapWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: the window of the memory is mapped for write while the guard is alive
+        unsafe { std::slice::from_raw_parts_mut(self.data, self.memory.size()) }
+    }
+}
+
+impl Drop for WgpuBufferMapWriteGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { self.memory.0.unmap() };
+    }
+}
+
+/// Safety: map state of the memory is protected by mutex, the mapped pointers can be used from any thread
+unsafe impl Send for WgpuBufferMapReadFuture<'_> {}
+unsafe impl Send for WgpuBufferMapWriteFuture<'_> {}
+unsafe impl Send for WgpuBufferMapReadGuard<'_> {}
+unsafe impl Sync for WgpuBufferMapReadGuard<'_> {}
+unsafe impl Send for WgpuBufferMapWriteGuard<'_> {}
+unsafe impl Sync for WgpuBufferMapWriteGuard<'_> {}
+
 impl WgpuBufferMemory {
+    /// Copies content of `src` to the memory, see [`Self::fill_with`]
     pub fn fill_from_gst(&mut self, src: &gst::MemoryRef) -> Result<(), glib::BoolError> {
-        let dst = self.get_mut().unwrap().upcast_memory_mut::<gst::Memory>();
-        let mut mapped_dst = dst.map_writable()?;
         let mapped_src = src.map_readable()?;
 
-        let copy_size = mapped_dst.size().min(mapped_src.size());
-        mapped_dst[0..copy_size].copy_from_slice(&mapped_src[0..copy_size]);
+        self.fill_with(|dst| {
+            let copy_size = dst.len().min(mapped_src.size());
+            dst[..copy_size].copy_from_slice(&mapped_src[..copy_size]);
+            dst[copy_size..].fill(0);
+        })
+    }
+
+    /// Fills the memory by `f`, which must write all bytes of the slice it gets
+    ///
+    /// Mappable buffers are filled by mapping, the ones which only have COPY_DST are written through
+    /// the queue staging memory, so GPU local buffers can be filled as well
+    pub fn fill_with(&mut self, f: impl FnOnce(&mut [u8])) -> Result<(), glib::BoolError> {
+        let usage = self.buffer().usage();
+
+        if usage.contains(wgpu::BufferUsages::MAP_WRITE) {
+            let dst = self.get_mut().unwrap().upcast_memory_mut::<gst::Memory>();
+            let mut mapped_dst = dst.map_writable()?;
+            f(&mut mapped_dst);
+
+            return Ok(());
+        }
+
+        if usage.contains(wgpu::BufferUsages::COPY_DST) {
+            return self.write_with(f);
+        }
+
+        Err(glib::bool_error!(
+            "buffer({:?}) can be neither mapped nor written by queue",
+            usage
+        ))
+    }
+
+    /// Writes the memory filled by `f` using [`wgpu::Queue::write_buffer_with`]
+    fn write_with(&self, f: impl FnOnce(&mut [u8])) -> Result<(), glib::BoolError> {
+        let offset = self.offset() as u64;
+        let size = self.size();
+
+        // Writes must be aligned, the bytes around the window cannot be preserved so only the padding behind
+        // the memory is allowed to be zeroed
+        let window_end = offset + size as u64;
+        let end = window_end
+            .next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+            .min(self.buffer().size());
+        if offset % wgpu::COPY_BUFFER_ALIGNMENT != 0
+            || (window_end < end && window_end < self.maxsize() as u64)
+        {
+            return Err(glib::bool_error!(
+                "cannot write unaligned window at {} of size {} without overwriting the rest of the memory",
+                offset,
+                size
+            ));
+        }
+
+        let Some(write_size) = wgpu::BufferSize::new(end - offset) else {
+            // Nothing to copy
+            return Ok(());
+        };
+
+        // The write is executed before the next submission, commands batched before it must go first
+        let written = self
+            .context()
+            .write_buffer(self.buffer(), offset, write_size, |view| {
+                let (window, padding) = view.split_at_mut(size);
+                f(window);
+                padding.fill(0);
+            });
+        if written.is_none() {
+            return Err(glib::bool_error!(
+                "failed to write {} bytes at {} to buffer",
+                write_size,
+                offset
+            ));
+        }
 
         Ok(())
     }
@@ -105,6 +331,13 @@ impl WgpuBufferMemoryAllocator {
         out
     }
 
+    /// Sets label of allocated buffers, see [`WgpuContext::label`]
+    pub fn with_label(self, label: Option<String>) -> Self {
+        // SAFETY: The allocator is not shared yet, it takes self by value right after creation
+        unsafe { *self.imp().label.get() = label };
+        self
+    }
+
     pub fn context(&self) -> WgpuContext {
         let imp = self.imp();
         let cell = unsafe { &*imp.context.get() };
@@ -122,9 +355,11 @@ mod imp {
     use std::cell::UnsafeCell;
     use std::ffi::c_void;
     use std::mem::ManuallyDrop;
+    use std::ops::Range;
     use std::sync::mpsc::Receiver;
     use std::sync::mpsc::TryRecvError;
-    use std::time::Duration;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Waker};
 
     use glib::object::Cast;
     use glib::object::ObjectType;
@@ -133,7 +368,7 @@ mod imp {
     use glib::subclass::types::ObjectSubclassExt;
     use glib::translate::{FromGlibPtrBorrow, ToGlibPtr};
     use gst::subclass::prelude::*;
-    use parking_lot::Mutex;
+    use parking_lot::{Condvar, Mutex};
 
     use crate::buffer_memory::CAT;
     use crate::glib;
@@ -157,67 +392,194 @@ mod imp {
         }
     }
 
+    /// State of the active maps of the memory
+    #[derive(Default)]
+    struct MapState {
+        view: Option<Box<dyn GetMappedPointer>>,
+        /// The mode GStreamer or the async API asked for, `None` if not mapped
+        ///
+        /// Nested maps are checked against it, the buffer itself may be mapped differently, see `view_mode`.
+        mode: Option<wgpu::MapMode>,
+        /// The mode the wgpu buffer of the view is mapped with, a staging buffer is always mapped for read
+        view_mode: Option<wgpu::MapMode>,
+        /// The mapped range of the buffer
+        range: Range<u64>,
+        /// Number of active maps sharing the view
+        count: usize,
+        /// The staging buffer the view belongs to if the buffer itself cannot be mapped
+        staging: Option<wgpu::Buffer>,
+        /// Async map is in progress, other maps have to wait for it
+        pending: bool,
+        /// Async maps waiting for the pending one
+        pending_wakers: Vec<Waker>,
+    }
+
+    impl MapState {
+        /// Pointer which GStreamer expects from `mem_map`: it adds the memory offset by itself,
+        /// so we return the pointer to the (virtual) start of the buffer
+        fn base_pointer(&self) -> glib::ffi::gpointer {
+            let Some(view) = self.view.as_ref() else {
+                return core::ptr::null_mut();
+            };
+
+            let p = view.get_mapped_pointer() as *mut u8;
+            p.wrapping_sub(self.range.start as usize) as glib::ffi::gpointer
+        }
+
+        fn set_mapped(
+            &mut self,
+            view: Box<dyn GetMappedPointer>,
+            mode: wgpu::MapMode,
+            view_mode: wgpu::MapMode,
+            range: Range<u64>,
+        ) -> glib::ffi::gpointer {
+            self.view = Some(view);
+            self.mode = Some(mode);
+            self.view_mode = Some(view_mode);
+            self.range = range;
+            self.count = 1;
+            self.base_pointer()
+        }
+
+        /// Checks whether a new map of `mode` asked by the caller can join the active one
+        fn join(&mut self, mode: wgpu::MapMode, range: &Range<u64>) -> Result<(), glib::BoolError> {
+            match (self.mode, mode) {
+                (Some(wgpu::MapMode::Read), wgpu::MapMode::Read) => {
+                    if range.start < self.range.start || self.range.end < range.end {
+                        return Err(glib::bool_error!(
+                            "requested range {:?} is outside of active read map {:?}",
+                            range,
+                            self.range
+                        ));
+                    }
+
+                    self.count += 1;
+                    Ok(())
+                }
+                (Some(wgpu::MapMode::Read), wgpu::MapMode::Write) => Err(glib::bool_error!(
+                    "cannot map for write while {} read maps are active",
+                    self.count
+                )),
+                (Some(wgpu::MapMode::Write), _) => {
+                    Err(glib::bool_error!("cannot map while write map is active"))
+                }
+                (None, _) => Err(glib::bool_error!("memory is not mapped")),
+            }
+        }
+
+        fn finish_pending(&mut self) {
+            self.pending = false;
+            for waker in self.pending_wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Callback result shared between wgpu and [`AsyncMap`]
+    #[derive(Default)]
+    pub struct MapSlot {
+        result: Option<Result<(), wgpu::BufferAsyncError>>,
+        waker: Option<Waker>,
+    }
+
+    /// Progress of the async map
+    #[derive(Default)]
+    pub enum AsyncMap {
+        /// The map is not requested yet
+        #[default]
+        Start,
+        /// Waiting for the wgpu callback
+        Waiting {
+            slot: Arc<Mutex<MapSlot>>,
+            /// The mode the wgpu buffer is mapped with
+            view_mode: wgpu::MapMode,
+            range: Range<u64>,
+        },
+        /// The map is finished or failed
+        Done,
+    }
+
     #[repr(C)]
     pub struct WgpuMemory {
         pub(super) parent: gst::ffi::GstMemory,
         pub(super) context: ManuallyDrop<WgpuContext>,
         pub(super) buffer: ManuallyDrop<wgpu::Buffer>,
-        buffer_view: Mutex<Option<Box<dyn GetMappedPointer>>>,
+        map_state: Mutex<MapState>,
+        /// Notified when pending async map is finished
+        map_cond: Condvar,
     }
 
     impl std::fmt::Debug for WgpuMemory {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let map_state = self.map_state.lock();
             f.debug_struct("WgpuMemory")
                 .field("parent", &self.parent)
                 .field("context", &self.context)
                 .field("buffer", &self.buffer)
-                .field("mapped", &self.buffer_view.lock().is_some())
+                .field("mapped", &map_state.mode)
+                .field("view_mode", &map_state.view_mode)
+                .field("map_count", &map_state.count)
                 .finish_non_exhaustive()
         }
     }
 
     impl WgpuMemory {
+        /// The range of the wgpu buffer which covers the offset/size window of the memory
+        ///
+        /// The bounds are aligned to [`wgpu::MAP_ALIGNMENT`] as required by [`wgpu::Buffer::map_async`], which
+        /// panics on empty ranges, so empty windows are rejected
+        fn map_range(&self) -> Result<Range<u64>, glib::BoolError> {
+            let offset = self.parent.offset as u64;
+            let size = self.parent.size as u64;
+
+            let start = offset - offset % wgpu::MAP_ALIGNMENT;
+            let end = (offset + size)
+                .next_multiple_of(wgpu::MAP_ALIGNMENT)
+                .min(self.buffer.size());
+
+            if size == 0 || end <= start {
+                return Err(glib::bool_error!(
+                    "cannot map empty window at {} of buffer with size {}",
+                    offset,
+                    self.buffer.size()
+                ));
+            }
+
+            Ok(start..end)
+        }
+
         fn poll_map(
             &self,
             rx: Receiver<Result<(), wgpu::BufferAsyncError>>,
             on_map: impl FnOnce() -> glib::ffi::gpointer,
         ) -> glib::ffi::gpointer {
-            let result;
+            let manual = matches!(self.context.poll_type(), crate::PollType::Manual);
+            let poller = self.context.poller();
+
+            let result = loop {
+                // Take the generation before checking, so a poll finished in between is not missed
+                let generation = poller.generation();
+                match rx.try_recv() {
+                    Ok(res) => break res,
+                    Err(TryRecvError::Disconnected) => {
+                        gst::error!(CAT, "Failed to map buffer: no response");
+                        return core::ptr::null_mut();
+                    }
+                    Err(TryRecvError::Empty) => {}
+                }
 
-            if matches!(self.context.poll_type(), crate::PollType::Manual) {
-                // If in manual mode, we need to poll the buffer manually.
-                let mut mut_last_poll = rx.try_recv();
-                while matches!(mut_last_poll, Err(TryRecvError::Empty)) {
+                if manual {
+                    // If in manual mode, we need to poll the device ourselves
                     self.context
                         .device()
                         .poll(wgpu::PollType::Wait {
                             submission_index: None,
-                            timeout: Some(Duration::from_millis(250)),
+                            timeout: None,
                         })
                         .ok();
-                    mut_last_poll = rx.try_recv();
-                }
-
-                match mut_last_poll {
-                    Ok(res) => {
-                        result = res;
-                    }
-                    Err(_) => {
-                        gst::error!(CAT, "Failed to map buffer: no response");
-                        return core::ptr::null_mut();
-                    }
-                }
-            } else {
-                // If threaded kind just wait
-                match rx.recv() {
-                    Ok(res) => {
-                        // Success mapping
-                        result = res;
-                    }
-                    Err(_) => {
-                        gst::error!(CAT, "Failed to map buffer: no response");
-                        return core::ptr::null_mut();
-                    }
+                } else {
+                    // The poll thread wakes us after every poll
+                    poller.wait_polled(generation);
                 }
             };
 
@@ -233,54 +595,309 @@ mod imp {
             }
         }
 
-        pub fn map_read(&self, size: u64) -> glib::ffi::gpointer {
-            if !self.buffer.usage().contains(wgpu::BufferUsages::MAP_READ) {
-                // gst::warning!(CAT, "trying to map read buffer which is not MAP_READ. You likely want to use buffer in GPU, but now trying to read from it directly");
-                return self.map_write(size);
+        /// Requests map of the `range`, `callback` is called when the map is ready
+        ///
+        /// Returns the mode the view is mapped with, buffers without MAP_READ are read through a staging buffer
+        fn start_map(
+            &self,
+            state: &mut MapState,
+            mode: wgpu::MapMode,
+            range: Range<u64>,
+            callback: impl FnOnce(Result<(), wgpu::BufferAsyncError>) + Send + 'static,
+        ) -> Result<wgpu::MapMode, glib::BoolError> {
+            let usage = self.buffer.usage();
+
+            let mode = match mode {
+                wgpu::MapMode::Write if usage.contains(wgpu::BufferUsages::MAP_WRITE) => {
+                    wgpu::MapMode::Write
+                }
+                wgpu::MapMode::Write => {
+                    return Err(glib::bool_error!(
+                        "trying to map write buffer which is not MAP_WRITE"
+                    ));
+                }
+                wgpu::MapMode::Read if usage.contains(wgpu::BufferUsages::MAP_READ) => {
+                    wgpu::MapMode::Read
+                }
+                wgpu::MapMode::Read if usage.contains(wgpu::BufferUsages::COPY_SRC) => {
+                    // Read back through a staging buffer from the context pool
+                    let size = range.end - range.start;
+                    let staging = self.context.staging_pool().acquire(
+                        self.context.device(),
+                        size,
+                        wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    );
+
+                    let mut encoder = self
+                        .context
+                        .device()
+                        .create_command_encoder(&Default::default());
+                    encoder.copy_buffer_to_buffer(&self.buffer, range.start, &staging, 0, size);
+                    self.context.submit([encoder.finish()]);
+
+                    staging.map_async(wgpu::MapMode::Read, ..size, callback);
+                    state.staging = Some(staging);
+                    self.context.poller().request_map();
+
+                    return Ok(wgpu::MapMode::Read);
+                }
+                wgpu::MapMode::Read => {
+                    return Err(glib::bool_error!(
+                        "trying to map read buffer({:?}) which is neither mappable nor COPY_SRC",
+                        usage
+                    ));
+                }
+            };
+
+            // Recorded commands may still use the pooled buffer, they must be submitted before it is mapped
+            self.context.flush();
+            self.buffer.map_async(mode, range, callback);
+            self.context.poller().request_map();
+
+            Ok(mode)
+        }
+
+        /// Creates the view after the map requested by [`Self::start_map`] is ready
+        fn finish_map(
+            &self,
+            state: &mut MapState,
+            mode: wgpu::MapMode,
+            view_mode: wgpu::MapMode,
+            range: Range<u64>,
+        ) -> glib::ffi::gpointer {
+            let view: Box<dyn GetMappedPointer> = match (&state.staging, view_mode) {
+                (Some(staging), _) => Box::new(staging.get_mapped_range(..range.end - range.start)),
+                (None, wgpu::MapMode::Read) => {
+                    Box::new(self.buffer.get_mapped_range(range.clone()))
+                }
+                (None, wgpu::MapMode::Write) => {
+                    Box::new(self.buffer.get_mapped_range_mut(range.clone()))
+                }
+            };
+
+            gst::trace!(
+                CAT,
+                "mapped {:?} {:p}, range {:?}, staging: {}",
+                mode,
+                &self,
+                range,
+                state.staging.is_some()
+            );
+
+            state.set_mapped(view, mode, view_mode, range)
+        }
+
+        /// Maps the offset/size window of the memory, blocks until the map is ready
+        ///
+        /// Read maps are reference counted: while the memory is mapped for read, further read maps share the same view.
+        /// Any other combination of maps is rejected.
+        pub fn map(&self, mode: wgpu::MapMode) -> glib::ffi::gpointer {
+            let range = match self.map_range() {
+                Ok(range) => range,
+                Err(err) => {
+                    gst::error!(CAT, "{}", err);
+                    return core::ptr::null_mut();
+                }
+            };
+            let mut state = self.map_state.lock();
+
+            while state.pending {
+                self.map_cond.wait(&mut state);
+            }
+
+            if state.mode.is_some() {
+                return match state.join(mode, &range) {
+                    Ok(()) => {
+                        gst::trace!(CAT, "nested map {:p}, {} maps active", &self, state.count);
+                        state.base_pointer()
+                    }
+                    Err(err) => {
+                        gst::error!(CAT, "{}", err);
+                        core::ptr::null_mut()
+                    }
+                };
             }
 
             let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let view_mode = match self.start_map(&mut state, mode, range.clone(), move |res| {
+                tx.send(res).ok();
+            }) {
+                Ok(view_mode) => view_mode,
+                Err(err) => {
+                    gst::error!(CAT, "{}", err);
+                    return core::ptr::null_mut();
+                }
+            };
 
-            self.buffer
-                .map_async(wgpu::MapMode::Read, ..size, move |res| {
-                    tx.send(res).ok();
-                });
-
-            self.poll_map(rx, || {
-                let view = Box::new(self.buffer.get_mapped_range(..size));
-                let p = view.get_mapped_pointer();
-                *self.buffer_view.lock() = Some(view);
-                gst::trace!(CAT, "mapped read {:p}", &self);
-                p
-            })
+            let p = self.poll_map(rx, || self.finish_map(&mut state, mode, view_mode, range));
+            if p.is_null() {
+                // Failed staging buffer is not returned to the pool
+                state.staging = None;
+            }
+
+            p
         }
 
-        pub fn map_write(&self, size: u64) -> glib::ffi::gpointer {
-            if !self.buffer.usage().contains(wgpu::BufferUsages::MAP_WRITE) {
-                gst::error!(CAT, "trying to map write buffer which is not MAP_WRITE");
-                return core::ptr::null_mut();
+        /// Drives the async map, returns base pointer of the mapped buffer when ready
+        pub(super) fn poll_map_async(
+            &self,
+            progress: &mut AsyncMap,
+            mode: wgpu::MapMode,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<glib::ffi::gpointer, glib::BoolError>> {
+            loop {
+                match progress {
+                    AsyncMap::Start => {
+                        let range = match self.map_range() {
+                            Ok(range) => range,
+                            Err(err) => {
+                                *progress = AsyncMap::Done;
+                                return Poll::Ready(Err(err));
+                            }
+                        };
+                        let mut state = self.map_state.lock();
+
+                        if state.pending {
+                            state.pending_wakers.push(cx.waker().clone());
+                            return Poll::Pending;
+                        }
+
+                        if state.mode.is_some() {
+                            *progress = AsyncMap::Done;
+                            return Poll::Ready(state.join(mode, &range).map(|()| {
+                                gst::trace!(
+                                    CAT,
+                                    "nested map {:p}, {} maps active",
+                                    &self,
+                                    state.count
+                                );
+                                state.base_pointer()
+                            }));
+                        }
+
+                        let slot = Arc::new(Mutex::new(MapSlot::default()));
+                        let callback_slot = Arc::clone(&slot);
+                        let callback = move |res| {
+                            let waker = {
+                                let mut slot = callback_slot.lock();
+                                slot.result = Some(res);
+                                slot.waker.take()
+                            };
+
+                            if let Some(waker) = waker {
+                                waker.wake();
+                            }
+                        };
+
+                        match self.start_map(&mut state, mode, range.clone(), callback) {
+                            Ok(view_mode) => {
+                                state.pending = true;
+                                *progress = AsyncMap::Waiting {
+                                    slot,
+                                    view_mode,
+                                    range,
+                                };
+                            }
+                            Err(err) => {
+                                *progress = AsyncMap::Done;
+                                return Poll::Ready(Err(err));
+                            }
+                        }
+                    }
+                    AsyncMap::Waiting {
+                        slot,
+                        view_mode,
+                        range,
+                    } => {
+                        let result = {
+                            let mut slot = slot.lock();
+                            match slot.result.take() {
+                                Some(result) => result,
+                                None => {
+                                    slot.waker = Some(cx.waker().clone());
+                                    return Poll::Pending;
+                                }
+                            }
+                        };
+
+                        let (view_mode, range) = (*view_mode, range.clone());
+                        *progress = AsyncMap::Done;
+
+                        let mut state = self.map_state.lock();
+                        state.finish_pending();
+                        self.map_cond.notify_all();
+
+                        return match result {
+                            Ok(()) => {
+                                Poll::Ready(Ok(self.finish_map(&mut state, mode, view_mode, range)))
+                            }
+                            Err(err) => {
+                                state.staging = None;
+                                Poll::Ready(Err(glib::bool_error!("Failed to map buffer: {}", err)))
+                            }
+                        };
+                    }
+                    AsyncMap::Done => {
+                        return Poll::Ready(Err(glib::bool_error!(
+                            "async map polled after completion"
+                        )));
+                    }
+                }
             }
+        }
 
-            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        /// Aborts the async map if it is still pending
+        pub(super) fn cancel_map_async(&self, progress: &mut AsyncMap) {
+            let AsyncMap::Waiting { slot, .. } = std::mem::take(progress) else {
+                return;
+            };
+            *progress = AsyncMap::Done;
 
-            self.buffer
-                .map_async(wgpu::MapMode::Write, ..size, move |res| {
-                    tx.send(res).ok();
-                });
-
-            self.poll_map(rx, || {
-                let view = Box::new(self.buffer.get_mapped_range_mut(..size));
-                let p = view.get_mapped_pointer();
-                *self.buffer_view.lock() = Some(view);
-                gst::trace!(CAT, "mapped write {:p}", &self);
-                p
-            })
+            let failed = matches!(slot.lock().result, Some(Err(_)));
+            let mut state = self.map_state.lock();
+            let staging = state.staging.take();
+
+            // Unmapping aborts the pending map or releases the finished one nobody is waiting for
+            if !failed {
+                match staging {
+                    Some(staging) => staging.unmap(),
+                    None => self.buffer.unmap(),
+                }
+            }
+
+            state.finish_pending();
+            self.map_cond.notify_all();
+            gst::trace!(CAT, "cancelled async map {:p}", &self);
         }
 
-        /// Safety: after the call all pointers to mapped memory is invalid
+        /// Releases one map, the buffer is unmapped when the last one is released
+        ///
+        /// Safety: after the last unmap all pointers to mapped memory is invalid
         pub unsafe fn unmap(&self) {
-            *self.buffer_view.lock() = None;
-            self.buffer.unmap();
+            let mut state = self.map_state.lock();
+            if state.count == 0 {
+                gst::error!(CAT, "unmapping not mapped memory {:p}", &self);
+                return;
+            }
+
+            state.count -= 1;
+            if 0 < state.count {
+                gst::trace!(CAT, "released map {:p}, {} maps active", &self, state.count);
+                return;
+            }
+
+            let staging = state.staging.take();
+            state.view = None;
+            state.mode = None;
+            state.view_mode = None;
+
+            match staging {
+                Some(staging) => {
+                    staging.unmap();
+                    self.context.staging_pool().release(staging);
+                }
+                None => self.buffer.unmap(),
+            }
             self.context.device().poll(wgpu::PollType::Poll).ok();
             gst::trace!(CAT, "unmapped {:p}", &self);
         }
@@ -308,7 +925,7 @@ mod imp {
 
     unsafe extern "C" fn gst_wgpu_mem_map(
         mem: *mut gst::ffi::GstMemory,
-        maxsize: usize,
+        _maxsize: usize,
         flags: gst::ffi::GstMapFlags,
     ) -> glib::ffi::gpointer {
         let mem = mem as *mut WgpuMemory;
@@ -327,15 +944,7 @@ mod imp {
             return core::ptr::null_mut();
         };
 
-        if mem_ref.buffer_view.lock().is_some() {
-            gst::error!(CAT, "only one map can be active");
-            return core::ptr::null_mut();
-        }
-
-        match mode {
-            wgpu::MapMode::Read => mem_ref.map_read(maxsize as u64),
-            wgpu::MapMode::Write => mem_ref.map_write(maxsize as u64),
-        }
+        mem_ref.map(mode)
     }
 
     unsafe extern "C" fn gst_wgpu_mem_unmap(mem: *mut gst::ffi::GstMemory) {
@@ -362,6 +971,7 @@ mod imp {
     pub struct WgpuMemoryAllocator {
         pub(super) context: UnsafeCell<Option<WgpuContext>>,
         pub(super) usages: UnsafeCell<Option<wgpu::BufferUsages>>,
+        pub(super) label: UnsafeCell<Option<String>>,
     }
 
     impl WgpuMemoryAllocator {
@@ -387,6 +997,7 @@ mod imp {
             Self {
                 context: Default::default(),
                 usages: Default::default(),
+                label: Default::default(),
             }
         }
     }
@@ -454,8 +1065,13 @@ mod imp {
                 (None, false) => wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::MAP_WRITE,
             };
 
+            // COPY_SRC buffers are mapped for read through staging buffers
             if !mem_flags.contains(gst::MemoryFlags::NOT_MAPPABLE)
-                && !usages.intersects(wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::MAP_WRITE)
+                && !usages.intersects(
+                    wgpu::BufferUsages::MAP_READ
+                        | wgpu::BufferUsages::MAP_WRITE
+                        | wgpu::BufferUsages::COPY_SRC,
+                )
             {
                 gst::warning!(
                     CAT, imp: self,
@@ -463,10 +1079,12 @@ mod imp {
                 );
             }
 
+            let label = unsafe { &*self.label.get() };
             let wgpu_buffer = self.device().create_buffer(&wgpu::BufferDescriptor {
-                label: None,
+                label: label.as_deref(),
                 mapped_at_creation: false,
-                size: maxsize as u64,
+                // Mapped ranges must be aligned, so the tail of the memory must be mappable as well
+                size: (maxsize as u64).next_multiple_of(wgpu::MAP_ALIGNMENT),
                 usage: usages,
             });
 
@@ -476,6 +1094,8 @@ mod imp {
                     ManuallyDrop::new(self.context().clone()),
                 );
                 core::ptr::write(&raw mut (*mem).buffer, ManuallyDrop::new(wgpu_buffer));
+                core::ptr::write(&raw mut (*mem).map_state, Mutex::new(MapState::default()));
+                core::ptr::write(&raw mut (*mem).map_cond, Condvar::new());
             }
 
             gst::debug!(CAT, "allocated buffer {:p}, maxsize {}", mem, maxsize);
@@ -488,6 +1108,10 @@ mod imp {
             let mut wgpu_mem: super::WgpuBufferMemory =
                 memory.downcast_memory().expect("non wgpu mem passed");
             let wgpu_mem_obj = unsafe { wgpu_mem.obj.as_mut() };
+            unsafe {
+                core::ptr::drop_in_place(&mut wgpu_mem_obj.map_state);
+                core::ptr::drop_in_place(&mut wgpu_mem_obj.map_cond);
+            };
             unsafe {
                 ManuallyDrop::drop(&mut wgpu_mem_obj.context);
             };
@@ -512,3 +1136,34 @@ mod imp {
     unsafe impl Send for WgpuMemoryAllocator {}
     unsafe impl Sync for WgpuMemoryAllocator {}
 }
+
+#[cfg(test)]
+mod tests {
+    use gst::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn empty_window_is_not_mapped() {
+        gst::init().unwrap();
+        let Some(ctx) = WgpuContext::for_tests(Default::default()) else {
+            return;
+        };
+
+        let allocator = WgpuBufferMemoryAllocator::new_with_explicit_usage(
+            ctx,
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
+        let mut mem = allocator.alloc(256, None).unwrap();
+        mem.get_mut().unwrap().resize(0, 0);
+
+        assert!(mem.map_readable().is_err());
+
+        let mut mem = mem.downcast_memory::<WgpuBufferMemory>().unwrap();
+        assert!(pollster::block_on(mem.map_read_async()).is_err());
+
+        // The failed maps leave the memory unmapped
+        mem.get_mut().unwrap().resize(0, 256);
+        assert_eq!(mem.map_readable().unwrap().size(), 256);
+    }
+}
@@ -2,28 +2,326 @@
 //! Helpers to work with caps
 //!
 
+pub mod format;
 pub mod transform;
+pub mod usage;
+
+use crate::glib;
+
+/// Typed view of WGPU related fields of a caps structure
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WgpuCapsInfo {
+    /// Value of the `buffer-usage` field if present
+    pub buffer_usages: Option<wgpu::BufferUsages>,
+    /// Value of the `texture-usage` field if present
+    pub texture_usages: Option<wgpu::TextureUsages>,
+    /// Value of the `texture-format` field if present and fixed
+    pub texture_format: Option<wgpu::TextureFormat>,
+}
+
+impl WgpuCapsInfo {
+    /// Reads info from the first structure of the caps, for fixed caps it is the only one
+    pub fn from_caps(caps: &gst::CapsRef) -> Result<Self, glib::BoolError> {
+        let Some(s) = caps.structure(0) else {
+            return Err(glib::bool_error!("missing structure in caps {}", caps));
+        };
+
+        Ok(Self::from_structure(s))
+    }
+
+    /// Reads info from the structure, absent or invalid fields are `None`
+    pub fn from_structure(s: &gst::StructureRef) -> Self {
+        Self {
+            buffer_usages: transform::gst_structure_buffer_usages(s).ok(),
+            texture_usages: transform::gst_structure_texture_usages(s).ok(),
+            texture_format: s
+                .get::<&str>(crate::texture_memory::GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT)
+                .ok()
+                .and_then(format::texture_format_from_name),
+        }
+    }
+
+    /// Buffer usages, error if the caps have no buffer usage
+    pub fn buffer_usages(&self) -> Result<wgpu::BufferUsages, glib::BoolError> {
+        self.buffer_usages
+            .ok_or_else(|| glib::bool_error!("caps have no buffer usage"))
+    }
+
+    /// Texture usages, error if the caps have no texture usage
+    pub fn texture_usages(&self) -> Result<wgpu::TextureUsages, glib::BoolError> {
+        self.texture_usages
+            .ok_or_else(|| glib::bool_error!("caps have no texture usage"))
+    }
+
+    /// Texture format, error if the caps have no fixed texture format
+    pub fn texture_format(&self) -> Result<wgpu::TextureFormat, glib::BoolError> {
+        self.texture_format
+            .ok_or_else(|| glib::bool_error!("caps have no texture format"))
+    }
+
+    /// True if all usages of `other` are present in self
+    pub fn contains(&self, other: &Self) -> bool {
+        let buffer = match (self.buffer_usages, other.buffer_usages) {
+            (_, None) => true,
+            (Some(this), Some(other)) => this.contains(other),
+            (None, Some(_)) => false,
+        };
+        let texture = match (self.texture_usages, other.texture_usages) {
+            (_, None) => true,
+            (Some(this), Some(other)) => this.contains(other),
+            (None, Some(_)) => false,
+        };
+
+        buffer && texture
+    }
+
+    /// Merges usages of both infos
+    pub fn union(&self, other: &Self) -> Self {
+        fn merge<T: std::ops::BitOr<Output = T>>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a | b),
+                (a, b) => a.or(b),
+            }
+        }
+
+        Self {
+            buffer_usages: merge(self.buffer_usages, other.buffer_usages),
+            texture_usages: merge(self.texture_usages, other.texture_usages),
+            texture_format: self.texture_format.or(other.texture_format),
+        }
+    }
+
+    /// False if wgpu rejects the usages together: without `MAPPABLE_PRIMARY_BUFFERS` buffers mapped for read can
+    /// only be copied into and buffers mapped for write can only be copied from
+    pub fn is_valid(&self) -> bool {
+        self.buffer_usages.is_none_or(|usages| {
+            if usages.contains(wgpu::BufferUsages::MAP_READ) {
+                (wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST).contains(usages)
+            } else if usages.contains(wgpu::BufferUsages::MAP_WRITE) {
+                (wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC).contains(usages)
+            } else {
+                true
+            }
+        })
+    }
+
+    fn usage_count(&self) -> u32 {
+        self.buffer_usages.map_or(0, |x| x.bits().count_ones())
+            + self.texture_usages.map_or(0, |x| x.bits().count_ones())
+    }
+}
+
+/// Union of usages over all structures of the caps
+pub fn wgpu_usages_union(caps: &gst::CapsRef) -> WgpuCapsInfo {
+    caps.iter()
+        .map(WgpuCapsInfo::from_structure)
+        .fold(WgpuCapsInfo::default(), |acc, info| acc.union(&info))
+}
+
+/// Keeps only the structure with the widest usages the peers accept, see [`fixate_wgpu_usages_requiring`]
+///
+/// Elements call it from `fixate_caps`, so later elements can use the memory as is.
+pub fn fixate_wgpu_usages(caps: gst::Caps) -> gst::Caps {
+    fixate_wgpu_usages_requiring(caps, &WgpuCapsInfo::default())
+}
+
+/// Keeps only the structure which suits the element best, so the negotiated memory suits as many usages the peers
+/// accept as possible
+///
+/// Every structure of `caps` is a usage set both sides accept, so the result is one of them rather than their union:
+/// the union mixes MAP_READ, MAP_WRITE and STORAGE, which cannot be used together. The structures are ranked by:
+/// 1. usages wgpu accepts together, see [`WgpuCapsInfo::is_valid`]
+/// 2. the `required` usages of the element, like MAP_WRITE for CPU writes
+/// 3. the number of valid structures whose usages it contains, so it is the largest of them where they are nested
+/// 4. the number of usages
+///
+/// On a tie the earlier structure wins. Other fields are left for the default fixation.
+pub fn fixate_wgpu_usages_requiring(caps: gst::Caps, required: &WgpuCapsInfo) -> gst::Caps {
+    if caps.size() < 2 {
+        return caps;
+    }
+
+    let infos = caps
+        .iter()
+        .map(WgpuCapsInfo::from_structure)
+        .collect::<Vec<_>>();
+
+    let mut best: Option<(usize, (bool, bool, usize, u32))> = None;
+    for (idx, info) in infos.iter().enumerate() {
+        let contained = infos
+            .iter()
+            .filter(|other| other.is_valid() && info.contains(other))
+            .count();
+        let rank = (
+            info.is_valid(),
+            info.contains(required),
+            contained,
+            info.usage_count(),
+        );
+
+        if best.is_none_or(|(_, best_rank)| best_rank < rank) {
+            best = Some((idx, rank));
+        }
+    }
+
+    let Some((idx, _)) = best else {
+        return caps;
+    };
+
+    let (Some(s), Some(features)) = (caps.structure(idx), caps.features(idx)) else {
+        return caps;
+    };
+
+    gst::Caps::builder_full()
+        .structure_with_features(s.to_owned(), features.to_owned())
+        .build()
+}
 
 /// Creates copy of caps where each structure copied with all buffer usages from `usages`
+///
+/// The memory features are replaced by `memory:WgpuBuffer`, other features of the structures are kept.
 pub fn make_wgpu_buffer_usages_for_caps<F, I>(input: &gst::Caps, usages: F) -> gst::Caps
 where
     F: Fn() -> I,
     I: IntoIterator<Item = wgpu::BufferUsages>,
 {
     let mut caps_builder = gst::Caps::builder_full();
-    let mem_feature =
-        gst::CapsFeatures::new([crate::buffer_memory::GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER]);
 
-    for s in input.iter() {
-        caps_builder = usages().into_iter().map(|usages| usages.bits()).fold(
-            caps_builder,
-            |caps_builder, bits| {
+    for (s, features) in input.iter_with_features() {
+        let mut mem_feature =
+            gst::CapsFeatures::new([crate::buffer_memory::GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER]);
+        for feature in features.iter().filter(|x| !x.starts_with("memory:")) {
+            mem_feature.add(feature);
+        }
+
+        caps_builder = usages()
+            .into_iter()
+            .map(usage::WgpuBufferUsageFlags::from)
+            .fold(caps_builder, |caps_builder, flags| {
                 let mut s_owned = s.to_owned();
-                s_owned.set(crate::buffer_memory::GST_CAPS_FIELD_WGPU_BUFFER_USAGE, bits);
+                s_owned.set(
+                    crate::buffer_memory::GST_CAPS_FIELD_WGPU_BUFFER_USAGE,
+                    flags,
+                );
                 caps_builder.structure_with_features(s_owned, mem_feature.clone())
-            },
-        )
+            })
+    }
+
+    transform::gst_caps_with_plain_usages(caps_builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_caps(usages: &[wgpu::BufferUsages]) -> gst::Caps {
+        let raw = gst::Caps::builder("video/x-raw")
+            .field("format", "RGBA")
+            .build();
+        make_wgpu_buffer_usages_for_caps(&raw, || usages.iter().copied())
+    }
+
+    fn map_write() -> WgpuCapsInfo {
+        WgpuCapsInfo {
+            buffer_usages: Some(wgpu::BufferUsages::MAP_WRITE),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn upload_to_download_keeps_map_write() {
+        gst::init().unwrap();
+
+        // Source pad of dekawgpubufferupload
+        let upload_src = buffer_caps(&[
+            wgpu::BufferUsages::MAP_WRITE,
+            wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::STORAGE,
+        ]);
+        // Sink pad of dekawgpubufferdownload
+        let download_sink = buffer_caps(&[
+            wgpu::BufferUsages::COPY_SRC,
+            wgpu::BufferUsages::MAP_WRITE,
+            wgpu::BufferUsages::MAP_READ,
+            wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::STORAGE,
+        ]);
+
+        let caps = upload_src.intersect_with_mode(&download_sink, gst::CapsIntersectMode::First);
+        let fixated = fixate_wgpu_usages_requiring(caps, &map_write());
+
+        assert_eq!(fixated.size(), 1);
+        assert_eq!(
+            WgpuCapsInfo::from_caps(&fixated).unwrap().buffer_usages,
+            Some(wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC)
+        );
+    }
+
+    #[test]
+    fn required_usages_outrank_wider_ones() {
+        gst::init().unwrap();
+
+        let caps = buffer_caps(&[
+            wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::STORAGE,
+            wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+        ]);
+
+        let widest = fixate_wgpu_usages(caps.clone());
+        assert_eq!(
+            WgpuCapsInfo::from_caps(&widest).unwrap().buffer_usages,
+            Some(
+                wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::STORAGE
+            )
+        );
+
+        let writable = fixate_wgpu_usages_requiring(caps, &map_write());
+        assert_eq!(
+            WgpuCapsInfo::from_caps(&writable).unwrap().buffer_usages,
+            Some(wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC)
+        );
+    }
+
+    #[test]
+    fn invalid_combination_loses_to_valid_one() {
+        gst::init().unwrap();
+
+        let caps = buffer_caps(&[
+            wgpu::BufferUsages::MAP_READ
+                | wgpu::BufferUsages::MAP_WRITE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        ]);
+
+        let fixated = fixate_wgpu_usages(caps);
+        assert_eq!(
+            WgpuCapsInfo::from_caps(&fixated).unwrap().buffer_usages,
+            Some(wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC)
+        );
     }
 
-    caps_builder.build()
+    #[test]
+    fn superset_of_accepted_usages_wins_over_more_bits() {
+        gst::init().unwrap();
+
+        let caps = buffer_caps(&[
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::INDEX,
+            wgpu::BufferUsages::COPY_DST,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        ]);
+
+        let fixated = fixate_wgpu_usages(caps);
+        assert_eq!(
+            WgpuCapsInfo::from_caps(&fixated).unwrap().buffer_usages,
+            Some(wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC)
+        );
+    }
 }
@@ -0,0 +1,64 @@
+//!
+//! Texture formats in caps, named the same way as in WebGPU: `rgba8unorm`, `rgba8unorm-srgb`
+//!
+
+/// Formats which can be negotiated through caps with their names
+const TEXTURE_FORMAT_NAMES: &[(wgpu::TextureFormat, &str)] = &[
+    (wgpu::TextureFormat::R8Unorm, "r8unorm"),
+    (wgpu::TextureFormat::Rg8Unorm, "rg8unorm"),
+    (wgpu::TextureFormat::R32Float, "r32float"),
+    (wgpu::TextureFormat::Rgba8Unorm, "rgba8unorm"),
+    (wgpu::TextureFormat::Rgba8UnormSrgb, "rgba8unorm-srgb"),
+    (wgpu::TextureFormat::Bgra8Unorm, "bgra8unorm"),
+    (wgpu::TextureFormat::Bgra8UnormSrgb, "bgra8unorm-srgb"),
+    (wgpu::TextureFormat::Rgb10a2Unorm, "rgb10a2unorm"),
+    (wgpu::TextureFormat::Rgba16Float, "rgba16float"),
+    (wgpu::TextureFormat::Rgba32Float, "rgba32float"),
+];
+
+/// WebGPU name of the format, `None` if the format cannot be used in caps
+pub fn texture_format_name(format: wgpu::TextureFormat) -> Option<&'static str> {
+    TEXTURE_FORMAT_NAMES
+        .iter()
+        .find(|(x, _)| *x == format)
+        .map(|(_, name)| *name)
+}
+
+/// Parses WebGPU name of the format
+pub fn texture_format_from_name(name: &str) -> Option<wgpu::TextureFormat> {
+    TEXTURE_FORMAT_NAMES
+        .iter()
+        .find(|(_, x)| *x == name)
+        .map(|(format, _)| *format)
+}
+
+/// Texture formats which holds the video format as is, the preferred one goes first
+pub fn texture_formats_for_video_format(
+    format: gst_video::VideoFormat,
+) -> &'static [wgpu::TextureFormat] {
+    match format {
+        gst_video::VideoFormat::Rgba | gst_video::VideoFormat::Rgbx => &[
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        ],
+        gst_video::VideoFormat::Bgra | gst_video::VideoFormat::Bgrx => &[
+            wgpu::TextureFormat::Bgra8Unorm,
+            wgpu::TextureFormat::Bgra8UnormSrgb,
+        ],
+        gst_video::VideoFormat::Gray8 => &[wgpu::TextureFormat::R8Unorm],
+        _ => &[],
+    }
+}
+
+/// View formats to create texture of `format` with, so it can be viewed both as sRGB and linear
+///
+/// The slices are static to fit into `wgpu::TextureDescriptor<'static>`
+pub fn srgb_view_formats(format: wgpu::TextureFormat) -> &'static [wgpu::TextureFormat] {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm => &[wgpu::TextureFormat::Rgba8UnormSrgb],
+        wgpu::TextureFormat::Rgba8UnormSrgb => &[wgpu::TextureFormat::Rgba8Unorm],
+        wgpu::TextureFormat::Bgra8Unorm => &[wgpu::TextureFormat::Bgra8UnormSrgb],
+        wgpu::TextureFormat::Bgra8UnormSrgb => &[wgpu::TextureFormat::Bgra8Unorm],
+        _ => &[],
+    }
+}
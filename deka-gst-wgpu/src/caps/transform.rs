@@ -1,18 +1,175 @@
+use gst::prelude::*;
+
 use crate::{
     buffer_memory::{GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER, GST_CAPS_FIELD_WGPU_BUFFER_USAGE},
-    texture_memory::{GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE, GST_CAPS_FIELD_WGPU_TEXTURE_USAGE},
+    caps::{
+        format::{texture_format_name, texture_formats_for_video_format},
+        usage::{WgpuBufferUsageFlags, WgpuTextureUsageFlags},
+    },
+    glib,
+    texture_memory::{
+        GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE, GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT,
+        GST_CAPS_FIELD_WGPU_TEXTURE_USAGE,
+    },
+    texture_meta::GST_CAPS_FEATURE_META_WGPU_TEXTURE,
 };
 
+/// The name texture meta caps used for usages before it was unified with memory caps, only read for compatibility
+const GST_CAPS_FIELD_WGPU_TEXTURE_USAGE_LEGACY: &str = "texture-usages";
+
+/// True if caps with the features carry a WGPU texture, either as memory or as meta
+pub fn gst_caps_features_has_wgpu_texture(features: &gst::CapsFeaturesRef) -> bool {
+    features.contains(GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE)
+        || features.contains(GST_CAPS_FEATURE_META_WGPU_TEXTURE)
+}
+
+/// Reads buffer usages from the structure
+///
+/// Both [`WgpuBufferUsageFlags`] and the plain `u32` bits are accepted
+pub fn gst_structure_buffer_usages(
+    s: &gst::StructureRef,
+) -> Result<wgpu::BufferUsages, glib::BoolError> {
+    if let Ok(flags) = s.get::<WgpuBufferUsageFlags>(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
+        return Ok(flags.into());
+    }
+
+    match s.get::<u32>(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
+        Ok(bits) => Ok(wgpu::BufferUsages::from_bits_truncate(bits)),
+        Err(err) => Err(glib::bool_error!(
+            "cannot get {} from {}: {}",
+            GST_CAPS_FIELD_WGPU_BUFFER_USAGE,
+            s.name(),
+            err
+        )),
+    }
+}
+
+/// Reads texture usages from the structure, the same for memory and meta caps
+///
+/// Both [`WgpuTextureUsageFlags`] and the plain `u32` bits are accepted
+pub fn gst_structure_texture_usages(
+    s: &gst::StructureRef,
+) -> Result<wgpu::TextureUsages, glib::BoolError> {
+    if let Ok(flags) = s.get::<WgpuTextureUsageFlags>(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE) {
+        return Ok(flags.into());
+    }
+
+    if let Ok(bits) = s.get::<u32>(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE_LEGACY) {
+        return Ok(wgpu::TextureUsages::from_bits_truncate(bits));
+    }
+
+    match s.get::<u32>(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE) {
+        Ok(bits) => Ok(wgpu::TextureUsages::from_bits_truncate(bits)),
+        Err(err) => Err(glib::bool_error!(
+            "cannot get {} from {}: {}",
+            GST_CAPS_FIELD_WGPU_TEXTURE_USAGE,
+            s.name(),
+            err
+        )),
+    }
+}
+
+/// Appends a copy of every structure with usages as plain `u32` bits, after all structures with the flags
+///
+/// Values of different types do not intersect, so peers using the integer form, like `texture-usage=(uint)6`
+/// capsfilters, negotiate with the copies. The flags go first and are preferred by fixation.
+pub fn gst_caps_with_plain_usages(mut caps: gst::Caps) -> gst::Caps {
+    let mut plain = gst::Caps::new_empty();
+    {
+        let plain = plain.get_mut().unwrap();
+        for (s, features) in caps.iter_with_features() {
+            let mut new_s = s.to_owned();
+            let mut has_flags = false;
+
+            if let Ok(flags) = s.get::<WgpuBufferUsageFlags>(GST_CAPS_FIELD_WGPU_BUFFER_USAGE) {
+                new_s.set(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, flags.bits());
+                has_flags = true;
+            }
+
+            if let Ok(flags) = s.get::<WgpuTextureUsageFlags>(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE) {
+                new_s.set(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE, flags.bits());
+                has_flags = true;
+            }
+
+            if has_flags {
+                plain.append_structure_full(new_s, Some(features.to_owned()));
+            }
+        }
+    }
+
+    caps.make_mut().append(plain);
+    caps
+}
+
 fn remove_wgpu_buffer_fields(s: &mut gst::Structure) {
     s.remove_field(GST_CAPS_FIELD_WGPU_BUFFER_USAGE);
 }
 
 fn remove_wgpu_texture_fields(s: &mut gst::Structure) {
     s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE);
+    s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE_LEGACY);
+    s.remove_field(GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT);
+}
+
+/// Copies of the structure with texture fields set for the `flags`, one per video format
+///
+/// Each copy has a single video `format` and the `texture-format` names which can hold it, so a fixated structure
+/// never pairs a video format with a texture format of another layout.
+fn structures_with_texture_fields(
+    s: &gst::StructureRef,
+    flags: WgpuTextureUsageFlags,
+) -> Vec<gst::Structure> {
+    let mut base = s.to_owned();
+    remove_wgpu_texture_fields(&mut base);
+    base.set(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE, flags);
+
+    let video_formats: Vec<&str> = if let Ok(format) = s.get::<&str>("format") {
+        vec![format]
+    } else if let Ok(list) = s.get::<gst::List>("format") {
+        list.iter().filter_map(|x| x.get::<&str>().ok()).collect()
+    } else {
+        vec![]
+    };
+
+    if video_formats.is_empty() {
+        return vec![base];
+    }
+
+    video_formats
+        .into_iter()
+        .map(|format| {
+            let mut new_s = base.clone();
+            new_s.set("format", format);
+            if let Some(formats) =
+                texture_formats_value(gst_video::VideoFormat::from_string(format))
+            {
+                new_s.set_value(GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT, formats);
+            }
+            new_s
+        })
+        .collect()
+}
+
+/// Texture formats suitable for the video format, as a string or list of names
+///
+/// Returns `None` if no texture format can hold the video format
+fn texture_formats_value(format: gst_video::VideoFormat) -> Option<glib::SendValue> {
+    let names: Vec<&'static str> = texture_formats_for_video_format(format)
+        .iter()
+        .filter_map(|x| texture_format_name(*x))
+        .collect();
+
+    match names.as_slice() {
+        [] => None,
+        [name] => Some(name.to_send_value()),
+        _ => Some(gst::List::from_values(names.iter().map(|x| x.to_send_value())).to_send_value()),
+    }
 }
 
 /// Create same caps but for texture usages
 ///
+/// Every video `format` gets its own structure whose `texture-format` field lists the texture formats which can hold it
+///
 /// # Note
 /// if caps haves WGPU related fields they will bre removed
 pub fn gst_caps_with_texture_usages<C, F, I>(caps: C, usages_factory: F) -> gst::Caps
@@ -26,18 +183,49 @@ where
     let feature = gst::CapsFeatures::new([GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE]);
 
     for s in original_caps.iter() {
-        builder = usages_factory().into_iter().map(|usage| usage.bits()).fold(
-            builder,
-            |builder, bits| {
-                let mut new_s = s.to_owned();
+        builder = usages_factory()
+            .into_iter()
+            .map(WgpuTextureUsageFlags::from)
+            .flat_map(|flags| structures_with_texture_fields(s, flags))
+            .fold(builder, |builder, mut new_s| {
                 remove_wgpu_buffer_fields(&mut new_s);
-                new_s.set(GST_CAPS_FIELD_WGPU_TEXTURE_USAGE, bits);
                 builder.structure_with_features(new_s, feature.clone())
-            },
-        );
+            });
     }
 
-    builder.build()
+    gst_caps_with_plain_usages(builder.build())
+}
+
+/// Create same caps but with the texture attached as [`crate::texture_meta::WgpuTextureMeta`]
+///
+/// Non-system memory features of the structures are kept, `memory:WgpuTexture` is replaced by the meta, so system
+/// memory caps become `meta:WgpuTextureMetaAPI`. The texture fields are the same as for
+/// [`gst_caps_with_texture_usages`], so both kinds of caps are read the same way.
+pub fn gst_caps_with_texture_meta_usages<C, F, I>(caps: C, usages_factory: F) -> gst::Caps
+where
+    C: AsRef<gst::CapsRef>,
+    F: Fn() -> I,
+    I: IntoIterator<Item = wgpu::TextureUsages>,
+{
+    let original_caps = caps.as_ref();
+    let mut builder = gst::Caps::builder_full();
+
+    for (s, features) in original_caps.iter_with_features() {
+        let mut feature = features.to_owned();
+        feature.remove(GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE);
+        feature.remove(gst::CAPS_FEATURE_MEMORY_SYSTEM_MEMORY);
+        feature.add(GST_CAPS_FEATURE_META_WGPU_TEXTURE);
+
+        builder = usages_factory()
+            .into_iter()
+            .map(WgpuTextureUsageFlags::from)
+            .flat_map(|flags| structures_with_texture_fields(s, flags))
+            .fold(builder, |builder, new_s| {
+                builder.structure_with_features(new_s, feature.clone())
+            });
+    }
+
+    gst_caps_with_plain_usages(builder.build())
 }
 
 pub fn gst_caps_with_buffer_usages<C, F, I>(caps: C, usages_factory: F) -> gst::Caps
@@ -51,16 +239,144 @@ where
     let feature = gst::CapsFeatures::new([GST_CAPS_FEATURE_MEMORY_WGPU_BUFFER]);
 
     for s in original_caps.iter() {
-        builder = usages_factory().into_iter().map(|usage| usage.bits()).fold(
-            builder,
-            |builder, bits| {
+        builder = usages_factory()
+            .into_iter()
+            .map(WgpuBufferUsageFlags::from)
+            .fold(builder, |builder, flags| {
                 let mut new_s = s.to_owned();
                 remove_wgpu_texture_fields(&mut new_s);
-                new_s.set(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, bits);
+                new_s.set(GST_CAPS_FIELD_WGPU_BUFFER_USAGE, flags);
                 builder.structure_with_features(new_s, feature.clone())
-            },
-        );
+            });
+    }
+
+    gst_caps_with_plain_usages(builder.build())
+}
+
+/// Create caps for texture usages which accept the texture both as memory and as meta, memory caps go first
+///
+/// The meta caps are in system memory like ones made by [`gst_caps_with_texture_meta_usages`] for system memory
+pub fn gst_caps_with_texture_or_meta_usages<C, F, I>(caps: C, usages_factory: F) -> gst::Caps
+where
+    C: AsRef<gst::CapsRef>,
+    F: Fn() -> I,
+    I: IntoIterator<Item = wgpu::TextureUsages>,
+{
+    let mut out = gst_caps_with_texture_usages(caps, usages_factory);
+
+    let meta_feature = gst::CapsFeatures::new([GST_CAPS_FEATURE_META_WGPU_TEXTURE]);
+    let meta_caps = out
+        .iter()
+        .fold(gst::Caps::builder_full(), |builder, s| {
+            builder.structure_with_features(s.to_owned(), meta_feature.clone())
+        })
+        .build();
+
+    out.merge(meta_caps);
+    out
+}
+
+/// Create same caps but without the WGPU texture: the texture fields, `memory:WgpuTexture` and the texture meta are
+/// removed, other memory features are kept
+pub fn gst_caps_without_texture<C>(caps: C) -> gst::Caps
+where
+    C: AsRef<gst::CapsRef>,
+{
+    let original_caps = caps.as_ref();
+    let mut builder = gst::Caps::builder_full();
+
+    for (s, features) in original_caps.iter_with_features() {
+        let mut feature = features.to_owned();
+        feature.remove(GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE);
+        feature.remove(GST_CAPS_FEATURE_META_WGPU_TEXTURE);
+
+        let mut new_s = s.to_owned();
+        remove_wgpu_texture_fields(&mut new_s);
+        builder = builder.structure_with_features(new_s, feature);
     }
 
     builder.build()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::caps::WgpuCapsInfo;
+
+    fn video_caps() -> gst::Caps {
+        gst_video::VideoCapsBuilder::new()
+            .format(gst_video::VideoFormat::Rgba)
+            .build()
+    }
+
+    #[test]
+    fn uint_usage_capsfilter_intersects_template() {
+        gst::init().unwrap();
+
+        let template = gst_caps_with_texture_usages(video_caps(), || {
+            [
+                wgpu::TextureUsages::COPY_DST,
+                wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            ]
+        });
+        let filter = gst::Caps::from_str(
+            "video/x-raw(memory:WgpuTexture), format=RGBA, texture-usage=(uint)6",
+        )
+        .unwrap();
+
+        let caps = filter.intersect_with_mode(&template, gst::CapsIntersectMode::First);
+        assert!(!caps.is_empty());
+        assert_eq!(
+            WgpuCapsInfo::from_caps(&caps).unwrap().texture_usages,
+            Some(wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING)
+        );
+    }
+
+    #[test]
+    fn flags_go_before_plain_usages() {
+        gst::init().unwrap();
+
+        let caps = gst_caps_with_buffer_usages(video_caps(), || {
+            [wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST]
+        });
+        assert_eq!(caps.size(), 2);
+
+        let flags = caps.structure(0).unwrap();
+        assert!(flags
+            .get::<WgpuBufferUsageFlags>(GST_CAPS_FIELD_WGPU_BUFFER_USAGE)
+            .is_ok());
+        let plain = caps.structure(1).unwrap();
+        assert_eq!(
+            plain.get::<u32>(GST_CAPS_FIELD_WGPU_BUFFER_USAGE).unwrap(),
+            9
+        );
+        assert_eq!(
+            gst_structure_buffer_usages(flags).unwrap(),
+            gst_structure_buffer_usages(plain).unwrap()
+        );
+    }
+
+    #[test]
+    fn texture_formats_follow_their_video_format() {
+        gst::init().unwrap();
+
+        let caps = gst_video::VideoCapsBuilder::new()
+            .format_list([gst_video::VideoFormat::Rgba, gst_video::VideoFormat::Gray8])
+            .build();
+        let caps = gst_caps_with_texture_usages(caps, || [wgpu::TextureUsages::COPY_DST]);
+
+        let gray = gst::Caps::from_str(
+            "video/x-raw(memory:WgpuTexture), format=GRAY8, texture-format=rgba8unorm",
+        )
+        .unwrap();
+        assert!(!caps.can_intersect(&gray));
+
+        let gray = gst::Caps::from_str(
+            "video/x-raw(memory:WgpuTexture), format=GRAY8, texture-format=r8unorm",
+        )
+        .unwrap();
+        assert!(caps.can_intersect(&gray));
+    }
+}
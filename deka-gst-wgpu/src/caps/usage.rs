@@ -0,0 +1,81 @@
+//!
+//! WGPU usages registered as GLib flags, so caps show them by name
+//!
+//! Value names are used when caps are serialized, so they are the same as nicks: `copy-dst+texture-binding`
+//!
+//! The plain `u32` usages are still accepted: caps made by [`crate::caps::transform`] carry a copy of every structure
+//! with `u32` usages after the ones with the flags, so `texture-usage=(uint)N` capsfilters keep negotiating, see
+//! [`crate::caps::transform::gst_caps_with_plain_usages`].
+//!
+
+use crate::glib;
+
+/// [`wgpu::BufferUsages`] as GLib flags, the bits are the same
+#[glib::flags(name = "GstWgpuBufferUsage")]
+pub enum WgpuBufferUsageFlags {
+    #[flags_value(name = "map-read", nick = "map-read")]
+    MAP_READ = 1 << 0,
+    #[flags_value(name = "map-write", nick = "map-write")]
+    MAP_WRITE = 1 << 1,
+    #[flags_value(name = "copy-src", nick = "copy-src")]
+    COPY_SRC = 1 << 2,
+    #[flags_value(name = "copy-dst", nick = "copy-dst")]
+    COPY_DST = 1 << 3,
+    #[flags_value(name = "index", nick = "index")]
+    INDEX = 1 << 4,
+    #[flags_value(name = "vertex", nick = "vertex")]
+    VERTEX = 1 << 5,
+    #[flags_value(name = "uniform", nick = "uniform")]
+    UNIFORM = 1 << 6,
+    #[flags_value(name = "storage", nick = "storage")]
+    STORAGE = 1 << 7,
+    #[flags_value(name = "indirect", nick = "indirect")]
+    INDIRECT = 1 << 8,
+    #[flags_value(name = "query-resolve", nick = "query-resolve")]
+    QUERY_RESOLVE = 1 << 9,
+    #[flags_value(name = "blas-input", nick = "blas-input")]
+    BLAS_INPUT = 1 << 10,
+    #[flags_value(name = "tlas-input", nick = "tlas-input")]
+    TLAS_INPUT = 1 << 11,
+}
+
+impl From<wgpu::BufferUsages> for WgpuBufferUsageFlags {
+    fn from(value: wgpu::BufferUsages) -> Self {
+        Self::from_bits_truncate(value.bits())
+    }
+}
+
+impl From<WgpuBufferUsageFlags> for wgpu::BufferUsages {
+    fn from(value: WgpuBufferUsageFlags) -> Self {
+        Self::from_bits_truncate(value.bits())
+    }
+}
+
+/// [`wgpu::TextureUsages`] as GLib flags, the bits are the same
+#[glib::flags(name = "GstWgpuTextureUsage")]
+pub enum WgpuTextureUsageFlags {
+    #[flags_value(name = "copy-src", nick = "copy-src")]
+    COPY_SRC = 1 << 0,
+    #[flags_value(name = "copy-dst", nick = "copy-dst")]
+    COPY_DST = 1 << 1,
+    #[flags_value(name = "texture-binding", nick = "texture-binding")]
+    TEXTURE_BINDING = 1 << 2,
+    #[flags_value(name = "storage-binding", nick = "storage-binding")]
+    STORAGE_BINDING = 1 << 3,
+    #[flags_value(name = "render-attachment", nick = "render-attachment")]
+    RENDER_ATTACHMENT = 1 << 4,
+    #[flags_value(name = "storage-atomic", nick = "storage-atomic")]
+    STORAGE_ATOMIC = 1 << 16,
+}
+
+impl From<wgpu::TextureUsages> for WgpuTextureUsageFlags {
+    fn from(value: wgpu::TextureUsages) -> Self {
+        Self::from_bits_truncate(value.bits())
+    }
+}
+
+impl From<WgpuTextureUsageFlags> for wgpu::TextureUsages {
+    fn from(value: WgpuTextureUsageFlags) -> Self {
+        Self::from_bits_truncate(value.bits())
+    }
+}
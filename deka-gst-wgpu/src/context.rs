@@ -3,13 +3,21 @@
 //!
 
 use std::{
-    sync::{atomic::Ordering, Arc, LazyLock},
-    time::Duration,
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
 };
 
 use gst::{glib::object::ObjectExt, prelude::*, subclass::prelude::*};
 
-use crate::glib;
+use crate::{
+    batcher::CommandBatcher,
+    glib,
+    options::WgpuContextOptions,
+    poller::{DevicePoller, PollWork},
+    shader_cache::ShaderCache,
+    staging::StagingBufferPool,
+    texture_pool::TexturePool,
+};
 
 /// GstContext type string use to match context on look up
 pub const GST_CONTEXT_WGPU_TYPE: &str = "rust.wgpu.Context";
@@ -29,11 +37,13 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
 ///
 #[derive(Debug, Clone, Copy, Default)]
 pub enum PollType {
-    /// The background thread will be spawned for polling device
+    /// The background thread will be spawned for polling device, it sleeps until work is submitted
+    /// and waits for the latest submission
     #[default]
     Threaded,
 
-    /// The background thread will be spawned for polling device in busy loop
+    /// The background thread will be spawned for polling device in busy loop while there is submitted work,
+    /// it sleeps when the queue is empty
     ThreadedBusy,
 
     /// The user will poll the device manually
@@ -50,18 +60,24 @@ glib::wrapper! {
 
 impl Default for WgpuContext {
     fn default() -> Self {
-        Self::new_with_all_limits(
+        Self::with_options(WgpuContextOptions::from_env())
+    }
+}
+
+impl WgpuContext {
+    /// Creates context like [`WgpuContext::default`] with the given options instead of the environment ones
+    pub fn with_options(options: WgpuContextOptions) -> Self {
+        Self::new_with_options(
             &wgpu::RequestAdapterOptions {
                 compatible_surface: None,
                 ..Default::default()
             },
             PollType::Manual,
+            options,
         )
         .expect("failed to create WGPU context")
     }
-}
 
-impl WgpuContext {
     /// Creates GstContext from self
     pub fn as_gst_context(&self) -> gst::Context {
         let mut ctx = gst::Context::new(GST_CONTEXT_WGPU_TYPE, true);
@@ -86,8 +102,9 @@ impl WgpuContext {
         desc: &wgpu::DeviceDescriptor<'_>,
         poll_type: PollType,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let instance_description = wgpu::InstanceDescriptor::from_env_or_default();
-        let instance = wgpu::Instance::new(&instance_description);
+        // The trace is set by `desc`, other options are taken from the environment
+        let options = WgpuContextOptions::from_env();
+        let instance = wgpu::Instance::new(&options.instance_descriptor());
 
         let adapter = match pollster::block_on(instance.request_adapter(&adapter_options)) {
             Ok(adapter) => adapter,
@@ -105,22 +122,39 @@ impl WgpuContext {
             }
         };
 
+        let shader_cache = ShaderCache::new(&adapter, &device);
         let inner = imp::Inner {
             instance,
             adapter,
             device,
             queue,
+            shader_cache,
+            options,
         };
 
         Ok(Self::from_inner(inner, poll_type))
     }
 
+    /// Creates WgpuContext with all features and limits of the adapter, options are read from the environment
     pub fn new_with_all_limits(
         adapter_options: &wgpu::RequestAdapterOptions<'_, '_>,
         poll_type: PollType,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let instance_description = wgpu::InstanceDescriptor::from_env_or_default();
-        let instance = wgpu::Instance::new(&instance_description);
+        Self::new_with_options(adapter_options, poll_type, WgpuContextOptions::from_env())
+    }
+
+    /// Creates WgpuContext with all features and limits of the adapter
+    ///
+    /// # Arguments
+    /// * `adapter_options` - Options to get WGPU Adapter
+    /// * `poll_type` - sets poll behavior
+    /// * `options` - trace, validation, debug labels and command batching
+    pub fn new_with_options(
+        adapter_options: &wgpu::RequestAdapterOptions<'_, '_>,
+        poll_type: PollType,
+        options: WgpuContextOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let instance = wgpu::Instance::new(&options.instance_descriptor());
 
         let adapter = match pollster::block_on(instance.request_adapter(&adapter_options)) {
             Ok(adapter) => adapter,
@@ -139,9 +173,16 @@ impl WgpuContext {
             required_features: features,
             required_limits: adapter.limits(),
             experimental_features: wgpu::ExperimentalFeatures::disabled(),
-            trace: wgpu::Trace::Off,
+            trace: options.trace(),
         };
 
+        if options.trace_dir.is_some() && matches!(dev_descriptor.trace, wgpu::Trace::Off) {
+            gst::warning!(
+                CAT,
+                "API trace is requested, but the `trace` feature is disabled"
+            );
+        }
+
         let (device, queue) = match pollster::block_on(adapter.request_device(&dev_descriptor)) {
             Ok(device) => device,
             Err(err) => {
@@ -150,11 +191,14 @@ impl WgpuContext {
             }
         };
 
+        let shader_cache = ShaderCache::new(&adapter, &device);
         let inner = imp::Inner {
             instance,
             adapter,
             device,
             queue,
+            shader_cache,
+            options,
         };
 
         Ok(Self::from_inner(inner, poll_type))
@@ -165,56 +209,64 @@ impl WgpuContext {
         let imp = out.imp();
 
         let device = inner.device.clone();
+        let command_batching = inner.options.command_batching;
 
         // SAFETY: This is the only place where we write - at creation. Should not be any problems with race conditions
         unsafe { *imp.inner.get() = Some(inner) };
         unsafe { *imp.poll_type.get() = poll_type };
 
+        if command_batching {
+            out.set_command_batching(true);
+        }
+
         // Spawn thread for polling
         let join_handle = {
-            let running = Arc::clone(&imp.running);
+            let poller = Arc::clone(&imp.poller);
             let obj = out.downgrade();
 
             std::thread::spawn(move || {
-                let poll_type = match poll_type {
+                let busy = match poll_type {
                     PollType::Manual => {
                         if let Some(obj) = obj.upgrade() {
                             gst::info!(CAT, obj: obj, "Manual polling");
                         }
                         return;
                     }
-                    PollType::Threaded => wgpu::PollType::Wait {
-                        submission_index: None,
-                        timeout: Some(Duration::from_millis(1_000)),
-                    },
-                    PollType::ThreadedBusy => wgpu::PollType::Poll,
+                    PollType::Threaded => false,
+                    PollType::ThreadedBusy => true,
                 };
 
-                running.store(true, Ordering::Relaxed);
                 if let Some(obj) = obj.upgrade() {
                     gst::info!(CAT, obj: obj, "ctx started");
                 }
 
-                while running.load(Ordering::Acquire) {
+                loop {
+                    let submission = match poller.next_work() {
+                        PollWork::Wait(submission) => submission,
+                        PollWork::Stop => break,
+                    };
+
                     if obj.upgrade().is_none() {
                         gst::info!(CAT, "ctx dropped, exiting");
                         break;
                     }
 
-                    if let Err(err) = device.poll(poll_type.clone()) {
-                        match err {
-                            wgpu::PollError::Timeout => {
-                                // Do nothing on timeout
-                            }
-                            other => {
-                                if let Some(obj) = obj.upgrade() {
-                                    gst::error!(CAT, obj: obj, "poll error: {}", other)
-                                }
-                            }
-                        }
-                    }
+                    let started = Instant::now();
+                    let timed_out = if busy {
+                        Self::poll_busy(&device, &obj)
+                    } else {
+                        Self::poll_wait(&device, submission.clone(), &obj)
+                    };
+
+                    gst::debug!(
+                        CAT,
+                        "polled {:?} in {:?}{}",
+                        submission,
+                        started.elapsed(),
+                        if timed_out { ", timed out" } else { "" }
+                    );
+                    poller.finish_poll(submission, timed_out);
                 }
-                running.store(false, Ordering::Relaxed);
                 gst::info!(CAT, "ctx stopped");
             })
         };
@@ -224,6 +276,43 @@ impl WgpuContext {
         out
     }
 
+    /// Waits for the `submission` with timeout, returns true if timed out
+    fn poll_wait(
+        device: &wgpu::Device,
+        submission: Option<wgpu::SubmissionIndex>,
+        obj: &glib::WeakRef<Self>,
+    ) -> bool {
+        match device.poll(wgpu::PollType::Wait {
+            submission_index: submission,
+            timeout: Some(Duration::from_millis(1_000)),
+        }) {
+            Ok(_) => false,
+            Err(wgpu::PollError::Timeout) => true,
+            Err(err) => {
+                if let Some(obj) = obj.upgrade() {
+                    gst::error!(CAT, obj: obj, "poll error: {}", err)
+                }
+                false
+            }
+        }
+    }
+
+    /// Polls the device in loop until the queue is empty, never times out
+    fn poll_busy(device: &wgpu::Device, obj: &glib::WeakRef<Self>) -> bool {
+        loop {
+            match device.poll(wgpu::PollType::Poll) {
+                Ok(status) if status.is_queue_empty() => return false,
+                Ok(_) => std::thread::yield_now(),
+                Err(err) => {
+                    if let Some(obj) = obj.upgrade() {
+                        gst::error!(CAT, obj: obj, "poll error: {}", err)
+                    }
+                    return false;
+                }
+            }
+        }
+    }
+
     /// Get the wgpu device
     #[inline]
     pub fn instance(&self) -> &wgpu::Instance {
@@ -259,6 +348,195 @@ impl WgpuContext {
             .expect("inner is None, you must create WgpuContext using associated WgpuContext::new")
     }
 
+    /// Submits command buffers to the queue and wakes the poll thread
+    ///
+    /// Use it instead of [`wgpu::Queue::submit`], otherwise the poll thread does not know about the submission.
+    /// The pending batch, if any, is submitted before `command_buffers`.
+    #[inline]
+    pub fn submit<I: IntoIterator<Item = wgpu::CommandBuffer>>(
+        &self,
+        command_buffers: I,
+    ) -> wgpu::SubmissionIndex {
+        let imp = self.imp();
+        imp.batcher
+            .submit(&imp.poller, self.queue(), command_buffers)
+    }
+
+    /// Records commands of `element` for the frame with `pts` into the shared batch, or submits them at once if batching is disabled
+    ///
+    /// Use it for work nobody waits for on CPU. `f` must not submit, see [`crate::batcher`] for when the batch is submitted.
+    pub fn record<R>(
+        &self,
+        element: &gst::Element,
+        pts: Option<gst::ClockTime>,
+        f: impl FnOnce(&mut wgpu::CommandEncoder) -> R,
+    ) -> R {
+        let imp = self.imp();
+        let label = self.label(element, "commands", pts);
+        imp.batcher.record(
+            self.device(),
+            &imp.poller,
+            self.queue(),
+            element,
+            |encoder| {
+                // The batch encoder is shared, so commands of the element are marked by a debug group
+                let Some(label) = label else {
+                    return f(encoder);
+                };
+
+                encoder.push_debug_group(&label);
+                let out = f(encoder);
+                encoder.pop_debug_group();
+                out
+            },
+        )
+    }
+
+    /// Submits the pending batch, returns `None` if there was nothing to submit
+    ///
+    /// Call it before [`wgpu::Queue`] writes into resources which may be used by the batch,
+    /// queue writes are executed before the commands of the next submission.
+    pub fn flush(&self) -> Option<wgpu::SubmissionIndex> {
+        let imp = self.imp();
+        imp.batcher.flush(&imp.poller, self.queue())
+    }
+
+    /// Writes `size` bytes at `offset` of `buffer` through the queue, ordered after the commands recorded so far
+    ///
+    /// Use it instead of [`wgpu::Queue::write_buffer_with`] for resources which may be used by the batch, see
+    /// [`CommandBatcher::write_buffer`]. Returns `None` if the queue cannot make the write.
+    pub fn write_buffer<R>(
+        &self,
+        buffer: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        size: wgpu::BufferSize,
+        f: impl FnOnce(&mut [u8]) -> R,
+    ) -> Option<R> {
+        let imp = self.imp();
+        imp.batcher
+            .write_buffer(&imp.poller, self.queue(), buffer, offset, size, f)
+    }
+
+    /// Writes `data` into `texture` through the queue, ordered after the commands recorded so far
+    ///
+    /// Use it instead of [`wgpu::Queue::write_texture`] for textures which may be used by the batch, see
+    /// [`CommandBatcher::write_texture`].
+    pub fn write_texture(
+        &self,
+        texture: wgpu::TexelCopyTextureInfo<'_>,
+        data: &[u8],
+        layout: wgpu::TexelCopyBufferLayout,
+        size: wgpu::Extent3d,
+    ) {
+        let imp = self.imp();
+        imp.batcher
+            .write_texture(&imp.poller, self.queue(), texture, data, layout, size)
+    }
+
+    /// Enables merging of recorded commands into one submission
+    ///
+    /// Disabled by default, [`WgpuContextOptions::command_batching`] enables it at creation.
+    pub fn set_command_batching(&self, enabled: bool) {
+        let imp = self.imp();
+        imp.batcher.set_enabled(&imp.poller, self.queue(), enabled);
+    }
+
+    #[inline]
+    pub fn command_batching(&self) -> bool {
+        self.imp().batcher.is_enabled()
+    }
+
+    /// Tracks the submitted work, allows to wait for the poll thread
+    #[inline]
+    pub fn poller(&self) -> &DevicePoller {
+        &self.imp().poller
+    }
+
+    /// Options the context was created with
+    #[inline]
+    pub fn options(&self) -> &WgpuContextOptions {
+        let out = unsafe { &*self.imp().inner.get() };
+        out.as_ref()
+            .map(|x| &x.options)
+            .expect("inner is None, you must create WgpuContext using associated WgpuContext::new")
+    }
+
+    /// Label for objects created by `element`, `None` if debug labels are disabled
+    ///
+    /// `pts` is set for per-frame objects like encoders and passes.
+    pub fn label(
+        &self,
+        element: &gst::Element,
+        what: &str,
+        pts: Option<gst::ClockTime>,
+    ) -> Option<String> {
+        if !self.options().debug_labels {
+            return None;
+        }
+
+        match pts {
+            Some(pts) => Some(format!("{} {} pts={}", element.name(), what, pts)),
+            None => Some(format!("{} {}", element.name(), what)),
+        }
+    }
+
+    /// Shader modules and pipelines shared by all elements of the context
+    #[inline]
+    pub fn shader_cache(&self) -> &ShaderCache {
+        let out = unsafe { &*self.imp().inner.get() };
+        out.as_ref()
+            .map(|x| &x.shader_cache)
+            .expect("inner is None, you must create WgpuContext using associated WgpuContext::new")
+    }
+
+    /// Returns shader module compiled from the same source or compiles a new one, see [`ShaderCache::shader_module`]
+    pub fn shader_module(&self, desc: wgpu::ShaderModuleDescriptor<'_>) -> wgpu::ShaderModule {
+        self.shader_cache().shader_module(self.device(), desc)
+    }
+
+    /// Returns compute pipeline shared with other elements, see [`ShaderCache::compute_pipeline`]
+    pub fn compute_pipeline(
+        &self,
+        desc: wgpu::ShaderModuleDescriptor<'_>,
+        entry_point: &str,
+        bind_group_layouts: Option<&[&[wgpu::BindGroupLayoutEntry]]>,
+    ) -> wgpu::ComputePipeline {
+        self.shader_cache()
+            .compute_pipeline(self.device(), desc, entry_point, bind_group_layouts)
+    }
+
+    /// Pool of staging buffers shared by all memories of the context
+    #[inline]
+    pub fn staging_pool(&self) -> &StagingBufferPool {
+        &self.imp().staging_pool
+    }
+
+    /// Zeroed COPY_SRC buffer of at least `size` bytes, shared by all elements of the context
+    ///
+    /// Nothing writes into it, a bigger one replaces it when `size` does not fit.
+    pub fn zero_buffer(&self, size: wgpu::BufferAddress) -> wgpu::Buffer {
+        let mut zeros = self.imp().zeros.lock();
+        if let Some(buffer) = zeros.as_ref().filter(|buffer| size <= buffer.size()) {
+            return buffer.clone();
+        }
+
+        // wgpu zeroes new buffers
+        let buffer = self.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("deka-gst-wgpu-zeros"),
+            size,
+            usage: wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        *zeros = Some(buffer.clone());
+        buffer
+    }
+
+    /// Pool of intermediate textures shared by all elements of the context
+    #[inline]
+    pub fn texture_pool(&self) -> &TexturePool {
+        &self.imp().texture_pool
+    }
+
     #[inline]
     pub fn poll_type(&self) -> PollType {
         let out = unsafe { &*self.imp().poll_type.get() };
@@ -402,21 +680,26 @@ impl WgpuContext {
     }
 }
 
+#[cfg(test)]
+impl WgpuContext {
+    /// Context with manual polling for tests, `None` if there is no adapter and the test has to be skipped
+    pub(crate) fn for_tests(options: WgpuContextOptions) -> Option<Self> {
+        Self::new_with_options(&Default::default(), PollType::Manual, options).ok()
+    }
+}
+
 mod imp {
-    use std::{
-        cell::UnsafeCell,
-        sync::{
-            atomic::{AtomicBool, Ordering},
-            Arc,
-        },
-        thread::JoinHandle,
-    };
+    use std::{cell::UnsafeCell, sync::Arc, thread::JoinHandle};
 
     use gst::glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
     use gst::subclass::prelude::*;
+    use parking_lot::Mutex;
 
     use super::{PollType, CAT};
-    use crate::glib;
+    use crate::{
+        batcher::CommandBatcher, glib, options::WgpuContextOptions, poller::DevicePoller,
+        shader_cache::ShaderCache, staging::StagingBufferPool, texture_pool::TexturePool,
+    };
 
     pub(super) struct Inner {
         /// Reserved for further use
@@ -427,13 +710,19 @@ mod imp {
         pub adapter: wgpu::Adapter,
         pub device: wgpu::Device,
         pub queue: wgpu::Queue,
+        pub shader_cache: ShaderCache,
+        pub options: WgpuContextOptions,
     }
 
     pub struct WgpuContext {
         pub(super) inner: UnsafeCell<Option<Inner>>,
         pub(super) poll_type: UnsafeCell<PollType>,
         pub(super) poll_thread: UnsafeCell<Option<JoinHandle<()>>>,
-        pub(super) running: Arc<AtomicBool>,
+        pub(super) poller: Arc<DevicePoller>,
+        pub(super) batcher: CommandBatcher,
+        pub(super) staging_pool: StagingBufferPool,
+        pub(super) texture_pool: TexturePool,
+        pub(super) zeros: Mutex<Option<wgpu::Buffer>>,
     }
 
     #[glib::object_subclass]
@@ -447,7 +736,11 @@ mod imp {
                 inner: Default::default(),
                 poll_type: UnsafeCell::new(PollType::Manual),
                 poll_thread: Default::default(),
-                running: Arc::new(AtomicBool::new(false)),
+                poller: Arc::new(DevicePoller::default()),
+                batcher: CommandBatcher::default(),
+                staging_pool: StagingBufferPool::default(),
+                texture_pool: TexturePool::default(),
+                zeros: Mutex::new(None),
             }
         }
     }
@@ -455,7 +748,11 @@ mod imp {
     impl ObjectImpl for WgpuContext {
         fn dispose(&self) {
             gst::info!(CAT, imp: self, "stopping ctx");
-            self.running.store(false, Ordering::Release);
+            // SAFETY: inner is not modified after creation
+            if let Some(inner) = unsafe { &*self.inner.get() } {
+                self.batcher.flush(&self.poller, &inner.queue);
+            }
+            self.poller.stop();
             // SAFETY: assuming dispose never be called in parallel
             let handle = unsafe { &mut *self.poll_thread.get() };
 
@@ -464,6 +761,11 @@ mod imp {
                     gst::error!(CAT, imp: self, "failed to join poll thread {:?}", err);
                 }
             }
+
+            // SAFETY: inner is not modified after creation
+            if let Some(inner) = unsafe { &*self.inner.get() } {
+                inner.shader_cache.save();
+            }
         }
     }
 
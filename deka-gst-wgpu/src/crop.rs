@@ -0,0 +1,400 @@
+//!
+//! Helpers for `GstVideoCropMeta`, which marks the visible part of frames with padding
+//!
+//! Decoders attach it, for example, to 1088 lines H.264 frames shown as 1080 lines.
+//!
+
+use crate::WgpuContext;
+
+/// Visible rectangle of a frame in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropRect {
+    /// Rectangle of the whole `width`x`height` frame
+    pub fn full(width: u32, height: u32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// Part of the rectangle inside `width`x`height` frame
+    pub fn clamp_to(self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+
+        Self {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+
+    /// The rectangle with the size limited to `width`x`height`, for copies into frames of that size
+    pub fn fit_size(self, width: u32, height: u32) -> Self {
+        Self {
+            width: self.width.min(width),
+            height: self.height.min(height),
+            ..self
+        }
+    }
+
+    /// True if the rectangle copied into the corner of a `width`x`height` frame covers the whole frame
+    pub fn covers(&self, width: u32, height: u32) -> bool {
+        width <= self.width && height <= self.height
+    }
+
+    /// Top-left corner of the rectangle for texture copies
+    pub fn origin(&self) -> wgpu::Origin3d {
+        wgpu::Origin3d {
+            x: self.x,
+            y: self.y,
+            z: 0,
+        }
+    }
+
+    /// Size of the rectangle for texture copies
+    pub fn extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    /// Offset of the top-left pixel in a packed plane with `stride` bytes per row and `pixel_size` bytes per pixel
+    pub fn offset_in_plane(&self, stride: u32, pixel_size: u32) -> u64 {
+        self.y as u64 * stride as u64 + self.x as u64 * pixel_size as u64
+    }
+}
+
+/// Visible rectangle of `buffer` with `width`x`height` frame, including the padding
+///
+/// It is the whole frame if the buffer has no crop meta, the meta is clamped to the frame otherwise.
+pub fn buffer_crop_rect(buffer: &gst::BufferRef, width: u32, height: u32) -> CropRect {
+    let Some(meta) = buffer.meta::<gst_video::VideoCropMeta>() else {
+        return CropRect::full(width, height);
+    };
+
+    let (x, y, crop_width, crop_height) = meta.rect();
+    CropRect {
+        x,
+        y,
+        width: crop_width,
+        height: crop_height,
+    }
+    .clamp_to(width, height)
+}
+
+/// Records clearing of `texture` unless the `crop` rectangle copied into its corner covers it, so pooled textures
+/// keep no content of older frames around the rectangle
+///
+/// Devices without [`wgpu::Features::CLEAR_TEXTURE`] clear render attachments by a render pass and other textures by
+/// a copy from the zeroed buffer of `ctx`, see [`WgpuContext::zero_buffer`]. Returns false if the texture has no format to copy into, it is left as is then. The
+/// texture must have the COPY_DST usage.
+pub fn clear_around_crop(
+    ctx: &WgpuContext,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    crop: CropRect,
+) -> bool {
+    if crop.covers(texture.width(), texture.height()) {
+        return true;
+    }
+
+    if ctx
+        .device()
+        .features()
+        .contains(wgpu::Features::CLEAR_TEXTURE)
+    {
+        encoder.clear_texture(texture, &wgpu::ImageSubresourceRange::default());
+        return true;
+    }
+
+    if texture
+        .usage()
+        .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+    {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        return true;
+    }
+
+    let format = texture.format();
+    let Some(block_size) = format.block_copy_size(None) else {
+        return false;
+    };
+    let (block_width, block_height) = format.block_dimensions();
+    let bytes_per_row = (texture.width().div_ceil(block_width) * block_size)
+        .next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let rows = texture.height().div_ceil(block_height);
+
+    let zeros = ctx.zero_buffer(bytes_per_row as u64 * rows as u64);
+    encoder.copy_buffer_to_texture(
+        wgpu::TexelCopyBufferInfo {
+            buffer: &zeros,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        texture.as_image_copy(),
+        texture.size(),
+    );
+    true
+}
+
+/// Layout of the first plane of a frame in a buffer
+///
+/// Padded frames describe it with `GstVideoMeta`, the caps have the visible size then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLayout {
+    /// Size of the frame including the padding
+    pub width: u32,
+    pub height: u32,
+    /// Offset of the plane in bytes
+    pub offset: u64,
+    /// Row stride in bytes
+    pub stride: u32,
+}
+
+impl FrameLayout {
+    /// Layout of the frame in `buffer`, the one of `info` if the buffer has no video meta
+    pub fn from_buffer(buffer: &gst::BufferRef, info: &gst_video::VideoInfo) -> Self {
+        let Some(meta) = buffer.meta::<gst_video::VideoMeta>() else {
+            return Self::from_info(info);
+        };
+
+        Self {
+            width: meta.width(),
+            height: meta.height(),
+            offset: meta.offset()[0] as u64,
+            stride: meta.stride()[0] as u32,
+        }
+    }
+
+    /// Layout of frames packed as `info` describes
+    pub fn from_info(info: &gst_video::VideoInfo) -> Self {
+        Self {
+            width: info.width(),
+            height: info.height(),
+            offset: info.offset()[0] as u64,
+            stride: info.stride()[0] as u32,
+        }
+    }
+
+    /// Visible rectangle of the frame in `buffer`, clamped to the padded frame
+    pub fn crop_rect(&self, buffer: &gst::BufferRef) -> CropRect {
+        buffer_crop_rect(buffer, self.width, self.height)
+    }
+
+    /// Offset of the top-left pixel of `crop` with `pixel_size` bytes per pixel
+    pub fn crop_offset(&self, crop: CropRect, pixel_size: u32) -> u64 {
+        self.offset + crop.offset_in_plane(self.stride, pixel_size)
+    }
+
+    /// Records a copy of `crop` from the frame in `src` into the corner of the packed frame in `dst`
+    ///
+    /// The rectangle is copied at once if `dst_stride` is the stride of the frame, row by row otherwise. It must fit
+    /// the `dst` frame, see [`CropRect::fit_size`].
+    pub fn copy_crop_to_buffer(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        src: &wgpu::Buffer,
+        crop: CropRect,
+        pixel_size: u32,
+        dst: &wgpu::Buffer,
+        dst_stride: u32,
+    ) {
+        if crop.width == 0 || crop.height == 0 {
+            return;
+        }
+
+        let src_offset = self.crop_offset(crop, pixel_size);
+        let row_size = crop.width as u64 * pixel_size as u64;
+
+        if self.stride == dst_stride {
+            let size = (crop.height as u64 - 1) * self.stride as u64 + row_size;
+            encoder.copy_buffer_to_buffer(src, src_offset, dst, 0, size);
+            return;
+        }
+
+        for row in 0..crop.height as u64 {
+            encoder.copy_buffer_to_buffer(
+                src,
+                src_offset + row * self.stride as u64,
+                dst,
+                row * dst_stride as u64,
+                row_size,
+            );
+        }
+    }
+
+    /// Copies `crop` from the frame in `src` into the corner of the packed frame in `dst` on CPU, like
+    /// [`Self::copy_crop_to_buffer`]
+    ///
+    /// Bytes of `dst` outside of the copied rows are zeroed.
+    pub fn copy_crop_to_slice(
+        &self,
+        src: &[u8],
+        crop: CropRect,
+        pixel_size: u32,
+        dst: &mut [u8],
+        dst_stride: u32,
+    ) {
+        dst.fill(0);
+
+        let src_offset = self.crop_offset(crop, pixel_size) as usize;
+        let row_size = crop.width as usize * pixel_size as usize;
+
+        for row in 0..crop.height as usize {
+            let src_start = src_offset + row * self.stride as usize;
+            let dst_start = row * dst_stride as usize;
+            dst[dst_start..dst_start + row_size]
+                .copy_from_slice(&src[src_start..src_start + row_size]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RGBA frame of 80x40 pixels, with `crop` meta if given
+    fn padded_buffer(crop: Option<(u32, u32, u32, u32)>) -> gst::Buffer {
+        let mut buffer = gst::Buffer::with_size(4 * 80 * 40).unwrap();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            gst_video::VideoMeta::add_full(
+                buffer,
+                gst_video::VideoFrameFlags::empty(),
+                gst_video::VideoFormat::Rgba,
+                80,
+                40,
+                &[0],
+                &[4 * 80],
+            )
+            .unwrap();
+            if let Some(rect) = crop {
+                gst_video::VideoCropMeta::add(buffer, rect);
+            }
+        }
+        buffer
+    }
+
+    fn caps_info() -> gst_video::VideoInfo {
+        gst_video::VideoInfo::builder(gst_video::VideoFormat::Rgba, 64, 32)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn crop_meta_uses_padded_frame() {
+        gst::init().unwrap();
+
+        let buffer = padded_buffer(Some((8, 4, 64, 32)));
+        let layout = FrameLayout::from_buffer(&buffer, &caps_info());
+        assert_eq!(
+            layout,
+            FrameLayout {
+                width: 80,
+                height: 40,
+                offset: 0,
+                stride: 320,
+            }
+        );
+
+        // The caps size would cut the rectangle to 56x28
+        let crop = layout.crop_rect(&buffer);
+        assert_eq!(
+            crop,
+            CropRect {
+                x: 8,
+                y: 4,
+                width: 64,
+                height: 32,
+            }
+        );
+        assert_eq!(layout.crop_offset(crop, 4), 4 * 320 + 8 * 4);
+        assert!(crop.covers(64, 32));
+    }
+
+    #[test]
+    fn crop_meta_is_clamped_to_padded_frame() {
+        gst::init().unwrap();
+
+        let buffer = padded_buffer(Some((60, 30, 64, 32)));
+        let crop = FrameLayout::from_buffer(&buffer, &caps_info()).crop_rect(&buffer);
+        assert_eq!(
+            crop,
+            CropRect {
+                x: 60,
+                y: 30,
+                width: 20,
+                height: 10,
+            }
+        );
+        // The rest of a 64x32 output must be cleared
+        assert!(!crop.covers(64, 32));
+        assert_eq!(crop.fit_size(16, 16).extent().width, 16);
+    }
+
+    #[test]
+    fn frame_without_meta_uses_caps() {
+        gst::init().unwrap();
+
+        let info = caps_info();
+        let buffer = gst::Buffer::with_size(info.size()).unwrap();
+        let layout = FrameLayout::from_buffer(&buffer, &info);
+        assert_eq!(layout, FrameLayout::from_info(&info));
+        assert_eq!(layout.crop_rect(&buffer), CropRect::full(64, 32));
+    }
+
+    #[test]
+    fn crop_is_copied_on_cpu() {
+        gst::init().unwrap();
+
+        // 4x3 frame of 1 byte pixels, each byte is its offset
+        let layout = FrameLayout {
+            width: 4,
+            height: 3,
+            offset: 0,
+            stride: 4,
+        };
+        let src: Vec<u8> = (0..12).collect();
+        let crop = CropRect {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2,
+        };
+
+        let mut dst = [0xff; 8];
+        layout.copy_crop_to_slice(&src, crop, 1, &mut dst, 4);
+        assert_eq!(dst, [5, 6, 0, 0, 9, 10, 0, 0]);
+    }
+}
@@ -0,0 +1,358 @@
+//!
+//! Parts shared by the elements: the context they work in, caps filtering and allocation of output textures
+//!
+
+use std::sync::LazyLock;
+
+use gst::prelude::*;
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+
+use crate::{
+    caps::{
+        format::{srgb_view_formats, texture_formats_for_video_format},
+        WgpuCapsInfo,
+    },
+    glib,
+    options::WgpuContextOptions,
+    texture_memory::WgpuTextureMemoryAllocator,
+    WgpuContext, GST_CONTEXT_WGPU_TYPE,
+};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "gstwgpuelement",
+        gst::DebugColorFlags::empty(),
+        Some("Gstreamer WGPU element helpers"),
+    )
+});
+
+/// Context of an element, shared by nearby elements or created by the element itself
+#[derive(Debug)]
+pub struct ElementContext {
+    context: Mutex<Option<WgpuContext>>,
+    /// Options of own context, set by the properties of [`WgpuContextOptions::param_specs`]
+    options: Mutex<WgpuContextOptions>,
+}
+
+impl Default for ElementContext {
+    fn default() -> Self {
+        Self {
+            context: Mutex::new(None),
+            options: Mutex::new(WgpuContextOptions::from_env()),
+        }
+    }
+}
+
+impl ElementContext {
+    /// Sets the option of own context, returns false if it is not one of [`WgpuContextOptions::param_specs`]
+    pub fn set_property(&self, value: &glib::Value, pspec: &glib::ParamSpec) -> bool {
+        self.options.lock().set_property(value, pspec)
+    }
+
+    /// Option of own context, `None` if it is not one of [`WgpuContextOptions::param_specs`]
+    pub fn property(&self, pspec: &glib::ParamSpec) -> Option<glib::Value> {
+        self.options.lock().property(pspec)
+    }
+
+    /// Sets the context if the element has none yet
+    pub fn set(&self, context: WgpuContext) {
+        let mut lock = self.context.lock();
+
+        if lock.is_some() {
+            return;
+        }
+
+        *lock = Some(context);
+    }
+
+    /// Takes the WGPU context given to `set_context` of the element, contexts of other types are ignored
+    pub fn set_gst_context(&self, element: &gst::Element, context: &gst::Context) {
+        if context.context_type() != GST_CONTEXT_WGPU_TYPE {
+            return;
+        }
+
+        gst::debug!(CAT, obj: element, "Received wgpu context");
+
+        let Some(wgpu_ctx) = WgpuContext::map_gst_context_to_wgpu(context.clone()) else {
+            gst::error!(CAT, obj: element, "Received invalid wgpu context");
+            return;
+        };
+
+        self.set(wgpu_ctx);
+    }
+
+    /// Takes the context of nearby elements or creates own one and shares it, call it from `start`
+    pub fn find_or_create(&self, element: &gst::Element) {
+        match WgpuContext::query_context_from_nearby_elements(element) {
+            Ok(true) => {
+                gst::info!(CAT, obj: element, "using shared wgpu context");
+                return;
+            }
+            Ok(false) => {}
+            Err(err) => {
+                gst::error!(CAT, obj: element, "failed to query wgpu context from nearby elements: {}", err);
+            }
+        }
+
+        gst::info!(CAT, obj: element, "creating own wgpu context");
+
+        let options = self.options.lock().clone();
+        let ctx = WgpuContext::with_options(options).as_gst_context();
+        // The element takes it in `set_context` like a context of other elements
+        element.set_context(&ctx);
+
+        let message = gst::message::HaveContext::builder(ctx).src(element).build();
+        element.post_message(message).unwrap();
+    }
+
+    /// Locks the context, `None` before `start`
+    pub fn lock(&self) -> MutexGuard<'_, Option<WgpuContext>> {
+        self.context.lock()
+    }
+
+    /// Locks the context, panics if there is none
+    pub fn locked(&self) -> MappedMutexGuard<'_, WgpuContext> {
+        MutexGuard::map(self.context.lock(), |x| x.as_mut().unwrap())
+    }
+
+    /// Copy of the context, `None` before `start`
+    ///
+    /// Unlike [`Self::lock`] the context stays unlocked, so the element may lock its state meanwhile: the state is
+    /// replaced in `set_caps` with the copy while `transform` locks the state first and the context second.
+    pub fn get(&self) -> Option<WgpuContext> {
+        self.context.lock().clone()
+    }
+}
+
+/// Intersects caps made by `transform_caps` with the optional filter of the base class
+pub fn filter_caps(caps: gst::Caps, filter: Option<&gst::Caps>) -> gst::Caps {
+    // In the end we need to filter the caps through an optional filter caps to get rid of any
+    // unwanted caps.
+    if let Some(filter) = filter {
+        filter.intersect_with_mode(&caps, gst::CapsIntersectMode::First)
+    } else {
+        caps
+    }
+}
+
+/// Answers the allocation query of the output: texture memories of the negotiated caps from a pool
+///
+/// Allocators of downstream are kept if their textures have the negotiated format, the `required` usages and can be
+/// viewed as `view_format`, which is the format the element writes through. Otherwise the textures are created by
+/// own allocator. Either way they come from a pool, so a texture is created only for the first frames.
+pub fn decide_texture_allocation(
+    element: &gst::Element,
+    context: &WgpuContext,
+    query: &mut gst::query::Allocation,
+    required: wgpu::TextureUsages,
+    view_format: Option<wgpu::TextureFormat>,
+) -> Result<(), gst::LoggableError> {
+    let (caps, _needs_pool) = query.get();
+    let Some(caps) = caps.map(|x| x.to_owned()) else {
+        return Err(gst::loggable_error!(
+            CAT,
+            "decide_allocation called wo caps"
+        ));
+    };
+
+    let info = match gst_video::VideoInfo::from_caps(&caps) {
+        Ok(info) => info,
+        Err(err) => {
+            return Err(gst::loggable_error!(CAT, "invalid output caps: {}", err));
+        }
+    };
+
+    let caps_info = match WgpuCapsInfo::from_caps(&caps) {
+        Ok(info) => info,
+        Err(err) => {
+            return Err(gst::loggable_error!(
+                CAT,
+                "cannot read output caps: {}",
+                err
+            ));
+        }
+    };
+    let usages = match caps_info.texture_usages() {
+        Ok(usages) => usages,
+        Err(err) => {
+            return Err(gst::loggable_error!(
+                CAT,
+                "cannot get texture usage in output caps: {}",
+                err
+            ));
+        }
+    };
+    // Caps without the format come from elements unaware of it, they get the preferred one for the video format
+    let format = match caps_info.texture_format() {
+        Ok(format) => format,
+        Err(_) => match texture_formats_for_video_format(info.format()).first() {
+            Some(format) => *format,
+            None => {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "no texture format for {:?}",
+                    info.format()
+                ));
+            }
+        },
+    };
+    if !usages.contains(required) {
+        return Err(gst::loggable_error!(
+            CAT,
+            "texture usage({:?}) in output caps does not contain {:?}",
+            usages,
+            required
+        ));
+    }
+
+    let suitable = query
+        .allocation_params()
+        .iter()
+        .enumerate()
+        .find_map(|(pos, (allocator, params))| {
+            let Some(wgpu_allocator) = allocator.and_downcast_ref::<WgpuTextureMemoryAllocator>()
+            else {
+                gst::trace!(CAT, obj: element, "skipping allocator at {pos}, not an WGPU texture");
+                return None;
+            };
+
+            let descriptor = wgpu_allocator.descriptor();
+            if descriptor.format != format
+                || !descriptor.usage.contains(required)
+                || view_format.is_some_and(|x| !wgpu_allocator.can_view_as(x))
+            {
+                gst::trace!(CAT, obj: element, "skipping allocator at {pos}, its textures cannot be written");
+                return None;
+            }
+
+            Some((wgpu_allocator.clone(), params.clone()))
+        });
+
+    let (allocator, params) = match suitable {
+        Some(found) => {
+            gst::trace!(CAT, obj: element, "using allocator of downstream: {:?}", found.0);
+            found
+        }
+        None => {
+            gst::debug!(CAT, obj: element, "have to use own allocator");
+
+            if view_format.is_some_and(|x| x != format && !srgb_view_formats(format).contains(&x)) {
+                return Err(gst::loggable_error!(
+                    CAT,
+                    "output texture format({:?}) cannot be viewed as {:?}",
+                    format,
+                    view_format
+                ));
+            }
+
+            let descriptor = wgpu::TextureDescriptor {
+                label: None,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                mip_level_count: 1,
+                sample_count: 1,
+                size: wgpu::Extent3d {
+                    width: info.width(),
+                    height: info.height(),
+                    depth_or_array_layers: 1,
+                },
+                usage: usages,
+                // Allows consumers to read the texture as sRGB or linear whatever is negotiated
+                view_formats: srgb_view_formats(format),
+            };
+
+            let label = context.label(element, "output textures", None);
+            let allocator =
+                WgpuTextureMemoryAllocator::new(context.clone(), descriptor).with_label(label);
+            let params = gst::AllocationParams::new(gst::MemoryFlags::NOT_MAPPABLE, 0, 0, 0);
+            (allocator, params)
+        }
+    };
+
+    set_pooled_allocation(query, &caps, info.size(), &allocator, params)
+}
+
+/// Replaces the pools and allocators of the allocation query with a pool of `allocator`
+///
+/// Memories are created once and reused for every frame, the base class activates the pool.
+pub fn set_pooled_allocation(
+    query: &mut gst::query::Allocation,
+    caps: &gst::Caps,
+    size: usize,
+    allocator: &impl IsA<gst::Allocator>,
+    params: gst::AllocationParams,
+) -> Result<(), gst::LoggableError> {
+    let pool = gst::BufferPool::new();
+    let mut config = pool.config();
+    config.set_params(Some(caps), size as u32, 0, 0);
+    config.set_allocator(Some(allocator), Some(&params));
+    if let Err(err) = pool.set_config(config) {
+        return Err(gst::loggable_error!(
+            CAT,
+            "failed to configure output pool: {}",
+            err
+        ));
+    }
+
+    while !query.allocation_pools().is_empty() {
+        query.remove_nth_allocation_pool(0);
+    }
+    while !query.allocation_params().is_empty() {
+        query.remove_nth_allocation_param(0);
+    }
+
+    query.add_allocation_pool(Some(&pool), size as u32, 0, 0);
+    query.add_allocation_param(Some(allocator), params);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn texture_allocation_is_pooled() {
+        gst::init().unwrap();
+        let Some(ctx) = WgpuContext::for_tests(Default::default()) else {
+            return;
+        };
+        let element = gst::Bin::new().upcast::<gst::Element>();
+
+        // Without texture-format the preferred format of RGBA is used
+        let caps = gst::Caps::from_str(
+            "video/x-raw(memory:WgpuTexture), format=RGBA, width=4, height=4, texture-usage=(uint)12",
+        )
+        .unwrap();
+        let mut query = gst::query::Allocation::new(Some(&caps), true);
+
+        decide_texture_allocation(
+            &element,
+            &ctx,
+            &mut query,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            Some(wgpu::TextureFormat::Rgba8Unorm),
+        )
+        .unwrap();
+
+        let pools = query.allocation_pools();
+        assert_eq!(pools.len(), 1);
+        let pool = pools[0].0.clone().unwrap();
+        pool.set_active(true).unwrap();
+        let buffer = pool.acquire_buffer(None).unwrap();
+        let allocator = query.allocation_params()[0]
+            .0
+            .clone()
+            .and_downcast::<WgpuTextureMemoryAllocator>()
+            .unwrap();
+        assert_eq!(
+            allocator.descriptor().format,
+            wgpu::TextureFormat::Rgba8Unorm
+        );
+        assert_eq!(buffer.n_memory(), 1);
+        drop(buffer);
+        pool.set_active(false).unwrap();
+    }
+}
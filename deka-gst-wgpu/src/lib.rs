@@ -1,8 +1,18 @@
+pub mod batcher;
 pub mod buffer_memory;
 pub mod caps;
 pub mod context;
+pub mod crop;
+pub mod element;
+pub mod options;
+pub mod poller;
+pub mod profiler;
+pub mod shader_cache;
+pub mod staging;
+pub mod texture_cache;
 pub mod texture_memory;
 pub mod texture_meta;
+pub mod texture_pool;
 
 use gst::glib;
 extern crate gstreamer as gst;
@@ -23,3 +33,4 @@ pub mod prelude {
 
 pub use buffer_memory::{WgpuBufferMemory, WgpuBufferMemoryAllocator};
 pub use context::{PollType, WgpuContext, GST_CONTEXT_WGPU_TYPE};
+pub use options::WgpuContextOptions;
@@ -0,0 +1,246 @@
+//!
+//! Options of the context: wgpu API trace capture, validation, debug labels and command batching
+//!
+//! The options are applied when the device is created, [`WgpuContextOptions::from_env`] reads them from:
+//! * `DEKA_GST_WGPU_TRACE_DIR` - directory to capture the API trace into, needs the `trace` feature
+//! * `DEKA_GST_WGPU_VALIDATION` - `1` enables validation of the backend
+//! * `DEKA_GST_WGPU_DEBUG_LABELS` - `1` labels objects with the element name and frame PTS
+//! * `DEKA_GST_WGPU_COMMAND_BATCHING` - `1` merges commands of chained elements into one submission per frame
+//!
+//! Elements expose them as properties with the environment values as defaults, see
+//! [`WgpuContextOptions::param_specs`]. The properties apply only to the context the element creates itself, a context
+//! shared by other elements keeps its own options.
+//!
+
+use std::path::PathBuf;
+
+use gst::prelude::*;
+
+use crate::glib;
+
+/// Environment variable with directory for wgpu API trace
+pub const DEKA_GST_WGPU_TRACE_DIR_ENV: &str = "DEKA_GST_WGPU_TRACE_DIR";
+/// Environment variable which enables validation
+pub const DEKA_GST_WGPU_VALIDATION_ENV: &str = "DEKA_GST_WGPU_VALIDATION";
+/// Environment variable which enables debug labels
+pub const DEKA_GST_WGPU_DEBUG_LABELS_ENV: &str = "DEKA_GST_WGPU_DEBUG_LABELS";
+/// Environment variable which enables command batching
+pub const DEKA_GST_WGPU_COMMAND_BATCHING_ENV: &str = "DEKA_GST_WGPU_COMMAND_BATCHING";
+
+/// Element property with [`WgpuContextOptions::trace_dir`]
+pub const GST_WGPU_TRACE_DIR_PROPERTY: &str = "wgpu-trace-dir";
+/// Element property with [`WgpuContextOptions::validation`]
+pub const GST_WGPU_VALIDATION_PROPERTY: &str = "wgpu-validation";
+/// Element property with [`WgpuContextOptions::debug_labels`]
+pub const GST_WGPU_DEBUG_LABELS_PROPERTY: &str = "wgpu-debug-labels";
+/// Element property with [`WgpuContextOptions::command_batching`]
+pub const GST_WGPU_COMMAND_BATCHING_PROPERTY: &str = "wgpu-command-batching";
+
+/// Parses boolean value of environment variable, `None` if the value is unknown
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads boolean environment variable, `None` if it is not set or has unknown value
+fn env_flag(name: &str) -> Option<bool> {
+    parse_flag(&std::env::var(name).ok()?)
+}
+
+/// Options applied at creation of [`crate::WgpuContext`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WgpuContextOptions {
+    /// Directory to capture wgpu API trace into
+    ///
+    /// wgpu records the trace only if the crate is built with the `trace` feature, otherwise it is ignored with a warning.
+    pub trace_dir: Option<PathBuf>,
+    /// Enables validation of the backend, for example Vulkan validation layers
+    pub validation: bool,
+    /// Passes labels to the backend and labels allocations, encoders and passes with the element name and frame PTS
+    pub debug_labels: bool,
+    /// Merges commands recorded by elements into one submission per frame, see [`crate::batcher`]
+    pub command_batching: bool,
+}
+
+impl WgpuContextOptions {
+    /// Reads options from environment variables, unset variables keep default values
+    pub fn from_env() -> Self {
+        let trace_dir = std::env::var_os(DEKA_GST_WGPU_TRACE_DIR_ENV)
+            .filter(|x| !x.is_empty())
+            .map(PathBuf::from);
+
+        Self {
+            trace_dir,
+            validation: env_flag(DEKA_GST_WGPU_VALIDATION_ENV).unwrap_or_default(),
+            debug_labels: env_flag(DEKA_GST_WGPU_DEBUG_LABELS_ENV).unwrap_or_default(),
+            command_batching: env_flag(DEKA_GST_WGPU_COMMAND_BATCHING_ENV).unwrap_or_default(),
+        }
+    }
+
+    /// Properties of the options, defaults are read from the environment
+    pub fn param_specs() -> Vec<glib::ParamSpec> {
+        let defaults = Self::from_env();
+
+        vec![
+            glib::ParamSpecString::builder(GST_WGPU_TRACE_DIR_PROPERTY)
+                .nick("Trace directory")
+                .blurb("Directory to capture wgpu API trace into, used when the element creates own context")
+                .default_value(
+                    defaults
+                        .trace_dir
+                        .as_ref()
+                        .and_then(|x| x.to_str())
+                        .map(|x| x.to_owned()),
+                )
+                .mutable_ready()
+                .build(),
+            glib::ParamSpecBoolean::builder(GST_WGPU_VALIDATION_PROPERTY)
+                .nick("Validation")
+                .blurb("Enables validation of the backend, used when the element creates own context")
+                .default_value(defaults.validation)
+                .mutable_ready()
+                .build(),
+            glib::ParamSpecBoolean::builder(GST_WGPU_DEBUG_LABELS_PROPERTY)
+                .nick("Debug labels")
+                .blurb("Labels objects with the element name and frame PTS, used when the element creates own context")
+                .default_value(defaults.debug_labels)
+                .mutable_ready()
+                .build(),
+            glib::ParamSpecBoolean::builder(GST_WGPU_COMMAND_BATCHING_PROPERTY)
+                .nick("Command batching")
+                .blurb("Merges commands of chained elements into one submission per frame, used when the element creates own context")
+                .default_value(defaults.command_batching)
+                .mutable_ready()
+                .build(),
+        ]
+    }
+
+    /// Sets the property, returns false if it is not one of [`Self::param_specs`]
+    pub fn set_property(&mut self, value: &glib::Value, pspec: &glib::ParamSpec) -> bool {
+        match pspec.name() {
+            GST_WGPU_TRACE_DIR_PROPERTY => {
+                self.trace_dir = value
+                    .get::<Option<String>>()
+                    .expect("type checked upstream")
+                    .filter(|x| !x.is_empty())
+                    .map(PathBuf::from);
+            }
+            GST_WGPU_VALIDATION_PROPERTY => {
+                self.validation = value.get().expect("type checked upstream");
+            }
+            GST_WGPU_DEBUG_LABELS_PROPERTY => {
+                self.debug_labels = value.get().expect("type checked upstream");
+            }
+            GST_WGPU_COMMAND_BATCHING_PROPERTY => {
+                self.command_batching = value.get().expect("type checked upstream");
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Value of the property, `None` if it is not one of [`Self::param_specs`]
+    pub fn property(&self, pspec: &glib::ParamSpec) -> Option<glib::Value> {
+        let value = match pspec.name() {
+            GST_WGPU_TRACE_DIR_PROPERTY => self
+                .trace_dir
+                .as_ref()
+                .map(|x| x.to_string_lossy().into_owned())
+                .to_value(),
+            GST_WGPU_VALIDATION_PROPERTY => self.validation.to_value(),
+            GST_WGPU_DEBUG_LABELS_PROPERTY => self.debug_labels.to_value(),
+            GST_WGPU_COMMAND_BATCHING_PROPERTY => self.command_batching.to_value(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Instance descriptor from wgpu environment variables with the options applied on top
+    pub fn instance_descriptor(&self) -> wgpu::InstanceDescriptor {
+        let mut desc = wgpu::InstanceDescriptor::from_env_or_default();
+
+        if self.validation {
+            desc.flags |= wgpu::InstanceFlags::VALIDATION;
+        }
+
+        if self.debug_labels {
+            desc.flags |= wgpu::InstanceFlags::DEBUG;
+            desc.flags.remove(wgpu::InstanceFlags::DISCARD_HAL_LABELS);
+        }
+
+        desc
+    }
+
+    /// Trace setting for the device descriptor
+    pub fn trace(&self) -> wgpu::Trace {
+        #[cfg(feature = "trace")]
+        if let Some(dir) = &self.trace_dir {
+            return wgpu::Trace::Directory(dir.clone());
+        }
+
+        wgpu::Trace::Off
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_are_parsed() {
+        for value in ["1", "true", "YES", " on "] {
+            assert_eq!(parse_flag(value), Some(true), "{value}");
+        }
+        for value in ["0", "False", "no", "off", ""] {
+            assert_eq!(parse_flag(value), Some(false), "{value}");
+        }
+        assert_eq!(parse_flag("2"), None);
+        assert_eq!(parse_flag("enabled"), None);
+    }
+
+    #[test]
+    fn options_follow_properties() {
+        gst::init().unwrap();
+
+        let specs = WgpuContextOptions::param_specs();
+        let spec = |name: &str| specs.iter().find(|x| x.name() == name).unwrap();
+
+        let mut options = WgpuContextOptions::default();
+        assert!(options.set_property(&true.to_value(), spec(GST_WGPU_VALIDATION_PROPERTY)));
+        assert!(options.set_property(&true.to_value(), spec(GST_WGPU_COMMAND_BATCHING_PROPERTY)));
+        assert!(options.set_property(
+            &Some("/tmp/trace").to_value(),
+            spec(GST_WGPU_TRACE_DIR_PROPERTY)
+        ));
+
+        assert_eq!(
+            options,
+            WgpuContextOptions {
+                trace_dir: Some(PathBuf::from("/tmp/trace")),
+                validation: true,
+                debug_labels: false,
+                command_batching: true,
+            }
+        );
+        assert_eq!(
+            options
+                .property(spec(GST_WGPU_TRACE_DIR_PROPERTY))
+                .unwrap()
+                .get::<Option<String>>()
+                .unwrap()
+                .as_deref(),
+            Some("/tmp/trace")
+        );
+
+        // An empty directory disables the trace like an empty environment variable
+        options.set_property(&Some("").to_value(), spec(GST_WGPU_TRACE_DIR_PROPERTY));
+        assert_eq!(options.trace_dir, None);
+
+        let stats = glib::ParamSpecBoolean::builder("stats").build();
+        assert!(!options.set_property(&true.to_value(), &stats));
+        assert!(options.property(&stats).is_none());
+    }
+}
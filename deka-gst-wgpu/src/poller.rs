@@ -0,0 +1,120 @@
+//!
+//! Tracks the work submitted to the device so it is polled only while there is something to wait for
+//!
+
+use parking_lot::{Condvar, Mutex};
+
+#[derive(Debug, Default)]
+struct PollerState {
+    /// The latest submission which is not waited for yet
+    submission: Option<wgpu::SubmissionIndex>,
+    /// Buffer map was requested after the last poll started
+    map_requested: bool,
+    /// Number of finished polls, waiters use it to detect progress
+    generation: u64,
+    /// Number of submissions made so far
+    submissions: u64,
+    stopped: bool,
+}
+
+/// Work the poll thread has to wait for
+#[derive(Debug)]
+pub(crate) enum PollWork {
+    /// Wait until the submission is done, `None` means the latest known by device
+    Wait(Option<wgpu::SubmissionIndex>),
+    /// The poller is stopped, thread should exit
+    Stop,
+}
+
+/// Wakes the poll thread when there is something to poll and the waiting threads after every poll
+#[derive(Debug, Default)]
+pub struct DevicePoller {
+    state: Mutex<PollerState>,
+    /// Signaled when new work for the poll thread arrives
+    work: Condvar,
+    /// Signaled after every finished poll
+    polled: Condvar,
+}
+
+impl DevicePoller {
+    /// Submits command buffers to the queue and wakes the poll thread
+    pub fn submit<I: IntoIterator<Item = wgpu::CommandBuffer>>(
+        &self,
+        queue: &wgpu::Queue,
+        command_buffers: I,
+    ) -> wgpu::SubmissionIndex {
+        // Submit under the lock, so the stored index is always the latest one
+        let mut state = self.state.lock();
+        let index = queue.submit(command_buffers);
+        state.submission = Some(index.clone());
+        state.submissions = state.submissions.wrapping_add(1);
+        self.work.notify_one();
+
+        index
+    }
+
+    /// Tells the poll thread that a map callback is waiting for the poll
+    pub fn request_map(&self) {
+        let mut state = self.state.lock();
+        state.map_requested = true;
+        self.work.notify_one();
+    }
+
+    /// Number of submissions made so far
+    pub fn submissions(&self) -> u64 {
+        self.state.lock().submissions
+    }
+
+    /// Number of polls finished so far
+    pub fn generation(&self) -> u64 {
+        self.state.lock().generation
+    }
+
+    /// Blocks until a poll started after `generation` is finished
+    ///
+    /// Returns immediately if the poller is stopped. Must not be used with the manual polling, no one would wake it up.
+    pub fn wait_polled(&self, generation: u64) {
+        let mut state = self.state.lock();
+        while state.generation == generation && !state.stopped {
+            self.polled.wait(&mut state);
+        }
+    }
+
+    /// Blocks until there is work to poll
+    pub(crate) fn next_work(&self) -> PollWork {
+        let mut state = self.state.lock();
+        while state.submission.is_none() && !state.map_requested && !state.stopped {
+            self.work.wait(&mut state);
+        }
+
+        if state.stopped {
+            return PollWork::Stop;
+        }
+
+        state.map_requested = false;
+        PollWork::Wait(state.submission.take())
+    }
+
+    /// Marks the poll as finished and wakes the waiting threads
+    ///
+    /// If the wait for `submission` timed out, the work is requeued so the next poll waits for it again.
+    pub(crate) fn finish_poll(&self, submission: Option<wgpu::SubmissionIndex>, timed_out: bool) {
+        let mut state = self.state.lock();
+        if timed_out {
+            if state.submission.is_none() {
+                state.submission = submission;
+            }
+            state.map_requested = true;
+        }
+        state.generation = state.generation.wrapping_add(1);
+        self.polled.notify_all();
+    }
+
+    /// Stops the poll thread and releases all waiters
+    pub(crate) fn stop(&self) {
+        let mut state = self.state.lock();
+        state.stopped = true;
+        self.work.notify_all();
+        self.polled.notify_all();
+    }
+}
@@ -0,0 +1,389 @@
+//!
+//! GPU time of elements measured with timestamp queries
+//!
+//! Profiling is disabled by default, the `wgpuprofiler` tracer enables it. Every element owns [`ElementProfiler`] which
+//! writes timestamps around its commands and reads them back when the work is done. Results are accumulated in
+//! [`GpuStats`] and sent to listeners added with [`add_profile_listener`].
+//!
+//! All commands an element records for a frame are measured at once. Timestamps are written by the encoder with
+//! [`wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS`], otherwise by empty compute passes around the commands, which
+//! needs only [`wgpu::Features::TIMESTAMP_QUERY`].
+//!
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+use parking_lot::Mutex;
+
+use crate::{glib, WgpuContext};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "gstwgpuprofiler",
+        gst::DebugColorFlags::empty(),
+        Some("Gstreamer WGPU GPU time profiler"),
+    )
+});
+
+/// Name of the structure with element statistics
+pub const GST_WGPU_STATS_STRUCTURE_NAME: &str = "deka-wgpu-stats";
+/// Read only element property with [`GpuStats`] as `GstStructure`
+pub const GST_WGPU_STATS_PROPERTY: &str = "stats";
+
+/// Number of frames which can be measured at the same time by one element, other frames are skipped
+const PROFILE_SLOTS: usize = 4;
+/// Start and end timestamps
+const QUERY_COUNT: u32 = 2;
+const QUERY_BUFFER_SIZE: wgpu::BufferAddress = QUERY_COUNT as u64 * wgpu::QUERY_SIZE as u64;
+
+static PROFILING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables profiling of all elements, elements start profiling on the next frame
+pub fn set_profiling_enabled(enabled: bool) {
+    PROFILING_ENABLED.store(enabled, Ordering::Release);
+}
+
+#[inline]
+pub fn profiling_enabled() -> bool {
+    PROFILING_ENABLED.load(Ordering::Acquire)
+}
+
+/// Measurement of one frame
+#[derive(Debug, Clone, Copy)]
+pub struct GpuSample {
+    /// Time between the first and the last timestamp of the element
+    pub gpu_time: Duration,
+    /// Time from recording of the commands until they are done minus the GPU time,
+    /// includes the wait in the queue and the poll delay
+    pub queue_latency: Duration,
+}
+
+/// Accumulated measurements of one element
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GpuStats {
+    pub frames: u64,
+    pub gpu_time_total: Duration,
+    pub gpu_time_last: Duration,
+    pub gpu_time_max: Duration,
+    pub queue_latency_total: Duration,
+    pub queue_latency_last: Duration,
+}
+
+impl GpuStats {
+    fn add(&mut self, sample: &GpuSample) {
+        self.frames += 1;
+        self.gpu_time_total += sample.gpu_time;
+        self.gpu_time_last = sample.gpu_time;
+        self.gpu_time_max = self.gpu_time_max.max(sample.gpu_time);
+        self.queue_latency_total += sample.queue_latency;
+        self.queue_latency_last = sample.queue_latency;
+    }
+
+    pub fn gpu_time_avg(&self) -> Duration {
+        self.gpu_time_total
+            .checked_div(self.frames as u32)
+            .unwrap_or_default()
+    }
+
+    pub fn queue_latency_avg(&self) -> Duration {
+        self.queue_latency_total
+            .checked_div(self.frames as u32)
+            .unwrap_or_default()
+    }
+
+    /// Statistics as structure, times are in nanoseconds
+    pub fn to_structure(&self) -> gst::Structure {
+        gst::Structure::builder(GST_WGPU_STATS_STRUCTURE_NAME)
+            .field("frames", self.frames)
+            .field("gpu-time-avg", self.gpu_time_avg().as_nanos() as u64)
+            .field("gpu-time-last", self.gpu_time_last.as_nanos() as u64)
+            .field("gpu-time-max", self.gpu_time_max.as_nanos() as u64)
+            .field(
+                "queue-latency-avg",
+                self.queue_latency_avg().as_nanos() as u64,
+            )
+            .field(
+                "queue-latency-last",
+                self.queue_latency_last.as_nanos() as u64,
+            )
+            .build()
+    }
+}
+
+/// Spec of the [`GST_WGPU_STATS_PROPERTY`] property
+pub fn stats_param_spec() -> glib::ParamSpec {
+    glib::ParamSpecBoxed::builder::<gst::Structure>(GST_WGPU_STATS_PROPERTY)
+        .nick("Statistics")
+        .blurb("GPU time and queue latency of the element, times are in nanoseconds")
+        .read_only()
+        .build()
+}
+
+type ProfileListener = Arc<dyn Fn(&gst::Element, &GpuSample, &GpuStats) + Send + Sync>;
+
+/// Id of the listener to remove it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProfileListenerId(u64);
+
+static LISTENERS: Mutex<Vec<(ProfileListenerId, ProfileListener)>> = Mutex::new(Vec::new());
+static NEXT_LISTENER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Adds function which is called for every measured frame
+///
+/// It is called from the poll thread, so it must be short.
+pub fn add_profile_listener(
+    listener: impl Fn(&gst::Element, &GpuSample, &GpuStats) + Send + Sync + 'static,
+) -> ProfileListenerId {
+    let id = ProfileListenerId(NEXT_LISTENER_ID.fetch_add(1, Ordering::Relaxed));
+    LISTENERS.lock().push((id, Arc::new(listener)));
+    id
+}
+
+pub fn remove_profile_listener(id: ProfileListenerId) {
+    LISTENERS.lock().retain(|(x, _)| *x != id);
+}
+
+fn notify_listeners(element: &gst::Element, sample: &GpuSample, stats: &GpuStats) {
+    // Do not hold the lock while calling, so listeners can remove themselves
+    let listeners = LISTENERS
+        .lock()
+        .iter()
+        .map(|(_, x)| Arc::clone(x))
+        .collect::<Vec<_>>();
+
+    for listener in listeners {
+        listener(element, sample, stats);
+    }
+}
+
+/// Queries and buffers to measure one frame
+#[derive(Debug)]
+struct ProfileSlot {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    /// Set while the frame is measured, until the readback is done
+    busy: AtomicBool,
+}
+
+/// Slot taken for one frame, it is free again when the lease is dropped
+///
+/// The lease moves into the readback callback, which wgpu drops without calling if the encoder is never submitted.
+#[derive(Debug)]
+struct SlotLease(Arc<ProfileSlot>);
+
+impl Drop for SlotLease {
+    fn drop(&mut self) {
+        self.0.busy.store(false, Ordering::Release);
+    }
+}
+
+/// Frame being measured, returned by [`ElementProfiler::begin`]
+#[derive(Debug)]
+pub struct ProfileScope {
+    lease: SlotLease,
+    started: Instant,
+}
+
+/// Measures GPU time of one element
+#[derive(Debug)]
+pub struct ElementProfiler {
+    element: glib::WeakRef<gst::Element>,
+    slots: Vec<Arc<ProfileSlot>>,
+    stats: Arc<Mutex<GpuStats>>,
+    timestamp_period: f32,
+    /// Timestamps can be written between commands, not only at pass boundaries
+    inside_encoders: bool,
+}
+
+impl ElementProfiler {
+    /// Creates profiler, `None` if the device does not support timestamp queries
+    pub fn new(context: &WgpuContext, element: &gst::Element) -> Option<Self> {
+        let device = context.device();
+        let features = device.features();
+        if !features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+            gst::info!(CAT, obj: element, "timestamp queries are not supported");
+            return None;
+        }
+
+        let slots = (0..PROFILE_SLOTS)
+            .map(|_| {
+                Arc::new(ProfileSlot {
+                    query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: Some("deka-gst-wgpu-profiler"),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: QUERY_COUNT,
+                    }),
+                    resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("deka-gst-wgpu-profiler-resolve"),
+                        size: QUERY_BUFFER_SIZE,
+                        usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    }),
+                    readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("deka-gst-wgpu-profiler-readback"),
+                        size: QUERY_BUFFER_SIZE,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    }),
+                    busy: AtomicBool::new(false),
+                })
+            })
+            .collect();
+
+        Some(Self {
+            element: element.downgrade(),
+            slots,
+            stats: Default::default(),
+            timestamp_period: context.queue().get_timestamp_period(),
+            inside_encoders: features.contains(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS),
+        })
+    }
+
+    /// Returns the profiler if profiling is enabled, creates it on the first call
+    pub fn get_or_init<'a>(
+        profiler: &'a mut Option<Self>,
+        context: &WgpuContext,
+        element: &gst::Element,
+    ) -> Option<&'a Self> {
+        if !profiling_enabled() {
+            return None;
+        }
+
+        if profiler.is_none() {
+            *profiler = Self::new(context, element);
+        }
+
+        profiler.as_ref()
+    }
+
+    /// Accumulated statistics
+    pub fn stats(&self) -> GpuStats {
+        *self.stats.lock()
+    }
+
+    /// Starts measuring a frame, `None` if all slots are busy and the frame is skipped
+    pub fn begin(&self, encoder: &mut wgpu::CommandEncoder) -> Option<ProfileScope> {
+        let Some(slot) = self.slots.iter().find(|slot| {
+            slot.busy
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        }) else {
+            gst::trace!(CAT, "all profile slots are busy, frame is skipped");
+            return None;
+        };
+
+        let lease = SlotLease(Arc::clone(slot));
+        self.write_timestamp(encoder, &slot.query_set, 0);
+
+        Some(ProfileScope {
+            lease,
+            started: Instant::now(),
+        })
+    }
+
+    /// Writes timestamp `index` between the commands of the encoder
+    fn write_timestamp(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        query_set: &wgpu::QuerySet,
+        index: u32,
+    ) {
+        if self.inside_encoders {
+            encoder.write_timestamp(query_set, index);
+            return;
+        }
+
+        // Passes run in the order they are recorded, an empty one marks the point between the commands
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("deka-gst-wgpu-profiler-timestamp"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: None,
+            }),
+        });
+    }
+
+    /// Finishes measuring the frame, the result is read back after `encoder` is submitted and done
+    pub fn end(&self, scope: ProfileScope, encoder: &mut wgpu::CommandEncoder) {
+        let ProfileScope { lease, started } = scope;
+        let slot = Arc::clone(&lease.0);
+        self.write_timestamp(encoder, &slot.query_set, 1);
+
+        encoder.resolve_query_set(&slot.query_set, 0..QUERY_COUNT, &slot.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &slot.resolve_buffer,
+            0,
+            &slot.readback_buffer,
+            0,
+            QUERY_BUFFER_SIZE,
+        );
+
+        let element = self.element.clone();
+        let stats = Arc::clone(&self.stats);
+        let timestamp_period = self.timestamp_period;
+        encoder.map_buffer_on_submit(
+            &slot.readback_buffer,
+            wgpu::MapMode::Read,
+            ..,
+            move |result| {
+                let slot = &lease.0;
+                if let Err(err) = result {
+                    gst::warning!(CAT, "failed to read timestamps: {}", err);
+                    return;
+                }
+
+                let timestamps = {
+                    let view = slot.readback_buffer.get_mapped_range(..);
+                    let start = u64::from_ne_bytes(view[0..8].try_into().unwrap());
+                    let end = u64::from_ne_bytes(view[8..16].try_into().unwrap());
+                    (start, end)
+                };
+                slot.readback_buffer.unmap();
+                drop(lease);
+
+                let ticks = timestamps.1.saturating_sub(timestamps.0);
+                let gpu_time =
+                    Duration::from_nanos((ticks as f64 * timestamp_period as f64) as u64);
+                let sample = GpuSample {
+                    gpu_time,
+                    queue_latency: started.elapsed().saturating_sub(gpu_time),
+                };
+
+                let stats = {
+                    let mut stats = stats.lock();
+                    stats.add(&sample);
+                    *stats
+                };
+
+                if let Some(element) = element.upgrade() {
+                    gst::trace!(CAT, obj: element, "{:?}", sample);
+                    notify_listeners(&element, &sample, &stats);
+                }
+            },
+        );
+    }
+
+    /// Measures all commands recorded by `f`
+    pub fn profile_encoder<R>(
+        profiler: Option<&Self>,
+        encoder: &mut wgpu::CommandEncoder,
+        f: impl FnOnce(&mut wgpu::CommandEncoder) -> R,
+    ) -> R {
+        let Some(profiler) = profiler else {
+            return f(encoder);
+        };
+
+        let Some(scope) = profiler.begin(encoder) else {
+            return f(encoder);
+        };
+
+        let out = f(encoder);
+        profiler.end(scope, encoder);
+
+        out
+    }
+}
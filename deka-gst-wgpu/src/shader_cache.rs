@@ -0,0 +1,250 @@
+//!
+//! Shader modules and compute pipelines shared by all elements of the context
+//!
+//! Pipelines are compiled once per shader source, entry point and bind group layouts. If the device supports
+//! [`wgpu::Features::PIPELINE_CACHE`], the driver cache is stored on disk, so the next start does not compile them again.
+//!
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+
+use crate::glib;
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "gstwgpushadercache",
+        gst::DebugColorFlags::empty(),
+        Some("Gstreamer WGPU shader and pipeline cache"),
+    )
+});
+
+/// Environment variable with directory for the pipeline cache, empty value disables storing the cache
+pub const DEKA_GST_WGPU_CACHE_DIR_ENV: &str = "DEKA_GST_WGPU_CACHE_DIR";
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct ComputePipelineKey {
+    source: String,
+    entry_point: String,
+    /// `None` if the layout is derived from the shader
+    bind_group_layouts: Option<Vec<Vec<wgpu::BindGroupLayoutEntry>>>,
+}
+
+/// Code of the shader source, `None` if the source kind cannot be cached
+fn wgsl_source<'a>(source: &'a wgpu::ShaderSource<'_>) -> Option<&'a str> {
+    match source {
+        wgpu::ShaderSource::Wgsl(code) => Some(code),
+        _ => None,
+    }
+}
+
+/// Directory where the pipeline cache is stored, `None` if it should not be stored
+fn cache_dir() -> Option<PathBuf> {
+    match std::env::var_os(DEKA_GST_WGPU_CACHE_DIR_ENV) {
+        Some(dir) if dir.is_empty() => None,
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => Some(glib::user_cache_dir().join("deka-gst-wgpu")),
+    }
+}
+
+/// Driver pipeline cache and the file it is stored in
+#[derive(Debug)]
+struct StoredPipelineCache {
+    cache: wgpu::PipelineCache,
+    path: PathBuf,
+}
+
+impl StoredPipelineCache {
+    fn load(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+
+        let file_name = wgpu::util::pipeline_cache_key(&adapter.get_info())?;
+        let path = cache_dir()?.join(file_name);
+        let data = std::fs::read(&path).ok();
+
+        gst::info!(
+            CAT,
+            "using pipeline cache {}, {} bytes loaded",
+            path.display(),
+            data.as_ref().map(|x| x.len()).unwrap_or_default()
+        );
+
+        // SAFETY: the data is either none or written by `save` for the same adapter,
+        // wgpu validates the header and falls back to the empty cache if it does not match
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("deka-gst-wgpu pipeline cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+
+        Some(Self { cache, path })
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let Some(data) = self.cache.get_data() else {
+            return Ok(());
+        };
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Write to the temporary file first, so other processes never read a partial cache
+        let tmp_path = self
+            .path
+            .with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&tmp_path, &data)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        gst::debug!(
+            CAT,
+            "stored pipeline cache {}, {} bytes",
+            self.path.display(),
+            data.len()
+        );
+
+        Ok(())
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Shader modules and compute pipelines of one device
+#[derive(Debug)]
+pub struct ShaderCache {
+    /// Keyed by the whole WGSL source, so different shaders never share a module
+    modules: Mutex<HashMap<String, wgpu::ShaderModule>>,
+    compute_pipelines: Mutex<HashMap<ComputePipelineKey, wgpu::ComputePipeline>>,
+    stored: Option<StoredPipelineCache>,
+}
+
+impl ShaderCache {
+    /// Creates the cache, loads the stored pipeline cache if the device supports it
+    pub fn new(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Self {
+        Self {
+            modules: Default::default(),
+            compute_pipelines: Default::default(),
+            stored: StoredPipelineCache::load(adapter, device),
+        }
+    }
+
+    /// Returns shader module compiled from the same source or compiles a new one
+    pub fn shader_module(
+        &self,
+        device: &wgpu::Device,
+        desc: wgpu::ShaderModuleDescriptor<'_>,
+    ) -> wgpu::ShaderModule {
+        let Some(source) = wgsl_source(&desc.source).map(str::to_owned) else {
+            return device.create_shader_module(desc);
+        };
+
+        let mut modules = self.modules.lock();
+        if let Some(module) = modules.get(&source) {
+            return module.clone();
+        }
+
+        gst::debug!(CAT, "compiling shader module {:?}", desc.label);
+        let module = device.create_shader_module(desc);
+        modules.insert(source, module.clone());
+
+        module
+    }
+
+    /// Returns compute pipeline with the same shader source, entry point and bind group layouts or creates a new one
+    ///
+    /// If `bind_group_layouts` is `None`, the layout is derived from the shader. In both cases use
+    /// [`wgpu::ComputePipeline::get_bind_group_layout`] to get layouts for bind groups.
+    pub fn compute_pipeline(
+        &self,
+        device: &wgpu::Device,
+        desc: wgpu::ShaderModuleDescriptor<'_>,
+        entry_point: &str,
+        bind_group_layouts: Option<&[&[wgpu::BindGroupLayoutEntry]]>,
+    ) -> wgpu::ComputePipeline {
+        let key = wgsl_source(&desc.source).map(|source| ComputePipelineKey {
+            source: source.to_owned(),
+            entry_point: entry_point.to_owned(),
+            bind_group_layouts: bind_group_layouts
+                .map(|layouts| layouts.iter().map(|entries| entries.to_vec()).collect()),
+        });
+
+        let Some(key) = key else {
+            let module = device.create_shader_module(desc);
+            return self.create_compute_pipeline(device, &module, entry_point, bind_group_layouts);
+        };
+
+        // Compile under the lock, so concurrently starting elements compile the pipeline once
+        let mut pipelines = self.compute_pipelines.lock();
+        if let Some(pipeline) = pipelines.get(&key) {
+            return pipeline.clone();
+        }
+
+        let module = self.shader_module(device, desc);
+        let pipeline =
+            self.create_compute_pipeline(device, &module, entry_point, bind_group_layouts);
+        pipelines.insert(key, pipeline.clone());
+
+        pipeline
+    }
+
+    fn create_compute_pipeline(
+        &self,
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        entry_point: &str,
+        bind_group_layouts: Option<&[&[wgpu::BindGroupLayoutEntry]]>,
+    ) -> wgpu::ComputePipeline {
+        gst::debug!(CAT, "compiling compute pipeline {}", entry_point);
+
+        let pipeline_layout = bind_group_layouts.map(|layouts| {
+            let bind_group_layouts = layouts
+                .iter()
+                .map(|entries| {
+                    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
+                push_constant_ranges: &[],
+            })
+        });
+
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: pipeline_layout.as_ref(),
+            module,
+            entry_point: Some(entry_point),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: self.stored.as_ref().map(|x| &x.cache),
+        })
+    }
+
+    /// Stores the pipeline cache on disk, does nothing if the device does not support it
+    pub fn save(&self) {
+        let Some(stored) = &self.stored else {
+            return;
+        };
+
+        if let Err(err) = stored.save() {
+            gst::warning!(
+                CAT,
+                "failed to store pipeline cache {}: {}",
+                stored.path().display(),
+                err
+            );
+        }
+    }
+}
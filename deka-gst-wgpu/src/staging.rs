@@ -0,0 +1,57 @@
+//!
+//! Pool of staging buffers used to move data between CPU and buffers which cannot be mapped
+//!
+
+use parking_lot::Mutex;
+
+/// How many free buffers the pool keeps, the oldest ones are dropped first
+const MAX_FREE_BUFFERS: usize = 8;
+
+/// Reuses staging buffers between transfers instead of creating new one for every map
+#[derive(Debug, Default)]
+pub struct StagingBufferPool {
+    free: Mutex<Vec<wgpu::Buffer>>,
+}
+
+impl StagingBufferPool {
+    /// Takes a buffer with exactly the `usage` and at least `size` bytes from the pool or creates a new one
+    pub fn acquire(
+        &self,
+        device: &wgpu::Device,
+        size: wgpu::BufferAddress,
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        {
+            let mut free = self.free.lock();
+
+            // Take the smallest buffer that fits
+            let best = free
+                .iter()
+                .enumerate()
+                .filter(|(_, buffer)| buffer.usage() == usage && size <= buffer.size())
+                .min_by_key(|(_, buffer)| buffer.size())
+                .map(|(pos, _)| pos);
+
+            if let Some(pos) = best {
+                return free.swap_remove(pos);
+            }
+        }
+
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("deka-gst-wgpu-staging"),
+            size: size.next_multiple_of(wgpu::MAP_ALIGNMENT),
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Returns the buffer to the pool. The buffer must not be mapped.
+    pub fn release(&self, buffer: wgpu::Buffer) {
+        let mut free = self.free.lock();
+        if MAX_FREE_BUFFERS <= free.len() {
+            free.remove(0);
+        }
+
+        free.push(buffer);
+    }
+}
@@ -0,0 +1,252 @@
+//!
+//! Views and bind groups cached per texture, so pooled textures are bound without creating objects every frame
+//!
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Weak};
+
+use parking_lot::Mutex;
+
+/// Maximum number of bind groups kept per texture, the least recently used one is dropped first
+pub const BIND_GROUP_CACHE_SIZE: usize = 8;
+
+/// [`wgpu::TextureViewDescriptor`] without the label, views which differ only in label are the same
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureViewKey {
+    pub format: Option<wgpu::TextureFormat>,
+    pub dimension: Option<wgpu::TextureViewDimension>,
+    pub usage: Option<wgpu::TextureUsages>,
+    pub aspect: wgpu::TextureAspect,
+    pub base_mip_level: u32,
+    pub mip_level_count: Option<u32>,
+    pub base_array_layer: u32,
+    pub array_layer_count: Option<u32>,
+}
+
+impl<L> From<&wgpu::TextureViewDescriptor<L>> for TextureViewKey {
+    fn from(desc: &wgpu::TextureViewDescriptor<L>) -> Self {
+        Self {
+            format: desc.format,
+            dimension: desc.dimension,
+            usage: desc.usage,
+            aspect: desc.aspect,
+            base_mip_level: desc.base_mip_level,
+            mip_level_count: desc.mip_level_count,
+            base_array_layer: desc.base_array_layer,
+            array_layer_count: desc.array_layer_count,
+        }
+    }
+}
+
+/// Resource of a single bind group entry, compared by the wgpu object ids
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum BindingResourceKey {
+    Buffer {
+        buffer: wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        size: Option<wgpu::BufferSize>,
+    },
+    Sampler(wgpu::Sampler),
+    TextureView(wgpu::TextureView),
+}
+
+impl BindingResourceKey {
+    /// `None` for resources which are not cached: arrays, acceleration structures and external textures
+    fn new(resource: &wgpu::BindingResource<'_>) -> Option<Self> {
+        match resource {
+            wgpu::BindingResource::Buffer(binding) => Some(Self::Buffer {
+                buffer: binding.buffer.clone(),
+                offset: binding.offset,
+                size: binding.size,
+            }),
+            wgpu::BindingResource::Sampler(sampler) => Some(Self::Sampler((*sampler).clone())),
+            wgpu::BindingResource::TextureView(view) => Some(Self::TextureView((*view).clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Marks resources which live as long as their owner: a pooled texture memory or the negotiated state of an element
+///
+/// Cached bind groups refer to the owners of their resources weakly and are dropped once an owner is gone.
+#[derive(Debug, Default)]
+pub struct ResourceOwner(Arc<()>);
+
+impl ResourceOwner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn watch(&self) -> Weak<()> {
+        Arc::downgrade(&self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct BindGroupKey {
+    layout: wgpu::BindGroupLayout,
+    entries: Vec<(u32, BindingResourceKey)>,
+}
+
+impl BindGroupKey {
+    fn new(layout: &wgpu::BindGroupLayout, entries: &[wgpu::BindGroupEntry<'_>]) -> Option<Self> {
+        let entries = entries
+            .iter()
+            .map(|entry| Some((entry.binding, BindingResourceKey::new(&entry.resource)?)))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            layout: layout.clone(),
+            entries,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct CachedBindGroup {
+    key: BindGroupKey,
+    owners: Vec<Weak<()>>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl CachedBindGroup {
+    fn is_alive(&self) -> bool {
+        self.owners.iter().all(|x| x.strong_count() > 0)
+    }
+}
+
+/// Views and bind groups of one texture
+///
+/// Cached bind groups keep their resources alive until one of their owners is gone, see [`Self::bind_group`].
+#[derive(Debug, Default)]
+pub struct TextureObjectCache {
+    owner: ResourceOwner,
+    views: Mutex<HashMap<TextureViewKey, wgpu::TextureView>>,
+    bind_groups: Mutex<VecDeque<CachedBindGroup>>,
+}
+
+impl TextureObjectCache {
+    /// Owner of the views of this texture, bind groups of other caches using them are dropped with the texture
+    pub fn owner(&self) -> &ResourceOwner {
+        &self.owner
+    }
+
+    /// Returns the cached view of `texture` or creates a new one
+    pub fn view(
+        &self,
+        texture: &wgpu::Texture,
+        desc: &wgpu::TextureViewDescriptor<'_>,
+    ) -> wgpu::TextureView {
+        self.views
+            .lock()
+            .entry(TextureViewKey::from(desc))
+            .or_insert_with(|| texture.create_view(desc))
+            .clone()
+    }
+
+    /// Returns the cached bind group with the same layout and resources or creates a new one
+    ///
+    /// The layout and every resource besides the views of this texture must belong to one of the `owners`, so they
+    /// are the same for many frames: resources created for a single frame only fill the cache. Bind groups whose
+    /// owner is gone are dropped on the next call. Bind groups with resources which cannot be compared (arrays,
+    /// acceleration structures) are created every time.
+    pub fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        entries: &[wgpu::BindGroupEntry<'_>],
+        owners: &[&ResourceOwner],
+    ) -> wgpu::BindGroup {
+        let create = || {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout,
+                entries,
+            })
+        };
+
+        let Some(key) = BindGroupKey::new(layout, entries) else {
+            return create();
+        };
+
+        let mut bind_groups = self.bind_groups.lock();
+        bind_groups.retain(CachedBindGroup::is_alive);
+
+        if let Some(pos) = bind_groups.iter().position(|x| x.key == key) {
+            let item = bind_groups.remove(pos).unwrap();
+            let bind_group = item.bind_group.clone();
+            bind_groups.push_front(item);
+            return bind_group;
+        }
+
+        let bind_group = create();
+        if bind_groups.len() >= BIND_GROUP_CACHE_SIZE {
+            bind_groups.pop_back();
+        }
+        bind_groups.push_front(CachedBindGroup {
+            key,
+            owners: owners.iter().map(|x| x.watch()).collect(),
+            bind_group: bind_group.clone(),
+        });
+
+        bind_group
+    }
+
+    /// Drops all cached objects
+    pub fn clear(&self) {
+        self.views.lock().clear();
+        self.bind_groups.lock().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WgpuContext;
+
+    #[test]
+    fn bind_groups_are_dropped_with_their_owner() {
+        gst::init().unwrap();
+        let Some(ctx) = WgpuContext::for_tests(Default::default()) else {
+            return;
+        };
+        let device = ctx.device();
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let entries = [wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }];
+
+        let cache = TextureObjectCache::default();
+        let owner = ResourceOwner::new();
+
+        let first = cache.bind_group(device, &layout, &entries, &[&owner]);
+        let second = cache.bind_group(device, &layout, &entries, &[&owner]);
+        assert_eq!(first, second);
+
+        drop(owner);
+        let owner = ResourceOwner::new();
+        let third = cache.bind_group(device, &layout, &entries, &[&owner]);
+        assert_ne!(first, third);
+        assert_eq!(cache.bind_groups.lock().len(), 1);
+    }
+}
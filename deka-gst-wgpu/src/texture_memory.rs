@@ -4,10 +4,12 @@
 
 use std::sync::LazyLock;
 
+use glib::object::Cast;
 use glib::translate::IntoGlibPtr;
 use glib::translate::{from_glib, from_glib_full};
 use gst::glib::subclass::types::ObjectSubclassIsExt;
 
+use crate::texture_cache::{ResourceOwner, TextureObjectCache};
 use crate::{glib, skip_assert_initialized, WgpuContext};
 
 static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
@@ -20,13 +22,63 @@ static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
 
 /// Caps with this feature implies that the buffer is a WGPU Texture.
 pub const GST_CAPS_FEATURE_MEMORY_WGPU_TEXTURE: &str = "memory:WgpuTexture";
-/// The field in structure to determinate texture usage, this is bitmask, the element should allocate output buffers which will
-/// contains all of required usages
+/// The field in structure to determinate texture usage, this is [`crate::caps::usage::WgpuTextureUsageFlags`], the element should
+/// allocate output buffers which will contains all of required usages
 pub const GST_CAPS_FIELD_WGPU_TEXTURE_USAGE: &str = "texture-usage";
+/// The field in structure with format of the texture, named as in WebGPU (see [`crate::caps::format`])
+pub const GST_CAPS_FIELD_WGPU_TEXTURE_FORMAT: &str = "texture-format";
 
 pub trait WgpuTextureMemoryExt {
     fn texture(&self) -> &wgpu::Texture;
     fn context(&self) -> &WgpuContext;
+    /// Views and bind groups created for this texture, they live as long as the memory
+    fn cache(&self) -> &TextureObjectCache;
+
+    /// Returns view of the texture, the view is created once per descriptor
+    fn view(&self, desc: &wgpu::TextureViewDescriptor<'_>) -> wgpu::TextureView {
+        self.cache().view(self.texture(), desc)
+    }
+
+    /// Returns the default view of the texture
+    fn default_view(&self) -> wgpu::TextureView {
+        self.view(&Default::default())
+    }
+
+    /// Creates view of the texture in `format`, which is either the texture format or one of its view formats
+    ///
+    /// For example `Rgba8UnormSrgb` view of `Rgba8Unorm` texture decodes sRGB to linear light on read.
+    /// The view is cached as [`Self::view`].
+    fn create_view_as(&self, format: wgpu::TextureFormat) -> wgpu::TextureView {
+        self.view(&wgpu::TextureViewDescriptor {
+            format: Some(format),
+            ..Default::default()
+        })
+    }
+
+    /// True if [`Self::create_view_as`] can be called with `format`
+    ///
+    /// Besides the texture format these are the view formats of the allocator, textures of other allocators have
+    /// none.
+    fn can_view_as(&self, format: wgpu::TextureFormat) -> bool;
+
+    /// Owner of the views of this memory, pass it to [`Self::bind_group`] of other memories binding them
+    fn owner(&self) -> &ResourceOwner {
+        self.cache().owner()
+    }
+
+    /// Returns bind group with `entries`, which is reused while the layout and all resources are the same
+    ///
+    /// Pooled memories are used again and again, so filters binding them do not create bind groups every frame.
+    /// Resources besides the views of this memory must belong to the `owners`, see [`TextureObjectCache::bind_group`].
+    fn bind_group(
+        &self,
+        layout: &wgpu::BindGroupLayout,
+        entries: &[wgpu::BindGroupEntry<'_>],
+        owners: &[&ResourceOwner],
+    ) -> wgpu::BindGroup {
+        self.cache()
+            .bind_group(self.context().device(), layout, entries, owners)
+    }
 }
 
 gst::memory_object_wrapper!(
@@ -43,9 +95,17 @@ impl WgpuTextureMemoryExt for WgpuTextureMemory {
         &self.0.texture
     }
 
+    fn can_view_as(&self, format: wgpu::TextureFormat) -> bool {
+        memory_can_view_as(self, &self.0.texture, format)
+    }
+
     fn context(&self) -> &WgpuContext {
         &self.0.context
     }
+
+    fn cache(&self) -> &TextureObjectCache {
+        &self.0.cache
+    }
 }
 
 impl WgpuTextureMemoryExt for WgpuTextureMemoryRef {
@@ -53,9 +113,29 @@ impl WgpuTextureMemoryExt for WgpuTextureMemoryRef {
         &self.0.texture
     }
 
+    fn can_view_as(&self, format: wgpu::TextureFormat) -> bool {
+        memory_can_view_as(self, &self.0.texture, format)
+    }
+
     fn context(&self) -> &WgpuContext {
         &self.0.context
     }
+
+    fn cache(&self) -> &TextureObjectCache {
+        &self.0.cache
+    }
+}
+
+fn memory_can_view_as(
+    mem: &gst::MemoryRef,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+) -> bool {
+    texture.format() == format
+        || mem
+            .allocator()
+            .and_then(|x| x.downcast_ref::<WgpuTextureMemoryAllocator>())
+            .is_some_and(|x| x.can_view_as(format))
 }
 
 glib::wrapper! {
@@ -78,6 +158,13 @@ impl WgpuTextureMemoryAllocator {
         out
     }
 
+    /// Sets label of allocated textures, see [`WgpuContext::label`]
+    pub fn with_label(self, label: Option<String>) -> Self {
+        // SAFETY: The allocator is not shared yet, it takes self by value right after creation
+        unsafe { *self.imp().label.get() = label };
+        self
+    }
+
     pub fn context(&self) -> WgpuContext {
         let imp = self.imp();
         let cell = unsafe { &*imp.context.get() };
@@ -89,6 +176,17 @@ impl WgpuTextureMemoryAllocator {
         let cell = unsafe { &*imp.descriptor.get() };
         cell
     }
+
+    /// Formats the allocated textures can be viewed as besides their own format
+    pub fn view_formats(&self) -> &'static [wgpu::TextureFormat] {
+        self.descriptor().view_formats
+    }
+
+    /// True if the allocated textures can be viewed in `format`
+    pub fn can_view_as(&self, format: wgpu::TextureFormat) -> bool {
+        let descriptor = self.descriptor();
+        descriptor.format == format || descriptor.view_formats.contains(&format)
+    }
 }
 
 mod imp {
@@ -105,6 +203,7 @@ mod imp {
 
     use super::CAT;
     use crate::glib;
+    use crate::texture_cache::{ResourceOwner, TextureObjectCache};
     use crate::WgpuContext;
 
     pub const GST_WGPU_ALLOCATOR_TYPE: &[u8] = b"RustWgpuTextureAllocator\0";
@@ -114,6 +213,7 @@ mod imp {
         pub(super) parent: gst::ffi::GstMemory,
         pub(super) context: ManuallyDrop<WgpuContext>,
         pub(super) texture: ManuallyDrop<wgpu::Texture>,
+        pub(super) cache: ManuallyDrop<TextureObjectCache>,
     }
 
     impl std::fmt::Debug for WgpuTextureMemory {
@@ -164,6 +264,7 @@ mod imp {
     pub struct WgpuMemoryAllocator {
         pub(super) context: UnsafeCell<Option<WgpuContext>>,
         pub(super) descriptor: UnsafeCell<wgpu::TextureDescriptor<'static>>,
+        pub(super) label: UnsafeCell<Option<String>>,
     }
 
     impl WgpuMemoryAllocator {
@@ -202,6 +303,7 @@ mod imp {
                     usage: wgpu::TextureUsages::empty(),
                     view_formats: &[],
                 }),
+                label: Default::default(),
             }
         }
     }
@@ -263,9 +365,11 @@ mod imp {
                 gst::warning!(CAT, imp: self, "trying to alloc tetxure without NOT_MAPPABLE set. Wgpu Textures cannot be mapped!");
             }
 
+            let descriptor = unsafe { &*self.descriptor.get() };
+            let label = unsafe { &*self.label.get() };
             let wgpu_texture = self
                 .device()
-                .create_texture(unsafe { &*self.descriptor.get() });
+                .create_texture(&descriptor.map_label(|_| label.as_deref()));
 
             unsafe {
                 core::ptr::write(
@@ -273,6 +377,10 @@ mod imp {
                     ManuallyDrop::new(self.context().clone()),
                 );
                 core::ptr::write(&raw mut (*mem).texture, ManuallyDrop::new(wgpu_texture));
+                core::ptr::write(
+                    &raw mut (*mem).cache,
+                    ManuallyDrop::new(TextureObjectCache::default()),
+                );
             }
 
             gst::debug!(CAT, "allocated buffer {:p}, maxsize {}", mem, maxsize);
@@ -285,6 +393,10 @@ mod imp {
             let mut wgpu_mem: super::WgpuTextureMemory =
                 memory.downcast_memory().expect("non wgpu mem passed");
             let wgpu_mem_obj = unsafe { wgpu_mem.obj.as_mut() };
+            // Cached views and bind groups reference the texture, drop them first
+            unsafe {
+                ManuallyDrop::drop(&mut wgpu_mem_obj.cache);
+            };
             unsafe {
                 ManuallyDrop::drop(&mut wgpu_mem_obj.context);
             };
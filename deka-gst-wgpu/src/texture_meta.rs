@@ -4,13 +4,17 @@
 
 use gst::{MetaAPI, MetaAPIExt};
 
-use crate::{glib, WgpuContext};
+use crate::{
+    glib,
+    texture_memory::{WgpuTextureMemory, WgpuTextureMemoryExt},
+    WgpuContext,
+};
 
 /// GstCapsFeature that tells that the buffer has a WGPU texture attached with uploaded content of WGPU buffer to this structure
 pub const GST_CAPS_FEATURE_META_WGPU_TEXTURE: &str = "meta:WgpuTextureMetaAPI";
 
-/// Field used to sync texture usages between elements
-pub const GST_CAPS_FIELD_WGPU_TEXTURE_USAGE: &str = "texture-usages";
+/// Field used to sync texture usages between elements, the same as for memory-based texture caps
+pub use crate::texture_memory::GST_CAPS_FIELD_WGPU_TEXTURE_USAGE;
 
 #[repr(transparent)]
 pub struct WgpuTextureMeta(imp::WgpuTextureMeta);
@@ -21,7 +25,47 @@ impl WgpuTextureMeta {
         context: WgpuContext,
         texture: wgpu::Texture,
     ) -> gst::MetaRefMut<'_, Self, gst::meta::Standalone> {
-        let mut params = imp::WgpuTextureMetaParams { context, texture };
+        Self::add_with_owner(dst, context, texture, None)
+    }
+
+    /// Attaches the texture of the first memory of `texture_buffer`, which must be [`WgpuTextureMemory`]
+    ///
+    /// The meta holds `texture_buffer` while it lives, so a pooled buffer comes back to its pool once all buffers
+    /// with the meta are gone. Returns `None` if the buffer has no texture memory.
+    pub fn add_from_buffer(
+        dst: &mut gst::BufferRef,
+        context: WgpuContext,
+        texture_buffer: gst::Buffer,
+    ) -> Option<gst::MetaRefMut<'_, Self, gst::meta::Standalone>> {
+        if texture_buffer.n_memory() == 0 {
+            return None;
+        }
+
+        let texture = texture_buffer
+            .peek_memory(0)
+            .downcast_memory_ref::<WgpuTextureMemory>()?
+            .texture()
+            .clone();
+
+        Some(Self::add_with_owner(
+            dst,
+            context,
+            texture,
+            Some(texture_buffer),
+        ))
+    }
+
+    fn add_with_owner(
+        dst: &mut gst::BufferRef,
+        context: WgpuContext,
+        texture: wgpu::Texture,
+        owner: Option<gst::Buffer>,
+    ) -> gst::MetaRefMut<'_, Self, gst::meta::Standalone> {
+        let mut params = imp::WgpuTextureMetaParams {
+            context,
+            texture,
+            owner,
+        };
         let meta = unsafe {
             gst::ffi::gst_buffer_add_meta(
                 dst.as_mut_ptr(),
@@ -42,6 +86,22 @@ impl WgpuTextureMeta {
     }
 }
 
+/// Texture carried by the buffer: the first memory if it is [`WgpuTextureMemory`], otherwise the attached [`WgpuTextureMeta`]
+///
+/// Allows elements to accept both memory and meta texture caps
+pub fn buffer_texture(buffer: &gst::BufferRef) -> Option<wgpu::Texture> {
+    if 0 < buffer.n_memory() {
+        let mem = buffer.peek_memory(0);
+        if let Some(mem) = mem.downcast_memory_ref::<WgpuTextureMemory>() {
+            return Some(mem.texture().clone());
+        }
+    }
+
+    buffer
+        .meta::<WgpuTextureMeta>()
+        .map(|meta| meta.texture().clone())
+}
+
 unsafe impl Send for WgpuTextureMeta {}
 unsafe impl Sync for WgpuTextureMeta {}
 
@@ -67,11 +127,17 @@ mod imp {
 
     use gst::glib::translate::{from_glib, IntoGlib};
 
-    use crate::{glib, WgpuContext};
+    use crate::{
+        glib,
+        texture_memory::{WgpuTextureMemory, WgpuTextureMemoryExt},
+        WgpuContext,
+    };
 
     pub(super) struct WgpuTextureMetaParams {
         pub context: WgpuContext,
         pub texture: wgpu::Texture,
+        /// Buffer the texture was taken from, kept alive with the meta
+        pub owner: Option<gst::Buffer>,
     }
 
     #[repr(C)]
@@ -79,6 +145,7 @@ mod imp {
         parent: gst::ffi::GstMeta,
         pub(super) context: ManuallyDrop<WgpuContext>,
         pub(super) texture: ManuallyDrop<wgpu::Texture>,
+        pub(super) owner: ManuallyDrop<Option<gst::Buffer>>,
     }
 
     #[no_mangle]
@@ -105,10 +172,15 @@ mod imp {
         let meta = &mut *(meta as *mut WgpuTextureMeta);
         let params = core::ptr::read(params as *const WgpuTextureMetaParams);
 
-        let WgpuTextureMetaParams { context, texture } = params;
+        let WgpuTextureMetaParams {
+            context,
+            texture,
+            owner,
+        } = params;
 
         core::ptr::write(&mut meta.context, ManuallyDrop::new(context));
         core::ptr::write(&mut meta.texture, ManuallyDrop::new(texture));
+        core::ptr::write(&mut meta.owner, ManuallyDrop::new(owner));
 
         true.into_glib()
     }
@@ -120,6 +192,7 @@ mod imp {
         let meta = &mut *(meta as *mut WgpuTextureMeta);
         ManuallyDrop::drop(&mut meta.context);
         ManuallyDrop::drop(&mut meta.texture);
+        ManuallyDrop::drop(&mut meta.owner);
     }
 
     unsafe extern "C" fn wgpu_texture_meta_transform(
@@ -139,8 +212,14 @@ mod imp {
 
         let context: &WgpuContext = &meta.context;
         let texture: &wgpu::Texture = &meta.texture;
-
-        super::WgpuTextureMeta::add(dst, context.clone(), texture.clone());
+        let owner: &Option<gst::Buffer> = &meta.owner;
+
+        super::WgpuTextureMeta::add_with_owner(
+            dst,
+            context.clone(),
+            texture.clone(),
+            owner.clone(),
+        );
 
         true.into_glib()
     }
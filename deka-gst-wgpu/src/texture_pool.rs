@@ -0,0 +1,61 @@
+//!
+//! Pool of intermediate textures used by multi-pass filters
+//!
+//! A texture is taken for recording a frame and returned right after, commands of the queue run in the recorded order,
+//! so the next user overwrites it only after the previous one is done with it.
+//!
+
+use parking_lot::Mutex;
+
+/// How many free textures the pool keeps, the oldest ones are dropped first
+const MAX_FREE_TEXTURES: usize = 8;
+
+/// Reuses textures between frames and elements instead of creating new ones for every frame
+#[derive(Debug, Default)]
+pub struct TexturePool {
+    free: Mutex<Vec<wgpu::Texture>>,
+}
+
+impl TexturePool {
+    /// Takes a 2D texture with exactly the `size`, `format` and `usage` from the pool or creates a new one
+    pub fn acquire(
+        &self,
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::Texture {
+        {
+            let mut free = self.free.lock();
+
+            let found = free.iter().position(|texture| {
+                texture.size() == size && texture.format() == format && texture.usage() == usage
+            });
+
+            if let Some(pos) = found {
+                return free.swap_remove(pos);
+            }
+        }
+
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("deka-gst-wgpu-intermediate"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        })
+    }
+
+    /// Returns the texture to the pool
+    pub fn release(&self, texture: wgpu::Texture) {
+        let mut free = self.free.lock();
+        if MAX_FREE_TEXTURES <= free.len() {
+            free.remove(0);
+        }
+
+        free.push(texture);
+    }
+}